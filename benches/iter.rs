@@ -124,7 +124,7 @@ fn generate_dual_component_list_group(list_size: u32) -> EntityList<EntityRef> {
                 c = 0;
             }
         }
-        c = c + 1;
+        c += 1;
         is_static = !is_static;
     }
 
@@ -210,6 +210,44 @@ fn update_dual_component_list(list: &mut EntityList<EntityRef>) {
     }
 }
 
+fn update_dual_component_list_pinned(list: &mut EntityList<EntityRef>) {
+    let (storage, iter) = list.iter_mut_pinned::<(Speed, CollisionBox,)>();
+    for (_i, e) in iter {
+        let Speed {x: speed_x, y: speed_y } = storage.get::<Speed>(e).unwrap();
+        let c: &CollisionBox = storage.get::<CollisionBox>(e).unwrap();
+        if ! c.is_static {
+            e.pos.x.set(e.pos.x.get() + speed_x.get());
+            e.pos.y.set(e.pos.y.get() + speed_y.get());
+        }
+    }
+}
+
+fn update_dual_component_list_archetypes(list: &mut EntityList<EntityRef>) {
+    for (_i, e) in list.iter_archetypes_mut::<(Speed, CollisionBox,)>() {
+        let Speed {x: speed_x, y: speed_y } = e.get::<Speed>().unwrap();
+        let c: &CollisionBox = e.get::<CollisionBox>().unwrap();
+        if ! c.is_static {
+            e.pos.x.set(e.pos.x.get() + speed_x.get());
+            e.pos.y.set(e.pos.y.get() + speed_y.get());
+        }
+    }
+}
+
+fn update_dual_component_list_cached(list: &mut EntityList<EntityRef>) {
+    for (_i, e) in list.iter_cached_mut::<(Speed, CollisionBox,)>() {
+        let Speed {x: speed_x, y: speed_y } = e.get::<Speed>().unwrap();
+        let c: &CollisionBox = e.get::<CollisionBox>().unwrap();
+        if ! c.is_static {
+            e.pos.x.set(e.pos.x.get() + speed_x.get());
+            e.pos.y.set(e.pos.y.get() + speed_y.get());
+        }
+    }
+}
+
+fn count_dual_component_ids(list: &EntityList<EntityRef>) -> usize {
+    list.iter_ids::<(Speed, CollisionBox,)>().filter(|id| id.generation == 0).count()
+}
+
 fn maybe_update_dual_component_list(list: &mut EntityList<EntityRef>) {
     for (_i, e) in list.iter_all_mut() {
         if e.has::<Speed>() && e.has::<CollisionBox>() {
@@ -256,6 +294,17 @@ pub fn iter_dual_component(c: &mut Criterion) {
     }
 }
 
+pub fn iter_dual_component_pinned(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dual_component_pinned");
+    for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            let mut list = generate_dual_component_list(size as u32);
+            b.iter(|| update_dual_component_list_pinned(&mut list))
+        });
+    }
+}
+
 pub fn iter_dual_component_sparse(c: &mut Criterion) {
     let mut group = c.benchmark_group("dual_component_sparse1");
     for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
@@ -278,6 +327,30 @@ pub fn iter_dual_component_very_sparse(c: &mut Criterion) {
     }
 }
 
+pub fn iter_dual_component_sparse_archetypes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dual_component_sparse1_archetypes");
+    for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            let mut list = generate_dual_component_list_quite_sparse(size as u32);
+            list.enable_archetypes();
+            b.iter(|| update_dual_component_list_archetypes(&mut list))
+        });
+    }
+}
+
+pub fn iter_dual_component_sparse_cached(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dual_component_sparse1_cached");
+    for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            let mut list = generate_dual_component_list_quite_sparse(size as u32);
+            list.cache_query::<(Speed, CollisionBox,)>();
+            b.iter(|| update_dual_component_list_cached(&mut list))
+        });
+    }
+}
+
 pub fn iter_dual_component_grouped(c: &mut Criterion) {
     let mut group = c.benchmark_group("dual_component_grouped");
     for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
@@ -300,6 +373,17 @@ pub fn iter_dual_component_packed(c: &mut Criterion) {
     }
 }
 
+pub fn iter_dual_component_ids(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dual_component_ids");
+    for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
+        group.throughput(Throughput::Elements(*size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
+            let list = generate_dual_component_list(size as u32);
+            b.iter(|| count_dual_component_ids(&list))
+        });
+    }
+}
+
 pub fn iter_all(c: &mut Criterion) {
     let mut group = c.benchmark_group("iter_all");
     for size in [100, 1_000, 10_000, 100_000, 1_000_000].iter() {
@@ -314,6 +398,6 @@ pub fn iter_all(c: &mut Criterion) {
 criterion_group!{
     name = benches;
     config = Criterion::default().sample_size(30);
-    targets = iter_single_component, iter_dual_component, iter_dual_component_sparse, iter_dual_component_very_sparse, iter_dual_component_grouped, iter_dual_component_packed, iter_all
+    targets = iter_single_component, iter_dual_component, iter_dual_component_pinned, iter_dual_component_sparse, iter_dual_component_sparse_archetypes, iter_dual_component_sparse_cached, iter_dual_component_very_sparse, iter_dual_component_grouped, iter_dual_component_packed, iter_dual_component_ids, iter_all
 }
 criterion_main!{benches}
\ No newline at end of file
@@ -0,0 +1,203 @@
+use hashbrown::HashMap;
+use hibitset::{BitSet, BitSetLike};
+
+use crate::{EntityBase, EntityRefBase, EntityList, EntityId, ComponentBitSets, MultiComponent};
+use crate::genarena::{GenArena, EntriesMut};
+use crate::change_ticks::ChangeRecorder;
+
+/// Entities grouped by the set of components they have (their archetype), see `EntityList::enable_archetypes`.
+#[derive(Debug, Clone, Default)]
+pub (crate) struct Archetypes {
+    /// Archetype for every set of components, as a mask of bitset positions
+    by_signature: HashMap<Vec<u64>, usize>,
+    /// Indexes of the entities of every archetype
+    archetypes: Vec<Vec<u32>>,
+    /// Archetype and row of every entity, by entity index
+    locations: Vec<Option<(usize, usize)>>,
+    /// Reused to compute the signature of an entity
+    signature: Vec<u64>,
+}
+
+impl Archetypes {
+    /// Move the entity to the archetype of its current components.
+    pub (crate) fn update<E: EntityBase>(&mut self, bitsets: &ComponentBitSets, id: EntityId, e: &E) {
+        let signature = &mut self.signature;
+        signature.clear();
+        signature.resize(bitsets.len().div_ceil(64), 0);
+        e.for_each_component_position(|p: usize, is_active: bool| {
+            if is_active {
+                signature[p / 64] |= 1 << (p % 64);
+            }
+        });
+        let archetype = match self.by_signature.get(self.signature.as_slice()) {
+            Some(archetype) => *archetype,
+            None => {
+                self.archetypes.push(Vec::new());
+                self.by_signature.insert(self.signature.clone(), self.archetypes.len() - 1);
+                self.archetypes.len() - 1
+            },
+        };
+        if self.locations.get(id.index).copied().flatten().is_some_and(|(current, _)| current == archetype) {
+            return;
+        }
+        self.remove(id);
+        if self.locations.len() <= id.index {
+            self.locations.resize(id.index + 1, None);
+        }
+        let indexes = &mut self.archetypes[archetype];
+        self.locations[id.index] = Some((archetype, indexes.len()));
+        indexes.push(id.index as u32);
+    }
+
+    pub (crate) fn remove(&mut self, id: EntityId) {
+        let (archetype, row) = match self.locations.get_mut(id.index).and_then(|l| l.take()) {
+            Some(location) => location,
+            None => return,
+        };
+        let indexes = &mut self.archetypes[archetype];
+        indexes.swap_remove(row);
+        if let Some(moved) = indexes.get(row) {
+            self.locations[*moved as usize] = Some((archetype, row));
+        }
+    }
+
+    pub (crate) fn clear(&mut self) {
+        for indexes in &mut self.archetypes {
+            indexes.clear();
+        }
+        self.locations.clear();
+    }
+
+    /// Indexes of the entities of every archetype matching `C`.
+    ///
+    /// All the entities of an archetype have the same components, so only the first one is checked.
+    fn matching<'a, E: EntityBase, C: MultiComponent<'a, E>>(&'a self, bitsets: &'a ComponentBitSets) -> Vec<&'a [u32]> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        let bitset = C::bitset(bitsets);
+        self.archetypes.iter()
+            .filter(|indexes| indexes.first().is_some_and(|index| bitset.contains(*index)))
+            .map(|indexes| indexes.as_slice())
+            .collect()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Group the entities by the set of components they have, so that `iter_archetypes` can go
+    /// through dense runs of entities instead of combining bitsets.
+    ///
+    /// Groups are kept up to date like the spatial index, see `enable_spatial_index`. This pays off
+    /// when few entities match a query made of several components, which are spread over the list.
+    /// Does nothing if archetypes are already enabled.
+    ///
+    /// **WARNING**: After adding or removing a component via `get_mut` or `iter_mut`, you must call
+    /// `refresh`, otherwise the entity stays in its previous group.
+    pub fn enable_archetypes(&mut self) {
+        if self.archetypes.is_some() {
+            return;
+        }
+        let mut archetypes = Archetypes::default();
+        for (id, e) in self.entities.iter() {
+            archetypes.update(&self.bitsets, id, e);
+        }
+        self.archetypes = Some(archetypes);
+    }
+
+    pub fn disable_archetypes(&mut self) {
+        self.archetypes = None;
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), going through the
+    /// groups of entities matching them, see `enable_archetypes`.
+    ///
+    /// Entities are yielded group by group: the order of iteration is unspecified.
+    ///
+    /// Panics if archetypes are not enabled.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, entity) in entities.iter_archetypes::<(Speed, CollisionBox)>() { }`
+    pub fn iter_archetypes<'a, C: MultiComponent<'a, E>>(&'a self) -> ArchetypeIter<'a, E> {
+        let archetypes = self.archetypes.as_ref().expect("archetypes are not enabled");
+        ArchetypeIter {
+            runs: archetypes.matching::<E, C>(&self.bitsets).into_iter(),
+            run: [].iter(),
+            disabled: self.bitsets.disabled(),
+            values: &self.entities,
+        }
+    }
+
+    /// Same as `iter_archetypes`, but mutably.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_archetypes_mut<'a, C: MultiComponent<'a, E>>(&'a mut self) -> ArchetypeIterMut<'a, E> {
+        self.flush_refresh();
+        self.lend_entities();
+        let archetypes = self.archetypes.as_ref().expect("archetypes are not enabled");
+        ArchetypeIterMut {
+            runs: archetypes.matching::<E, C>(&self.bitsets).into_iter(),
+            run: [].iter(),
+            disabled: self.bitsets.disabled(),
+            values: self.entities.entries_mut(),
+            changes: ChangeRecorder { ticks: &mut self.ticks, bitsets: &self.bitsets },
+        }
+    }
+}
+
+/// Iterator returned by `EntityList::iter_archetypes`.
+pub struct ArchetypeIter<'a, E> {
+    runs: std::vec::IntoIter<&'a [u32]>,
+    run: std::slice::Iter<'a, u32>,
+    /// Entities of a group are skipped one by one when disabled, see `EntityList::set_enabled`
+    disabled: &'a BitSet,
+    values: &'a GenArena<E>,
+}
+
+impl<'a, E> Iterator for ArchetypeIter<'a, E> {
+    type Item = (EntityId, &'a E);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(index) = self.run.next() {
+                if self.disabled.contains(*index) {
+                    continue;
+                }
+                let (e, generation) = self.values.get_raw(*index as usize).expect(crate::FATAL_ERR_BITSET);
+                return Some((EntityId::new(*index as usize, generation), e));
+            }
+            self.run = self.runs.next()?.iter();
+        }
+    }
+}
+
+/// Iterator returned by `EntityList::iter_archetypes_mut`.
+pub struct ArchetypeIterMut<'a, E> {
+    runs: std::vec::IntoIter<&'a [u32]>,
+    run: std::slice::Iter<'a, u32>,
+    /// Entities of a group are skipped one by one when disabled, see `EntityList::set_enabled`
+    disabled: &'a BitSet,
+    values: EntriesMut<'a, E>,
+    changes: ChangeRecorder<'a>,
+}
+
+impl<'a, E> Iterator for ArchetypeIterMut<'a, E> {
+    type Item = (EntityId, &'a mut E);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(index) = self.run.next() {
+                if self.disabled.contains(*index) {
+                    continue;
+                }
+                self.changes.record(*index as usize);
+                // SAFETY: an entity belongs to a single archetype, and appears once in it, so every
+                // index is yielded once
+                let (e, generation) = unsafe { self.values.get_raw_mut(*index as usize) }.expect(crate::FATAL_ERR_BITSET);
+                return Some((EntityId::new(*index as usize, generation), e));
+            }
+            self.run = self.runs.next()?.iter();
+        }
+    }
+}
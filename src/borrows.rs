@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::Component;
+#[cfg(feature = "debug-borrows")]
+use std::any::TypeId;
+#[cfg(feature = "debug-borrows")]
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "debug-borrows")]
+use crate::TypeIdMap;
+
+/// The components lent by the iterators of a list, stored along with its components.
+///
+/// With the `debug-borrows` feature, iterators handing out components lend them for as long as they
+/// live, and accessing a lent component through an entity panics instead of aliasing it: for
+/// instance getting the component `C` of an entity returned by `iter_single_mut::<C>`.
+///
+/// Only the iterators are tracked by the feature, not the references returned by `EntityBase::get`.
+/// Without it, these checks compile to nothing.
+///
+/// Whatever the features, the tracker also knows when the list hands out several entities at once,
+/// like `iter_mut` or `get_many_mut` do, see `lend_entities`. Adding or removing a component writes
+/// to its slab, and may move it: while entities are lent, this panics if a component of the same
+/// type was borrowed through another entity, see `SlabBorrower`.
+#[derive(Default)]
+pub struct BorrowTracker {
+    /// Odd while several entities are lent, and incremented for every such loan
+    entities_loan: AtomicUsize,
+    /// By component, the number of shared loans, or -1 for a mutable loan
+    #[cfg(feature = "debug-borrows")]
+    loans: Mutex<TypeIdMap<isize>>,
+}
+
+impl BorrowTracker {
+    /// Start lending several entities at once, until `end_entities_loan` is called.
+    pub fn lend_entities(&self) {
+        let loan = self.entities_loan.load(Ordering::Relaxed);
+        self.entities_loan.store(loan.wrapping_add(1) | 1, Ordering::Relaxed);
+    }
+
+    /// Stop lending several entities at once: the list is borrowed again, so the entities lent
+    /// before are not borrowed anymore.
+    pub fn end_entities_loan(&self) {
+        let loan = self.entities_loan.load(Ordering::Relaxed);
+        self.entities_loan.store(loan.wrapping_add(1) & !1, Ordering::Relaxed);
+    }
+
+    /// Returns the current loan of several entities, if any.
+    #[inline]
+    fn entities_loan(&self) -> Option<usize> {
+        let loan = self.entities_loan.load(Ordering::Relaxed);
+        (loan & 1 == 1).then_some(loan)
+    }
+
+    /// The tracker is locked rather than borrowed, so that a frozen list can be read from several threads.
+    #[cfg(feature = "debug-borrows")]
+    fn loans(&self) -> MutexGuard<'_, TypeIdMap<isize>> {
+        // a failed check panics with the lock held, the loans are still consistent
+        self.loans.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Lend the component `C` until the returned loan is dropped.
+    ///
+    /// Panics if `C` is already lent mutably, or if it is lent at all and `mutable` is true.
+    #[inline]
+    pub fn lend<E, C: Component<E>>(&self, mutable: bool) -> Loan {
+        #[cfg(feature = "debug-borrows")] {
+            let mut loans = self.loans();
+            let count = loans.entry(TypeId::of::<C>()).or_insert(0);
+            match (*count, mutable) {
+                (-1, _) => panic!("{} is already borrowed mutably by an iterator", C::NAME),
+                (n, true) if n > 0 => panic!("{} is already borrowed by an iterator", C::NAME),
+                (_, true) => *count = -1,
+                (_, false) => *count += 1,
+            }
+            Loan { tracker: self, type_id: TypeId::of::<C>() }
+        }
+        #[cfg(not(feature = "debug-borrows"))] {
+            let _ = mutable;
+            Loan {}
+        }
+    }
+
+    /// Panics if the component `C` is lent mutably.
+    #[inline]
+    pub fn check_shared<E, C: Component<E>>(&self) {
+        #[cfg(feature = "debug-borrows")]
+        if self.loans().get(&TypeId::of::<C>()) == Some(&-1) {
+            panic!(
+                "{} is borrowed mutably by an iterator, it cannot be accessed through the entity at the same time",
+                C::NAME
+            );
+        }
+    }
+
+    /// Panics if the component `C` is lent at all.
+    #[inline]
+    pub fn check_exclusive<E, C: Component<E>>(&self) {
+        #[cfg(feature = "debug-borrows")]
+        if self.loans().get(&TypeId::of::<C>()).is_some_and(|count| *count != 0) {
+            panic!(
+                "{} is borrowed by an iterator, it cannot be mutated, added or removed through the entity at the same time",
+                C::NAME
+            );
+        }
+    }
+}
+
+/// A component lent by an iterator, returned by `BorrowTracker::lend`.
+pub struct Loan {
+    #[cfg(feature = "debug-borrows")]
+    tracker: *const BorrowTracker,
+    #[cfg(feature = "debug-borrows")]
+    type_id: TypeId,
+}
+
+#[cfg(feature = "debug-borrows")]
+impl Drop for Loan {
+    fn drop(&mut self) {
+        // SAFETY: loans are held by iterators, which borrow the list owning the tracker
+        let tracker = unsafe { &*self.tracker };
+        let mut loans = tracker.loans();
+        if let Some(count) = loans.get_mut(&self.type_id) {
+            *count = if *count == -1 { 0 } else { *count - 1 };
+        }
+    }
+}
+
+/// Returned by `SlabBorrower` when components were borrowed through several entities.
+const SEVERAL_ENTITIES: usize = usize::MAX;
+
+/// The entity through which the components of a slab were borrowed while several entities are lent,
+/// see `BorrowTracker::lend_entities`.
+///
+/// The components of an entity can be added or removed as long as no other entity borrowed a
+/// component of the same type, since the borrows of the entity itself ended when it was borrowed
+/// mutably. Entities are told apart by their address, which does not change while they are lent.
+#[derive(Default)]
+pub struct SlabBorrower {
+    /// The loan of entities during which `entity` was recorded
+    loan: AtomicUsize,
+    entity: AtomicUsize,
+}
+
+impl SlabBorrower {
+    /// Record that a component of the slab is borrowed through the entity at the address `entity`.
+    #[inline]
+    pub fn borrow(&self, tracker: &BorrowTracker, entity: usize) {
+        if let Some(loan) = tracker.entities_loan() {
+            if self.loan.load(Ordering::Relaxed) != loan {
+                self.loan.store(loan, Ordering::Relaxed);
+                self.entity.store(entity, Ordering::Relaxed);
+            } else if self.entity.load(Ordering::Relaxed) != entity {
+                self.entity.store(SEVERAL_ENTITIES, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Record that the components of the slab are handed out along with several entities.
+    pub fn borrow_all(&self, tracker: &BorrowTracker) {
+        if let Some(loan) = tracker.entities_loan() {
+            self.loan.store(loan, Ordering::Relaxed);
+            self.entity.store(SEVERAL_ENTITIES, Ordering::Relaxed);
+        }
+    }
+
+    /// Panics if a component `C` of the slab was borrowed through an entity other than `entity`
+    /// during the current loan of entities.
+    #[inline]
+    pub fn check_resize<E, C: Component<E>>(&self, tracker: &BorrowTracker, entity: usize) {
+        if let Some(loan) = tracker.entities_loan() {
+            if self.loan.load(Ordering::Relaxed) == loan && self.entity.load(Ordering::Relaxed) != entity {
+                panic!(
+                    "{} is borrowed through another entity, it cannot be added to or removed from an entity at the same time",
+                    C::NAME
+                );
+            }
+        }
+    }
+}
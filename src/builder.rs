@@ -0,0 +1,91 @@
+use std::marker::PhantomData;
+
+use crate::{EntityRefBase, EntityList, DEFAULT_BITSET_CAPACITY};
+
+/// Builder to configure an `EntityList` at creation, see `EntityList::builder`.
+///
+/// # Example
+///
+/// ```ignore
+/// let entities = EntityList::<EntityRef>::builder()
+///     .capacity(100_000)
+///     .bitset_capacity(100_000)
+///     .change_tracking(false)
+///     .build();
+/// ```
+pub struct EntityListBuilder<E: EntityRefBase> {
+    capacity: usize,
+    bitset_capacity: u32,
+    change_tracking: bool,
+    uuids: bool,
+    reuse_indices: bool,
+    _phantom: PhantomData<fn() -> E>,
+}
+
+impl<E: EntityRefBase> EntityListBuilder<E> {
+    pub fn new() -> Self {
+        EntityListBuilder {
+            capacity: 0,
+            bitset_capacity: DEFAULT_BITSET_CAPACITY,
+            change_tracking: true,
+            uuids: false,
+            reuse_indices: true,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Number of entities the arena can hold without reallocating. Defaults to 0.
+    ///
+    /// The bitsets are grown to hold at least as many entities.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Initial capacity of every component bitset. Defaults to `DEFAULT_BITSET_CAPACITY`.
+    pub fn bitset_capacity(mut self, bitset_capacity: u32) -> Self {
+        self.bitset_capacity = bitset_capacity;
+        self
+    }
+
+    /// Whether additions, changes and removals of components are recorded. Defaults to true.
+    ///
+    /// Without change tracking, `Added<C>` and `Changed<C>` only match since tick 0, and
+    /// `removed_since` never yields anything.
+    pub fn change_tracking(mut self, enabled: bool) -> Self {
+        self.change_tracking = enabled;
+        self
+    }
+
+    /// Whether entities get a uuid, see `EntityList::enable_uuids`. Defaults to false.
+    pub fn uuids(mut self, enabled: bool) -> Self {
+        self.uuids = enabled;
+        self
+    }
+
+    /// Whether the indexes of removed entities are given to new entities, see
+    /// `EntityList::set_reuse_indices`. Defaults to true.
+    pub fn reuse_indices(mut self, enabled: bool) -> Self {
+        self.reuse_indices = enabled;
+        self
+    }
+
+    pub fn build(self) -> EntityList<E> {
+        let mut list = EntityList::with_bitset_capacity(self.bitset_capacity);
+        list.ticks.enabled = self.change_tracking;
+        if self.capacity > 0 {
+            list.reserve(self.capacity);
+        }
+        if self.uuids {
+            list.enable_uuids();
+        }
+        list.set_reuse_indices(self.reuse_indices);
+        list
+    }
+}
+
+impl<E: EntityRefBase> Default for EntityListBuilder<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{EntityBase, EntityRefBase, EntityList, EntityId, Component};
+
+/// Several components added to an entity at once, implemented for tuples of up to 8 components.
+///
+/// # Example
+///
+/// `let e = Entity::new(props).with_bundle((Position::default(), Speed::default(), Sprite::new("ship")));`
+pub trait Bundle<E: EntityBase> {
+    /// Set every component of the bundle on the entity. The old components are discarded.
+    fn set(self, entity: &mut E);
+
+    /// Call `f` with the position of every component of the bundle (see `Component::POSITION`), and
+    /// whether the entity has it.
+    fn for_each_position(entity: &E, f: impl FnMut(usize, bool));
+}
+
+macro_rules! bundle_impl {
+    ($($ty:ident),*) => {
+        impl<E: EntityBase, $($ty: Component<E>),*> Bundle<E> for ($($ty,)*) {
+            #[inline]
+            #[allow(non_snake_case)]
+            fn set(self, entity: &mut E) {
+                let ($($ty,)*) = self;
+                $(
+                    <$ty as Component<E>>::set($ty, entity);
+                )*
+            }
+
+            #[inline]
+            fn for_each_position(entity: &E, mut f: impl FnMut(usize, bool)) {
+                $(
+                    f($ty::POSITION, $ty::get(entity).is_some());
+                )*
+            }
+        }
+    }
+}
+
+bundle_impl!(C1);
+bundle_impl!(C1, C2);
+bundle_impl!(C1, C2, C3);
+bundle_impl!(C1, C2, C3, C4);
+bundle_impl!(C1, C2, C3, C4, C5);
+bundle_impl!(C1, C2, C3, C4, C5, C6);
+bundle_impl!(C1, C2, C3, C4, C5, C6, C7);
+bundle_impl!(C1, C2, C3, C4, C5, C6, C7, C8);
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Add every component of a bundle to the given entity, updating its bitsets in one pass, and its
+    /// indices once for all the components.
+    ///
+    /// If the entity does not exist anymore, `Some(bundle)` is returned.
+    ///
+    /// # Example
+    ///
+    /// `entities.add_bundle_for_entity(id, (Burning { damage: 2 }, Smoke));`
+    pub fn add_bundle_for_entity<B: Bundle<E>>(&mut self, id: EntityId, bundle: B) -> Option<B> {
+        let deferred = self.is_refresh_deferred();
+        self.lend_entity();
+        let e = match self.entities.get_mut(id) {
+            Some(e) => e,
+            None => return Some(bundle),
+        };
+        if deferred {
+            // same as `add_component_for_entity`: only the change ticks are set immediately
+            let ticks = &mut self.ticks;
+            B::for_each_position(e, |p, has| if has {
+                ticks.set_changed(p, id.index);
+            });
+            bundle.set(e);
+            self.queue_refresh(id);
+            return None;
+        }
+        bundle.set(e);
+        let e = &self.entities[id];
+        let (bitsets, ticks, observers) = (&mut self.bitsets, &mut self.ticks, &mut self.observers);
+        B::for_each_position(e, |p, _| {
+            if bitsets.get_at_mut(p).add(id.index as u32) {
+                // it already had the component, it was replaced
+                ticks.set_changed(p, id.index);
+            } else {
+                ticks.set_added(p, id.index);
+                observers.component_added(p, id, e);
+            }
+        });
+        self.update_indices(id);
+        None
+    }
+}
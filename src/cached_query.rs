@@ -0,0 +1,154 @@
+use std::any::TypeId;
+
+use hibitset::{BitSet, BitSetLike};
+
+use crate::{EntityBase, TypeIdMap, EntityRefBase, EntityList, ComponentBitSets, MultiComponent, MultiComponentIter, MultiComponentIterMut};
+use crate::change_ticks::ChangeRecorder;
+
+/// The combined bitsets of the queries cached with `EntityList::cache_query`, kept along with the
+/// bitsets of the components.
+#[derive(Debug, Clone, Default)]
+pub (crate) struct CachedQueries {
+    queries: Vec<CachedQuery>,
+    by_type: TypeIdMap<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedQuery {
+    /// Returns true if the entity at the given index matches the query
+    matches: fn(&ComponentBitSets, u32) -> bool,
+    /// Returns every index matching the query
+    collect: fn(&ComponentBitSets) -> BitSet,
+    bitset: BitSet,
+}
+
+fn matches<E: EntityBase, C: for<'a> MultiComponent<'a, E>>(bitsets: &ComponentBitSets, index: u32) -> bool {
+    bitsets.enabled(C::bitset(bitsets)).contains(index)
+}
+
+fn collect<E: EntityBase, C: for<'a> MultiComponent<'a, E>>(bitsets: &ComponentBitSets) -> BitSet {
+    bitsets.enabled(C::bitset(bitsets)).iter().collect()
+}
+
+impl CachedQueries {
+    #[inline]
+    pub (crate) fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    pub (crate) fn get(&self, type_id: &TypeId) -> Option<&BitSet> {
+        self.by_type.get(type_id).map(|i| &self.queries[*i].bitset)
+    }
+
+    /// Remove the entity at the given index from every query.
+    pub (crate) fn remove(&mut self, index: u32) {
+        for query in &mut self.queries {
+            query.bitset.remove(index);
+        }
+    }
+
+    pub (crate) fn clear(&mut self) {
+        for query in &mut self.queries {
+            query.bitset.clear();
+        }
+    }
+}
+
+impl ComponentBitSets {
+    /// Update the cached queries for the entity at the given index, once its bitsets are up to date.
+    #[inline]
+    pub (crate) fn update_cached(&mut self, index: u32) {
+        if self.cached.is_empty() {
+            return;
+        }
+        let mut cached = std::mem::take(&mut self.cached);
+        for query in &mut cached.queries {
+            if (query.matches)(self, index) {
+                query.bitset.add(index);
+            } else {
+                query.bitset.remove(index);
+            }
+        }
+        self.cached = cached;
+    }
+
+    /// Compute the bitset of every cached query again, from the bitsets of the components.
+    pub (crate) fn rebuild_cached(&mut self) {
+        let mut cached = std::mem::take(&mut self.cached);
+        for query in &mut cached.queries {
+            query.bitset = (query.collect)(self);
+        }
+        self.cached = cached;
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Cache the query (C1, C2, C3, ...): its combined bitset is kept up to date on every change of
+    /// components, instead of being computed while iterating, see `iter_cached`.
+    ///
+    /// This turns the work done every time the query runs into work done every time an entity
+    /// changes, which pays off for queries run every frame over entities which rarely change. The
+    /// query may use tags, `Without`, `Or` and `Option`. Does nothing if the query is already cached.
+    ///
+    /// **WARNING**: After adding or removing a component via `get_mut` or `iter_mut`, you must call
+    /// `refresh`, like for the bitsets.
+    pub fn cache_query<C: for<'a> MultiComponent<'a, E> + 'static>(&mut self) {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        if self.bitsets.cached.by_type.contains_key(&TypeId::of::<C>()) {
+            return;
+        }
+        let bitset = collect::<E, C>(&self.bitsets);
+        let cached = &mut self.bitsets.cached;
+        cached.by_type.insert(TypeId::of::<C>(), cached.queries.len());
+        cached.queries.push(CachedQuery { matches: matches::<E, C>, collect: collect::<E, C>, bitset });
+    }
+
+    /// Stop caching the query (C1, C2, C3, ...). Returns false if it was not cached.
+    pub fn uncache_query<C: 'static>(&mut self) -> bool {
+        let cached = &mut self.bitsets.cached;
+        let i = match cached.by_type.remove(&TypeId::of::<C>()) {
+            Some(i) => i,
+            None => return false,
+        };
+        cached.queries.swap_remove(i);
+        if let Some(moved) = cached.by_type.values_mut().find(|moved| **moved == cached.queries.len()) {
+            *moved = i;
+        }
+        true
+    }
+
+    /// Returns true if the query (C1, C2, C3, ...) is cached, see `cache_query`.
+    pub fn is_query_cached<C: 'static>(&self) -> bool {
+        self.bitsets.cached.by_type.contains_key(&TypeId::of::<C>())
+    }
+
+    /// Same as `iter`, but with the bitset of a query cached by `cache_query`.
+    ///
+    /// Panics if the query is not cached.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// entities.cache_query::<(Speed, Sprite)>();
+    /// for (id, entity) in entities.iter_cached::<(Speed, Sprite)>() { }
+    /// ```
+    pub fn iter_cached<C: 'static>(&self) -> MultiComponentIter<'_, E, &BitSet> {
+        let bitset = self.bitsets.cached.get(&TypeId::of::<C>()).expect("query is not cached");
+        MultiComponentIter::new(bitset.iter(), &self.entities)
+    }
+
+    /// Same as `iter_cached`, but mutably.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_cached_mut<C: 'static>(&mut self) -> MultiComponentIterMut<'_, E, &BitSet> {
+        self.flush_refresh();
+        self.lend_entities();
+        let bitset = self.bitsets.cached.get(&TypeId::of::<C>()).expect("query is not cached");
+        let mut iter = MultiComponentIterMut::new(bitset.iter(), &mut self.entities);
+        iter.changes = Some(ChangeRecorder { ticks: &mut self.ticks, bitsets: &self.bitsets });
+        iter
+    }
+}
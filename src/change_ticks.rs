@@ -0,0 +1,342 @@
+use hibitset::{BitSet, BitSetLike};
+
+use crate::{
+    Component, EntityBase, EntityRefBase, EntityList, EntityId, MultiComponent, MultiComponentIter, MultiComponentIterMut,
+    QueryElement, ComponentBitSets, component_bitset,
+};
+#[cfg(feature = "profiling")]
+use crate::profiling::QueryProfile;
+
+/// A point in time of an `EntityList`, see `EntityList::advance_tick`.
+pub type Tick = u64;
+
+#[derive(Debug, Clone, Default)]
+pub (crate) struct ComponentTicks {
+    /// Tick at which the component was added, by entity index
+    added: Vec<Tick>,
+    /// Tick at which the component was last changed, by entity index
+    changed: Vec<Tick>,
+    /// Entities which had this component removed, and when
+    removed: Vec<(EntityId, Tick)>,
+    /// Entities which had this component added or changed since the last `take_dirty`, if enabled
+    dirty: Option<BitSet>,
+}
+
+/// Change detection data of an `EntityList`: for every component and every entity, the tick at which the
+/// component was added and last changed. Components are stored by their `ComponentBitSets` position.
+///
+/// Entities that were never recorded are considered to be added and changed at tick 0.
+#[derive(Debug, Clone)]
+pub struct ChangeTicks {
+    pub (crate) current: Tick,
+    pub (crate) components: Vec<ComponentTicks>,
+    /// If false, nothing is recorded, see `EntityListBuilder::change_tracking`
+    pub (crate) enabled: bool,
+}
+
+impl ChangeTicks {
+    pub (crate) fn new(component_count: usize) -> Self {
+        ChangeTicks {
+            current: 0,
+            components: vec![ComponentTicks::default(); component_count],
+            enabled: true,
+        }
+    }
+
+    #[inline]
+    /// Returns the tick at which the component at `position` was added for the entity at `index`.
+    pub fn added(&self, position: usize, index: usize) -> Tick {
+        self.components[position].added.get(index).copied().unwrap_or(0)
+    }
+
+    #[inline]
+    /// Returns the tick at which the component at `position` was last changed for the entity at `index`.
+    pub fn changed(&self, position: usize, index: usize) -> Tick {
+        self.components[position].changed.get(index).copied().unwrap_or(0)
+    }
+
+    fn set(ticks: &mut Vec<Tick>, index: usize, tick: Tick) {
+        if ticks.len() <= index {
+            ticks.resize(index + 1, 0);
+        }
+        ticks[index] = tick;
+    }
+
+    pub (crate) fn set_added(&mut self, position: usize, index: usize) {
+        let c = &mut self.components[position];
+        if let Some(dirty) = &mut c.dirty {
+            dirty.add(index as u32);
+        }
+        if !self.enabled {
+            return;
+        }
+        let current = self.current;
+        Self::set(&mut c.added, index, current);
+        Self::set(&mut c.changed, index, current);
+    }
+
+    pub (crate) fn set_changed(&mut self, position: usize, index: usize) {
+        if let Some(dirty) = &mut self.components[position].dirty {
+            dirty.add(index as u32);
+        }
+        if !self.enabled {
+            return;
+        }
+        let current = self.current;
+        Self::set(&mut self.components[position].changed, index, current);
+    }
+
+    /// Same as `set_changed`, but does nothing if the entity at `index` does not have the component.
+    #[inline]
+    pub (crate) fn set_changed_if_present(&mut self, bitsets: &ComponentBitSets, position: usize, index: usize) {
+        if bitsets.get_at(position).contains(index as u32) {
+            self.set_changed(position, index);
+        }
+    }
+
+    /// Record every component of the entity at `index` as changed. Used when the entity itself is
+    /// handed out mutably, since any of its components may then be written.
+    pub (crate) fn set_entity_changed(&mut self, bitsets: &ComponentBitSets, index: usize) {
+        for position in 0..self.components.len() {
+            self.set_changed_if_present(bitsets, position, index);
+        }
+    }
+
+    pub (crate) fn set_removed(&mut self, position: usize, id: EntityId) {
+        if let Some(dirty) = &mut self.components[position].dirty {
+            dirty.remove(id.index as u32);
+        }
+        if !self.enabled {
+            return;
+        }
+        let current = self.current;
+        self.components[position].removed.push((id, current));
+    }
+}
+
+/// Records the entities handed out by a mutable iterator as changed, see `ChangeTicks::set_entity_changed`.
+pub (crate) struct ChangeRecorder<'a> {
+    pub (crate) ticks: &'a mut ChangeTicks,
+    pub (crate) bitsets: &'a ComponentBitSets,
+}
+
+impl<'a> ChangeRecorder<'a> {
+    #[inline]
+    pub (crate) fn record(&mut self, index: usize) {
+        self.ticks.set_entity_changed(self.bitsets, index);
+    }
+}
+
+/// Query filter, matches entities which have the component `C`, and where `C` was added at or after
+/// the tick given to `iter_since`.
+///
+/// It can be used inside an `Or`. Queries using it anywhere else than `iter_since` and `iter_mut_since`
+/// do not compile.
+pub struct Added<C>(std::marker::PhantomData<C>);
+
+/// Query filter, matches entities which have the component `C`, and where `C` was added or changed
+/// at or after the tick given to `iter_since`.
+///
+/// Every mutable access given by the `EntityList` is recorded as a change, whether the component was
+/// written or not. Handing out the entity itself (`get_mut`, `iter_mut`, ...) records all of its
+/// components, since any of them may be written, for instance via `EntityBase::mutate`. Handing out
+/// components (`get_component_mut`, `iter_components_mut`, ...) only records the components fetched
+/// mutably.
+///
+/// It can be used inside an `Or`. Queries using it anywhere else than `iter_since` and `iter_mut_since`
+/// do not compile:
+///
+/// ```compile_fail
+/// # use smec::{define_entity, EntityList, Changed};
+/// # pub struct Speed(f32);
+/// # define_entity! {
+/// #     pub struct Entity {
+/// #         props => {},
+/// #         components => { speed => Speed }
+/// #     }
+/// # }
+/// let entities: EntityList<EntityRef> = EntityList::new();
+/// for (id, entity) in entities.iter::<(Changed<Speed>,)>() { }
+/// ```
+pub struct Changed<C>(std::marker::PhantomData<C>);
+
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for Added<C> {
+    type BitSet = &'a BitSet;
+
+    const TICK_FILTERED: bool = true;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        component_bitset::<E, C>(bitsets)
+    }
+
+    #[inline]
+    fn matches(_bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+        ticks.added(C::POSITION, index) >= since
+    }
+}
+
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for Changed<C> {
+    type BitSet = &'a BitSet;
+
+    const TICK_FILTERED: bool = true;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        component_bitset::<E, C>(bitsets)
+    }
+
+    #[inline]
+    fn matches(_bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+        ticks.changed(C::POSITION, index) >= since
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    #[inline]
+    /// Returns the current tick. Every change is recorded with this tick.
+    pub fn current_tick(&self) -> Tick {
+        self.ticks.current
+    }
+
+    /// Advances the tick by one, and returns the new one.
+    ///
+    /// The usual pattern for a system is to iterate with `iter_since(last_run)`, and then
+    /// set `last_run = list.advance_tick()`.
+    pub fn advance_tick(&mut self) -> Tick {
+        self.ticks.current += 1;
+        self.ticks.current
+    }
+
+    /// Record that the component `C` of the given entity was changed at the current tick.
+    ///
+    /// Mutable accesses given by the list are already recorded, see `Changed`: this is for changes
+    /// the list cannot see, such as writes through interior mutability.
+    ///
+    /// Does nothing if the entity does not exist or does not have this component.
+    pub fn mark_changed<C: Component<E>>(&mut self, id: EntityId) {
+        if self.entities.get(id).is_some_and(|e| e.has::<C>()) {
+            self.ticks.set_changed(C::POSITION, id.index);
+        }
+    }
+
+    /// Retrieve a component of an entity mutably, and record it as changed at the current tick.
+    pub fn get_component_mut<C: Component<E>>(&mut self, id: EntityId) -> Option<&mut C> {
+        self.lend_entity();
+        let c = self.entities.get_mut(id).and_then(|e| e.get_mut::<C>())?;
+        self.ticks.set_changed(C::POSITION, id.index);
+        Some(c)
+    }
+
+    /// Start recording which entities had the component `C` added or changed, see `take_dirty`.
+    ///
+    /// Unlike change ticks, this costs a single bit by entity, and is recorded even if change
+    /// tracking is disabled. The same changes are recorded, see `Changed`.
+    pub fn enable_dirty_tracking<C: Component<E>>(&mut self) {
+        let c = &mut self.ticks.components[C::POSITION];
+        if c.dirty.is_none() {
+            c.dirty = Some(BitSet::new());
+        }
+    }
+
+    /// Stop recording which entities had the component `C` added or changed.
+    pub fn disable_dirty_tracking<C: Component<E>>(&mut self) {
+        self.ticks.components[C::POSITION].dirty = None;
+    }
+
+    /// Returns the indexes of the entities which had the component `C` added or changed since the
+    /// last call, and clears them.
+    ///
+    /// Entities which lost the component `C` (or were removed) since are not included.
+    ///
+    /// Returns an empty bitset if dirty tracking is not enabled for `C`, see `enable_dirty_tracking`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for index in entities.take_dirty::<Sprite>().iter() {
+    ///     upload_sprite(index);
+    /// }
+    /// ```
+    pub fn take_dirty<C: Component<E>>(&mut self) -> BitSet {
+        let c = &mut self.ticks.components[C::POSITION];
+        c.dirty.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Iterate over all entities which had the component `C` removed (or were removed with it)
+    /// at or after the given tick.
+    ///
+    /// The entities may not exist anymore.
+    ///
+    /// Removals are kept until the next `maintain`, so that they do not pile up: a system which
+    /// runs less often than `maintain` misses the removals made before it.
+    pub fn removed_since<C: Component<E>>(&self, since: Tick) -> impl Iterator<Item=EntityId> + '_ {
+        self.ticks.components[C::POSITION].removed.iter()
+            .filter(move |(_, tick)| *tick >= since)
+            .map(|(id, _)| *id)
+    }
+
+    /// Forget all the removals recorded so far, see `removed_since`.
+    ///
+    /// This is done at the start of every `maintain`.
+    pub fn clear_removed(&mut self) {
+        for c in &mut self.ticks.components {
+            c.removed.clear();
+        }
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), where the `Added<C>` and
+    /// `Changed<C>` filters are applied since the given tick.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, entity) in entities.iter_since::<(Changed<Position>, Sprite)>(last_run) { }`
+    pub fn iter_since<'a, C: MultiComponent<'a, E>>(&'a self, since: Tick) -> impl Iterator<Item=(EntityId, &'a E)> {
+        let ticks = &self.ticks;
+        let bitsets = &self.bitsets;
+        // not `C::iter`, which rejects tick filters
+        #[allow(unused_mut)]
+        let mut iter = MultiComponentIter::new(bitsets.enabled(C::bitset(bitsets)).iter(), &self.entities);
+        #[cfg(feature = "profiling")]
+        let profile = QueryProfile::new_filtered(&self.query_stats, std::any::type_name::<C>());
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(profile.clone());
+        }
+        iter.filter(move |(id, _)| {
+            let matches = C::matches(bitsets, ticks, id.index, since);
+            #[cfg(feature = "profiling")]
+            if matches {
+                profile.record_match();
+            }
+            matches
+        })
+    }
+
+    /// Same as `iter_since`, but mutably. The yielded entities are recorded as changed, once they
+    /// matched the filters.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_mut_since<'a, C: MultiComponent<'a, E>>(&'a mut self, since: Tick) -> impl Iterator<Item=(EntityId, &'a mut E)> {
+        self.flush_refresh();
+        self.lend_entities();
+        let ticks = &mut self.ticks;
+        let bitsets = &self.bitsets;
+        // not `C::iter_mut`, which rejects tick filters
+        #[allow(unused_mut)]
+        let mut iter = MultiComponentIterMut::new(bitsets.enabled(C::bitset(bitsets)).iter(), &mut self.entities);
+        #[cfg(feature = "profiling")]
+        let profile = QueryProfile::new_filtered(&self.query_stats, std::any::type_name::<C>());
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(profile.clone());
+        }
+        iter.filter(move |(id, _)| {
+            let matches = C::matches(bitsets, ticks, id.index, since);
+            if matches {
+                ticks.set_entity_changed(bitsets, id.index);
+                #[cfg(feature = "profiling")]
+                profile.record_match();
+            }
+            matches
+        })
+    }
+}
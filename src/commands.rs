@@ -0,0 +1,92 @@
+use crate::{EntityRefBase, EntityList, EntityId, Component};
+
+type Command<E> = Box<dyn FnOnce(&mut EntityList<E>)>;
+
+/// A buffer of structural changes, recorded while the list cannot be changed (for instance while
+/// iterating), and applied afterwards.
+///
+/// Commands are applied in the order they were recorded, either directly with `apply`, or at the
+/// next `EntityList::maintain` with `EntityList::queue_commands`.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut commands = Commands::new();
+/// for (id, e) in entities.iter::<(Health,)>() {
+///     if e.get::<Health>().unwrap().hp <= 0 {
+///         commands.despawn(id);
+///         commands.spawn(Entity::new((Corpse,)));
+///     }
+/// }
+/// commands.apply(&mut entities);
+/// ```
+pub struct Commands<E: EntityRefBase> {
+    commands: Vec<Command<E>>,
+}
+
+impl<E: EntityRefBase> Commands<E> {
+    pub fn new() -> Self {
+        Commands { commands: Vec::new() }
+    }
+
+    /// Number of recorded commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Insert an entity, see `EntityList::insert`.
+    pub fn spawn(&mut self, entity: E::Owned) {
+        self.run(move |list| { list.insert(entity); });
+    }
+
+    /// Remove an entity, see `EntityList::remove`. Does nothing if the entity does not exist anymore.
+    pub fn despawn(&mut self, id: EntityId) {
+        self.run(move |list| { list.remove(id); });
+    }
+
+    /// Add a component to an entity, see `EntityList::add_component_for_entity`.
+    pub fn add_component<C: Component<E>>(&mut self, id: EntityId, component: C) {
+        self.run(move |list| { list.add_component_for_entity(id, component); });
+    }
+
+    /// Remove a component from an entity, see `EntityList::remove_component_for_entity`.
+    pub fn remove_component<C: Component<E>>(&mut self, id: EntityId) {
+        self.run(move |list| { list.remove_component_for_entity::<C>(id); });
+    }
+
+    /// Record any other change.
+    pub fn run<F: FnOnce(&mut EntityList<E>) + 'static>(&mut self, f: F) {
+        self.commands.push(Box::new(f));
+    }
+
+    /// Apply every recorded command, in order, and leave the buffer empty.
+    pub fn apply(&mut self, list: &mut EntityList<E>) {
+        for command in self.commands.drain(..) {
+            command(list);
+        }
+    }
+
+    /// Append the commands of `other` after the commands of this buffer.
+    pub fn append(&mut self, other: &mut Commands<E>) {
+        self.commands.append(&mut other.commands);
+    }
+}
+
+impl<E: EntityRefBase> Default for Commands<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Queue commands, which are applied at the next `maintain`, after the commands queued before.
+    ///
+    /// Queued commands are not kept by `clone`.
+    pub fn queue_commands(&mut self, mut commands: Commands<E>) {
+        self.pending.commands.append(&mut commands);
+    }
+}
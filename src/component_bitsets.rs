@@ -0,0 +1,142 @@
+use std::any::TypeId;
+
+use hibitset::BitSet;
+
+use crate::{EntityBase, TypeIdMap};
+use crate::cached_query::CachedQueries;
+
+/// The bitsets of every component of an entity type.
+///
+/// Bitsets are stored in a `Vec`, and each component gets a fixed position in it, following the
+/// order of `EntityBase::for_all_components`. Two lists of the same entity type always have the
+/// same positions.
+///
+/// `define_entity!` gives every component its position as `Component::POSITION`, so queries and
+/// typed accesses index the table directly, with no lookup at run time. The `TypeId` map is only
+/// used by generic code, like `validate`.
+///
+/// The bitsets of tags are stored separately, since tags are not known in advance, see `Tagged`.
+#[derive(Debug, Clone)]
+pub struct ComponentBitSets {
+    pub (crate) positions: TypeIdMap<usize>,
+    pub (crate) bitsets: Vec<BitSet>,
+    pub (crate) tags: TypeIdMap<BitSet>,
+    /// Indexes of every entity of the list, so that queries made only of filters like `Without`
+    /// do not match free slots
+    pub (crate) occupied: BitSet,
+    /// Combined bitsets of the cached queries, see `EntityList::cache_query`
+    pub (crate) cached: CachedQueries,
+    /// Returned for tags which were never used
+    empty: BitSet,
+}
+
+impl ComponentBitSets {
+    /// Create an empty bitset for every component of `E`, with the given capacity.
+    pub fn new<E: EntityBase>(capacity: u32) -> Self {
+        let mut positions = TypeIdMap::default();
+        let mut bitsets = Vec::new();
+        E::for_all_components(|type_id: TypeId| {
+            positions.insert(type_id, bitsets.len());
+            bitsets.push(BitSet::with_capacity(capacity));
+        });
+        ComponentBitSets {
+            positions,
+            bitsets,
+            tags: TypeIdMap::default(),
+            occupied: BitSet::with_capacity(capacity),
+            cached: CachedQueries::default(),
+            empty: BitSet::new(),
+        }
+    }
+
+    #[inline]
+    /// Returns the position of the bitset for the given component type.
+    pub fn position(&self, type_id: &TypeId) -> Option<usize> {
+        self.positions.get(type_id).copied()
+    }
+
+    #[inline]
+    pub fn get(&self, type_id: &TypeId) -> Option<&BitSet> {
+        self.positions.get(type_id).map(|p| &self.bitsets[*p])
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, type_id: &TypeId) -> Option<&mut BitSet> {
+        self.positions.get(type_id).map(|p| &mut self.bitsets[*p])
+    }
+
+    #[inline]
+    /// Returns the bitset at the given position, see `position`.
+    ///
+    /// Panics if the position is out of bounds.
+    pub fn get_at(&self, position: usize) -> &BitSet {
+        &self.bitsets[position]
+    }
+
+    #[inline]
+    /// Returns the bitset at the given position mutably, see `position`.
+    ///
+    /// Panics if the position is out of bounds.
+    pub fn get_at_mut(&mut self, position: usize) -> &mut BitSet {
+        &mut self.bitsets[position]
+    }
+
+    /// Grow every bitset so that it can hold indexes up to `capacity` without reallocating.
+    pub fn reserve(&mut self, capacity: u32) {
+        if capacity == 0 {
+            return;
+        }
+        let max = capacity - 1;
+        for bitset in self.bitsets.iter_mut().chain(std::iter::once(&mut self.occupied)) {
+            // hibitset has no reserve, but adding an index grows the bitset up to it
+            if !bitset.add(max) {
+                bitset.remove(max);
+            }
+        }
+    }
+
+    #[inline]
+    /// Returns the number of bitsets, which is the number of components of the entity type.
+    pub fn len(&self) -> usize {
+        self.bitsets.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bitsets.is_empty()
+    }
+
+    #[inline]
+    /// Returns the bitset of the indexes of every entity of the list.
+    pub fn occupied(&self) -> &BitSet {
+        &self.occupied
+    }
+
+    #[inline]
+    /// Returns the bitset of the given tag type, which is empty if the tag was never used.
+    pub fn tag(&self, type_id: &TypeId) -> &BitSet {
+        self.tags.get(type_id).unwrap_or(&self.empty)
+    }
+
+    /// Returns the bitset of the given tag type mutably, creating it if needed.
+    pub fn tag_mut(&mut self, type_id: TypeId) -> &mut BitSet {
+        self.tags.entry(type_id).or_default()
+    }
+
+    /// Remove every tag of the entity at the given index.
+    pub fn remove_tags(&mut self, index: u32) {
+        for bitset in self.tags.values_mut() {
+            bitset.remove(index);
+        }
+    }
+
+    /// Iterate over all the bitsets, in position order.
+    pub fn iter(&self) -> impl Iterator<Item=&BitSet> {
+        self.bitsets.iter()
+    }
+
+    /// Iterate over all the bitsets mutably, in position order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut BitSet> {
+        self.bitsets.iter_mut()
+    }
+}
@@ -0,0 +1,80 @@
+use hashbrown::HashMap;
+use slab::Slab;
+
+use crate::{EntityRefBase, EntityList, RefComponent, ComponentsStorage};
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Reorder the storage of the component `C` so that its components follow the order of the
+    /// indices of their entities.
+    ///
+    /// Components are stored wherever their slab has a free slot, so after entities and components
+    /// come and go, iterating over `C` jumps around in memory. Calling this once in a while, for
+    /// instance after loading a level, makes queries over `C` read the storage sequentially again.
+    /// The capacity of the storage is kept.
+    pub fn sort_component_storage<C: RefComponent<E>>(&mut self) {
+        // SAFETY: the storage is owned by the list, which we borrow mutably, so no component is borrowed
+        let slab = C::get_single_cs_mut(unsafe { &mut *self.components_storage.get() });
+        let mut sorted = Slab::with_capacity(slab.capacity());
+        // soft removed entities hold their components as well, they go last
+        for e in self.entities_holding_components() {
+            if let Some(key) = C::get_cs_id(e) {
+                let key_sorted = sorted.insert(slab.remove(key));
+                // SAFETY: the key was just given by the sorted slab, which replaces the storage below
+                unsafe { C::set_cs_id(e, key_sorted) };
+            }
+        }
+        debug_assert!(slab.is_empty(), "component held by no entity of the list");
+        *slab = sorted;
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Move the components `C` to the lowest free slots of their storage, and give the memory left
+    /// back to the allocator.
+    ///
+    /// The storage of a component never shrinks by itself, and a deserialized storage keeps the holes
+    /// of the one it was serialized from. See `shrink_to_fit` to shrink every component storage.
+    pub fn shrink_component_storage<C: RefComponent<E>>(&mut self) {
+        // SAFETY: the storage is owned by the list, which we borrow mutably, so no component is borrowed
+        let slab = C::get_single_cs_mut(unsafe { &mut *self.components_storage.get() });
+        let mut moved = HashMap::new();
+        slab.compact(|_, from, to| {
+            moved.insert(from, to);
+            true
+        });
+        if moved.is_empty() {
+            return;
+        }
+        for e in self.entities_holding_components() {
+            if let Some(to) = C::get_cs_id(e).and_then(|key| moved.get(&key)) {
+                // SAFETY: the component was moved by `compact` from the key of the entity to `to`
+                unsafe { C::set_cs_id(e, *to) };
+            }
+        }
+    }
+
+    /// Same as `shrink_component_storage`, for every component.
+    pub fn shrink_to_fit(&mut self) {
+        let mut moved = HashMap::new();
+        // SAFETY: the storage is owned by the list, which we borrow mutably, so no component is borrowed
+        unsafe { &mut *self.components_storage.get() }.compact(|type_id, from, to| {
+            moved.insert((type_id, from), to);
+        });
+        if moved.is_empty() {
+            return;
+        }
+        for e in self.entities_holding_components() {
+            // SAFETY: the components were moved by `compact` from the keys of the entity to the new ones
+            unsafe {
+                e.remap_component_keys(|type_id, key| moved.get(&(type_id, key)).copied().unwrap_or(key));
+            }
+        }
+    }
+
+    /// Returns every entity holding keys of the component storage: the ones of the list, then the soft
+    /// removed ones.
+    fn entities_holding_components(&mut self) -> impl Iterator<Item=&mut E> {
+        self.entities.iter_mut().map(|(_, e)| e)
+            .chain(self.recycle_bin.entities.values_mut().map(|soft_removed| &mut soft_removed.entity))
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use slab::Slab;
 
 // Components storage, should be made of `Slab`s.
 // 
@@ -9,7 +10,233 @@ use super::*;
 //
 // BUT as long as we do'nt directly iterate on the slab, we should be fine. If we do directly
 // iterate on the slab at some point though, you will get weird shit...
-pub trait ComponentsStorage: Clone {
+pub trait ComponentsStorage {
     type Ref: EntityRefBase;
     fn new() -> Self;
-}
\ No newline at end of file
+
+    /// Remove every component, keeping the allocated memory.
+    fn clear(&mut self);
+
+    /// Call `f` with the name, the number of components, the capacity and the size of a slot of
+    /// every slab, see `slab_slot_size`.
+    fn for_each_slab(&self, f: impl FnMut(&'static str, usize, usize, usize));
+
+    /// Call `f` with the type and the key of every component stored.
+    fn for_each_key(&self, f: impl FnMut(std::any::TypeId, usize));
+
+    /// Copy every slab which is shared with a clone of the list, see `ComponentSlab`.
+    fn unshare(&mut self);
+
+    /// Move the components of every slab to the lowest free keys and release the memory left, calling
+    /// `f` with the type, the old key and the new key of every component moved.
+    fn compact(&mut self, f: impl FnMut(std::any::TypeId, usize, usize));
+
+    /// Returns the components lent by the iterators of the list, see `BorrowTracker`.
+    fn borrow_tracker(&self) -> &BorrowTracker;
+}
+/// Implemented by `define_entity!` for the storages whose components all implement `Clone`, which is
+/// required to clone an `EntityList`, or to copy the components of its entities, like
+/// `EntityRefBase::clone_owned`.
+pub trait CloneStorage: ComponentsStorage + Clone {
+    #[doc(hidden)]
+    fn clone_owned(&self, e: &Self::Ref) -> <Self::Ref as EntityRefBase>::Owned;
+
+    #[doc(hidden)]
+    fn merge_from_owned(e: &mut Self::Ref, other: &<Self::Ref as EntityRefBase>::Owned, mode: MergeMode);
+}
+
+/// The slab of a component in the storage of a list.
+///
+/// With the `cow` feature, the slab is shared by the clones of a list, and only copied when one of
+/// them writes to it: cloning a list copies its entities, but not its components. Without it, this is
+/// a plain slab.
+pub struct ComponentSlab<C> {
+    #[cfg(not(feature = "cow"))]
+    slab: Slab<ComponentCell<C>>,
+    #[cfg(feature = "cow")]
+    slab: std::sync::Arc<Slab<ComponentCell<C>>>,
+    borrower: SlabBorrower,
+}
+
+/// The bound of the components stored in a `ComponentSlab`: with the `cow` feature, components must
+/// implement `Clone` to be copied on write.
+#[cfg(feature = "cow")]
+pub trait SlabComponent: Clone {}
+
+#[cfg(feature = "cow")]
+impl<C: Clone> SlabComponent for C {}
+
+/// The bound of the components stored in a `ComponentSlab`: with the `cow` feature, components must
+/// implement `Clone` to be copied on write.
+#[cfg(not(feature = "cow"))]
+pub trait SlabComponent {}
+
+#[cfg(not(feature = "cow"))]
+impl<C> SlabComponent for C {}
+
+impl<C: SlabComponent> ComponentSlab<C> {
+    pub fn new() -> Self {
+        ComponentSlab { slab: Default::default(), borrower: SlabBorrower::default() }
+    }
+
+    #[inline]
+    pub fn slab(&self) -> &Slab<ComponentCell<C>> {
+        &self.slab
+    }
+
+    /// Returns the entity through which components of the slab were borrowed, see `SlabBorrower`.
+    #[inline]
+    pub fn borrower(&self) -> &SlabBorrower {
+        &self.borrower
+    }
+
+    /// Returns the slab mutably, after copying it if it is shared.
+    #[inline]
+    pub fn slab_mut(&mut self) -> &mut Slab<ComponentCell<C>> {
+        #[cfg(not(feature = "cow"))] {
+            &mut self.slab
+        }
+        #[cfg(feature = "cow")] {
+            std::sync::Arc::make_mut(&mut self.slab)
+        }
+    }
+
+    /// Copy the slab if it is shared, so that it can be written through `slab_ptr`.
+    #[inline]
+    pub fn unshare(&mut self) {
+        #[cfg(feature = "cow")]
+        self.slab_mut();
+    }
+
+    /// Remove every component. A shared slab is replaced by an empty one instead of being copied.
+    pub fn clear(&mut self) {
+        #[cfg(feature = "cow")]
+        if std::sync::Arc::get_mut(&mut self.slab).is_none() {
+            self.slab = Default::default();
+            return;
+        }
+        self.slab_mut().clear();
+    }
+
+    /// Returns a pointer to the slab, without creating any intermediate reference to it.
+    ///
+    /// # Safety
+    ///
+    /// `this` must point to a live `ComponentSlab`. The slab must be unshared before being written
+    /// through the returned pointer, see `unshare`.
+    #[inline]
+    pub unsafe fn slab_ptr(this: *mut Self) -> *mut Slab<ComponentCell<C>> {
+        #[cfg(not(feature = "cow"))] {
+            std::ptr::addr_of_mut!((*this).slab)
+        }
+        #[cfg(feature = "cow")] {
+            std::sync::Arc::as_ptr(&(*this).slab) as *mut _
+        }
+    }
+}
+
+impl<C: SlabComponent> Default for ComponentSlab<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clone> Clone for ComponentSlab<C> {
+    fn clone(&self) -> Self {
+        ComponentSlab { slab: self.slab.clone(), borrower: SlabBorrower::default() }
+    }
+
+    fn clone_from(&mut self, other: &Self) {
+        self.slab.clone_from(&other.slab);
+    }
+}
+
+#[cfg(feature = "use_serde")]
+impl<C: serde::Serialize> serde::Serialize for ComponentSlab<C> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.slab.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "use_serde")]
+impl<'de, C: serde::Deserialize<'de>> serde::Deserialize<'de> for ComponentSlab<C> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // the slab is turned into an `Arc` with the `cow` feature
+        #[allow(clippy::useless_conversion)]
+        Slab::deserialize(deserializer).map(|slab| ComponentSlab { slab: slab.into(), borrower: SlabBorrower::default() })
+    }
+}
+
+#[doc(hidden)]
+/// Used by `define_entity!`: the size of a slot of the slab of `C`, occupied or not.
+pub fn slab_slot_size<C>() -> usize {
+    // a slot of a `Slab` is either a component or the key of the next free slot, like this `Result`
+    std::mem::size_of::<Result<ComponentCell<C>, usize>>()
+}
+
+/// A component in the storage of a list.
+///
+/// Components are borrowed, mutably or not, through a shared reference to their slab: borrowing
+/// the slab mutably would invalidate the borrows of every other component of the slab, for instance
+/// the ones returned by `get_many_mut` or `iter_mut`. The slab itself is only borrowed mutably to
+/// insert or remove a component, which is rejected while these entities are alive if one of them
+/// borrowed a component of the slab, see `SlabBorrower`.
+#[repr(transparent)]
+pub struct ComponentCell<C>(std::cell::UnsafeCell<C>);
+
+impl<C> ComponentCell<C> {
+    #[inline]
+    pub fn new(component: C) -> Self {
+        ComponentCell(std::cell::UnsafeCell::new(component))
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> C {
+        self.0.into_inner()
+    }
+
+    /// # Safety
+    ///
+    /// The component must not be borrowed mutably for `'a`.
+    #[inline]
+    pub unsafe fn get<'a>(&self) -> &'a C {
+        &*self.0.get()
+    }
+
+    /// # Safety
+    ///
+    /// The component must not be borrowed at all for `'a`.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut<'a>(&self) -> &'a mut C {
+        &mut *self.0.get()
+    }
+}
+
+// SAFETY: a component is only written through `get_mut`, whose callers borrow its entity or its list
+// mutably. A storage shared between threads is only ever read: either by a `FrozenEntityList`, or with
+// the `cow` feature by lists sharing a slab, which is copied before being written, see `ComponentSlab`.
+unsafe impl<C: Sync> Sync for ComponentCell<C> {}
+
+impl<C: Clone> Clone for ComponentCell<C> {
+    fn clone(&self) -> Self {
+        // SAFETY: components are only borrowed mutably through a mutable borrow of their list, and
+        // the storage is only cloned through a shared borrow of it
+        ComponentCell::new(unsafe { self.get() }.clone())
+    }
+}
+
+#[cfg(feature = "use_serde")]
+impl<C: serde::Serialize> serde::Serialize for ComponentCell<C> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // SAFETY: see `Clone`
+        unsafe { self.get() }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "use_serde")]
+impl<'de, C: serde::Deserialize<'de>> serde::Deserialize<'de> for ComponentCell<C> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        C::deserialize(deserializer).map(ComponentCell::new)
+    }
+}
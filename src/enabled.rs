@@ -0,0 +1,84 @@
+use std::any::TypeId;
+
+use hibitset::{BitSet, BitSetAnd, BitSetLike, BitSetNot};
+
+use crate::{EntityRefBase, EntityList, EntityId, ComponentBitSets, MultiComponent, MultiComponentIter, MultiComponentIterMut};
+use crate::change_ticks::ChangeRecorder;
+
+/// The tag of disabled entities, see `EntityList::set_enabled`.
+///
+/// Queries skip the entities tagged with it, so it can be queried along with
+/// `iter_including_disabled` to find them.
+///
+/// # Example
+///
+/// `for (id, entity) in entities.iter_including_disabled::<(Speed, Tagged<Disabled>)>() { }`
+pub struct Disabled;
+
+/// The bitset of a query, without the disabled entities.
+pub type Enabled<'a, B> = BitSetAnd<B, BitSetNot<&'a BitSet>>;
+
+impl ComponentBitSets {
+    #[inline]
+    /// Returns the bitset of the disabled entities, see `EntityList::set_enabled`.
+    pub fn disabled(&self) -> &BitSet {
+        self.tag(&TypeId::of::<Disabled>())
+    }
+
+    #[inline]
+    /// Remove the disabled entities from the bitset of a query.
+    pub fn enabled<'a, B: BitSetLike>(&'a self, bitset: B) -> Enabled<'a, B> {
+        BitSetAnd(bitset, BitSetNot(self.disabled()))
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Enable or disable an entity. Disabled entities keep their components, but are skipped by
+    /// every query, until they are enabled again.
+    ///
+    /// This is cheaper than removing and adding back the components of entities which are
+    /// deactivated for a while, like paused or pooled entities. Getting a disabled entity by its
+    /// id still works, and `iter_including_disabled` iterates over them as well.
+    ///
+    /// Entities are disabled with the tag `Disabled`. Unlike other tags, it is kept when the list is
+    /// serialized. Returns false if the entity does not exist.
+    ///
+    /// # Example
+    ///
+    /// `entities.set_enabled(id, false);`
+    pub fn set_enabled(&mut self, id: EntityId, enabled: bool) -> bool {
+        if enabled {
+            self.untag::<Disabled>(id);
+            self.contains(id)
+        } else {
+            self.tag::<Disabled>(id)
+        }
+    }
+
+    /// Returns true if the entity exists and is not disabled, see `set_enabled`.
+    pub fn is_enabled(&self, id: EntityId) -> bool {
+        self.contains(id) && !self.has_tag::<Disabled>(id)
+    }
+
+    /// Same as `iter`, but disabled entities are not skipped, see `set_enabled`.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    pub fn iter_including_disabled<'a, C: MultiComponent<'a, E>>(&'a self) -> MultiComponentIter<'a, E, C::BitSet> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        MultiComponentIter::new(C::bitset(&self.bitsets).iter(), &self.entities)
+    }
+
+    /// Same as `iter_mut`, but disabled entities are not skipped, see `set_enabled`.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_including_disabled_mut<'a, C: MultiComponent<'a, E>>(&'a mut self) -> MultiComponentIterMut<'a, E, C::BitSet> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        self.flush_refresh();
+        self.lend_entities();
+        let mut iter = MultiComponentIterMut::new(C::bitset(&self.bitsets).iter(), &mut self.entities);
+        iter.changes = Some(ChangeRecorder { ticks: &mut self.ticks, bitsets: &self.bitsets });
+        iter
+    }
+}
@@ -1,16 +1,38 @@
-use crate::{ComponentsStorage};
+use crate::{ComponentsStorage, CloneStorage, ComponentCell, SlabBorrower, Bundle, EntityDiff};
 use std::any::TypeId;
 use slab::Slab;
 
-pub trait Component<E: Sized>: 'static + Clone {
+/// A component of the entity `E`, implemented by `define_entity!`.
+///
+/// Components do not have to implement `Clone`: only cloning an entity or a list, and the methods
+/// copying components from an entity to another, require it, see `CloneComponents`.
+pub trait Component<E: Sized>: 'static + Sized {
+    /// Position of the bitset of this component in the lists of `E`, see `ComponentBitSets`.
+    const POSITION: usize;
+
+    /// Name of this component, as written in `define_entity!`, see `EntityBase::component_name`.
+    const NAME: &'static str;
+
     fn set(self, entity: &mut E);
 
     fn get(entity: &E) -> Option<&Self>;
 
     fn get_mut(entity: &mut E) -> Option<&mut Self>;
 
+    /// Same as `get_mut`, but through a pointer to the entity, so that the components of different
+    /// types can be borrowed at the same time, see `EntityBase::update2`.
+    ///
+    /// # Safety
+    ///
+    /// `entity` must be valid for writes, and the returned pointer must not be used once the
+    /// component is accessed in another way.
+    unsafe fn get_mut_ptr(entity: *mut E) -> Option<*mut Self>;
+
     /// Delete a component from an entity
     fn remove(entity: &mut E) -> Option<Box<Self>>;
+
+    /// Delete a component from an entity, and return it by value instead of boxed.
+    fn take(entity: &mut E) -> Option<Self>;
     
     // read a component with the given predicate. You may return a custom result of your choice.
     fn peek<O, F: FnOnce(&Self) -> O>(entity: &E, f: F) -> Option<O>;
@@ -20,22 +42,148 @@ pub trait Component<E: Sized>: 'static + Clone {
 }
 
 pub trait RefComponent<E: Sized + EntityRefBase>: Component<E> {
-    fn get_single_cs(cs: &E::CS) -> &Slab<Self>;
+    fn get_single_cs(cs: &E::CS) -> &Slab<ComponentCell<Self>>;
+
+    /// Returns a pointer to the slab of this component, without creating any intermediate reference
+    /// to the whole storage.
+    ///
+    /// # Safety
+    ///
+    /// `cs` must point to a valid, live components storage.
+    unsafe fn get_single_cs_ptr(cs: *mut E::CS) -> *mut Slab<ComponentCell<Self>>;
+
+    /// Returns the slab of this component mutably, after copying it if it is shared, see `ComponentSlab`.
+    fn get_single_cs_mut(cs: &mut E::CS) -> &mut Slab<ComponentCell<Self>>;
+
+    /// Returns the entity through which this component was borrowed while entities are lent, see
+    /// `SlabBorrower`.
+    ///
+    /// # Safety
+    ///
+    /// `cs` must point to a valid, live components storage.
+    unsafe fn get_borrower<'a>(cs: *const E::CS) -> &'a SlabBorrower;
 
     fn get_cs_id(entity: &E) -> Option<usize>;
+
+    /// Set the key of the component of this entity in its storage, after the slab was reordered.
+    ///
+    /// # Safety
+    ///
+    /// `key` must be the key of a component `Self` in the storage of the entity, which no other
+    /// entity holds.
+    unsafe fn set_cs_id(entity: &mut E, key: usize);
+}
+
+/// How a component is held by an owned entity: boxed, or inline for the components marked `#[inline]`
+/// in `define_entity!`.
+pub trait ComponentSlot<C> {
+    fn from_component(component: Option<C>) -> Self;
+
+    fn component(&self) -> Option<&C>;
+
+    fn component_mut(&mut self) -> Option<&mut C>;
+
+    fn take_component(&mut self) -> Option<C>;
+
+    fn take_boxed(&mut self) -> Option<Box<C>> {
+        self.take_component().map(Box::new)
+    }
+}
+
+impl<C> ComponentSlot<C> for Option<Box<C>> {
+    #[inline]
+    fn from_component(component: Option<C>) -> Self {
+        component.map(Box::new)
+    }
+
+    #[inline]
+    fn component(&self) -> Option<&C> {
+        self.as_deref()
+    }
+
+    #[inline]
+    fn component_mut(&mut self) -> Option<&mut C> {
+        self.as_deref_mut()
+    }
+
+    #[inline]
+    fn take_component(&mut self) -> Option<C> {
+        self.take().map(|c| *c)
+    }
+
+    #[inline]
+    fn take_boxed(&mut self) -> Option<Box<C>> {
+        self.take()
+    }
+}
+
+impl<C> ComponentSlot<C> for Option<C> {
+    #[inline]
+    fn from_component(component: Option<C>) -> Self {
+        component
+    }
+
+    #[inline]
+    fn component(&self) -> Option<&C> {
+        self.as_ref()
+    }
+
+    #[inline]
+    fn component_mut(&mut self) -> Option<&mut C> {
+        self.as_mut()
+    }
+
+    #[inline]
+    fn take_component(&mut self) -> Option<C> {
+        self.take()
+    }
 }
 
-pub enum ChangeComponent<C> {
+/// The change returned by the predicate of `EntityBase::with_component_change`.
+///
+/// The mutation is boxed by default, so that different closures can be returned from the same
+/// predicate. A closure can be returned unboxed instead to avoid the allocation, by naming the
+/// type `ChangeComponent<C, _>`.
+pub enum ChangeComponent<C, M = Box<dyn FnOnce(&mut C)>> {
     /// Do not change the given component
     NoChange,
     /// Replace the given component by a new one. Works even if there was no component to begin with.
     Replace(C),
     /// Mutate the currently available component. Only works if there is a component to begin with.
-    Mutate(Box<dyn FnOnce(&mut C)>),
+    Mutate(M),
     /// Remove the component without adding a new one.
     Remove,
 }
 
+/// Implemented by `define_entity!` for the entities whose components all implement `Clone`, which is
+/// required by the methods copying components from an entity to another, like `EntityBase::merge_from`.
+///
+/// The owned entity can only be cloned if its components are as well. See `CloneStorage` for the
+/// lists.
+pub trait CloneComponents: EntityBase {
+    #[doc(hidden)]
+    fn diff_components(&self, other: &Self) -> EntityDiff<Self>;
+
+    #[doc(hidden)]
+    fn merge_components(&mut self, other: &Self, mode: MergeMode);
+}
+
+/// Implemented by `define_entity!` when every property and every component implements `Hash`,
+/// see `EntityBase::state_hash`.
+pub trait HashState: EntityBase {
+    #[doc(hidden)]
+    fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H);
+}
+
+/// How `EntityBase::merge_from` treats the components both entities have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Replace the components of the entity by the ones merged in
+    Overwrite,
+    /// Keep the components the entity already has, only add the missing ones
+    KeepExisting,
+}
+
 pub trait EntityOwnedBase: EntityBase {
     /// CreationParams are always the properties of an entity.
     type CreationParams;
@@ -49,24 +197,99 @@ pub trait EntityOwnedBase: EntityBase {
     /// * for a single property A, the param is (A,)
     /// * for a two properties A and B, the param is (A, B)
     /// * and so on
+    ///
+    /// `define_entity!` also generates `Entity::with_props`, to set the properties by name, and
+    /// omit the ones which implement `Default`.
     fn new(params: Self::CreationParams) -> Self;
 }
 
+#[doc(hidden)]
+/// Used by `define_entity!` to get the default value of a prop which was not given to the builder.
+///
+/// Calling `(&PropDefault::<P>(PhantomData)).prop_default()` with both traits in scope picks the
+/// impl of `PropDefaultDefault` if `P: Default`, and falls back to `PropDefaultFallback` through an
+/// extra auto-ref otherwise, like `ComponentEq`.
+pub struct PropDefault<P>(pub std::marker::PhantomData<P>);
+
+#[doc(hidden)]
+pub trait PropDefaultDefault<P> {
+    fn prop_default(&self) -> Option<P>;
+}
+
+impl<P: Default> PropDefaultDefault<P> for PropDefault<P> {
+    #[inline]
+    fn prop_default(&self) -> Option<P> {
+        Some(P::default())
+    }
+}
+
+#[doc(hidden)]
+pub trait PropDefaultFallback<P> {
+    fn prop_default(&self) -> Option<P>;
+}
+
+impl<P> PropDefaultFallback<P> for &PropDefault<P> {
+    #[inline]
+    fn prop_default(&self) -> Option<P> {
+        None
+    }
+}
+
 pub trait EntityRefBase: EntityBase + Clone {
-    type CS: ComponentsStorage;
+    type CS: ComponentsStorage<Ref = Self>;
     // naked is the Ref struct but without the component storage part, used for serializing
     type Naked: Clone;
     type Owned: EntityOwnedBase;
 
     fn from_owned(owned: Self::Owned, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self;
 
+    /// Creates an entity with the given properties and no components, directly attached to `cs`.
+    fn from_params(params: <Self::Owned as EntityOwnedBase>::CreationParams, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self;
+
     fn to_owned(self, cs: &mut Self::CS) -> Self::Owned;
 
-    fn from_naked(naked: Self::Naked, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self;
+    /// Creates an owned copy of this entity, cloning its components from `cs`.
+    fn clone_owned(&self, cs: &Self::CS) -> Self::Owned where Self::CS: CloneStorage {
+        cs.clone_owned(self)
+    }
+
+    /// Creates an entity from its properties and the keys of its components in `cs`.
+    ///
+    /// # Safety
+    ///
+    /// Before any component of the entity is accessed, every key must exist in `cs`, and must not be
+    /// used by any other entity. See `EntityList::validate`.
+    unsafe fn from_naked(naked: Self::Naked, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self;
 
     fn as_naked(&self) -> Self::Naked;
 
+    /// Move the components of `naked` from the storage `from` to the storage `to`, and return it with
+    /// the keys of its components in `to`.
+    fn move_components(naked: Self::Naked, from: &mut Self::CS, to: &mut Self::CS) -> Self::Naked;
+
     fn set_cs(&mut self, cs: std::rc::Weak<std::cell::UnsafeCell<Self::CS>>);
+
+    /// Returns a pointer to the storage this entity is attached to, without checking that it still
+    /// exists. Only meant to be compared, see `PinnedStorage`.
+    fn cs_ptr(&self) -> *const std::cell::UnsafeCell<Self::CS>;
+
+    /// Go through every component this entity has, with its key in the component storage.
+    fn for_each_component_key(&self, f: impl FnMut(TypeId, usize));
+
+    /// Same as `EntityBase::merge_from`, with the components of an owned entity, see
+    /// `EntityList::merge_into_entity`.
+    fn merge_from_owned(&mut self, other: &Self::Owned, mode: MergeMode) where Self::CS: CloneStorage {
+        Self::CS::merge_from_owned(self, other, mode)
+    }
+
+    /// Replace the key of every component this entity has by the one returned by `f`, after the
+    /// storage was compacted.
+    ///
+    /// # Safety
+    ///
+    /// Every key returned must be the key of a component of the same type in the storage of the
+    /// entity, which no other entity holds.
+    unsafe fn remap_component_keys(&mut self, f: impl FnMut(TypeId, usize) -> usize);
 }
 
 pub trait EntityBase: Sized + 'static {
@@ -80,6 +303,114 @@ pub trait EntityBase: Sized + 'static {
     // Go through all possible components this kind of entity might have.
     fn for_all_components(f: impl FnMut(TypeId));
 
+    /// Returns true if the entity has the component of the given type, for types only known at
+    /// runtime, see `has` otherwise. Returns false if the type is not a component of this entity.
+    fn has_type(&self, type_id: TypeId) -> bool;
+
+    /// Returns true if the entity has every component of the given types, see `has_type`.
+    ///
+    /// # Example
+    ///
+    /// `if e.has_all(&script.required_components) { }`
+    fn has_all(&self, type_ids: &[TypeId]) -> bool {
+        type_ids.iter().all(|type_id| self.has_type(*type_id))
+    }
+
+    /// Returns true if the entity has at least one component of the given types, see `has_type`.
+    fn has_any(&self, type_ids: &[TypeId]) -> bool {
+        type_ids.iter().any(|type_id| self.has_type(*type_id))
+    }
+
+    /// Returns the name of the component of the given type, as written in `define_entity!`, or
+    /// `None` if the type is not a component of this entity.
+    fn component_name(type_id: TypeId) -> Option<&'static str>;
+
+    /// Returns the number of components the entity has.
+    fn component_count(&self) -> usize {
+        let mut count = 0;
+        self.for_each_active_component(|_| count += 1);
+        count
+    }
+
+    /// Returns the type of every component the entity has, in the order of `for_all_components`.
+    fn active_components(&self) -> impl Iterator<Item=TypeId> {
+        let mut type_ids = Vec::new();
+        self.for_each_active_component(|type_id| type_ids.push(type_id));
+        type_ids.into_iter()
+    }
+
+    /// Same as `active_components`, with the names of the components, see `component_name`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let names: Vec<_> = e.active_component_names().collect();
+    /// println!("Entity {:?}: {}", id, names.join(", "));
+    /// ```
+    fn active_component_names(&self) -> impl Iterator<Item=&'static str> {
+        self.active_components().filter_map(Self::component_name)
+    }
+
+    /// Remove every component of the entity, leaving only its properties.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList, or use
+    /// `clear_components_for_entity` instead.
+    fn clear_components(&mut self);
+
+    /// Returns the components to add, remove and change to turn this entity into `other`, see
+    /// `EntityDiff`. Properties are not compared.
+    fn diff(&self, other: &Self) -> EntityDiff<Self> where Self: CloneComponents {
+        self.diff_components(other)
+    }
+
+    /// Copy every component of `other` onto this entity, for instance to apply a template. The
+    /// components `other` does not have are left untouched, see `MergeMode` for the others.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList
+    fn merge_from(&mut self, other: &Self, mode: MergeMode) where Self: CloneComponents {
+        self.merge_components(other, mode)
+    }
+
+    /// Feed the properties of the entity, then every component along with whether the entity has
+    /// it, to `hasher`, in declaration order.
+    ///
+    /// Two entities with the same state give the same hash, whatever the list they belong to and
+    /// the order their components were added in, so it can be compared between lockstep peers to
+    /// detect desyncs. Use a hasher which does not depend on a random seed, unlike `RandomState`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    /// e.state_hash(&mut hasher);
+    /// let hash = hasher.finish();
+    /// ```
+    fn state_hash<H: std::hash::Hasher>(&self, hasher: &mut H) where Self: HashState {
+        self.hash_state(hasher)
+    }
+
+    /// Add, remove and replace the components of this entity as described by `diff`: applying
+    /// `a.diff(&b)` to `a` gives it the components of `b`.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList
+    fn apply_diff(&mut self, diff: &EntityDiff<Self>) {
+        diff.apply(self);
+    }
+
+    /// Same as `for_each_component`, but with the position of every component instead of its type,
+    /// see `ComponentBitSets`.
+    ///
+    /// Components are always visited in the same order as `for_all_components`, which is the
+    /// order of their positions.
+    #[inline]
+    fn for_each_component_position(&self, mut f: impl FnMut(usize, bool)) {
+        let mut position = 0;
+        self.for_each_component(|_, is_active| {
+            f(position, is_active);
+            position += 1;
+        });
+    }
+
     #[inline]
     /// Returns the ntity with the specified component. The old component is discarded.
     fn with<C: Component<Self>>(mut self, component: C) -> Self {
@@ -87,6 +418,15 @@ pub trait EntityBase: Sized + 'static {
         self
     }
 
+    #[inline]
+    /// Returns the entity with every component of the bundle. The old components are discarded.
+    ///
+    /// `e.with_bundle((a, b, c))` is the same as `e.with(a).with(b).with(c)`.
+    fn with_bundle<B: Bundle<Self>>(mut self, bundle: B) -> Self {
+        bundle.set(&mut self);
+        self
+    }
+
     #[inline]
     /// Mutates the component for the given entity.
     ///
@@ -99,8 +439,21 @@ pub trait EntityBase: Sized + 'static {
 
     /// Returns the mutable component if there is one, otherwise fills default and then returns it
     fn get_mut_or_default<C: Component<Self> + Default>(&mut self) -> &mut C {
+        self.get_or_insert_with(C::default)
+    }
+
+    /// Returns the mutable component if there is one, otherwise inserts the one returned by `f` and
+    /// then returns it. `f` is only called if the component is missing.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList and the
+    /// component was inserted.
+    ///
+    /// # Example
+    ///
+    /// `entity.get_or_insert_with(|| Inventory::with_slots(8)).push(item);`
+    fn get_or_insert_with<C: Component<Self>, F: FnOnce() -> C>(&mut self, f: F) -> &mut C {
         if C::get(self).is_none() {
-            C::default().set(self);
+            f().set(self);
         };
         // we defined the unit just above if it didn't exist
         self.get_mut::<C>().unwrap()
@@ -125,7 +478,7 @@ pub trait EntityBase: Sized + 'static {
     #[inline]
     /// Removes the given component for the given entity.
     fn with_removed<C: Component<Self>>(mut self) -> Self {
-        self.remove::<C>();
+        self.take::<C>();
         self
     }
 
@@ -152,8 +505,13 @@ pub trait EntityBase: Sized + 'static {
     ///         ChangeComponent::NoChange
     ///     }
     /// });
+    ///
+    /// // same, without boxing the closure
+    /// let e = e.with_component_change(|e: &mut Entity| -> ChangeComponent<ComponentA, _> {
+    ///     ChangeComponent::Mutate(move |a: &mut ComponentA| a.alpha += i as f32)
+    /// });
     /// ```
-    fn with_component_change<'a, C: Component<Self>, F: FnOnce(&mut Self) -> ChangeComponent<C>>(mut self, f: F) -> Self {
+    fn with_component_change<C: Component<Self>, M: FnOnce(&mut C), F: FnOnce(&mut Self) -> ChangeComponent<C, M>>(mut self, f: F) -> Self {
         match f(&mut self) {
             ChangeComponent::NoChange => self,
             ChangeComponent::Remove => self.with_removed::<C>(),
@@ -185,6 +543,35 @@ pub trait EntityBase: Sized + 'static {
         self.get_mut::<C>().map(f)
     }
 
+    #[inline]
+    /// Same as `peek`, for two components of the entity at once.
+    ///
+    /// Returns `None` if the entity lacks either component.
+    fn peek2<A: Component<Self>, B: Component<Self>, O>(&self, f: impl FnOnce(&A, &B) -> O) -> Option<O> {
+        Some(f(self.get::<A>()?, self.get::<B>()?))
+    }
+
+    /// Same as `mutate`, for two components of the entity at once.
+    ///
+    /// Returns `None` if the entity lacks either component. Panics if `A` and `B` are the same type.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// entity.update2::<Position, Speed, _>(|position, speed| position.add(speed));
+    /// ```
+    fn update2<A: Component<Self>, B: Component<Self>, O>(&mut self, f: impl FnOnce(&mut A, &mut B) -> O) -> Option<O> {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>(), "update2 requires two different components");
+        let entity: *mut Self = self;
+        // SAFETY: the components have different types, so they are stored in different fields or
+        // slabs, and the entity stays borrowed mutably while they are
+        unsafe {
+            let a = A::get_mut_ptr(entity)?;
+            let b = B::get_mut_ptr(entity)?;
+            Some(f(&mut *a, &mut *b))
+        }
+    }
+
     #[inline]
     /// Returns true if the entity has the requested component type as an active component.
     fn has<C: Component<Self>>(&self) -> bool {
@@ -209,6 +596,14 @@ pub trait EntityBase: Sized + 'static {
         C::remove(self)
     }
 
+    #[inline]
+    /// Same as `remove`, but returns the component by value, without boxing it.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList
+    fn take<C: Component<Self>>(&mut self) -> Option<C> {
+        C::take(self)
+    }
+
     #[inline]
     /// Add a component to the given entity.
     ///
@@ -216,4 +611,28 @@ pub trait EntityBase: Sized + 'static {
     fn add<C: Component<Self>>(&mut self, c: C) {
         c.set(self);
     }
+
+    #[inline]
+    /// Add every component of the bundle to the given entity.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList, or use
+    /// `add_bundle_for_entity` instead.
+    fn add_bundle<B: Bundle<Self>>(&mut self, bundle: B) {
+        bundle.set(self);
+    }
+
+    #[inline]
+    /// Set a component of the given entity, and return the previous one if there was one.
+    ///
+    /// You MUST call `refresh(e_id)` if this entity is already part of the EntityList and did not
+    /// have the component.
+    fn replace<C: Component<Self>>(&mut self, c: C) -> Option<C> {
+        match C::get_mut(self) {
+            Some(old) => Some(std::mem::replace(old, c)),
+            None => {
+                c.set(self);
+                None
+            },
+        }
+    }
 }
\ No newline at end of file
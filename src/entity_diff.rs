@@ -0,0 +1,186 @@
+use std::any::{Any, TypeId};
+
+use crate::{EntityBase, Component};
+
+/// How a component differs between two entities, see `EntityDiff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentChange {
+    /// Only the newest entity has the component
+    Added,
+    /// Only the oldest entity has the component
+    Removed,
+    /// Both entities have the component, with different values
+    Changed,
+}
+
+/// A component which differs between two entities, see `EntityDiff`.
+pub struct ComponentDiff<E> {
+    pub type_id: TypeId,
+    /// Name of the component, as written in `define_entity!`
+    pub name: &'static str,
+    pub change: ComponentChange,
+    /// The component of the newest entity, for added and changed components
+    value: Option<Box<dyn Any>>,
+    clone: fn(&dyn Any) -> Box<dyn Any>,
+    /// Sets the component on an entity, or removes it if there is no value
+    set: fn(&mut E, Option<&dyn Any>),
+}
+
+impl<E> ComponentDiff<E> {
+    /// Returns the component of the newest entity, for added and changed components.
+    ///
+    /// Returns `None` for removed components, or if `C` is not the type of the component.
+    pub fn value<C: 'static>(&self) -> Option<&C> {
+        self.value.as_ref().and_then(|value| value.downcast_ref())
+    }
+}
+
+impl<E> Clone for ComponentDiff<E> {
+    fn clone(&self) -> Self {
+        ComponentDiff {
+            type_id: self.type_id,
+            name: self.name,
+            change: self.change,
+            value: self.value.as_deref().map(self.clone),
+            clone: self.clone,
+            set: self.set,
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for ComponentDiff<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ComponentDiff")
+            .field("name", &self.name)
+            .field("change", &self.change)
+            .finish()
+    }
+}
+
+/// The components to add, remove and change to turn an entity into another one, see `EntityBase::diff`.
+///
+/// Properties are not compared. Components which implement `PartialEq` are compared by value; the
+/// others are always considered changed when both entities have them.
+///
+/// # Example
+///
+/// ```ignore
+/// let undo = after.diff(&before);
+/// // later, to go back
+/// entity.apply_diff(&undo);
+/// ```
+pub struct EntityDiff<E> {
+    components: Vec<ComponentDiff<E>>,
+}
+
+fn clone_component<C: Clone + 'static>(value: &dyn Any) -> Box<dyn Any> {
+    Box::new(value.downcast_ref::<C>().expect("FATAL: component diff has the wrong type").clone())
+}
+
+fn set_component<E: EntityBase, C: Component<E> + Clone>(entity: &mut E, value: Option<&dyn Any>) {
+    match value {
+        Some(value) => value.downcast_ref::<C>().expect("FATAL: component diff has the wrong type").clone().set(entity),
+        None => {
+            C::take(entity);
+        },
+    }
+}
+
+impl<E> EntityDiff<E> {
+    /// Returns true if both entities have the same components.
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Returns every component which differs, in the order of `EntityBase::for_all_components`.
+    pub fn components(&self) -> &[ComponentDiff<E>] {
+        &self.components
+    }
+
+    /// Returns the way the component `C` differs, or `None` if it does not.
+    pub fn change<C: 'static>(&self) -> Option<ComponentChange> {
+        self.components.iter()
+            .find(|c| c.type_id == TypeId::of::<C>())
+            .map(|c| c.change)
+    }
+}
+
+impl<E: EntityBase> EntityDiff<E> {
+    #[doc(hidden)]
+    /// Used by `define_entity!`.
+    pub fn new() -> Self {
+        EntityDiff { components: Vec::new() }
+    }
+
+    #[doc(hidden)]
+    /// Used by `define_entity!` to compare a component of two entities, with `eq` telling whether two
+    /// components are equal, see `ComponentEq`.
+    pub fn compare<C: Component<E> + Clone>(&mut self, old: Option<&C>, new: Option<&C>, eq: impl FnOnce(&C, &C) -> bool) {
+        let change = match (old, new) {
+            (None, None) => return,
+            (None, Some(_)) => ComponentChange::Added,
+            (Some(_), None) => ComponentChange::Removed,
+            (Some(old), Some(new)) if eq(old, new) => return,
+            (Some(_), Some(_)) => ComponentChange::Changed,
+        };
+        self.components.push(ComponentDiff {
+            type_id: TypeId::of::<C>(),
+            name: C::NAME,
+            change,
+            value: new.map(|c| Box::new(c.clone()) as Box<dyn Any>),
+            clone: clone_component::<C>,
+            set: set_component::<E, C>,
+        });
+    }
+
+    /// Add, remove and replace the components of `entity`, see `EntityBase::apply_diff`.
+    pub (crate) fn apply(&self, entity: &mut E) {
+        for c in &self.components {
+            (c.set)(entity, c.value.as_deref());
+        }
+    }
+}
+
+impl<E> Clone for EntityDiff<E> {
+    fn clone(&self) -> Self {
+        EntityDiff { components: self.components.clone() }
+    }
+}
+
+impl<E> std::fmt::Debug for EntityDiff<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(&self.components).finish()
+    }
+}
+
+#[doc(hidden)]
+/// Used by `define_entity!` to compare components with `PartialEq` when they implement it.
+///
+/// Calling `(&ComponentEq(a, b)).component_eq()` with both traits in scope picks the impl of
+/// `ComponentEqPartialEq` if `C: PartialEq`, and falls back to `ComponentEqFallback` through an
+/// extra auto-ref otherwise. This only works with concrete types, which the macro has.
+pub struct ComponentEq<'a, C>(pub &'a C, pub &'a C);
+
+#[doc(hidden)]
+pub trait ComponentEqPartialEq {
+    fn component_eq(&self) -> bool;
+}
+
+impl<C: PartialEq> ComponentEqPartialEq for ComponentEq<'_, C> {
+    #[inline]
+    fn component_eq(&self) -> bool {
+        self.0 == self.1
+    }
+}
+
+#[doc(hidden)]
+pub trait ComponentEqFallback {
+    fn component_eq(&self) -> bool;
+}
+
+impl<C> ComponentEqFallback for &ComponentEq<'_, C> {
+    #[inline]
+    fn component_eq(&self) -> bool {
+        false
+    }
+}
@@ -1,17 +1,28 @@
-use std::any::TypeId;
 use std::convert::TryInto;
-use std::cell::UnsafeCell;
+use std::cell::{OnceCell, UnsafeCell};
 use std::rc::Rc;
 
-use hashbrown::HashMap;
-use hibitset::{BitSet};
+use hibitset::BitSetLike;
 
 use crate::genarena::{GenArena, Index};
 
-use crate::{EntityBase, EntityRefBase, EntityOwnedBase, Component, ComponentsStorage};
+use crate::{EntityBase, EntityOwnedBase, EntityRefBase, MergeMode, Component, RefComponent, ComponentsStorage, CloneStorage, MultiComponent, ComponentBitSets, ChangeTicks, EntityListBuilder};
+use crate::spatial::SpatialIndex;
+use crate::archetypes::Archetypes;
+use crate::uuids::Uuids;
+use crate::resources::Resources;
+use crate::maintain::Pending;
+use crate::recycle_bin::RecycleBin;
+use crate::observers::Observers;
+use crate::events::Events;
+use crate::handle::Liveness;
+use crate::prop_index::PropIndices;
 
 pub type EntityId = Index;
 
+/// Default capacity of every component bitset of a new `EntityList`.
+pub const DEFAULT_BITSET_CAPACITY: u32 = 4096;
+
 /// The struct holding a list/array of entities.
 ///
 /// It is backed by a `generational_arena`, and a `hibitset`.
@@ -20,33 +31,109 @@ pub type EntityId = Index;
 ///
 /// * Creations and removals are mostly `O(1)`
 /// * Iteration is linear time (unless you specify the components you're looking for,
-/// where it is at worse the same, at best hundreds of time faster, thanks to hibitset).
+///   where it is at worse the same, at best hundreds of time faster, thanks to hibitset).
 /// * IDs cannot be reused, but their memory space is reusable.
 pub struct EntityList<E: EntityRefBase> {
-    pub (crate) bitsets: HashMap<TypeId, BitSet>,
+    pub (crate) bitsets: ComponentBitSets,
+    pub (crate) ticks: ChangeTicks,
     pub (crate) entities: GenArena<E>,
+    pub (crate) spatial: Option<SpatialIndex<E>>,
+    pub (crate) archetypes: Option<Archetypes>,
+    pub (crate) prop_indices: PropIndices<E>,
+    pub (crate) uuids: Option<Uuids>,
+    pub (crate) resources: Resources,
+    pub (crate) pending: Pending<E>,
+    pub (crate) recycle_bin: RecycleBin<E>,
+    pub (crate) observers: Observers<E>,
+    pub (crate) events: Events,
+    /// Created along with the first handle, see `EntityHandle`.
+    pub (crate) liveness: OnceCell<Rc<Liveness>>,
+    #[cfg(feature = "profiling")]
+    pub (crate) query_stats: crate::profiling::QueryStatsMap,
     pub components_storage: Rc<UnsafeCell<E::CS>>,
 }
 
 impl<E: EntityRefBase> EntityList<E> {
     pub fn new() -> EntityList<E> {
+        Self::with_bitset_capacity(DEFAULT_BITSET_CAPACITY)
+    }
+
+    /// Returns a builder to configure a new list, see `EntityListBuilder`.
+    pub fn builder() -> EntityListBuilder<E> {
+        EntityListBuilder::new()
+    }
+
+    pub (crate) fn with_bitset_capacity(bitset_capacity: u32) -> EntityList<E> {
         let components_storage = <<E as EntityRefBase>::CS as ComponentsStorage>::new();
-        let mut l = EntityList {
-            bitsets: HashMap::new(),
+        let bitsets = ComponentBitSets::new::<E>(bitset_capacity);
+        EntityList {
+            ticks: ChangeTicks::new(bitsets.len()),
+            bitsets,
             entities: GenArena::new(),
+            spatial: None,
+            archetypes: None,
+            prop_indices: PropIndices::default(),
+            uuids: None,
+            resources: Resources::default(),
+            pending: Pending::default(),
+            recycle_bin: RecycleBin::default(),
+            observers: Observers::default(),
+            events: Events::default(),
+            liveness: OnceCell::new(),
+            #[cfg(feature = "profiling")]
+            query_stats: Default::default(),
             components_storage: Rc::new(UnsafeCell::new(components_storage))
-        };
-        l.init_bitsets(None);
-        l
+        }
+    }
+
+    /// Create a list which can hold `capacity` entities without reallocating the arena or the bitsets.
+    ///
+    /// Component storages are not reserved, since entities rarely have every component, see
+    /// `reserve_components`.
+    pub fn with_capacity(capacity: usize) -> EntityList<E> {
+        let mut list = Self::new();
+        list.reserve(capacity);
+        list
+    }
+
+    /// Reserve room for `additional` more entities in the arena and the bitsets.
+    ///
+    /// See `reserve_components` to also reserve room in a component storage.
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        let capacity: u32 = self.entities.capacity().try_into().expect("too many entities");
+        self.bitsets.reserve(capacity);
+    }
+
+    /// Reserve room for `additional` more components `C` in the component storage.
+    pub fn reserve_components<C: RefComponent<E>>(&mut self, additional: usize) {
+        // SAFETY: the storage is owned by the list, which we borrow mutably
+        unsafe {
+            C::get_single_cs_mut(&mut *self.components_storage.get()).reserve(additional);
+        }
     }
 
     pub fn from_raw(arena: GenArena<E>, components_storage: Rc<UnsafeCell<E::CS>>) -> Self {
+        let bitsets = ComponentBitSets::new::<E>(0);
         let mut l = Self {
-            bitsets: HashMap::with_capacity(0),
+            ticks: ChangeTicks::new(bitsets.len()),
+            bitsets,
             entities: arena,
+            spatial: None,
+            archetypes: None,
+            prop_indices: PropIndices::default(),
+            uuids: None,
+            resources: Resources::default(),
+            pending: Pending::default(),
+            recycle_bin: RecycleBin::default(),
+            observers: Observers::default(),
+            events: Events::default(),
+            liveness: OnceCell::new(),
+            #[cfg(feature = "profiling")]
+            query_stats: Default::default(),
             components_storage,
         };
-        l.regenerate_all_component_bitsets();
+        l.rebuild_bitsets();
         l
     }
 
@@ -54,29 +141,297 @@ impl<E: EntityRefBase> EntityList<E> {
     ///
     /// Returns the ID of the entity you've just inserted.
     pub fn insert(&mut self, entity: E::Owned) -> EntityId {
-        let mut type_ids: Vec<TypeId> = Vec::with_capacity(8);
-        entity.for_each_active_component(|type_id: TypeId| {
-            type_ids.push(type_id);
-        });
         let entity_id = self.entities.push(EntityRefBase::from_owned(entity, &self.components_storage));
-        for type_id in type_ids {
-            if let Some(bitset) = self.bitsets.get_mut(&type_id) {
-                bitset.add(entity_id.index as u32);
+        self.register_inserted(entity_id);
+        entity_id
+    }
+
+    /// Allocate an id for an entity which will be inserted later with `insert_reserved`.
+    ///
+    /// Until then, the id is not contained in the list, and is never given to another entity.
+    /// Reserved ids are invalidated by `clear`: they never refer to an entity afterwards. They are not
+    /// serialized.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let id = entities.reserve_id();
+    /// plan.spawn(id);
+    /// // later
+    /// entities.insert_reserved(id, Entity::new((Position::default(),)));
+    /// ```
+    pub fn reserve_id(&mut self) -> EntityId {
+        self.entities.reserve_index()
+    }
+
+    /// Insert an entity with an id returned by `reserve_id`.
+    ///
+    /// Returns the entity back if the id is not reserved.
+    pub fn insert_reserved(&mut self, id: EntityId, entity: E::Owned) -> Result<(), E::Owned> {
+        if !self.entities.reserved.contains(&id.index) {
+            return Err(entity);
+        }
+        let entity = E::from_owned(entity, &self.components_storage);
+        match self.entities.insert_reserved(id, entity) {
+            Ok(()) => {
+                self.register_inserted(id);
+                Ok(())
+            },
+            // SAFETY: the storage is owned by the list, which we borrow mutably
+            Err(e) => Err(unsafe { e.to_owned(&mut *self.components_storage.get()) }),
+        }
+    }
+
+    /// Insert an entity with an explicit id, for instance to mirror the ids of another list.
+    ///
+    /// The slot must be free, neither reserved nor retired, and the generation of the id must not be lower than the
+    /// generation the slot would give, see `GenArena::insert_at`, which also limits the index.
+    ///
+    /// Returns the entity back if the id cannot be used.
+    pub fn insert_at(&mut self, id: EntityId, entity: E::Owned) -> Result<(), E::Owned> {
+        let entity = E::from_owned(entity, &self.components_storage);
+        match self.entities.insert_at(id, entity) {
+            Ok(()) => {
+                self.register_inserted(id);
+                Ok(())
+            },
+            // SAFETY: the storage is owned by the list, which we borrow mutably
+            Err(e) => Err(unsafe { e.to_owned(&mut *self.components_storage.get()) }),
+        }
+    }
+
+    /// Choose whether the indexes of removed entities are given to new entities. Defaults to true.
+    ///
+    /// When indexes are not reused, `EntityId::index` is unique for every entity until `vacuum` is
+    /// called, so it can key external caches. The arena grows instead, along with the bitsets.
+    pub fn set_reuse_indices(&mut self, reuse: bool) {
+        self.entities.set_reuse_slots(reuse);
+    }
+
+    /// Make the indexes of the entities removed while indexes were not reused available again, see
+    /// `set_reuse_indices`. Returns the number of indexes made available.
+    pub fn vacuum(&mut self) -> usize {
+        self.entities.vacuum()
+    }
+
+    /// Release an id returned by `reserve_id`, without inserting anything.
+    ///
+    /// Returns false if the id is not reserved.
+    pub fn cancel_reserved_id(&mut self, id: EntityId) -> bool {
+        self.entities.cancel_reserved(id)
+    }
+
+    /// Copy the component slabs shared with a clone of the list, and record that entities are lent,
+    /// before handing out several entities or components at once.
+    ///
+    /// With the `cow` feature, a slab is only copied when one of its components is first written,
+    /// which would leave the references already handed out pointing to the old copy. While entities
+    /// are lent, adding or removing a component which was borrowed through another entity panics,
+    /// see `BorrowTracker`.
+    pub (crate) fn lend_entities(&mut self) {
+        // SAFETY: the storage is owned by the list, which we borrow mutably
+        unsafe {
+            let cs = &mut *self.components_storage.get();
+            cs.unshare();
+            cs.borrow_tracker().lend_entities();
+        }
+    }
+
+    /// Record that the entities lent by `lend_entities` are not borrowed anymore, before handing out
+    /// a single entity mutably, or adding and removing components through the list.
+    pub (crate) fn lend_entity(&mut self) {
+        // SAFETY: the storage is owned by the list, which we borrow mutably
+        unsafe { (*self.components_storage.get()).borrow_tracker().end_entities_loan() }
+    }
+
+    /// Update the bitsets, change ticks, indices and uuids for an entity which was just put in the arena.
+    pub (crate) fn register_inserted(&mut self, id: EntityId) {
+        self.sync_liveness(id.index);
+        self.bitsets.occupied.add(id.index as u32);
+        let e = &self.entities[id];
+        refresh_bitsets(&mut self.bitsets, &mut self.ticks, &mut self.observers, id, e);
+        if let Some(spatial) = &mut self.spatial {
+            spatial.update(id, e);
+        }
+        if let Some(archetypes) = &mut self.archetypes {
+            archetypes.update(&self.bitsets, id, e);
+        }
+        self.prop_indices.update(id, e);
+        if let Some(uuids) = &mut self.uuids {
+            uuids.assign(id, None);
+        }
+        self.observers.inserted(id, e);
+    }
+
+    /// Insert several entities, and return their ids.
+    ///
+    /// The arena and the bitsets are reserved once from the size hint of `entities`, instead of
+    /// growing during the insertions.
+    pub fn extend<I: IntoIterator<Item=E::Owned>>(&mut self, entities: I) -> Vec<EntityId> {
+        let mut ids = Vec::new();
+        self.extend_into(entities, &mut ids);
+        ids
+    }
+
+    /// Same as `extend`, but the ids are appended to `ids`, which allows to re-use the buffer.
+    pub fn extend_into<I: IntoIterator<Item=E::Owned>>(&mut self, entities: I, ids: &mut Vec<EntityId>) {
+        let entities = entities.into_iter();
+        let (additional, _) = entities.size_hint();
+        self.reserve(additional);
+        ids.reserve(additional);
+        for entity in entities {
+            ids.push(self.insert(entity));
+        }
+    }
+
+    /// Create entities from their properties, and attach their components with `build`, then return
+    /// their ids.
+    ///
+    /// Unlike `insert`, components are put directly in the component storage, instead of being boxed
+    /// in an owned entity first.
+    ///
+    /// # Example
+    ///
+    /// `entities.spawn_batch(positions.map(|p| (p,)), |e| e.add(Speed::default()));`
+    pub fn spawn_batch<I, F>(&mut self, params: I, mut build: F) -> Vec<EntityId>
+    where
+        I: IntoIterator<Item=<E::Owned as EntityOwnedBase>::CreationParams>,
+        F: FnMut(&mut E),
+    {
+        let params = params.into_iter();
+        let (additional, _) = params.size_hint();
+        self.reserve(additional);
+        let mut ids = Vec::with_capacity(additional);
+        for p in params {
+            let mut entity = E::from_params(p, &self.components_storage);
+            build(&mut entity);
+            let id = self.entities.push(entity);
+            self.register_inserted(id);
+            ids.push(id);
+        }
+        ids
+    }
+
+    /// Insert a copy of the given entity and its components, and return the id of the copy.
+    ///
+    /// Returns `None` if the entity does not exist.
+    pub fn duplicate(&mut self, id: EntityId) -> Option<EntityId> where E::CS: CloneStorage {
+        let e = self.entities.get(id)?;
+        let owned = unsafe { e.clone_owned(&*self.components_storage.get()) };
+        Some(self.insert(owned))
+    }
+
+    /// Move every entity of `other` into this list, and return the new id of every moved entity, by
+    /// its id in `other`.
+    ///
+    /// If uuids are enabled in both lists, moved entities keep their uuid, unless it is already
+    /// used in this list.
+    pub fn merge(&mut self, mut other: EntityList<E>) -> std::collections::HashMap<EntityId, EntityId> {
+        let mut remap = std::collections::HashMap::with_capacity(other.len());
+        self.reserve(other.len());
+        let other_uuids = other.uuids.take();
+        for (old_id, e) in other.drain() {
+            let id = self.insert(e);
+            if let (Some(uuids), Some(uuid)) = (&mut self.uuids, other_uuids.as_ref().and_then(|u| u.get(old_id))) {
+                uuids.assign(id, Some(uuid));
             }
+            remap.insert(old_id, id);
         }
-        entity_id
+        remap
+    }
+
+    /// Remove every entity which has the components (C1, C2, C3, ...), and return them in a new list.
+    ///
+    /// Entities get new ids in the new list, which is configured like this one, see `extract_if`.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    ///
+    /// # Example
+    ///
+    /// `let frozen = entities.split_off_matching::<(InRegion42,)>();`
+    pub fn split_off_matching<C: for<'a> MultiComponent<'a, E>>(&mut self) -> EntityList<E> {
+        self.flush_refresh();
+        let ids: Vec<EntityId> = self.iter_ids::<C>().collect();
+        self.split_off_ids(ids)
+    }
+
+    /// Remove every entity for which `f` returns true, and return them in a new list.
+    ///
+    /// Entities get new ids in the new list. The new list is configured like this one: change
+    /// tracking, uuids, reuse of indexes, deferred refreshes, spatial index and archetypes. If uuids
+    /// are enabled, moved entities keep their uuid.
+    pub fn extract_if<F: FnMut(EntityId, &mut E) -> bool>(&mut self, mut f: F) -> EntityList<E> {
+        let mut ids = Vec::new();
+        self.retain(|id, e| {
+            if f(id, e) {
+                ids.push(id);
+            }
+            true
+        });
+        self.split_off_ids(ids)
+    }
+
+    /// Create an empty list configured like this one, see `extract_if`.
+    fn empty_like(&self, capacity: usize) -> EntityList<E> {
+        let mut list = EntityList::with_capacity(capacity);
+        list.ticks.enabled = self.ticks.enabled;
+        if self.uuids.is_some() {
+            list.enable_uuids();
+        }
+        list.set_reuse_indices(self.entities.reuse_slots);
+        list.set_deferred_refresh(self.is_refresh_deferred());
+        list.spatial = self.spatial.as_ref().map(SpatialIndex::empty_like);
+        if self.archetypes.is_some() {
+            list.enable_archetypes();
+        }
+        list
+    }
+
+    /// Move the given entities to a new list configured like this one, keeping their uuids.
+    fn split_off_ids(&mut self, ids: Vec<EntityId>) -> EntityList<E> {
+        let mut list = self.empty_like(ids.len());
+        for id in ids {
+            let uuid = self.uuid(id);
+            if let Some(e) = self.remove(id) {
+                let new_id = list.insert(e);
+                if let (Some(uuids), Some(uuid)) = (&mut list.uuids, uuid) {
+                    uuids.assign(new_id, Some(uuid));
+                }
+            }
+        }
+        list
     }
 
     /// Remove an entity
     ///
     /// If the entity wasn't already removed, it is returned as an `Option`.
     pub fn remove(&mut self, id: EntityId) -> Option<E::Owned> {
+        if self.contains(id) {
+            self.flush_refresh_at(id.index);
+        }
         if let Some(e) = self.entities.remove(id) {
-            e.for_each_active_component(|type_id: TypeId| {
-                if let Some(bitset) = self.bitsets.get_mut(&type_id) {
-                    bitset.remove(id.index as u32);
+            self.sync_liveness(id.index);
+            self.observers.removed(id, &e);
+            e.for_each_component_position(|p: usize, is_active: bool| {
+                if is_active {
+                    self.bitsets.get_at_mut(p).remove(id.index as u32);
+                    self.ticks.set_removed(p, id);
+                    self.observers.component_removed(p, id);
                 }
             });
+            self.bitsets.remove_tags(id.index as u32);
+            self.bitsets.occupied.remove(id.index as u32);
+            self.bitsets.cached.remove(id.index as u32);
+            if let Some(spatial) = &mut self.spatial {
+                spatial.remove(id);
+            }
+            if let Some(archetypes) = &mut self.archetypes {
+                archetypes.remove(id);
+            }
+            self.prop_indices.remove(id);
+            if let Some(uuids) = &mut self.uuids {
+                uuids.remove(id);
+            }
             unsafe {
                 let cs = &mut *self.components_storage.get();
                 Some(e.to_owned(cs))
@@ -86,24 +441,117 @@ impl<E: EntityRefBase> EntityList<E> {
         }
     }
 
-    /// Refresh bitset for an entity
+    /// Keep only the entities for which `f` returns true, and remove the others, in a single pass.
     ///
-    /// You need to call this after a `.add::<C>()` or `.remove::<C>()`
-    pub fn refresh(&mut self, id: EntityId) {
-        println!("refresh {:?}", id);
-        if let Some(e) = self.entities.get_mut(id) {
-            let bitsets = &mut self.bitsets;
-            e.for_each_component(|type_id: TypeId, is_active: bool| {
-                dbg!(type_id, is_active);
-                if let Some(bitset) = bitsets.get_mut(&type_id) {
-                    if is_active {
-                        bitset.add(id.index as u32);
-                    } else {
-                        bitset.remove(id.index as u32);
+    /// Removed entities are dropped, along with their components. See `retain_with` to get them back.
+    ///
+    /// # Example
+    ///
+    /// `entities.retain(|_id, e| e.position.y > -1000.0);`
+    pub fn retain<F: FnMut(EntityId, &mut E) -> bool>(&mut self, f: F) {
+        self.retain_with(f, |_id, _e| {})
+    }
+
+    /// Same as `retain`, but every removed entity is given to `removed`.
+    pub fn retain_with<F, R>(&mut self, mut f: F, mut removed: R)
+    where
+        F: FnMut(EntityId, &mut E) -> bool,
+        R: FnMut(EntityId, E::Owned),
+    {
+        self.lend_entity();
+        for index in 0..self.entities.capacity() {
+            let id = match self.entities.get_raw_mut(index) {
+                Some((e, generation)) => {
+                    let id = EntityId::new(index, generation);
+                    if f(id, e) {
+                        self.ticks.set_entity_changed(&self.bitsets, index);
+                        continue;
                     }
+                    id
+                },
+                None => continue,
+            };
+            if let Some(e) = self.remove(id) {
+                removed(id, e);
+            }
+        }
+    }
+
+    /// Remove every entity, and yield them along with their ids.
+    ///
+    /// The list is empty once the iterator is dropped, even if it was not consumed entirely.
+    ///
+    /// # Example
+    ///
+    /// `for (_id, entity) in level.drain() { other_level.insert(entity); }`
+    pub fn drain(&mut self) -> Drain<'_, E> {
+        Drain {
+            list: self,
+            index: 0,
+        }
+    }
+
+    /// Remove every entity, keeping the allocated memory of the arena, bitsets and component storage.
+    ///
+    /// Unlike creating a new list, generations are kept, so the ids of removed entities stay invalid.
+    pub fn clear(&mut self) {
+        self.flush_refresh();
+        for (id, e) in self.entities.iter() {
+            self.observers.removed(id, e);
+            e.for_each_component_position(|p: usize, is_active: bool| {
+                if is_active {
+                    self.ticks.set_removed(p, id);
+                    self.observers.component_removed(p, id);
                 }
             });
         }
+        for bitset in self.bitsets.iter_mut() {
+            bitset.clear();
+        }
+        self.bitsets.tags.clear();
+        self.bitsets.occupied.clear();
+        self.bitsets.cached.clear();
+        self.entities.clear();
+        self.recycle_bin.entities.clear();
+        self.sync_all_liveness();
+        if let Some(spatial) = &mut self.spatial {
+            spatial.clear();
+        }
+        if let Some(archetypes) = &mut self.archetypes {
+            archetypes.clear();
+        }
+        self.prop_indices.clear();
+        if let Some(uuids) = &mut self.uuids {
+            uuids.clear();
+        }
+        unsafe {
+            (*self.components_storage.get()).clear();
+        }
+    }
+
+    /// Refresh bitset for an entity
+    ///
+    /// You need to call this after a `.add::<C>()` or `.remove::<C>()`
+    ///
+    /// If refreshes are deferred, the entity is only queued, see `set_deferred_refresh`.
+    pub fn refresh(&mut self, id: EntityId) {
+        if !self.queue_refresh(id) {
+            self.apply_refresh(id);
+        }
+    }
+
+    /// Update the bitsets, change ticks, observers and indices of an entity from its components.
+    pub (crate) fn apply_refresh(&mut self, id: EntityId) {
+        if let Some(e) = self.entities.get(id) {
+            refresh_bitsets(&mut self.bitsets, &mut self.ticks, &mut self.observers, id, e);
+            if let Some(spatial) = &mut self.spatial {
+                spatial.update(id, e);
+            }
+            if let Some(archetypes) = &mut self.archetypes {
+                archetypes.update(&self.bitsets, id, e);
+            }
+            self.prop_indices.update(id, e);
+        }
     }
 
     #[inline]
@@ -122,7 +570,63 @@ impl<E: EntityRefBase> EntityList<E> {
     /// To add or remove a component for an entity, use `add_component_for_entity` and
     /// `remove_component_for_entity`.
     pub fn get_mut(&mut self, id: EntityId) -> Option<&mut E> {
-        self.entities.get_mut(id)
+        self.lend_entity();
+        let e = self.entities.get_mut(id)?;
+        self.ticks.set_entity_changed(&self.bitsets, id.index);
+        Some(e)
+    }
+
+    #[inline]
+    /// Same as `get`, without checking that the entity exists, for hot loops where it is already
+    /// known, for instance from a bitset.
+    ///
+    /// # Safety
+    ///
+    /// The entity must exist, which is only checked in debug builds.
+    pub unsafe fn get_unchecked(&self, id: EntityId) -> &E {
+        self.entities.get_unchecked(id)
+    }
+
+    #[inline]
+    /// Same as `get_mut`, without checking that the entity exists. The same warning as `get_mut`
+    /// applies.
+    ///
+    /// # Safety
+    ///
+    /// The entity must exist, which is only checked in debug builds.
+    pub unsafe fn get_unchecked_mut(&mut self, id: EntityId) -> &mut E {
+        self.lend_entity();
+        self.ticks.set_entity_changed(&self.bitsets, id.index);
+        self.entities.get_unchecked_mut(id)
+    }
+
+    #[inline]
+    /// Returns the entity at the given index along with its id, without checking that there is one,
+    /// for indexes kept alongside the list, for instance in a bitset of its own.
+    ///
+    /// # Safety
+    ///
+    /// An entity must exist at this index, which is only checked in debug builds.
+    pub unsafe fn get_raw_unchecked(&self, index: usize) -> (EntityId, &E) {
+        let (e, generation) = self.entities.get_raw_unchecked(index);
+        (EntityId::new(index, generation), e)
+    }
+
+    /// Retrieves several entities mutably at once.
+    ///
+    /// Returns `None` if any of the entities does not exist, or if the same id is given twice.
+    /// The same warning as `get_mut` applies.
+    ///
+    /// # Example
+    ///
+    /// `if let Some([attacker, defender]) = entities.get_many_mut([attacker_id, defender_id]) { }`
+    pub fn get_many_mut<const N: usize>(&mut self, ids: [EntityId; N]) -> Option<[&mut E; N]> {
+        self.lend_entities();
+        let entities = self.entities.get_many_mut(ids)?;
+        for id in ids {
+            self.ticks.set_entity_changed(&self.bitsets, id.index);
+        }
+        Some(entities)
     }
 
     #[inline]
@@ -131,81 +635,101 @@ impl<E: EntityRefBase> EntityList<E> {
         self.entities.contains(id)
     }
 
+    #[inline]
+    /// Returns true if the entity exists and has the component `C`.
+    ///
+    /// This only reads the bitset of `C` and the generation of the entity, without resolving the
+    /// entity itself.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes, see `set_deferred_refresh`.
+    pub fn contains_component<C: Component<E>>(&self, id: EntityId) -> bool {
+        self.bitsets.get_at(C::POSITION).contains(id.index as u32)
+            && self.entities.generation_at(id.index) == Some(id.generation)
+    }
+
     #[inline]
     /// Returns the number of entities in the list.
     pub fn len(&self) -> usize {
         self.entities.len()
     }
 
-    /// Initialize bitsets for all components of entity E
-    ///
-    /// Default capacity is 4096, and is applied for all bitsets.
-    pub (crate) fn init_bitsets(&mut self, capacity: Option<u32>) {
-        E::for_all_components(|type_id: TypeId| {
-            self.bitsets.insert(type_id, BitSet::with_capacity(capacity.unwrap_or(4096)));
-        });
+    #[inline]
+    /// Returns true if the list has no entity.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
     }
 
-    #[allow(dead_code)] // we might find a use for it in the future, it used to be used in EntityList::from_arena
-    /// In case the bitsets are out of date, this function can re-generate them.
-    fn regenerate_all_component_bitsets(&mut self) {
-        let capacity = self.entities.len();
+    #[inline]
+    /// Returns the number of entities the list can hold without reallocating the arena, including
+    /// the occupied slots.
+    pub fn capacity(&self) -> usize {
+        self.entities.capacity()
+    }
 
-        E::for_all_components(|type_id: TypeId| {
-            self.bitsets.insert(type_id, BitSet::with_capacity(capacity as u32));
+    /// Returns the number of components and the capacity of every component storage.
+    pub fn component_capacities(&self) -> Vec<ComponentCapacity> {
+        let mut capacities = Vec::new();
+        // SAFETY: the storage is owned by the list, which we borrow immutably
+        let cs = unsafe { &*self.components_storage.get() };
+        cs.for_each_slab(|name, len, capacity, _| {
+            capacities.push(ComponentCapacity { name, len, capacity });
         });
-        let mut bitsets: Vec<(TypeId, &mut BitSet)> = self.bitsets.iter_mut().map(|(k, v)| (*k, v)).collect::<Vec<_>>();
-        bitsets.sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2));
-        for (id, el) in &self.entities {
-            el.for_each_active_component(|seek_type_id: TypeId| {
-                if let Ok(i) = bitsets.binary_search_by(|(tid, _)| tid.cmp(&seek_type_id)) {
-                    bitsets[i].1.add(id.index as u32);
-                } else {
-                    unreachable!()
-                }
-            })
-        }
+        capacities
     }
 
-    // Add a bitset for a specific component for all entities.
-    //
-    // Typically done at the very start of the ECS
-    #[allow(dead_code)]
-    pub (crate) fn add_bitset_for_component<C: Component<E>>(&mut self) {
-        let bitset_capacity: u32 = self.entities.capacity().try_into().expect("too many entities");
-        let mut bitset = BitSet::with_capacity(bitset_capacity);
-        for (entity_id, entity) in &self.entities {
-            if entity.has::<C>() {
-                bitset.add(entity_id.index as u32);
+    /// In case the bitsets are out of date, this function can re-generate them from the components
+    /// of every entity, for instance after mutations via `get_mut` without `refresh`.
+    ///
+    /// Tags of removed entities are cleared, other tags are kept. Change ticks are not updated.
+    pub fn rebuild_bitsets(&mut self) {
+        self.flush_refresh();
+        let capacity: u32 = self.entities.capacity().try_into().expect("too many entities");
+        let mut tags = std::mem::take(&mut self.bitsets.tags);
+        for bitset in tags.values_mut() {
+            let stale: Vec<u32> = bitset.iter().filter(|i| self.entities.generation_at(*i as usize).is_none()).collect();
+            for index in stale {
+                bitset.remove(index);
             }
         }
-        self.bitsets.insert(
-            TypeId::of::<C>(),
-            bitset
-        );
-    }
-
-    // Remove a bitset for a specific component for all entities.
-    //
-    // Returns true if the bitset was actually there and was removed
-    #[allow(dead_code)]
-    pub (crate) fn remove_bitset_for_component<C: Component<E>>(&mut self) -> bool {
-        let bitset_capacity: u32 = self.entities.capacity().try_into().expect("too many entities");
-        let mut bitset = BitSet::with_capacity(bitset_capacity);
-        for (entity_id, entity) in &self.entities {
-            if entity.has::<C>() {
-                bitset.remove(entity_id.index as u32);
+        let cached = std::mem::take(&mut self.bitsets.cached);
+        self.bitsets = ComponentBitSets::new::<E>(capacity);
+        self.bitsets.tags = tags;
+        self.bitsets.cached = cached;
+        self.fill_bitsets(None);
+    }
+
+    /// Same as `rebuild_bitsets`, but only for the bitset of the component `C`.
+    pub fn rebuild_bitset_for<C: Component<E>>(&mut self) {
+        self.flush_refresh();
+        self.bitsets.get_at_mut(C::POSITION).clear();
+        self.fill_bitsets(Some(C::POSITION));
+    }
+
+    /// Add the bits of every entity, for all the components or only the one at `position`, then bring
+    /// the cached queries and the archetypes up to date.
+    fn fill_bitsets(&mut self, position: Option<usize>) {
+        for (id, e) in &self.entities {
+            self.bitsets.occupied.add(id.index as u32);
+            e.for_each_component_position(|p: usize, is_active: bool| {
+                if is_active && position.is_none_or(|position| position == p) {
+                    self.bitsets.get_at_mut(p).add(id.index as u32);
+                }
+            });
+            if let Some(archetypes) = &mut self.archetypes {
+                archetypes.update(&self.bitsets, id, e);
             }
         }
-        self.bitsets.remove(
-            &TypeId::of::<C>()
-        ).is_some()
+        self.bitsets.rebuild_cached();
     }
 
     /// Add a component for the given entity.
     ///
     /// If the entity does not exist anymore, `Some(component)` is returned.
     pub fn add_component_for_entity<C: Component<E>>(&mut self, entity_id: EntityId, component: C) -> Option<C> {
+        if self.is_refresh_deferred() {
+            return self.add_component_deferred(entity_id, component);
+        }
+        self.lend_entity();
         let maybe_component = match self.entities.get_mut(entity_id) {
             Some(e) => {
                 component.set(e);
@@ -217,35 +741,328 @@ impl<E: EntityRefBase> EntityList<E> {
         };
         // maybe_component is Some if it hasn't been applied, None if it has been applied.
         if maybe_component.is_none() {
-            // if it has been added, see if we have a bitset for this component
-            if let Some(bitset) = self.bitsets.get_mut(&TypeId::of::<C>()) {
-                // we have a bitset, so add the info that this entity has the given component
-                bitset.add(entity_id.index as u32);
-            };
+            // if it has been added, add the info that this entity has the given component
+            let p = C::POSITION;
+            if self.bitsets.get_at_mut(p).add(entity_id.index as u32) {
+                // it already had the component, it was replaced
+                self.ticks.set_changed(p, entity_id.index);
+            } else {
+                self.ticks.set_added(p, entity_id.index);
+                self.observers.component_added(p, entity_id, &self.entities[entity_id]);
+            }
+            self.update_indices(entity_id);
         };
 
         maybe_component
     }
 
+    /// Add a component for several entities, looking up the position of the component only once.
+    ///
+    /// Returns the components which could not be added, because their entity does not exist anymore.
+    ///
+    /// # Example
+    ///
+    /// `entities.add_component_for_entities(ids.iter().map(|id| (*id, Poisoned { damage: 2 })));`
+    pub fn add_component_for_entities<C, I>(&mut self, components: I) -> Vec<(EntityId, C)>
+    where
+        C: Component<E>,
+        I: IntoIterator<Item=(EntityId, C)>,
+    {
+        let p = C::POSITION;
+        let mut not_added = Vec::new();
+        self.lend_entity();
+        for (id, component) in components {
+            if self.is_refresh_deferred() {
+                not_added.extend(self.add_component_deferred(id, component).map(|c| (id, c)));
+                continue;
+            }
+            match self.entities.get_mut(id) {
+                Some(e) => component.set(e),
+                None => {
+                    not_added.push((id, component));
+                    continue;
+                },
+            }
+            if self.bitsets.get_at_mut(p).add(id.index as u32) {
+                self.ticks.set_changed(p, id.index);
+            } else {
+                self.ticks.set_added(p, id.index);
+                self.observers.component_added(p, id, &self.entities[id]);
+            }
+            self.update_indices(id);
+        }
+        not_added
+    }
+
+    /// Same as `add_component_for_entities`, but every entity gets a clone of `component`.
+    ///
+    /// Returns the ids of the entities which do not exist anymore.
+    pub fn add_component_for_entities_cloned<C, I>(&mut self, ids: I, component: C) -> Vec<EntityId>
+    where
+        C: Component<E> + Clone,
+        I: IntoIterator<Item=EntityId>,
+    {
+        self.add_component_for_entities(ids.into_iter().map(|id| (id, component.clone())))
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
     /// Remove a component for the given entity.
     ///
     /// If the entity exists and it has the component, `Some(component)` is returned.
     pub fn remove_component_for_entity<C: Component<E>>(&mut self, entity_id: EntityId) -> Option<Box<C>> {
+        self.take_component_for_entity(entity_id).map(Box::new)
+    }
+
+    /// Same as `remove_component_for_entity`, but returns the component by value, without boxing it.
+    ///
+    /// # Example
+    ///
+    /// `let shield: Option<Shield> = entities.take_component_for_entity(id);`
+    pub fn take_component_for_entity<C: Component<E>>(&mut self, entity_id: EntityId) -> Option<C> {
+        if self.is_refresh_deferred() {
+            return self.take_component_deferred(entity_id);
+        }
+        self.lend_entity();
         let maybe_component = self.entities
             .get_mut(entity_id)
-            .and_then(C::remove);
+            .and_then(C::take);
 
         // maybe_component is Some if it was a component, None if it wasn't.
         if maybe_component.is_some() {
-            // if it has been removed, see if we have a bitset for this component
-            if let Some(bitset) = self.bitsets.get_mut(&TypeId::of::<C>()) {
-                // we have a bitset, so remove the info that this entity has the given component
-                bitset.remove(entity_id.index as u32);
-            };
+            // if it has been removed, remove the info that this entity has the given component
+            let p = C::POSITION;
+            self.bitsets.get_at_mut(p).remove(entity_id.index as u32);
+            self.ticks.set_removed(p, entity_id);
+            self.observers.component_removed(p, entity_id);
+            self.update_indices(entity_id);
         };
 
         maybe_component
     }
+
+    /// Remove every component of the given entity, leaving only its properties.
+    ///
+    /// Returns false if the entity does not exist.
+    pub fn clear_components_for_entity(&mut self, id: EntityId) -> bool {
+        let deferred = self.is_refresh_deferred();
+        self.lend_entity();
+        let e = match self.entities.get_mut(id) {
+            Some(e) => e,
+            None => return false,
+        };
+        if deferred {
+            e.clear_components();
+            self.queue_refresh(id);
+            return true;
+        }
+        let mut removed = Vec::new();
+        e.for_each_component_position(|p, active| if active {
+            removed.push(p);
+        });
+        e.clear_components();
+        for p in removed {
+            self.bitsets.get_at_mut(p).remove(id.index as u32);
+            self.ticks.set_removed(p, id);
+            self.observers.component_removed(p, id);
+        }
+        self.update_indices(id);
+        true
+    }
+
+    /// Copy every component of `template` onto the given entity, see `EntityBase::merge_from`, and
+    /// refresh it. Components which are replaced are recorded as changed.
+    ///
+    /// Returns false if the entity does not exist.
+    ///
+    /// # Example
+    ///
+    /// `entities.merge_into_entity(id, &loadouts.knight, MergeMode::Overwrite);`
+    pub fn merge_into_entity(&mut self, id: EntityId, template: &E::Owned, mode: MergeMode) -> bool where E::CS: CloneStorage {
+        self.lend_entity();
+        let e = match self.entities.get_mut(id) {
+            Some(e) => e,
+            None => return false,
+        };
+        if mode == MergeMode::Overwrite {
+            // the entity itself is checked rather than the bitsets, which may be deferred
+            let mut had = Vec::new();
+            e.for_each_component_position(|_, active| had.push(active));
+            let ticks = &mut self.ticks;
+            template.for_each_component_position(|p, active| if active && had[p] {
+                ticks.set_changed(p, id.index);
+            });
+        }
+        e.merge_from_owned(template, mode);
+        self.refresh(id);
+        true
+    }
+
+    /// Remove a component for several entities, looking up the position of the component only once.
+    ///
+    /// Returns the removed components, along with the id of their entity. Entities which do not exist
+    /// or do not have the component are skipped.
+    ///
+    /// # Example
+    ///
+    /// `let dispelled = entities.remove_component_for_entities::<Buff, _>(ids_in_area);`
+    pub fn remove_component_for_entities<C, I>(&mut self, ids: I) -> Vec<(EntityId, Box<C>)>
+    where
+        C: Component<E>,
+        I: IntoIterator<Item=EntityId>,
+    {
+        let p = C::POSITION;
+        let mut removed = Vec::new();
+        self.lend_entity();
+        for id in ids {
+            if self.is_refresh_deferred() {
+                removed.extend(self.take_component_deferred(id).map(|c| (id, Box::new(c))));
+                continue;
+            }
+            let component = match self.entities.get_mut(id).and_then(C::remove) {
+                Some(component) => component,
+                None => continue,
+            };
+            self.bitsets.get_at_mut(p).remove(id.index as u32);
+            self.ticks.set_removed(p, id);
+            self.observers.component_removed(p, id);
+            self.update_indices(id);
+            removed.push((id, component));
+        }
+        removed
+    }
+
+    /// Remove the component `C` from every entity, and clear its bitset at once.
+    ///
+    /// See `drain_component` to get the removed components back.
+    pub fn remove_all<C: Component<E>>(&mut self) {
+        self.remove_all_with::<C, _>(|_id, _c| {})
+    }
+
+    /// Remove the component `C` from every entity, and return the removed components along with the
+    /// id of their entity.
+    ///
+    /// # Example
+    ///
+    /// `for (id, shield) in entities.drain_component::<Shield>() { }`
+    pub fn drain_component<C: Component<E>>(&mut self) -> Vec<(EntityId, C)> {
+        let mut removed = Vec::new();
+        self.remove_all_with(|id, c| removed.push((id, c)));
+        removed
+    }
+
+    fn remove_all_with<C: Component<E>, F: FnMut(EntityId, C)>(&mut self, mut f: F) {
+        self.flush_refresh();
+        self.lend_entity();
+        let p = C::POSITION;
+        let indexes: Vec<u32> = self.bitsets.get_at(p).iter().collect();
+        for index in indexes {
+            let (e, generation) = match self.entities.get_raw_mut(index as usize) {
+                Some(e) => e,
+                None => continue,
+            };
+            let id = EntityId::new(index as usize, generation);
+            if let Some(c) = C::take(e) {
+                self.bitsets.get_at_mut(p).remove(index);
+                self.ticks.set_removed(p, id);
+                self.observers.component_removed(p, id);
+                self.update_indices(id);
+                f(id, c);
+            }
+        }
+        self.bitsets.get_at_mut(p).clear();
+    }
+
+    /// Update the cached queries, the spatial index, the archetypes and the property indices of an entity.
+    pub (crate) fn update_indices(&mut self, id: EntityId) {
+        if let Some(e) = self.entities.get(id) {
+            self.bitsets.update_cached(id.index as u32);
+            if let Some(spatial) = &mut self.spatial {
+                spatial.update(id, e);
+            }
+            if let Some(archetypes) = &mut self.archetypes {
+                archetypes.update(&self.bitsets, id, e);
+            }
+            self.prop_indices.update(id, e);
+        }
+    }
+}
+
+/// The size of the storage of a component, see `EntityList::component_capacities`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentCapacity {
+    /// Name of the component, as written in `define_entity!`
+    pub name: &'static str,
+    /// Number of components stored
+    pub len: usize,
+    /// Number of components which can be stored without reallocating
+    pub capacity: usize,
+}
+
+/// Iterator removing every entity of a list, see `EntityList::drain`.
+pub struct Drain<'a, E: EntityRefBase> {
+    list: &'a mut EntityList<E>,
+    index: usize,
+}
+
+impl<'a, E: EntityRefBase> Iterator for Drain<'a, E> {
+    type Item = (EntityId, E::Owned);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.list.entities.capacity() {
+            let index = self.index;
+            self.index += 1;
+            if let Some(generation) = self.list.entities.generation_at(index) {
+                let id = EntityId::new(index, generation);
+                return self.list.remove(id).map(|e| (id, e));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len(), Some(self.list.len()))
+    }
+}
+
+impl<'a, E: EntityRefBase> ExactSizeIterator for Drain<'a, E> {}
+
+impl<'a, E: EntityRefBase> std::iter::FusedIterator for Drain<'a, E> {}
+
+impl<'a, E: EntityRefBase> Drop for Drain<'a, E> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+/// Update the bitsets (and change ticks) of a single entity, from its active components.
+pub (crate) fn refresh_bitsets<E: EntityBase>(
+    bitsets: &mut ComponentBitSets,
+    ticks: &mut ChangeTicks,
+    observers: &mut Observers<E>,
+    id: EntityId,
+    e: &E,
+) {
+    e.for_each_component_position(|p: usize, is_active: bool| {
+        let bitset = bitsets.get_at_mut(p);
+        if is_active {
+            if !bitset.add(id.index as u32) {
+                ticks.set_added(p, id.index);
+                observers.component_added(p, id, e);
+            }
+        } else if bitset.remove(id.index as u32) {
+            ticks.set_removed(p, id);
+            observers.component_removed(p, id);
+        }
+    });
+    bitsets.update_cached(id.index as u32);
+}
+
+impl<E: EntityRefBase> Default for EntityList<E> {
+    fn default() -> EntityList<E> {
+        Self::new()
+    }
 }
 
 impl<E: EntityRefBase> std::fmt::Debug for EntityList<E> where E: std::fmt::Debug {
@@ -254,7 +1071,10 @@ impl<E: EntityRefBase> std::fmt::Debug for EntityList<E> where E: std::fmt::Debu
     }
 }
 
-impl<E: EntityRefBase> Clone for EntityList<E> {
+/// With the `cow` feature, the components are shared by the clone until either list writes them,
+/// see `ComponentSlab`. Only lists whose components all implement `Clone` can be cloned, see
+/// `CloneStorage`.
+impl<E: EntityRefBase> Clone for EntityList<E> where E::CS: CloneStorage {
     fn clone(&self) -> EntityList<E> {
         let cloned_cs = unsafe { (&*self.components_storage.get()).clone() };
         let cs = Rc::new(UnsafeCell::new(cloned_cs));
@@ -262,23 +1082,54 @@ impl<E: EntityRefBase> Clone for EntityList<E> {
         for entity in gen_arena.values_mut() {
             entity.set_cs(Rc::downgrade(&cs))
         }
+        let mut recycle_bin = self.recycle_bin.clone();
+        for soft_removed in recycle_bin.entities.values_mut() {
+            soft_removed.entity.set_cs(Rc::downgrade(&cs))
+        }
         EntityList {
             bitsets: self.bitsets.clone(),
+            ticks: self.ticks.clone(),
             entities: gen_arena,
+            spatial: self.spatial.clone(),
+            archetypes: self.archetypes.clone(),
+            prop_indices: self.prop_indices.clone(),
+            uuids: self.uuids.clone(),
+            resources: self.resources.clone(),
+            pending: self.pending.clone(),
+            recycle_bin,
+            observers: Observers::default(),
+            events: self.events.clone(),
+            // handles refer to the entities of this list, not the ones of the clone
+            liveness: OnceCell::new(),
+            #[cfg(feature = "profiling")]
+            query_stats: Default::default(),
             components_storage: cs,
         }
     }
 
     fn clone_from(&mut self, other: &Self) {
         self.bitsets.clone_from(&other.bitsets);
+        self.ticks.clone_from(&other.ticks);
+        self.spatial.clone_from(&other.spatial);
+        self.archetypes.clone_from(&other.archetypes);
+        self.prop_indices.clone_from(&other.prop_indices);
+        self.uuids.clone_from(&other.uuids);
+        self.resources.clone_from(&other.resources);
+        self.pending.clone_from(&other.pending);
+        self.events.clone_from(&other.events);
         unsafe {
             let self_cs: &mut E::CS = &mut *self.components_storage.get();
             let other_cs: &E::CS = &*other.components_storage.get();
-            self_cs.clone_from(&other_cs);
+            self_cs.clone_from(other_cs);
         }
         self.entities.clone_from(&other.entities);
         for entity in self.entities.values_mut() {
             entity.set_cs(Rc::downgrade(&self.components_storage))
         }
+        self.recycle_bin.clone_from(&other.recycle_bin);
+        for soft_removed in self.recycle_bin.entities.values_mut() {
+            soft_removed.entity.set_cs(Rc::downgrade(&self.components_storage))
+        }
+        self.sync_all_liveness();
     }
 }
\ No newline at end of file
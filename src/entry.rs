@@ -0,0 +1,137 @@
+use crate::{EntityRefBase, EntityList, EntityId, Component};
+
+/// A view into a single id of an `EntityList`, which may or may not be contained in the list.
+///
+/// Returned by `EntityList::entry`.
+pub enum Entry<'a, E: EntityRefBase> {
+    Occupied(OccupiedEntry<'a, E>),
+    Vacant(VacantEntry<'a, E>),
+}
+
+/// An id contained in the list, see `Entry`.
+pub struct OccupiedEntry<'a, E: EntityRefBase> {
+    list: &'a mut EntityList<E>,
+    id: EntityId,
+}
+
+/// An id which is not contained in the list (anymore), see `Entry`.
+pub struct VacantEntry<'a, E: EntityRefBase> {
+    list: &'a mut EntityList<E>,
+    id: EntityId,
+}
+
+impl<'a, E: EntityRefBase> Entry<'a, E> {
+    /// Returns the id this entry was created with.
+    pub fn id(&self) -> EntityId {
+        match self {
+            Entry::Occupied(o) => o.id,
+            Entry::Vacant(v) => v.id,
+        }
+    }
+
+    /// Call `f` on the entity if it exists, and refresh it afterwards.
+    pub fn and_modify<F: FnOnce(&mut E)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(o) = &mut self {
+            f(o.get_mut());
+            o.refresh();
+        }
+        self
+    }
+
+    /// Returns the entity if it exists, otherwise inserts the one given by `f`, with a new id.
+    pub fn or_insert_with<F: FnOnce() -> E::Owned>(self, f: F) -> OccupiedEntry<'a, E> {
+        match self {
+            Entry::Occupied(o) => o,
+            Entry::Vacant(v) => v.insert(f()),
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase> OccupiedEntry<'a, E> {
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn get(&self) -> &E {
+        // the generation was checked when the entry was created
+        self.list.entities.get_raw(self.id.index).expect("FATAL: occupied entry is not in the arena").0
+    }
+
+    /// Retrieves the entity mutably.
+    ///
+    /// You MUST call `refresh` if components are added or removed this way, see `add` and `remove`.
+    pub fn get_mut(&mut self) -> &mut E {
+        self.list.lend_entity();
+        self.list.ticks.set_entity_changed(&self.list.bitsets, self.id.index);
+        self.list.entities.get_raw_mut(self.id.index).expect("FATAL: occupied entry is not in the arena").0
+    }
+
+    /// Same as `get_mut`, but the reference lives as long as the list is borrowed.
+    pub fn into_mut(self) -> &'a mut E {
+        self.list.lend_entity();
+        self.list.ticks.set_entity_changed(&self.list.bitsets, self.id.index);
+        self.list.entities.get_raw_mut(self.id.index).expect("FATAL: occupied entry is not in the arena").0
+    }
+
+    /// Add a component to the entity. The old component is discarded.
+    pub fn add<C: Component<E>>(&mut self, component: C) {
+        self.list.add_component_for_entity(self.id, component);
+    }
+
+    /// Remove a component from the entity, and return it if there was one.
+    pub fn remove<C: Component<E>>(&mut self) -> Option<Box<C>> {
+        self.list.remove_component_for_entity::<C>(self.id)
+    }
+
+    /// Refresh the bitsets of the entity, see `EntityList::refresh`.
+    pub fn refresh(&mut self) {
+        self.list.refresh(self.id);
+    }
+
+    /// Remove the entity from the list.
+    pub fn remove_entity(self) -> E::Owned {
+        self.list.remove(self.id).expect("FATAL: occupied entry is not in the arena")
+    }
+}
+
+impl<'a, E: EntityRefBase> VacantEntry<'a, E> {
+    /// Returns the id this entry was created with, which is not contained in the list.
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    /// Insert an entity, with a new id: the id of this entry may not be usable anymore.
+    ///
+    /// See `insert_at` to insert the entity with the id of this entry.
+    pub fn insert(self, entity: E::Owned) -> OccupiedEntry<'a, E> {
+        let id = self.list.insert(entity);
+        OccupiedEntry { list: self.list, id }
+    }
+
+    /// Insert an entity with the id of this entry, see `EntityList::insert_at`.
+    ///
+    /// Returns the entity back if the id cannot be used.
+    pub fn insert_at(self, entity: E::Owned) -> Result<OccupiedEntry<'a, E>, E::Owned> {
+        self.list.insert_at(self.id, entity)?;
+        Ok(OccupiedEntry { list: self.list, id: self.id })
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Returns an entry for the given id, to inspect, modify or insert an entity in a single operation.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let e = entities.entry(id)
+    ///     .and_modify(|e| e.mutate(|h: &mut Health| h.hp += 10))
+    ///     .or_insert_with(|| Entity::new((Position::default(),)));
+    /// ```
+    pub fn entry(&mut self, id: EntityId) -> Entry<'_, E> {
+        if self.entities.contains(id) {
+            Entry::Occupied(OccupiedEntry { list: self, id })
+        } else {
+            Entry::Vacant(VacantEntry { list: self, id })
+        }
+    }
+}
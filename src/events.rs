@@ -0,0 +1,134 @@
+use std::any::{Any, TypeId};
+use std::marker::PhantomData;
+
+use crate::{EntityRefBase, EntityList, TypeIdMap};
+
+/// Events of a single type, sent since the last `maintain`.
+#[derive(Clone)]
+struct EventQueue<T> {
+    events: Vec<T>,
+    /// Number of events sent before `events[0]`, since the creation of the queue
+    start: u64,
+}
+
+impl<T> EventQueue<T> {
+    fn end(&self) -> u64 {
+        self.start + self.events.len() as u64
+    }
+}
+
+struct AnyEventQueue {
+    queue: Box<dyn Any>,
+    clone: fn(&dyn Any) -> Box<dyn Any>,
+    clear: fn(&mut dyn Any),
+}
+
+fn clone_queue<T: Clone + 'static>(queue: &dyn Any) -> Box<dyn Any> {
+    Box::new(queue.downcast_ref::<EventQueue<T>>().expect("FATAL: event queue has the wrong type").clone())
+}
+
+fn clear_queue<T: 'static>(queue: &mut dyn Any) {
+    let queue = queue.downcast_mut::<EventQueue<T>>().expect("FATAL: event queue has the wrong type");
+    queue.start += queue.events.len() as u64;
+    queue.events.clear();
+}
+
+/// Event queues attached to a list, by event type.
+#[derive(Default)]
+pub (crate) struct Events {
+    queues: TypeIdMap<AnyEventQueue>,
+}
+
+impl Events {
+    fn queue<T: 'static>(&self) -> Option<&EventQueue<T>> {
+        self.queues.get(&TypeId::of::<T>())
+            .and_then(|q| q.queue.downcast_ref::<EventQueue<T>>())
+    }
+
+    /// Drop every event, see `EntityList::maintain`.
+    pub (crate) fn clear(&mut self) {
+        for q in self.queues.values_mut() {
+            (q.clear)(&mut *q.queue);
+        }
+    }
+}
+
+impl Clone for Events {
+    fn clone(&self) -> Self {
+        let queues = self.queues.iter()
+            .map(|(type_id, q)| (*type_id, AnyEventQueue { queue: (q.clone)(&*q.queue), clone: q.clone, clear: q.clear }))
+            .collect();
+        Events { queues }
+    }
+}
+
+/// A cursor in the events `T` of a list: every event is read once by each reader.
+///
+/// Returned by `EntityList::event_reader`, and used by `EntityList::read_events`.
+pub struct EventReader<T> {
+    cursor: u64,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for EventReader<T> {
+    fn clone(&self) -> Self {
+        EventReader { cursor: self.cursor, _phantom: PhantomData }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Send an event, which can be read until the next `maintain`.
+    ///
+    /// Events are cloned with the list, they are neither serialized nor moved by `merge`.
+    ///
+    /// # Example
+    ///
+    /// `entities.send(DamageEvent { target: id, amount: 10 });`
+    pub fn send<T: Clone + 'static>(&mut self, event: T) {
+        let q = self.events.queues.entry(TypeId::of::<T>()).or_insert_with(|| AnyEventQueue {
+            queue: Box::new(EventQueue::<T> { events: Vec::new(), start: 0 }),
+            clone: clone_queue::<T>,
+            clear: clear_queue::<T>,
+        });
+        q.queue.downcast_mut::<EventQueue<T>>()
+            .expect("FATAL: event queue has the wrong type")
+            .events.push(event);
+    }
+
+    /// Iterate over every event `T` sent since the last `maintain`, in the order they were sent.
+    pub fn events<T: 'static>(&self) -> std::slice::Iter<'_, T> {
+        match self.events.queue::<T>() {
+            Some(q) => q.events.iter(),
+            None => [].iter(),
+        }
+    }
+
+    /// Returns a reader which only sees the events `T` sent from now on, see `read_events`.
+    pub fn event_reader<T: 'static>(&self) -> EventReader<T> {
+        EventReader {
+            cursor: self.events.queue::<T>().map_or(0, |q| q.end()),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Iterate over the events `T` this reader has not read yet, and mark them as read.
+    ///
+    /// Events dropped by `maintain` before being read are lost for this reader.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut damages = entities.event_reader::<DamageEvent>();
+    /// // every frame
+    /// for damage in entities.read_events(&mut damages) { }
+    /// ```
+    pub fn read_events<T: 'static>(&self, reader: &mut EventReader<T>) -> std::slice::Iter<'_, T> {
+        let q = match self.events.queue::<T>() {
+            Some(q) => q,
+            None => return [].iter(),
+        };
+        let from = reader.cursor.saturating_sub(q.start).min(q.events.len() as u64) as usize;
+        reader.cursor = q.end();
+        q.events[from..].iter()
+    }
+}
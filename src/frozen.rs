@@ -0,0 +1,145 @@
+use std::any::TypeId;
+use std::cell::UnsafeCell;
+use std::rc::Rc;
+
+use crate::{
+    EntityRefBase, CloneStorage, EntityList, EntityId, Component, RefComponent, ComponentBitSets, SpatialIndex, Aabb,
+    MultiComponent, MultiComponentIter, MultiComponentFetch, ReadOnlyMultiFetch,
+    SingleComponentIter, ComponentIter, ComponentsIter, Enabled,
+};
+use crate::genarena::GenArena;
+use crate::uuids::{Uuids, Uuid};
+
+/// An immutable copy of an `EntityList`, which can be read from several threads at once, see
+/// `EntityList::freeze`.
+///
+/// Unlike a list, this is `Send` and `Sync` as long as the properties and the components are, so it
+/// can be shared through an `Arc`, while the list it was frozen from keeps being updated.
+///
+/// # Example
+///
+/// ```ignore
+/// let frame = Arc::new(entities.freeze());
+/// let render = {
+///     let frame = Arc::clone(&frame);
+///     std::thread::spawn(move || for (id, sprite) in frame.iter_component::<Sprite>() { draw(id, sprite) })
+/// };
+/// simulate(&mut entities);
+/// render.join().unwrap();
+/// ```
+pub struct FrozenEntityList<E: EntityRefBase> {
+    entities: GenArena<E>,
+    components_storage: Rc<UnsafeCell<E::CS>>,
+    bitsets: ComponentBitSets,
+    spatial: Option<SpatialIndex<E>>,
+    uuids: Option<Uuids>,
+}
+
+// SAFETY: the storage and every entity referencing it are owned by the frozen list, and none of them
+// is reachable mutably: the reference counts of the storage are never touched until the frozen list is
+// dropped (cloned entities are detached), and the storage is only ever read, see `ComponentCell`.
+unsafe impl<E: EntityRefBase> Send for FrozenEntityList<E> where E::Naked: Send + Sync, E::CS: Send + Sync {}
+unsafe impl<E: EntityRefBase> Sync for FrozenEntityList<E> where E::Naked: Send + Sync, E::CS: Send + Sync {}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Copy the entities of this list into an immutable list, which can be shared with other threads,
+    /// see `FrozenEntityList`.
+    ///
+    /// Entities keep their ids, along with their tags and uuids, as well as the spatial index.
+    /// Soft removed entities, change ticks, property indices, resources, observers, events and queued
+    /// operations are not kept. With the `cow` feature, the components are not copied.
+    ///
+    /// Deferred refreshes are not flushed: the bitsets are copied as of the last flush, see
+    /// `set_deferred_refresh`.
+    pub fn freeze(&self) -> FrozenEntityList<E> where E::CS: CloneStorage {
+        // SAFETY: the storage is owned by the list, which we borrow immutably
+        let cs = unsafe { (*self.components_storage.get()).clone() };
+        let components_storage = Rc::new(UnsafeCell::new(cs));
+        let mut entities = self.entities.clone();
+        for entity in entities.values_mut() {
+            entity.set_cs(Rc::downgrade(&components_storage));
+        }
+        FrozenEntityList {
+            entities,
+            components_storage,
+            bitsets: self.bitsets.clone(),
+            spatial: self.spatial.clone(),
+            uuids: self.uuids.clone(),
+        }
+    }
+}
+
+impl<E: EntityRefBase> FrozenEntityList<E> {
+    /// Retrieves an entity, see `EntityList::get`.
+    pub fn get(&self, id: EntityId) -> Option<&E> {
+        self.entities.get(id)
+    }
+
+    /// Returns true if the id exists.
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.entities.contains(id)
+    }
+
+    /// Returns true if the entity exists and has the component `C`, see `EntityList::contains_component`.
+    pub fn contains_component<C: Component<E>>(&self, id: EntityId) -> bool {
+        self.bitsets.get_at(C::POSITION).contains(id.index as u32)
+            && self.entities.generation_at(id.index) == Some(id.generation)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Returns true if the entity has the tag `T`.
+    pub fn has_tag<T: 'static>(&self, id: EntityId) -> bool {
+        self.entities.contains(id) && self.bitsets.tag(&TypeId::of::<T>()).contains(id.index as u32)
+    }
+
+    /// Returns the uuid of the given entity, if uuids were enabled.
+    pub fn uuid(&self, id: EntityId) -> Option<Uuid> {
+        self.uuids.as_ref().and_then(|uuids| uuids.get(id))
+            .filter(|_| self.entities.contains(id))
+    }
+
+    /// Retrieves an entity by its uuid.
+    pub fn get_by_uuid(&self, uuid: Uuid) -> Option<&E> {
+        self.uuids.as_ref().and_then(|uuids| uuids.id(uuid))
+            .and_then(|id| self.entities.get(id))
+    }
+
+    /// Iterate over all entities
+    pub fn iter_all(&self) -> impl Iterator<Item=(EntityId, &E)> + Clone {
+        self.entities.iter()
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), see `EntityList::iter`.
+    pub fn iter<'a, C: MultiComponent<'a, E>>(&'a self) -> MultiComponentIter<'a, E, Enabled<'a, C::BitSet>> {
+        C::iter(&self.bitsets, &self.entities)
+    }
+
+    /// Iterate over all entities which have the component `C`, see `EntityList::iter_single`.
+    pub fn iter_single<C: RefComponent<E>>(&self) -> SingleComponentIter<'_, E, C> {
+        SingleComponentIter::from_parts(&self.bitsets, &self.entities, &self.components_storage)
+    }
+
+    /// Iterate over the component `C` of every entity which has it, see `EntityList::iter_component`.
+    pub fn iter_component<C: RefComponent<E>>(&self) -> ComponentIter<'_, E, C> {
+        ComponentIter::from_parts(&self.bitsets, &self.entities, &self.components_storage)
+    }
+
+    /// Iterate over the components of every entity which has them, see `EntityList::iter_components`.
+    pub fn iter_components<'a, C: MultiComponentFetch<'a, E> + ReadOnlyMultiFetch<E>>(&'a self) -> ComponentsIter<'a, E, C> {
+        ComponentsIter::from_parts(&self.bitsets, &self.entities, &self.components_storage)
+    }
+
+    /// Iterate over all entities whose position is inside `rect`, see `EntityList::iter_in_aabb`.
+    ///
+    /// Panics if the spatial index was not enabled.
+    pub fn iter_in_aabb(&self, rect: Aabb) -> impl Iterator<Item=(EntityId, &E)> {
+        self.spatial.as_ref().expect("spatial index is not enabled").iter_in_aabb(&self.entities, rect)
+    }
+}
@@ -1,4 +1,6 @@
-use super::{GenArena, Index, Entry};
+use std::iter::FusedIterator;
+
+use super::{GenArena, Index, Entry, FREE};
 
 impl<'a, T> IntoIterator for &'a GenArena<T> {
     type Item = (Index, &'a T);
@@ -19,6 +21,7 @@ impl<'a, T> IntoIterator for &'a mut GenArena<T> {
 #[derive(Debug, Clone)]
 pub struct Iter<'a, T> {
     pub (super) entries: &'a [Entry<T>],
+    pub (super) generations: &'a [u64],
     /// The total length, including Free + Occupied. Used for ExactSizeIterator
     pub (super) tot_length: usize,
     pub (super) seen: usize,
@@ -29,16 +32,15 @@ impl <'a, T> Iterator for Iter<'a, T> {
     type Item = (Index, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        for i in self.curr..self.entries.len() {
-            self.curr += 1;
-            if let Entry::Occupied { generation, value } = &self.entries[i] {
-                self.seen += 1;
-                return Some((Index::new(i, *generation), value));
-            } else {
-                continue;
-            }
+        // free entries are skipped by only reading their generation
+        let i = self.curr + self.generations[self.curr..].iter().position(|g| *g != FREE)?;
+        self.curr = i + 1;
+        if let Entry::Occupied { generation, value } = &self.entries[i] {
+            self.seen += 1;
+            Some((Index::new(i, *generation), value))
+        } else {
+            unreachable!("entry {i} in genarena has a generation but is not occupied")
         }
-        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -53,9 +55,12 @@ impl<'a, T> ExactSizeIterator for Iter<'a, T> {
     }
 }
 
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
 #[derive(Debug)]
 pub struct IterMut<'a, T> {
     pub (super) entries: &'a mut [Entry<T>],
+    pub (super) generations: &'a [u64],
     /// The total length, including Free + Occupied. Used for ExactSizeIterator
     pub (super) tot_length: usize,
     pub (super) curr: usize,
@@ -66,23 +71,22 @@ impl <'a, T> Iterator for IterMut<'a, T> {
     type Item = (Index, &'a mut T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        for i in self.curr..self.entries.len() {
-            self.curr += 1;
-            if let Entry::Occupied { generation, value } = &mut self.entries[i] {
-                self.seen += 1;
-
-                // this unsafe code is necessary (as it is in general to have IterMut iterators)
-                // because otherwise we get borrow errors.
-                // here we can say that 2 .next() will never call the 2 same value because self.curr
-                // increments every loop
-                #[allow(unsafe_code)]
-                let value = unsafe { &mut *(value as *mut _) };
-                return Some((Index::new(i, *generation), value));
-            } else {
-                continue;
-            }
+        // free entries are skipped by only reading their generation
+        let i = self.curr + self.generations[self.curr..].iter().position(|g| *g != FREE)?;
+        self.curr = i + 1;
+        if let Entry::Occupied { generation, value } = &mut self.entries[i] {
+            self.seen += 1;
+
+            // this unsafe code is necessary (as it is in general to have IterMut iterators)
+            // because otherwise we get borrow errors.
+            // here we can say that 2 .next() will never call the 2 same value because self.curr
+            // increments every call
+            #[allow(unsafe_code)]
+            let value = unsafe { &mut *(value as *mut _) };
+            Some((Index::new(i, *generation), value))
+        } else {
+            unreachable!("entry {i} in genarena has a generation but is not occupied")
         }
-        None
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -95,4 +99,6 @@ impl<'a, T> ExactSizeIterator for IterMut<'a, T> {
     fn len(&self) -> usize {
         self.tot_length
     }
-}
\ No newline at end of file
+}
+
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
@@ -2,11 +2,11 @@
 //! changes.
 //!
 //! * This is not a standalone crate because `smec` needs to do a custom Serde implementation for Arena<T>
-//! and see the internals
+//!   and see the internals
 //! * Generation is stored not in a global (in Arena) variable, but in each entry. This means that
-//! the generation will be way less inclined to grow fast, which was a risk with the other code (if thousands
-//! or more entites were removed per second, this would be a reaity after a few years. If we want a persistent
-//! Arena over a few years, this is a necessity.
+//!   the generation will be way less inclined to grow fast, which was a risk with the other code (if thousands
+//!   or more entites were removed per second, this would be a reaity after a few years. If we want a persistent
+//!   Arena over a few years, this is a necessity.
 //! * When Serializing/Deserializing, empty/free entries are kept (and not filtered out)
 
 #[cfg(feature = "use_serde")]
@@ -20,11 +20,25 @@ mod tests;
 #[derive(Debug)]
 pub struct GenArena<T> {
     pub (crate) entries: Vec<Entry<T>>,
+    /// Generation of every entry, or `FREE` for free entries.
+    ///
+    /// This mirrors the generations stored in `entries`, in a dense array, so that looking up the
+    /// generation of an index (to build an `Index`, or to check one) does not touch the values.
+    pub (crate) generations: Vec<u64>,
     /// Points to the next Free Entry. Free entries are are single-way linked list,
     /// so they may not be in order
     pub (crate) next_free: Option<usize>,
     /// The length of the arena, or the number of `Occupied` variant in entries.
     pub (crate) length: usize,
+    /// Free entries taken out of the free list by `reserve_index`, waiting for `insert_reserved`.
+    pub (crate) reserved: hashbrown::HashSet<usize>,
+    /// Whether removed entries go back to the free list. See `set_reuse_slots`.
+    pub (crate) reuse_slots: bool,
+    /// Free entries removed while `reuse_slots` was false, waiting for `vacuum`.
+    pub (crate) retired: Vec<usize>,
+    /// Generation of the entries created when the arena grows. It is raised by `truncate` above
+    /// every generation the truncated entries gave out, so that their indexes stay invalid.
+    pub (crate) generation_floor: u64,
 }
 
 #[derive(Debug)]
@@ -89,6 +103,16 @@ impl<T> Default for GenArena<T> {
 
 pub const DEFAULT_ARENA_CAPACITY: usize = 32;
 
+/// Generation of free entries in `GenArena::generations`, never reached by an occupied entry.
+pub (crate) const FREE: u64 = u64::MAX;
+
+/// Indexes refused by `insert_at`: the indexes of a list are stored in `hibitset`s, which hold
+/// 64^4 bits.
+pub (crate) const MAX_INDEX: usize = 1 << 24;
+
+/// Minimum number of entries reserved when a full arena grows.
+const MIN_RESERVE: usize = 8;
+
 impl<T> GenArena<T> {
     pub fn new() -> Self {
         Self::with_capacity(DEFAULT_ARENA_CAPACITY)
@@ -97,21 +121,52 @@ impl<T> GenArena<T> {
     /// Internal usage only.
     ///
     /// Mostly used for EntityList::deserialize
+    ///
+    /// Free entries which are not in the free list (reserved or retired when serialized) are put back in it.
     #[cfg(feature = "use_serde")]
     pub (crate) fn from_raw(entries: Vec<Entry<T>>, length: usize, next_free: Option<usize>) -> Self {
         debug_assert!(length == entries.iter().filter(|e| matches!(e, Entry::Occupied { .. })).count());
-        Self {
+        let generations = entries.iter().map(|e| match e {
+            Entry::Occupied { generation, .. } => *generation,
+            Entry::Free { .. } => FREE,
+        }).collect();
+        let mut arena = Self {
             entries,
+            generations,
             length,
-            next_free
+            next_free,
+            reserved: Default::default(),
+            reuse_slots: true,
+            retired: Vec::new(),
+            generation_floor: 0,
+        };
+        let mut linked = vec![false; arena.entries.len()];
+        let mut curr = arena.next_free;
+        while let Some(i) = curr {
+            linked[i] = true;
+            curr = match arena.entries[i] {
+                Entry::Free { next_free, .. } => next_free,
+                Entry::Occupied { .. } => None,
+            };
         }
+        arena.retired = arena.entries.iter().enumerate()
+            .filter(|(i, e)| matches!(e, Entry::Free { .. }) && !linked[*i])
+            .map(|(i, _)| i)
+            .collect();
+        arena.vacuum();
+        arena
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
         let mut arena = GenArena {
             entries: Vec::new(),
+            generations: Vec::new(),
             next_free: None,
             length: 0,
+            reserved: Default::default(),
+            reuse_slots: true,
+            retired: Vec::new(),
+            generation_floor: 0,
         };
         if capacity > 0 {
             arena.reserve_exact(capacity);
@@ -124,19 +179,49 @@ impl<T> GenArena<T> {
         self.entries.reserve_exact(added_capacity);
         let reserve_start = self.entries.len();
         for i in 0..(added_capacity-1) {
-            self.entries.push(Entry::Free { next_generation: 0, next_free: Some(reserve_start + i + 1) });
+            self.entries.push(Entry::Free { next_generation: self.generation_floor, next_free: Some(reserve_start + i + 1) });
         }
-        self.entries.push(Entry::Free { next_generation: 0, next_free: self.next_free });
+        self.entries.push(Entry::Free { next_generation: self.generation_floor, next_free: self.next_free });
+        self.generations.resize(self.entries.len(), FREE);
         self.next_free = Some(reserve_start);
         reserve_start
     }
 
     #[inline]
     pub fn reserve_exact(&mut self, added_capacity: usize) {
-        self.internal_reserve_exact(added_capacity);
+        if added_capacity > 0 {
+            self.internal_reserve_exact(added_capacity);
+        }
     }
 
+    /// Make sure that `additional` more values can be pushed without reallocating.
+    pub fn reserve(&mut self, additional: usize) {
+        let free = self.entries.len() - self.length;
+        if additional > free {
+            self.reserve_exact(additional - free);
+        }
+    }
+
+    /// Remove every value.
+    ///
+    /// If slots are not reused, the occupied slots are retired instead of going back to the free
+    /// list, see `set_reuse_slots`. Reservations are cancelled either way, see `reserve_index`.
     pub fn clear(&mut self) {
+        self.generations.fill(FREE);
+        if !self.reuse_slots {
+            for (i, entry) in self.entries.iter_mut().enumerate() {
+                if let Entry::Occupied { generation, .. } = *entry {
+                    *entry = Entry::Free { next_generation: generation + 1, next_free: None };
+                    self.retired.push(i);
+                }
+            }
+            for index in self.expire_reserved() {
+                self.link_free(index);
+            }
+            self.length = 0;
+            return;
+        }
+        self.expire_reserved();
         if let Some((last, head)) = self.entries.split_last_mut() {
             match *last {
                 Entry::Free { next_generation, .. } => {
@@ -159,6 +244,61 @@ impl<T> GenArena<T> {
         }
         self.length = 0;
         self.next_free = Some(0);
+        self.retired.clear();
+    }
+
+    /// Remove every entry at a raw index `>= max_slots`, and give the trailing capacity back.
+    ///
+    /// Occupied entries past the cutoff are dropped. The free list is rebuilt from the remaining
+    /// entries, lowest index first.
+    ///
+    /// If the arena grows again past `max_slots`, the new slots start above every generation the
+    /// truncated slots gave out, so that old `Index`es pointing there never become valid again.
+    /// This floor is not serialized, like reservations.
+    ///
+    /// Reservations are cancelled, see `reserve_index`. Retired slots stay out of the free list until
+    /// `vacuum` is called.
+    pub fn truncate(&mut self, max_slots: usize) {
+        if max_slots >= self.entries.len() {
+            return;
+        }
+        self.expire_reserved();
+        self.retired.retain(|index| *index < max_slots);
+        let mut removed = 0;
+        for entry in &self.entries[max_slots..] {
+            let next_generation = match *entry {
+                Entry::Occupied { generation, .. } => {
+                    removed += 1;
+                    generation + 1
+                },
+                Entry::Free { next_generation, .. } => next_generation,
+            };
+            self.generation_floor = std::cmp::max(self.generation_floor, next_generation);
+        }
+        self.entries.truncate(max_slots);
+        self.entries.shrink_to_fit();
+        self.generations.truncate(max_slots);
+        self.generations.shrink_to_fit();
+        self.length -= removed;
+
+        let mut is_retired = vec![false; max_slots];
+        for index in &self.retired {
+            is_retired[*index] = true;
+        }
+        let mut next_free = None;
+        for (i, entry) in self.entries.iter_mut().enumerate().rev() {
+            match *entry {
+                Entry::Free { next_generation, .. } if is_retired[i] => {
+                    *entry = Entry::Free { next_generation, next_free: None };
+                },
+                Entry::Free { next_generation, .. } => {
+                    *entry = Entry::Free { next_generation, next_free };
+                    next_free = Some(i);
+                },
+                Entry::Occupied { .. } => {},
+            }
+        }
+        self.next_free = next_free;
     }
 
     /// Force an insert as `index`, panicking if a previous value exists. Internal use only.
@@ -167,6 +307,7 @@ impl<T> GenArena<T> {
     fn force_insert_at(&mut self, index: usize, value: T) -> Index {
         if let Entry::Free { next_generation, next_free } = self.entries[index] {
             self.entries[index] = Entry::Occupied { generation: next_generation, value };
+            self.generations[index] = next_generation;
             self.next_free = next_free;
             self.length += 1;
             Index { generation: next_generation, index }
@@ -182,16 +323,168 @@ impl<T> GenArena<T> {
                 self.force_insert_at(next_free, value)
             },
             None => {
-                const MIN_RESERVE: usize = 8;
                 // reserve to have double the amount we have, but there is a special case:
-                // if the amount we have is zero, double zero is zero. For that case, we have a minimum reserve
-                // constant just above.
+                // if the amount we have is zero, double zero is zero. For that case, we have a minimum reserve,
+                // see `MIN_RESERVE`.
                 let next_free = self.internal_reserve_exact(std::cmp::max(self.entries.len(), MIN_RESERVE));
                 self.force_insert_at(next_free, value)
             }
         }
     }
 
+    /// Take a free slot out of the free list, and return the index a value will have once inserted
+    /// there with `insert_reserved`.
+    ///
+    /// The slot stays free, so the index is not contained until then. Reservations are cancelled by
+    /// `clear` and `truncate`, after which the index never becomes valid, and are not serialized.
+    pub fn reserve_index(&mut self) -> Index {
+        let index = match self.next_free {
+            Some(next_free) => next_free,
+            None => self.internal_reserve_exact(std::cmp::max(self.entries.len(), MIN_RESERVE)),
+        };
+        match self.entries[index] {
+            Entry::Free { next_generation, next_free } => {
+                self.next_free = next_free;
+                self.entries[index] = Entry::Free { next_generation, next_free: None };
+                self.reserved.insert(index);
+                Index { index, generation: next_generation }
+            },
+            Entry::Occupied { .. } => panic!("index {index} in genarena is in the free list but occupied"),
+        }
+    }
+
+    /// Insert a value at an index returned by `reserve_index`.
+    ///
+    /// Returns the value back if the index is not reserved.
+    pub fn insert_reserved(&mut self, index: Index, value: T) -> Result<(), T> {
+        match self.entries.get(index.index) {
+            Some(Entry::Free { next_generation, .. })
+                if *next_generation == index.generation && self.reserved.remove(&index.index) => {
+                self.entries[index.index] = Entry::Occupied { generation: index.generation, value };
+                self.generations[index.index] = index.generation;
+                self.length += 1;
+                Ok(())
+            },
+            _ => Err(value),
+        }
+    }
+
+    /// Give a reserved slot back to the free list, see `reserve_index`.
+    ///
+    /// Returns false if the index is not reserved.
+    pub fn cancel_reserved(&mut self, index: Index) -> bool {
+        match self.entries.get(index.index) {
+            Some(Entry::Free { next_generation, .. })
+                if *next_generation == index.generation && self.reserved.remove(&index.index) => {
+                self.link_free(index.index);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Remove a value, but keep its slot reserved with the same generation, so that the value can be
+    /// put back with `insert_reserved`, at the same index. See `release_reserved` to free the slot.
+    pub fn remove_reserved(&mut self, index: Index) -> Option<T> {
+        if !self.contains(index) {
+            return None;
+        }
+        let entry = Entry::Free { next_generation: index.generation, next_free: None };
+        self.reserved.insert(index.index);
+        self.generations[index.index] = FREE;
+        self.length -= 1;
+        match std::mem::replace(&mut self.entries[index.index], entry) {
+            Entry::Occupied { value, .. } => Some(value),
+            Entry::Free { .. } => unreachable!("removed entry in remove_reserved is not Occupied variant"),
+        }
+    }
+
+    /// Same as `cancel_reserved`, but the slot gets the next generation, so that `index` can never be
+    /// used again. Use this to free slots reserved by `remove_reserved`.
+    pub fn release_reserved(&mut self, index: Index) -> bool {
+        match self.entries.get_mut(index.index) {
+            Some(Entry::Free { next_generation, .. })
+                if *next_generation == index.generation && self.reserved.contains(&index.index) => {
+                *next_generation += 1;
+                self.cancel_reserved(Index::new(index.index, index.generation + 1))
+            },
+            _ => false,
+        }
+    }
+
+    /// Insert a value at an explicit index, chosen by the caller instead of the arena.
+    ///
+    /// The slot must be free, neither reserved nor retired (see `set_reuse_slots`), and its next
+    /// generation must not be greater than the given one: generations never go backwards, so that stale indexes never become valid again.
+    /// The arena grows if the index is past its capacity, up to 2^24 entries: larger indexes are
+    /// refused, as is the generation `u64::MAX`.
+    ///
+    /// Returns the value back if the slot cannot be used.
+    ///
+    /// This walks the free list to unlink the slot, so it is linear in the number of free slots.
+    pub fn insert_at(&mut self, index: Index, value: T) -> Result<(), T> {
+        if index.index >= MAX_INDEX || index.generation == FREE {
+            return Err(value);
+        }
+        if index.index >= self.entries.len() {
+            self.internal_reserve_exact(index.index + 1 - self.entries.len());
+        }
+        match self.entries[index.index] {
+            Entry::Free { next_generation, next_free }
+                if next_generation <= index.generation
+                    && !self.reserved.contains(&index.index)
+                    && !self.retired.contains(&index.index) => {
+                self.unlink_free(index.index, next_free);
+                self.entries[index.index] = Entry::Occupied { generation: index.generation, value };
+                self.generations[index.index] = index.generation;
+                self.length += 1;
+                Ok(())
+            },
+            _ => Err(value),
+        }
+    }
+
+    /// Put the free slot at `index`, which must not be in the free list, at the head of the free list.
+    fn link_free(&mut self, index: usize) {
+        if let Entry::Free { next_free, .. } = &mut self.entries[index] {
+            *next_free = self.next_free;
+            self.next_free = Some(index);
+        }
+    }
+
+    /// Cancel every reservation, giving the reserved slots the next generation so that the indexes
+    /// returned by `reserve_index` can never become valid. Returns the slots, which are not put back
+    /// in the free list.
+    fn expire_reserved(&mut self) -> hashbrown::HashSet<usize> {
+        let reserved = std::mem::take(&mut self.reserved);
+        for index in &reserved {
+            if let Entry::Free { next_generation, .. } = &mut self.entries[*index] {
+                *next_generation += 1;
+            }
+        }
+        reserved
+    }
+
+    /// Remove the free slot at `index` from the free list, `next_free` being the slot it points to.
+    fn unlink_free(&mut self, index: usize, next_free: Option<usize>) {
+        if self.next_free == Some(index) {
+            self.next_free = next_free;
+            return;
+        }
+        let mut curr = self.next_free;
+        while let Some(i) = curr {
+            match &mut self.entries[i] {
+                Entry::Free { next_free: next, .. } if *next == Some(index) => {
+                    *next = next_free;
+                    return;
+                },
+                Entry::Free { next_free: next, .. } => curr = *next,
+                Entry::Occupied { .. } => panic!("index {i} in genarena is in the free list but occupied"),
+            }
+        }
+        panic!("index {index} in genarena is free but not in the free list");
+    }
+
     pub fn remove(&mut self, index: Index) -> Option<T> {
         if let Some(entry) = self.entries.get_mut(index.index) {
             let Entry::Occupied { generation, .. } = entry else {
@@ -200,9 +493,16 @@ impl<T> GenArena<T> {
             if *generation != index.generation {
                 return None;
             }
-            let new_entry = Entry::Free { next_generation: *generation + 1, next_free: self.next_free };
-            let removed_entry = std::mem::replace(entry, new_entry);
-            self.next_free = Some(index.index);
+            let removed_entry = if self.reuse_slots {
+                let new_entry = Entry::Free { next_generation: *generation + 1, next_free: self.next_free };
+                self.next_free = Some(index.index);
+                std::mem::replace(entry, new_entry)
+            } else {
+                let new_entry = Entry::Free { next_generation: *generation + 1, next_free: None };
+                self.retired.push(index.index);
+                std::mem::replace(entry, new_entry)
+            };
+            self.generations[index.index] = FREE;
             self.length -= 1;
             if let Entry::Occupied { value, .. } = removed_entry {
                 Some(value)
@@ -214,19 +514,64 @@ impl<T> GenArena<T> {
         }
     }
 
+    /// Choose whether the slots of removed values are reused by later inserts. Defaults to true.
+    ///
+    /// When slots are not reused, removed slots are retired until `vacuum` is called, so every raw index
+    /// is given to a single value in the meantime. The arena keeps growing instead.
+    pub fn set_reuse_slots(&mut self, reuse: bool) {
+        self.reuse_slots = reuse;
+    }
+
+    /// Returns false if removed slots are retired instead of reused, see `set_reuse_slots`.
+    pub fn reuses_slots(&self) -> bool {
+        self.reuse_slots
+    }
+
+    /// Give every retired slot back to the free list, see `set_reuse_slots`. Returns the number of slots
+    /// given back.
+    pub fn vacuum(&mut self) -> usize {
+        let retired = std::mem::take(&mut self.retired);
+        // link in reverse, so that slots are reused in the order they were retired
+        for index in retired.iter().rev() {
+            self.link_free(*index);
+        }
+        retired.len()
+    }
+
+    /// Exchange the values stored at `a` and `b`.
+    ///
+    /// Generations are attached to the slot, not to the value, so both indexes stay valid
+    /// afterwards: `a` now points to the value that was in `b` and vice versa.
+    ///
+    /// Returns false (and does nothing) if either index is not occupied with the right generation.
+    pub fn swap(&mut self, a: Index, b: Index) -> bool {
+        if !self.contains(a) || !self.contains(b) {
+            return false;
+        }
+        if a.index != b.index {
+            self.entries.swap(a.index, b.index);
+            if let Entry::Occupied { generation, .. } = &mut self.entries[a.index] {
+                *generation = a.generation;
+            }
+            if let Entry::Occupied { generation, .. } = &mut self.entries[b.index] {
+                *generation = b.generation;
+            }
+        }
+        true
+    }
+
     #[inline]
     pub fn contains(&self, index: Index) -> bool {
-        self.get(index).is_some()
+        self.generation_at(index.index) == Some(index.generation)
     }
 
     pub fn get(&self, index: Index) -> Option<&T> {
-        if let Some(Entry::Occupied { generation, value }) = self.entries.get(index.index) {
-            if *generation != index.generation {
-                return None;
-            }
-            Some(value)
-        } else {
-            None
+        if self.generation_at(index.index) != Some(index.generation) {
+            return None;
+        }
+        match &self.entries[index.index] {
+            Entry::Occupied { value, .. } => Some(value),
+            Entry::Free { .. } => None,
         }
     }
 
@@ -239,14 +584,53 @@ impl<T> GenArena<T> {
         }
     }
 
+    /// Returns the generation of the entry at `index`, if it is occupied.
+    ///
+    /// This only reads the dense array of generations, not the entry itself.
+    #[inline]
+    pub fn generation_at(&self, index: usize) -> Option<u64> {
+        match self.generations.get(index) {
+            Some(&generation) if generation != FREE => Some(generation),
+            _ => None,
+        }
+    }
+
     pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
-        if let Some(Entry::Occupied { generation, value }) = self.entries.get_mut(index.index) {
-            if *generation != index.generation {
+        if self.generation_at(index.index) != Some(index.generation) {
+            return None;
+        }
+        match &mut self.entries[index.index] {
+            Entry::Occupied { value, .. } => Some(value),
+            Entry::Free { .. } => None,
+        }
+    }
+
+    /// Get mutable references to several values at once.
+    ///
+    /// Returns `None` if any of the indexes does not exist, or if the same index is given twice.
+    pub fn get_many_mut<const N: usize>(&mut self, indexes: [Index; N]) -> Option<[&mut T; N]> {
+        for (i, index) in indexes.iter().enumerate() {
+            if !self.contains(*index) || indexes[..i].iter().any(|other| other.index == index.index) {
                 return None;
             }
-            Some(value)
-        } else {
-            None
+        }
+        let entries = self.entries.as_mut_ptr();
+        Some(indexes.map(|index| {
+            // SAFETY: every index was checked to be in bounds, occupied, and distinct from the
+            // others, so every reference points to a different entry.
+            match unsafe { &mut *entries.add(index.index) } {
+                Entry::Occupied { value, .. } => value,
+                Entry::Free { .. } => unreachable!(),
+            }
+        }))
+    }
+
+    /// Borrow the entries mutably, to hand out mutable references to several values at once, by index.
+    pub (crate) fn entries_mut(&mut self) -> EntriesMut<'_, T> {
+        EntriesMut {
+            entries: self.entries.as_mut_ptr(),
+            len: self.entries.len(),
+            _phantom: std::marker::PhantomData,
         }
     }
 
@@ -259,6 +643,57 @@ impl<T> GenArena<T> {
         }
     }
 
+    /// Same as `get`, without checking the index, for loops where it is known to exist, for
+    /// instance from a bitset.
+    ///
+    /// # Safety
+    ///
+    /// The index must exist, which is only checked in debug builds.
+    #[inline]
+    pub unsafe fn get_unchecked(&self, index: Index) -> &T {
+        debug_assert!(self.contains(index), "index {index} does not exist");
+        self.get_raw_unchecked(index.index).0
+    }
+
+    /// Same as `get_mut`, without checking the index, see `get_unchecked`.
+    ///
+    /// # Safety
+    ///
+    /// The index must exist, which is only checked in debug builds.
+    #[inline]
+    pub unsafe fn get_unchecked_mut(&mut self, index: Index) -> &mut T {
+        debug_assert!(self.contains(index), "index {index} does not exist");
+        self.get_raw_unchecked_mut(index.index).0
+    }
+
+    /// Same as `get_raw`, without checking that the entry is in bounds and occupied.
+    ///
+    /// # Safety
+    ///
+    /// The entry at `index` must be occupied, which is only checked in debug builds.
+    #[inline]
+    pub unsafe fn get_raw_unchecked(&self, index: usize) -> (&T, u64) {
+        debug_assert!(self.generation_at(index).is_some(), "entry {index} is not occupied");
+        match self.entries.get_unchecked(index) {
+            Entry::Occupied { generation, value } => (value, *generation),
+            Entry::Free { .. } => std::hint::unreachable_unchecked(),
+        }
+    }
+
+    /// Same as `get_raw_mut`, without checking that the entry is in bounds and occupied.
+    ///
+    /// # Safety
+    ///
+    /// The entry at `index` must be occupied, which is only checked in debug builds.
+    #[inline]
+    pub unsafe fn get_raw_unchecked_mut(&mut self, index: usize) -> (&mut T, u64) {
+        debug_assert!(self.generation_at(index).is_some(), "entry {index} is not occupied");
+        match self.entries.get_unchecked_mut(index) {
+            Entry::Occupied { generation, value } => (value, *generation),
+            Entry::Free { .. } => std::hint::unreachable_unchecked(),
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.length
     }
@@ -267,18 +702,20 @@ impl<T> GenArena<T> {
         self.length == 0
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
             entries: &self.entries,
+            generations: &self.generations,
             tot_length: self.length,
             seen: 0,
             curr: 0,
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
             entries: &mut self.entries,
+            generations: &self.generations,
             tot_length: self.length,
             seen: 0,
             curr: 0,
@@ -296,21 +733,91 @@ impl<T> GenArena<T> {
     pub fn capacity(&self) -> usize {
         self.entries.len()
     }
+
+    /// Returns the number of bytes allocated by the arena, for its entries and its generations.
+    ///
+    /// Values are counted by their size only: memory they allocate themselves is not.
+    pub fn allocated_bytes(&self) -> usize {
+        self.entries.capacity() * std::mem::size_of::<Entry<T>>()
+            + self.generations.capacity() * std::mem::size_of::<u64>()
+            + self.retired.capacity() * std::mem::size_of::<usize>()
+    }
+
+    /// Take every entry out, leaving the arena empty, without any capacity.
+    pub (crate) fn take_entries(&mut self) -> Vec<Entry<T>> {
+        self.generations.clear();
+        self.next_free = None;
+        self.length = 0;
+        self.reserved.clear();
+        self.retired.clear();
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Convert every value, keeping the indexes, generations and free slots as they are.
+    pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> GenArena<U> {
+        GenArena {
+            entries: self.entries.into_iter().map(|e| e.map(&mut f)).collect(),
+            generations: self.generations,
+            next_free: self.next_free,
+            length: self.length,
+            reserved: self.reserved,
+            reuse_slots: self.reuse_slots,
+            retired: self.retired,
+            generation_floor: self.generation_floor,
+        }
+    }
+}
+
+/// The entries of an arena, borrowed mutably, see `GenArena::entries_mut`.
+///
+/// Unlike `get_raw_mut`, getting a value does not borrow the whole arena again, which would
+/// invalidate the values handed out before.
+pub (crate) struct EntriesMut<'a, T> {
+    entries: *mut Entry<T>,
+    len: usize,
+    _phantom: std::marker::PhantomData<&'a mut [Entry<T>]>,
+}
+
+impl<'a, T> EntriesMut<'a, T> {
+    /// Get a mutable value and its generation from an `usize` index (without generation)
+    ///
+    /// # Safety
+    ///
+    /// The value at `index` must not be borrowed already: the same index must not be given twice.
+    pub (crate) unsafe fn get_raw_mut(&self, index: usize) -> Option<(&'a mut T, u64)> {
+        if index >= self.len {
+            return None;
+        }
+        match &mut *self.entries.add(index) {
+            Entry::Occupied { generation, value } => Some((value, *generation)),
+            Entry::Free { .. } => None,
+        }
+    }
 }
 
 impl<T:Clone> Clone for GenArena<T> {
     fn clone(&self) -> Self {
         Self {
             entries: self.entries.clone(),
+            generations: self.generations.clone(),
             next_free: self.next_free,
-            length: self.length
+            length: self.length,
+            reserved: self.reserved.clone(),
+            reuse_slots: self.reuse_slots,
+            retired: self.retired.clone(),
+            generation_floor: self.generation_floor,
         }
     }
 
     fn clone_from(&mut self, other: &Self) {
         self.entries.clone_from(&other.entries);
+        self.generations.clone_from(&other.generations);
         self.next_free = other.next_free;
         self.length = other.length;
+        self.reserved.clone_from(&other.reserved);
+        self.reuse_slots = other.reuse_slots;
+        self.retired.clone_from(&other.retired);
+        self.generation_floor = other.generation_floor;
     }
 }
 
@@ -336,7 +843,6 @@ impl<T: Clone> Clone for Entry<T> {
             ) => {
                 *dest_gen = *generation;
                 dest_value.clone_from(value);
-                return;
             },
             (s, o) => { *s = o.clone() }
         }
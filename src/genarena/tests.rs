@@ -81,4 +81,270 @@ fn removals() {
     assert_eq!(arena.push(8), Index::new(3, 0));
     assert_eq!(arena.push(9), Index::new(4, 0));
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn swap() {
+    let mut arena = GenArena::with_capacity(4);
+    let idx1 = arena.push(1);
+    let idx2 = arena.push(2);
+    arena.remove(idx2);
+    let idx2 = arena.push(2);
+    assert_eq!(idx2, Index::new(1, 1));
+    assert!(arena.swap(idx1, idx2));
+    assert_eq!(arena.get(idx1), Some(&2));
+    assert_eq!(arena.get(idx2), Some(&1));
+    // old generation is not valid
+    assert!(!arena.swap(idx1, Index::new(1, 0)));
+    assert_eq!(arena.get(idx1), Some(&2));
+    assert_eq!(arena.len(), 2);
+}
+
+#[test]
+fn truncate() {
+    let mut arena = GenArena::with_capacity(8);
+    let idx: Vec<_> = (0..6).map(|i| arena.push(i)).collect();
+    arena.remove(idx[1]);
+    arena.truncate(4);
+    assert_eq!(arena.capacity(), 4);
+    assert_eq!(arena.len(), 3);
+    assert_eq!(arena.get(idx[4]), None);
+    assert_eq!(arena.iter().map(|(_, v)| *v).collect::<Vec<_>>(), vec![0, 2, 3]);
+    // the freed slot is reused first, then the arena grows again
+    assert_eq!(arena.push(10), Index::new(1, 1));
+    // regrown slots start above the generations of the truncated ones
+    assert_eq!(arena.push(11), Index::new(4, 1));
+    assert_eq!(arena.get(idx[4]), None);
+    assert_eq!(arena.get(idx[5]), None);
+    assert_eq!(arena.len(), 5);
+}
+
+#[test]
+fn reserve() {
+    let mut arena = GenArena::with_capacity(4);
+    arena.push(1);
+    arena.push(2);
+    arena.reserve(2);
+    assert_eq!(arena.capacity(), 4);
+    arena.reserve(5);
+    assert_eq!(arena.capacity(), 7);
+    for i in 0..5 {
+        arena.push(i);
+    }
+    assert_eq!(arena.capacity(), 7);
+    assert_eq!(arena.len(), 7);
+}
+
+#[test]
+fn get_many_mut() {
+    let mut arena = GenArena::with_capacity(4);
+    let a = arena.push(1);
+    let b = arena.push(2);
+    let c = arena.push(3);
+    arena.remove(c);
+
+    if let Some([x, y]) = arena.get_many_mut([a, b]) {
+        std::mem::swap(x, y);
+    }
+    assert_eq!(arena.get(a), Some(&2));
+    assert_eq!(arena.get(b), Some(&1));
+    assert!(arena.get_many_mut([a, a]).is_none());
+    assert!(arena.get_many_mut([a, c]).is_none());
+}
+
+#[test]
+fn reserve_index() {
+    let mut arena = GenArena::with_capacity(2);
+    let a = arena.reserve_index();
+    let b = arena.push(2);
+    assert_ne!(a.index, b.index);
+    assert!(!arena.contains(a));
+    assert_eq!(arena.len(), 1);
+
+    assert_eq!(arena.insert_reserved(a, 1), Ok(()));
+    assert_eq!(arena.insert_reserved(a, 3), Err(3));
+    assert_eq!(arena.get(a), Some(&1));
+    assert_eq!(arena.len(), 2);
+
+    // the arena grows if there is no free slot left
+    let c = arena.reserve_index();
+    assert_eq!(c, Index::new(2, 0));
+    assert!(arena.cancel_reserved(c));
+    assert!(!arena.cancel_reserved(c));
+    assert_eq!(arena.push(4), c);
+}
+
+#[test]
+fn reservations_expire() {
+    let mut arena = GenArena::with_capacity(1);
+    let a = arena.reserve_index();
+    arena.clear();
+    let b = arena.push(1);
+    assert_eq!(b.index, a.index);
+    assert_eq!(arena.get(a), None);
+    assert_eq!(arena.insert_reserved(a, 2), Err(2));
+
+    // the reserved slot is kept by the truncation
+    let c = arena.reserve_index();
+    assert!(arena.capacity() > c.index + 1);
+    arena.truncate(c.index + 1);
+    assert_eq!(arena.insert_reserved(c, 3), Err(3));
+    let d = arena.push(4);
+    assert_eq!(d.index, c.index);
+    assert_eq!(arena.get(c), None);
+
+    // without reusing slots, reserved slots are still handed out again
+    arena.set_reuse_slots(false);
+    let e = arena.reserve_index();
+    arena.clear();
+    let f = arena.push(5);
+    assert_eq!(f.index, e.index);
+    assert_eq!(arena.get(e), None);
+}
+
+#[test]
+fn insert_at() {
+    let mut arena = GenArena::with_capacity(4);
+    assert_eq!(arena.insert_at(Index::new(2, 3), 1), Ok(()));
+    assert_eq!(arena.get(Index::new(2, 3)), Some(&1));
+    assert_eq!(arena.insert_at(Index::new(2, 4), 2), Err(2));
+
+    // generations never go backwards
+    arena.remove(Index::new(2, 3));
+    assert_eq!(arena.insert_at(Index::new(2, 3), 3), Err(3));
+    assert_eq!(arena.insert_at(Index::new(2, 5), 3), Ok(()));
+
+    // the arena grows up to the index, but not past what a bitset can hold
+    assert_eq!(arena.insert_at(Index::new(9, 0), 4), Ok(()));
+    assert_eq!(arena.capacity(), 10);
+    assert_eq!(arena.len(), 2);
+    assert_eq!(arena.insert_at(Index::new(usize::MAX, 0), 5), Err(5));
+    assert_eq!(arena.insert_at(Index::new(MAX_INDEX, 0), 5), Err(5));
+    assert_eq!(arena.insert_at(Index::new(3, u64::MAX), 5), Err(5));
+    assert_eq!(arena.capacity(), 10);
+
+    // the other slots are still in the free list
+    let pushed: Vec<_> = (0..8).map(|i| arena.push(i).index).collect();
+    let mut sorted = pushed.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![0, 1, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(arena.push(8).index, 10);
+}
+
+#[test]
+fn retire_slots() {
+    let mut arena = GenArena::with_capacity(2);
+    arena.set_reuse_slots(false);
+    let a = arena.push(1);
+    let b = arena.push(2);
+    arena.remove(a);
+    arena.remove(b);
+    let c = arena.push(3);
+    assert!(c.index >= 2);
+
+    arena.clear();
+    assert!(arena.is_empty());
+    assert!(arena.push(4).index > c.index);
+
+    assert_eq!(arena.vacuum(), 3);
+    assert_eq!(arena.push(5), Index::new(a.index, 1));
+    assert_eq!(arena.push(6), Index::new(b.index, 1));
+}
+
+#[test]
+fn retired_slots() {
+    let mut arena = GenArena::with_capacity(4);
+    arena.set_reuse_slots(false);
+    let idx: Vec<_> = (0..4).map(|i| arena.push(i)).collect();
+    arena.remove(idx[0]);
+    arena.remove(idx[3]);
+
+    // retired slots cannot be taken back explicitly either
+    assert_eq!(arena.insert_at(Index::new(0, 5), 10), Err(10));
+
+    // the retired slot below the cutoff is still not reused
+    arena.truncate(3);
+    assert_eq!(arena.push(11).index, 3);
+    assert_eq!(arena.vacuum(), 1);
+    assert_eq!(arena.insert_at(Index::new(0, 5), 10), Ok(()));
+}
+
+#[test]
+fn remove_reserved() {
+    let mut arena = GenArena::with_capacity(2);
+    let a = arena.push(1);
+    assert_eq!(arena.remove_reserved(a), Some(1));
+    assert!(!arena.contains(a));
+    assert_eq!(arena.len(), 0);
+    assert_ne!(arena.push(2).index, a.index);
+
+    assert_eq!(arena.insert_reserved(a, 1), Ok(()));
+    assert_eq!(arena.get(a), Some(&1));
+
+    assert_eq!(arena.remove_reserved(a), Some(1));
+    assert!(arena.release_reserved(a));
+    assert!(!arena.release_reserved(a));
+    assert_eq!(arena.push(3), Index::new(a.index, a.generation + 1));
+}
+
+/// Check that the dense generations match the entries.
+fn assert_generations<T>(arena: &GenArena<T>) {
+    assert_eq!(arena.generations.len(), arena.entries.len());
+    for (i, entry) in arena.entries.iter().enumerate() {
+        match entry {
+            Entry::Occupied { generation, .. } => assert_eq!(arena.generation_at(i), Some(*generation)),
+            Entry::Free { .. } => assert_eq!(arena.generation_at(i), None),
+        }
+    }
+}
+
+#[test]
+fn generations() {
+    let mut arena = GenArena::with_capacity(2);
+    let idx: Vec<_> = (0..6).map(|i| arena.push(i)).collect();
+    assert_generations(&arena);
+    arena.remove(idx[1]);
+    arena.remove_reserved(idx[2]);
+    assert_generations(&arena);
+    assert_eq!(arena.generation_at(idx[1].index), None);
+    assert_eq!(arena.insert_reserved(idx[2], 2), Ok(()));
+    assert_eq!(arena.insert_at(Index::new(9, 3), 9), Ok(()));
+    assert_eq!(arena.generation_at(9), Some(3));
+    assert_generations(&arena);
+    arena.swap(idx[0], idx[3]);
+    arena.truncate(8);
+    assert_generations(&arena);
+    assert_eq!(arena.iter().map(|(_, v)| *v).collect::<Vec<_>>(), vec![3, 2, 0, 4, 5]);
+    arena.clear();
+    assert_generations(&arena);
+    assert_eq!(arena.iter().count(), 0);
+    let pushed = arena.push(7);
+    assert_eq!(arena.generation_at(pushed.index), Some(pushed.generation));
+    assert_generations(&arena.clone().map(|v| v + 1));
+}
+
+#[test]
+fn unchecked() {
+    let mut arena = GenArena::new();
+    let a = arena.push(1);
+    let b = arena.push(2);
+    arena.remove(a);
+    let c = arena.push(3);
+    unsafe {
+        assert_eq!(*arena.get_unchecked(b), 2);
+        *arena.get_unchecked_mut(c) += 10;
+        assert_eq!(arena.get_raw_unchecked(c.index), (&13, c.generation));
+        assert_eq!(arena.get_raw_unchecked_mut(b.index), (&mut 2, b.generation));
+    }
+}
+
+#[test]
+#[should_panic]
+#[cfg(debug_assertions)]
+fn unchecked_removed() {
+    let mut arena = GenArena::new();
+    let a = arena.push(1);
+    arena.remove(a);
+    arena.push(2);
+    unsafe { arena.get_unchecked(a); }
+}
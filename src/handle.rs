@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+use crate::{EntityRefBase, EntityList, EntityId};
+use crate::genarena::FREE;
+
+/// The generation of every slot of a list, shared with the handles of its entities, see
+/// `EntityHandle`.
+///
+/// This mirrors the generations of the arena. It is only created along with the first handle, and
+/// kept up to date by the list from then on.
+#[derive(Debug)]
+pub (crate) struct Liveness {
+    generations: RefCell<Vec<u64>>,
+}
+
+impl Liveness {
+    #[inline]
+    fn contains(&self, id: EntityId) -> bool {
+        self.generations.borrow().get(id.index) == Some(&id.generation)
+    }
+}
+
+/// An id along with a weak reference to the list of the entity, which tells whether the entity
+/// still exists without the list, see `EntityList::handle`.
+///
+/// Unlike an `EntityId`, a handle only ever refers to an entity of the list it was created by:
+/// getting the entity from another list, like a clone, returns `None`.
+///
+/// # Example
+///
+/// ```ignore
+/// let target = entities.handle(enemy_id).unwrap();
+/// let homing = HomingMissile { target };
+/// // later
+/// if !homing.target.is_alive() { }
+/// if let Some(enemy) = homing.target.get(&entities) { }
+/// ```
+#[derive(Clone)]
+pub struct EntityHandle {
+    id: EntityId,
+    liveness: Weak<Liveness>,
+}
+
+impl EntityHandle {
+    /// Returns the id of the entity, whether it still exists or not.
+    #[inline]
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    /// Returns true if the entity still exists. Returns false once the list is dropped.
+    ///
+    /// Soft removed entities do not exist until they are restored, see `EntityList::remove_soft`.
+    #[inline]
+    pub fn is_alive(&self) -> bool {
+        self.liveness.upgrade().is_some_and(|liveness| liveness.contains(self.id))
+    }
+
+    /// Returns true if the handle was created by this list.
+    #[inline]
+    pub fn belongs_to<E: EntityRefBase>(&self, list: &EntityList<E>) -> bool {
+        list.liveness.get().is_some_and(|liveness| Weak::as_ptr(&self.liveness) == Rc::as_ptr(liveness))
+    }
+
+    /// Returns the entity, if it still exists, and the handle was created by this list.
+    #[inline]
+    pub fn get<'a, E: EntityRefBase>(&self, list: &'a EntityList<E>) -> Option<&'a E> {
+        if !self.belongs_to(list) {
+            return None;
+        }
+        list.get(self.id)
+    }
+
+    /// Same as `get`, but mutably.
+    #[inline]
+    pub fn get_mut<'a, E: EntityRefBase>(&self, list: &'a mut EntityList<E>) -> Option<&'a mut E> {
+        if !self.belongs_to(list) {
+            return None;
+        }
+        list.get_mut(self.id)
+    }
+}
+
+impl PartialEq for EntityHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.liveness.ptr_eq(&other.liveness)
+    }
+}
+
+impl Eq for EntityHandle {}
+
+impl std::fmt::Debug for EntityHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EntityHandle")
+            .field("id", &self.id)
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Returns a handle to the entity, which tells whether it still exists without the list, see
+    /// `EntityHandle`. Returns `None` if the entity does not exist.
+    ///
+    /// Once a handle was created, the list keeps a copy of the generations of its slots up to
+    /// date, which costs a little on every insertion and removal.
+    pub fn handle(&self, id: EntityId) -> Option<EntityHandle> {
+        if !self.contains(id) {
+            return None;
+        }
+        let liveness = self.liveness.get_or_init(|| Rc::new(Liveness {
+            generations: RefCell::new(self.entities.generations.clone()),
+        }));
+        Some(EntityHandle { id, liveness: Rc::downgrade(liveness) })
+    }
+
+    /// Update the generation of the slot `index` for the handles, after an insertion or a removal.
+    #[inline]
+    pub (crate) fn sync_liveness(&self, index: usize) {
+        if let Some(liveness) = self.liveness.get() {
+            let mut generations = liveness.generations.borrow_mut();
+            if generations.len() < self.entities.generations.len() {
+                generations.resize(self.entities.generations.len(), FREE);
+            }
+            generations[index] = self.entities.generations[index];
+        }
+    }
+
+    /// Same as `sync_liveness`, for every slot.
+    pub (crate) fn sync_all_liveness(&self) {
+        if let Some(liveness) = self.liveness.get() {
+            liveness.generations.borrow_mut().clone_from(&self.entities.generations);
+        }
+    }
+}
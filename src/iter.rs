@@ -1,58 +1,402 @@
 use crate::{
-    Component, RefComponent, EntityBase, EntityRefBase, EntityOwnedBase, EntityList, EntityId,
-    genarena::{GenArena}
+    Component, RefComponent, ComponentCell, ComponentsStorage, Loan, EntityBase, EntityRefBase, EntityList, EntityId, ComponentBitSets, ChangeTicks, Tick, Enabled,
+    genarena::{GenArena, EntriesMut},
+    change_ticks::ChangeRecorder,
 };
 use slab::Slab;
-use hibitset::{BitIter, BitSet, BitSetLike, BitSetAll, BitSetAnd};
+use hashbrown::HashMap;
+use hibitset::{BitIter, BitSet, BitSetLike, BitSetAnd, BitSetNot, BitSetOr};
 use tuple_utils::Split;
 
-use std::any::TypeId;
+use std::hash::Hash;
+use std::cell::{Cell, UnsafeCell};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
 
-use hashbrown::HashMap;
+#[cfg(feature = "profiling")]
+use crate::profiling::QueryProfile;
 
 impl<E: EntityRefBase> EntityList<E> {
     /// Iterate over all entities
-    pub fn iter_all<'a>(&'a self) -> impl Iterator<Item=(EntityId, &'a E)> + Clone {
+    pub fn iter_all(&self) -> impl Iterator<Item=(EntityId, &E)> + Clone {
         self.entities.iter()
     }
 
     /// Iterate over all entities mutably
-    pub fn iter_all_mut<'a>(&'a mut self) -> impl Iterator<Item=(EntityId, &'a mut E)> {
-        self.entities.iter_mut()
+    pub fn iter_all_mut(&mut self) -> impl Iterator<Item=(EntityId, &mut E)> {
+        self.lend_entities();
+        let mut changes = ChangeRecorder { ticks: &mut self.ticks, bitsets: &self.bitsets };
+        self.entities.iter_mut().inspect(move |(id, _)| changes.record(id.index))
+    }
+
+    /// Iterate over all entities, grouped by the key returned by `f`.
+    ///
+    /// Each item is a key with all the entities sharing it. Groups are ordered by key, and entities
+    /// within a group are in iteration order.
+    ///
+    /// # Example
+    ///
+    /// `for (team, members) in entities.iter_grouped_by(|e| e.team) { }`
+    pub fn iter_grouped_by<K: Ord, F: FnMut(&E) -> K>(&self, mut f: F) -> std::vec::IntoIter<(K, Vec<(EntityId, &E)>)> {
+        let mut keyed: Vec<(K, EntityId, &E)> = self.entities.iter().map(|(id, e)| (f(e), id, e)).collect();
+        // stable sort, so that entities keep their order within a group
+        keyed.sort_by(|(k1, _, _), (k2, _, _)| k1.cmp(k2));
+        let mut groups: Vec<(K, Vec<(EntityId, &E)>)> = Vec::new();
+        for (k, id, e) in keyed {
+            match groups.last_mut() {
+                Some((last_k, group)) if *last_k == k => group.push((id, e)),
+                _ => groups.push((k, vec![(id, e)])),
+            }
+        }
+        groups.into_iter()
+    }
+
+    /// Iterate over all pairs of entities from this list and `other` which share the same key.
+    ///
+    /// `other` is indexed by key first, so this is `O(n + m)` instead of nested loops. Pairs are
+    /// yielded in the iteration order of this list, then of `other`.
+    ///
+    /// # Example
+    ///
+    /// `for ((actor_id, actor), (_, tile)) in actors.join_by(&tiles, |a| a.cell, |t| t.cell) { }`
+    pub fn join_by<'a, E2, K, F1, F2>(&'a self, other: &'a EntityList<E2>, mut key: F1, mut other_key: F2)
+        -> impl Iterator<Item=((EntityId, &'a E), (EntityId, &'a E2))>
+    where
+        E2: EntityRefBase,
+        K: Hash + Eq,
+        F1: FnMut(&E) -> K,
+        F2: FnMut(&E2) -> K,
+    {
+        let mut groups: HashMap<K, Vec<(EntityId, &'a E2)>> = HashMap::new();
+        for (id, e) in other.entities.iter() {
+            groups.entry(other_key(e)).or_default().push((id, e));
+        }
+        // store the groups contiguously, so that matches are looked up by range without copying them
+        let mut matches = Vec::with_capacity(other.len());
+        let index: HashMap<K, Range<usize>> = groups.into_iter()
+            .map(|(k, group)| {
+                let start = matches.len();
+                matches.extend(group);
+                (k, start..matches.len())
+            })
+            .collect();
+        let mut entities = self.entities.iter();
+        let mut current: Option<((EntityId, &'a E), Range<usize>)> = None;
+        std::iter::from_fn(move || loop {
+            if let Some((entity, range)) = &mut current {
+                if let Some(i) = range.next() {
+                    return Some((*entity, matches[i]));
+                }
+            }
+            let (id, e) = entities.next()?;
+            current = Some(((id, e), index.get(&key(e)).cloned().unwrap_or(0..0)));
+        })
     }
 
     /// Iterate over all entities which have the component `C`, immutably.
     ///
-    /// There is no mutable version of this, use iter::<(C,)>() if you need one
+    /// See `iter_single_mut` for the mutable version.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
     pub fn iter_single<'a, C: RefComponent<E>>(&'a self) -> SingleComponentIter<'a, E, C> {
         SingleComponentIter::new(self)
     }
 
+    /// Iterate over all entities which have the component `C`, mutably.
+    ///
+    /// The component is handed out alongside the entity: do not access `C` through the entity
+    /// itself while holding it, and do not add or remove components during the iteration.
+    ///
+    /// With the `debug-borrows` feature, accessing `C` through the entities panics while the
+    /// iterator is alive.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_single_mut<'a, C: RefComponent<E>>(&'a mut self) -> SingleComponentIterMut<'a, E, C> {
+        SingleComponentIterMut::new(self)
+    }
+
+    /// Iterate over the component `C` of every entity which has it, along with the id of the entity.
+    ///
+    /// Entities are only read for the key of their component in the storage.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, health) in entities.iter_component::<Health>() { }`
+    pub fn iter_component<'a, C: RefComponent<E>>(&'a self) -> ComponentIter<'a, E, C> {
+        ComponentIter::new(self)
+    }
+
+    /// Same as `iter_component`, but the components are returned mutably.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_component_mut<'a, C: RefComponent<E>>(&'a mut self) -> ComponentIterMut<'a, E, C> {
+        ComponentIterMut::new(self)
+    }
+
     /// Iterate over all entities which have the components (C1, C2, C3, ...)
     /// 
     /// Even if you want only one component, it must be a tuple.
+    ///
+    /// Deferred refreshes are not flushed: the entities queued since the last flush are matched by the
+    /// components they had then, see `set_deferred_refresh`.
     /// 
     /// # Example
     /// 
     /// `for (id, entity) in entities.iter::<(Speed,)>() { }`
-    pub fn iter<'a, C: MultiComponent<'a, E>>(&'a self) -> MultiComponentIter<'a, E, C::BitSet> {
-        C::iter(&self.bitsets, &self.entities)
+    pub fn iter<'a, C: MultiComponent<'a, E>>(&'a self) -> MultiComponentIter<'a, E, Enabled<'a, C::BitSet>> {
+        #[allow(unused_mut)]
+        let mut iter = C::iter(&self.bitsets, &self.entities);
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(QueryProfile::new(&self.query_stats, std::any::type_name::<C>()));
+        }
+        iter
     }
 
     /// Iterate over all entities which have the components (C1, C2, C3, ...), mutably
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
     /// 
     /// # Example
     /// 
     /// `for (id, entity) in entities.iter_mut::<(Speed, Gravity)>() { }`
-    pub fn iter_mut<'a, C: MultiComponent<'a, E>>(&'a mut self) -> MultiComponentIterMut<'a, E, C::BitSet> {
-        C::iter_mut(&self.bitsets, &mut self.entities)
+    pub fn iter_mut<'a, C: MultiComponent<'a, E>>(&'a mut self) -> MultiComponentIterMut<'a, E, Enabled<'a, C::BitSet>> {
+        self.flush_refresh();
+        self.lend_entities();
+        #[allow(unused_mut)]
+        let mut iter = C::iter_mut(&self.bitsets, &mut self.entities);
+        iter.changes = Some(ChangeRecorder { ticks: &mut self.ticks, bitsets: &self.bitsets });
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(QueryProfile::new(&self.query_stats, std::any::type_name::<C>()));
+        }
+        iter
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), and for which `predicate`
+    /// returns true. The predicate only runs on the entities matched by the bitsets.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, entity) in entities.iter_filtered::<(Speed,), _>(|e| e.age.age > 10) { }`
+    pub fn iter_filtered<'a, C, F>(&'a self, mut predicate: F) -> impl Iterator<Item=(EntityId, &'a E)>
+    where
+        C: MultiComponent<'a, E>,
+        F: FnMut(&E) -> bool,
+    {
+        self.iter::<C>().filter(move |(_, e)| predicate(e))
+    }
+
+    /// Same as `iter_filtered`, but mutably.
+    pub fn iter_filtered_mut<'a, C, F>(&'a mut self, mut predicate: F) -> impl Iterator<Item=(EntityId, &'a mut E)>
+    where
+        C: MultiComponent<'a, E>,
+        F: FnMut(&E) -> bool,
+    {
+        self.iter_mut::<C>().filter(move |(_, e)| predicate(e))
+    }
+
+    /// Clear `ids`, and fill it with the ids of all entities which have the components (C1, C2, C3, ...).
+    ///
+    /// Re-using the same buffer every frame avoids allocating a new `Vec` for the common
+    /// "gather the ids, then do structural changes" pattern.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `entities.collect_ids_into::<(Health,)>(&mut buffer);`
+    pub fn collect_ids_into<'a, C: MultiComponent<'a, E>>(&'a self, ids: &mut Vec<EntityId>) {
+        ids.clear();
+        ids.extend(self.iter_ids::<C>());
+    }
+
+    /// Iterate over the ids of all entities which have the components (C1, C2, C3, ...).
+    ///
+    /// Only the generations are read from the arena, the entities themselves are not accessed.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `let dead: Vec<EntityId> = entities.iter_ids::<(Dead,)>().collect();`
+    pub fn iter_ids<'a, C: MultiComponent<'a, E>>(&'a self) -> IdsIter<'a, E, Enabled<'a, C::BitSet>> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        IdsIter {
+            iter: CountedBitIter::new(self.bitsets.enabled(C::bitset(&self.bitsets)).iter()),
+            values: &self.entities,
+        }
+    }
+
+    /// Returns the number of entities which have the components (C1, C2, C3, ...).
+    ///
+    /// Only the bitsets are combined and their bits counted, the arena is not accessed.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `let moving = entities.count::<(Speed, Position)>();`
+    pub fn count<'a, C: MultiComponent<'a, E>>(&'a self) -> usize {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        self.bitsets.enabled(C::bitset(&self.bitsets)).iter().count()
+    }
+
+    /// Returns the first entity which has the components (C1, C2, C3, ...), in iteration order.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `let (id, camera) = entities.first::<(Camera,)>().unwrap();`
+    pub fn first<'a, C: MultiComponent<'a, E>>(&'a self) -> Option<(EntityId, &'a E)> {
+        self.iter::<C>().next()
+    }
+
+    /// Returns the only entity which has the components (C1, C2, C3, ...), or an error if none or
+    /// several entities have them, see `SingleError`.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `let (id, player) = entities.single::<(Player,)>()?;`
+    pub fn single<'a, C: MultiComponent<'a, E>>(&'a self) -> Result<(EntityId, &'a E), SingleError> {
+        let mut iter = self.iter::<C>();
+        match (iter.next(), iter.next()) {
+            (Some(found), None) => Ok(found),
+            (None, _) => Err(SingleError::NoMatch),
+            (Some(_), Some(_)) => Err(SingleError::MultipleMatches),
+        }
+    }
+
+    /// Same as `single`, but mutably.
+    pub fn single_mut<'a, C: MultiComponent<'a, E>>(&'a mut self) -> Result<(EntityId, &'a mut E), SingleError> {
+        let mut iter = self.iter_mut::<C>();
+        match (iter.next(), iter.next()) {
+            (Some(found), None) => Ok(found),
+            (None, _) => Err(SingleError::NoMatch),
+            (Some(_), Some(_)) => Err(SingleError::MultipleMatches),
+        }
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), in batches of `chunk_size`
+    /// entities. The last batch may be shorter.
+    ///
+    /// Panics if `chunk_size` is 0.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for chunk in entities.iter_chunks::<(Speed, Gravity)>(256) { }`
+    pub fn iter_chunks<'a, C: MultiComponent<'a, E>>(&'a self, chunk_size: usize) -> Chunks<MultiComponentIter<'a, E, Enabled<'a, C::BitSet>>> {
+        Chunks::new(self.iter::<C>(), chunk_size)
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), mutably, in batches of
+    /// `chunk_size` entities. The last batch may be shorter.
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn iter_chunks_mut<'a, C: MultiComponent<'a, E>>(&'a mut self, chunk_size: usize) -> Chunks<MultiComponentIterMut<'a, E, Enabled<'a, C::BitSet>>> {
+        Chunks::new(self.iter_mut::<C>(), chunk_size)
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), and return the components,
+    /// fetched directly from the component storage.
+    ///
+    /// A component may be wrapped in an `Option` to fetch it when present, without filtering on it.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, speed, friction) in entities.iter_components::<(Speed, Option<Friction>)>() { }`
+    pub fn iter_components<'a, C: MultiComponentFetch<'a, E> + ReadOnlyMultiFetch<E>>(&'a self) -> ComponentsIter<'a, E, C> {
+        ComponentsIter::new(self)
+    }
+
+    /// Same as `iter_components`, but ordered by `compare`. The sort is stable, so entities which are
+    /// equal keep their iteration order.
+    ///
+    /// Like `iter`, this does not flush deferred refreshes.
+    ///
+    /// # Example
+    ///
+    /// `for (id, sprite) in entities.iter_sorted_by::<(Sprite,)>(|(_, a), (_, b)| a.z.cmp(&b.z)) { }`
+    pub fn iter_sorted_by<'a, C, F>(&'a self, compare: F) -> std::vec::IntoIter<C::Item>
+    where
+        C: MultiComponentFetch<'a, E> + ReadOnlyMultiFetch<E>,
+        F: FnMut(&C::Item, &C::Item) -> std::cmp::Ordering,
+    {
+        let mut items: Vec<C::Item> = self.iter_components::<C>().collect();
+        items.sort_by(compare);
+        items.into_iter()
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), and return the components
+    /// mutably, fetched directly from the component storage.
+    ///
+    /// A component may be wrapped in an `Option` to fetch it when present, without filtering on it.
+    /// A component may be requested as `&C` to only fetch it immutably. The same component type cannot
+    /// be requested twice, unless every request is `&C`: such queries fail to compile.
+    ///
+    /// Since the list is borrowed mutably, no other query can run at the same time.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    ///
+    /// # Example
+    ///
+    /// `for (id, speed, gravity) in entities.iter_components_mut::<(Speed, &Gravity)>() { }`
+    ///
+    /// Fetching a component mutably twice does not compile:
+    ///
+    /// ```compile_fail
+    /// # use smec::{define_entity, EntityList};
+    /// # pub struct Speed(f32);
+    /// # define_entity! {
+    /// #     pub struct Entity {
+    /// #         props => {},
+    /// #         components => { speed => Speed }
+    /// #     }
+    /// # }
+    /// let mut entities: EntityList<EntityRef> = EntityList::new();
+    /// for (id, speed, same_speed) in entities.iter_components_mut::<(Speed, &Speed)>() { }
+    /// ```
+    pub fn iter_components_mut<'a, C: MultiComponentFetch<'a, E>>(&'a mut self) -> ComponentsIterMut<'a, E, C> {
+        ComponentsIterMut::new(self)
     }
 }
 
+/// Error returned by `EntityList::single` when the query does not match exactly one entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SingleError {
+    /// No entity matches the query
+    NoMatch,
+    /// Several entities match the query
+    MultipleMatches,
+}
+
+impl std::fmt::Display for SingleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SingleError::NoMatch => write!(f, "no entity matches the query"),
+            SingleError::MultipleMatches => write!(f, "several entities match the query"),
+        }
+    }
+}
+
+impl std::error::Error for SingleError {}
+
 pub struct SingleComponentIter<'a, E: EntityRefBase, C: Component<E>> {
-    pub (crate) iter: BitIter<&'a BitSet>,
+    pub (crate) iter: CountedBitIter<Enabled<'a, &'a BitSet>>,
     pub (crate) values: &'a GenArena<E>,
-    pub (crate) slab_ref: &'a Slab<C>,
+    pub (crate) slab_ref: &'a Slab<ComponentCell<C>>,
 }
 
 impl<'a, E: EntityRefBase, C: Component<E>> Clone for SingleComponentIter<'a, E, C> {
@@ -67,62 +411,260 @@ impl<'a, E: EntityRefBase, C: Component<E>> Clone for SingleComponentIter<'a, E,
 
 impl<'a, E: EntityRefBase, C: RefComponent<E>> SingleComponentIter<'a, E, C> {
     pub fn new(list: &'a EntityList<E>) -> SingleComponentIter<'a, E, C> {
-        let bitset = list.bitsets.get(&TypeId::of::<C>()).expect("FATAL: bitset is non-existant for composant");
-        let cs_ref: &E::CS = unsafe { &*list.components_storage.get() };
-        let slab_ref: &Slab<C> = C::get_single_cs(cs_ref);
+        Self::from_parts(&list.bitsets, &list.entities, &list.components_storage)
+    }
+
+    pub (crate) fn from_parts(bitsets: &'a ComponentBitSets, entities: &'a GenArena<E>, cs: &'a UnsafeCell<E::CS>) -> Self {
+        let bitset = bitsets.enabled(bitsets.get_at(C::POSITION));
+        // SAFETY: the storage belongs to the same list as the bitsets, which is borrowed immutably for 'a
+        let cs_ref: &E::CS = unsafe { &*cs.get() };
+        let slab_ref: &Slab<ComponentCell<C>> = C::get_single_cs(cs_ref);
         SingleComponentIter {
-            iter: bitset.iter(),
-            values: &list.entities,
+            iter: CountedBitIter::new(bitset.iter()),
+            values: entities,
             slab_ref,
         }
     }
 }
 
-impl<'a, E: EntityBase, B: BitSetLike> Iterator for MultiComponentIter<'a, E, B> {
-    type Item = (EntityId, &'a E);
+pub struct SingleComponentIterMut<'a, E: EntityRefBase, C: Component<E>> {
+    pub (crate) iter: CountedBitIter<Enabled<'a, &'a BitSet>>,
+    pub (crate) values: EntriesMut<'a, E>,
+    pub (crate) slab: *mut Slab<ComponentCell<C>>,
+    /// `C` is handed out alongside the entities, see `BorrowTracker`
+    pub (crate) _loan: Loan,
+    pub (crate) changes: ChangeRecorder<'a>,
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> SingleComponentIterMut<'a, E, C> {
+    pub fn new(list: &'a mut EntityList<E>) -> SingleComponentIterMut<'a, E, C> {
+        list.flush_refresh();
+        list.lend_entities();
+        let bitset = list.bitsets.enabled(list.bitsets.get_at(C::POSITION));
+        let cs = list.components_storage.get();
+        // SAFETY: the storage is owned by the list, which we borrow mutably for 'a
+        let (slab, loan) = unsafe {
+            // every component is handed out along with its entity
+            C::get_borrower(cs).borrow_all((*cs).borrow_tracker());
+            (C::get_single_cs_ptr(cs), (*cs).borrow_tracker().lend::<E, C>(true))
+        };
+        SingleComponentIterMut {
+            iter: CountedBitIter::new(bitset.iter()),
+            values: list.entities.entries_mut(),
+            slab,
+            _loan: loan,
+            changes: ChangeRecorder { ticks: &mut list.ticks, bitsets: &list.bitsets },
+        }
+    }
+}
+
+pub struct ComponentIter<'a, E: EntityRefBase, C: Component<E>> {
+    pub (crate) iter: CountedBitIter<Enabled<'a, &'a BitSet>>,
+    pub (crate) values: &'a GenArena<E>,
+    pub (crate) slab_ref: &'a Slab<ComponentCell<C>>,
+}
+
+impl<'a, E: EntityRefBase, C: Component<E>> Clone for ComponentIter<'a, E, C> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+            values: self.values,
+            slab_ref: self.slab_ref,
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentIter<'a, E, C> {
+    pub fn new(list: &'a EntityList<E>) -> ComponentIter<'a, E, C> {
+        Self::from_parts(&list.bitsets, &list.entities, &list.components_storage)
+    }
+
+    pub (crate) fn from_parts(bitsets: &'a ComponentBitSets, entities: &'a GenArena<E>, cs: &'a UnsafeCell<E::CS>) -> Self {
+        let bitset = bitsets.enabled(bitsets.get_at(C::POSITION));
+        // SAFETY: the storage belongs to the same list as the bitsets, which is borrowed immutably for 'a
+        let cs_ref: &E::CS = unsafe { &*cs.get() };
+        ComponentIter {
+            iter: CountedBitIter::new(bitset.iter()),
+            values: entities,
+            slab_ref: C::get_single_cs(cs_ref),
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> Iterator for ComponentIter<'a, E, C> {
+    type Item = (EntityId, &'a C);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.iter.next().map(|index| {
+            let (v, g) = self.values.get_raw(index as usize).expect(FATAL_ERR_BITSET);
+            let cs_id = C::get_cs_id(v).expect(FATAL_ERR_BITSET);
+            // SAFETY: the list is borrowed immutably for 'a, so no component is borrowed mutably
+            let c = unsafe { self.slab_ref.get(cs_id).expect(FATAL_ERR_CS).get() };
+            (EntityId::new(index as usize, g), c)
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub struct ComponentIterMut<'a, E: EntityRefBase, C: Component<E>> {
+    pub (crate) iter: CountedBitIter<Enabled<'a, &'a BitSet>>,
+    pub (crate) values: &'a GenArena<E>,
+    pub (crate) slab: *mut Slab<ComponentCell<C>>,
+    pub (crate) ticks: &'a mut ChangeTicks,
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentIterMut<'a, E, C> {
+    pub fn new(list: &'a mut EntityList<E>) -> ComponentIterMut<'a, E, C> {
+        list.flush_refresh();
+        list.lend_entities();
+        let bitset = list.bitsets.enabled(list.bitsets.get_at(C::POSITION));
+        // SAFETY: the storage is owned by the list, which we borrow mutably for 'a
+        let slab = unsafe { C::get_single_cs_ptr(list.components_storage.get()) };
+        ComponentIterMut {
+            iter: CountedBitIter::new(bitset.iter()),
+            values: &list.entities,
+            slab,
+            ticks: &mut list.ticks,
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> Iterator for ComponentIterMut<'a, E, C> {
+    type Item = (EntityId, &'a mut C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|index| {
+            let (v, g) = self.values.get_raw(index as usize).expect(FATAL_ERR_BITSET);
+            let cs_id = C::get_cs_id(v).expect(FATAL_ERR_BITSET);
+            // SAFETY: the bitset iterator never yields the same index twice, and no two entities share
+            // a component, so every component is only handed out once.
+            let c = unsafe { (*self.slab).get(cs_id).expect(FATAL_ERR_CS).get_mut() };
+            self.ticks.set_changed(C::POSITION, index as usize);
+            (EntityId::new(index as usize, g), c)
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> Iterator for MultiComponentIter<'a, E, B> {
+    type Item = (EntityId, &'a E);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
+        let item = self.iter.next().map(|index| {
             self.values.get_raw(index as usize)
                 .map(|(v, g)| (EntityId::new(index as usize, g), v))
                 .expect(FATAL_ERR_BITSET)
-        })
+        });
+        #[cfg(feature = "profiling")]
+        if let Some(profile) = &self.profile {
+            profile.record_next(start, item.is_some());
+        }
+        item
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
     }
 }
 
 pub struct MultiComponentIter<'a, E: EntityBase, B: BitSetLike> {
-    pub (crate) iter: BitIter<B>,
+    pub (crate) iter: CountedBitIter<B>,
     pub (crate) values: &'a GenArena<E>,
+    #[cfg(feature = "profiling")]
+    pub (crate) profile: Option<QueryProfile>,
+}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> Clone for MultiComponentIter<'a, E, B> {
+    fn clone(&self) -> Self {
+        MultiComponentIter {
+            iter: self.iter.clone(),
+            values: self.values,
+            #[cfg(feature = "profiling")]
+            profile: self.profile.clone(),
+        }
+    }
 }
 
 impl<'a, E: EntityBase, B: BitSetLike> MultiComponentIter<'a, E, B> {
     pub fn new(iter: BitIter<B>, values: &'a GenArena<E>) -> Self {
         MultiComponentIter {
-            iter,
+            iter: CountedBitIter::new(iter),
             values,
+            #[cfg(feature = "profiling")]
+            profile: None,
         }
     }
 }
 
+/// Iterator over the ids of a query, see `EntityList::iter_ids`.
+pub struct IdsIter<'a, E: EntityBase, B: BitSetLike> {
+    pub (crate) iter: CountedBitIter<B>,
+    pub (crate) values: &'a GenArena<E>,
+}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> Clone for IdsIter<'a, E, B> {
+    fn clone(&self) -> Self {
+        IdsIter {
+            iter: self.iter.clone(),
+            values: self.values,
+        }
+    }
+}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> Iterator for IdsIter<'a, E, B> {
+    type Item = EntityId;
+
+    fn next(&mut self) -> Option<EntityId> {
+        self.iter.next().map(|index| {
+            let generation = self.values.generation_at(index as usize).expect(FATAL_ERR_BITSET);
+            EntityId::new(index as usize, generation)
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
 pub struct MultiComponentIterMut<'a, E: EntityBase, B: BitSetLike> {
-    pub (crate) iter: BitIter<B>,
-    pub (crate) values: &'a mut GenArena<E>,
+    pub (crate) iter: CountedBitIter<B>,
+    pub (crate) values: EntriesMut<'a, E>,
+    /// Set when created from a list, so that yielded entities are recorded as changed
+    pub (crate) changes: Option<ChangeRecorder<'a>>,
     #[cfg(debug_assertions)]
     pub (crate) n: Option<usize>,
+    #[cfg(feature = "profiling")]
+    pub (crate) profile: Option<QueryProfile>,
 }
 
 impl<'a, E: EntityBase, B: BitSetLike> MultiComponentIterMut<'a, E, B> {
     pub fn new(iter: BitIter<B>, values: &'a mut GenArena<E>) -> Self {
         MultiComponentIterMut {
-            iter,
-            values,
+            iter: CountedBitIter::new(iter),
+            values: values.entries_mut(),
+            changes: None,
             #[cfg(debug_assertions)]
             n: None,
+            #[cfg(feature = "profiling")]
+            profile: None,
         }
     }
 }
 
-const FATAL_ERR_BITSET: &str = r##"
+#[doc(hidden)]
+pub const FATAL_ERR_BITSET: &str = r##"
     !!!!FATAL: bitset is out of date, bitset returned true for an entity, but no entity exists at this location!!!! \
     Check that your code adds components and entities via the legal methods!"
 "##;
@@ -137,29 +679,60 @@ impl<'a, E: EntityRefBase, C: RefComponent<E>> Iterator for SingleComponentIter<
                 .map(|(v, g)| (
                     EntityId::new(index as usize, g),
                     v,
-                    self.slab_ref.get(C::get_cs_id(v).expect(FATAL_ERR_BITSET))
-                    .expect(FATAL_ERR_CS)
+                    // SAFETY: the list is borrowed immutably for 'a, so no component is borrowed mutably
+                    unsafe {
+                        self.slab_ref.get(C::get_cs_id(v).expect(FATAL_ERR_BITSET))
+                        .expect(FATAL_ERR_CS)
+                        .get()
+                    }
                 ))
                 .expect(FATAL_ERR_BITSET)
         })
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
 }
 
-impl<'a, E: EntityBase, B: BitSetLike> Iterator for MultiComponentIterMut<'a, E, B> {
-    type Item = (EntityId, &'a mut E);
+impl<'a, E: EntityRefBase, C: RefComponent<E>> Iterator for SingleComponentIterMut<'a, E, C> {
+    type Item = (EntityId, &'a mut E, &'a mut C);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.iter.next().map(|index| {
             let index = index as usize;
-            let (id, v) = self.values.get_raw_mut(index)
-                .map(|(v, g)| (EntityId::new(index, g), v))
-                .expect(FATAL_ERR_BITSET);
-        
+            self.changes.record(index);
+            // SAFETY: the bitset iterator never yields the same index twice, so every entity and every
+            // component is only handed out once.
+            unsafe {
+                let (v, g) = self.values.get_raw_mut(index).expect(FATAL_ERR_BITSET);
+                let cs_id = C::get_cs_id(v).expect(FATAL_ERR_BITSET);
+                let c = (*self.slab).get(cs_id).expect(FATAL_ERR_CS).get_mut();
+                (EntityId::new(index, g), v, c)
+            }
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> Iterator for MultiComponentIterMut<'a, E, B> {
+    type Item = (EntityId, &'a mut E);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
+        let item = self.iter.next().map(|index| {
+            let index = index as usize;
             #[cfg(debug_assertions)] {
                 // check that n is strictly monotonic increasing,
                 // meaning that the same value will never be indexed twice,
-                // THEREFORE we can safely allow the unsafe code below, that unlinks
-                // the lifetime of the source with the lifetime of the Iterator::Item
+                // THEREFORE we can safely allow the unsafe code below, that hands out
+                // references living as long as the source, and not as long as the Iterator.
                 // we still cannot make the items of the iterator outlive the source,
                 // nor can we mutate the source object, but at least we can call .next() safely.
                 if let Some(old_n) = self.n {
@@ -167,63 +740,358 @@ impl<'a, E: EntityBase, B: BitSetLike> Iterator for MultiComponentIterMut<'a, E,
                 }
                 self.n = Some(index);
             }
-            
-            #[allow(unsafe_code)]
-            (id, unsafe { &mut *(v as *mut _) }) 
+
+            if let Some(changes) = &mut self.changes {
+                changes.record(index);
+            }
+            // SAFETY: the bitset iterator never yields the same index twice (checked above in debug),
+            // so every entity is only handed out once.
+            unsafe { self.values.get_raw_mut(index) }
+                .map(|(v, g)| (EntityId::new(index, g), v))
+                .expect(FATAL_ERR_BITSET)
+        });
+        #[cfg(feature = "profiling")]
+        if let Some(profile) = &self.profile {
+            profile.record_next(start, item.is_some());
+        }
+        item
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub struct ComponentsIter<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> {
+    pub (crate) iter: CountedBitIter<Enabled<'a, C::BitSet>>,
+    pub (crate) values: &'a GenArena<E>,
+    pub (crate) slabs: C::Slabs,
+}
+
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> ComponentsIter<'a, E, C> {
+    pub fn new(list: &'a EntityList<E>) -> Self {
+        Self::from_parts(&list.bitsets, &list.entities, &list.components_storage)
+    }
+
+    pub (crate) fn from_parts(bitsets: &'a ComponentBitSets, entities: &'a GenArena<E>, cs: &'a UnsafeCell<E::CS>) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        ComponentsIter {
+            iter: CountedBitIter::new(bitsets.enabled(C::bitset(bitsets)).iter()),
+            values: entities,
+            // SAFETY: the storage is owned by the list, which we borrow for 'a
+            slabs: unsafe { C::slabs(cs.get()) },
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> Iterator for ComponentsIter<'a, E, C> {
+    type Item = C::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|index| {
+            let (id, v) = self.values.get_raw(index as usize)
+                .map(|(v, g)| (EntityId::new(index as usize, g), v))
+                .expect(FATAL_ERR_BITSET);
+            // SAFETY: the list is borrowed immutably for 'a, so nothing can mutate the storage
+            unsafe { C::fetch(self.slabs, id, v) }
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+pub struct ComponentsIterMut<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> {
+    pub (crate) iter: CountedBitIter<Enabled<'a, C::BitSet>>,
+    pub (crate) values: &'a GenArena<E>,
+    pub (crate) slabs: C::Slabs,
+    pub (crate) changes: ChangeRecorder<'a>,
+}
+
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> ComponentsIterMut<'a, E, C> {
+    pub fn new(list: &'a mut EntityList<E>) -> Self {
+        // evaluated at compile time, for every query type
+        #[allow(clippy::let_unit_value)]
+        let () = C::DISJOINT;
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        list.flush_refresh();
+        list.lend_entities();
+        let cs: *mut E::CS = list.components_storage.get();
+        ComponentsIterMut {
+            iter: CountedBitIter::new(list.bitsets.enabled(C::bitset(&list.bitsets)).iter()),
+            values: &list.entities,
+            // SAFETY: the storage is owned by the list, which we borrow for 'a
+            slabs: unsafe { C::slabs(cs) },
+            changes: ChangeRecorder { ticks: &mut list.ticks, bitsets: &list.bitsets },
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> Iterator for ComponentsIterMut<'a, E, C> {
+    type Item = C::ItemMut;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|index| {
+            let (id, v) = self.values.get_raw(index as usize)
+                .map(|(v, g)| (EntityId::new(index as usize, g), v))
+                .expect(FATAL_ERR_BITSET);
+            C::record_changes(self.changes.ticks, self.changes.bitsets, index as usize);
+            // SAFETY: the bitset iterator never yields the same index twice, and every slab
+            // is distinct (checked by `DISJOINT`), so no component is borrowed mutably twice.
+            unsafe { C::fetch_mut(self.slabs, id, v) }
         })
     }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
 }
 
+impl<'a, E: EntityBase, B: BitSetLike + Clone> ExactSizeIterator for MultiComponentIter<'a, E, B> {}
+impl<'a, E: EntityBase, B: BitSetLike + Clone> ExactSizeIterator for MultiComponentIterMut<'a, E, B> {}
+impl<'a, E: EntityBase, B: BitSetLike + Clone> ExactSizeIterator for IdsIter<'a, E, B> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ExactSizeIterator for SingleComponentIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ExactSizeIterator for SingleComponentIterMut<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ExactSizeIterator for ComponentIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ExactSizeIterator for ComponentIterMut<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> ExactSizeIterator for ComponentsIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> ExactSizeIterator for ComponentsIterMut<'a, E, C> {}
+
+impl<'a, E: EntityBase, B: BitSetLike + Clone> FusedIterator for MultiComponentIter<'a, E, B> {}
+impl<'a, E: EntityBase, B: BitSetLike + Clone> FusedIterator for IdsIter<'a, E, B> {}
+impl<'a, E: EntityBase, B: BitSetLike + Clone> FusedIterator for MultiComponentIterMut<'a, E, B> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> FusedIterator for SingleComponentIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> FusedIterator for SingleComponentIterMut<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> FusedIterator for ComponentIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: RefComponent<E>> FusedIterator for ComponentIterMut<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> FusedIterator for ComponentsIter<'a, E, C> {}
+impl<'a, E: EntityRefBase, C: MultiComponentFetch<'a, E>> FusedIterator for ComponentsIterMut<'a, E, C> {}
+
+/// Iterator adapter grouping the items of a query in `Vec`s of at most `chunk_size` items.
+#[derive(Debug, Clone)]
+pub struct Chunks<I: Iterator> {
+    pub (crate) iter: I,
+    pub (crate) chunk_size: usize,
+}
+
+impl<I: Iterator> Chunks<I> {
+    pub fn new(iter: I, chunk_size: usize) -> Self {
+        assert!(chunk_size != 0, "chunk size must be non-zero");
+        Chunks {
+            iter,
+            chunk_size,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Chunks<I> {
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk: Vec<I::Item> = self.iter.by_ref().take(self.chunk_size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (lower.div_ceil(self.chunk_size), upper.map(|u| u.div_ceil(self.chunk_size)))
+    }
+}
+
+impl<I: FusedIterator> FusedIterator for Chunks<I> {}
+
+/// A `BitIter` which knows how many indexes it has left.
+///
+/// The count is only computed the first time it is asked for (typically by `collect`), by
+/// walking a copy of the bitset, which does not touch the entities themselves.
+#[derive(Debug, Clone)]
+pub struct CountedBitIter<B: BitSetLike> {
+    iter: BitIter<B>,
+    remaining: Cell<Option<usize>>,
+}
+
+impl<B: BitSetLike> CountedBitIter<B> {
+    pub fn new(iter: BitIter<B>) -> Self {
+        CountedBitIter {
+            iter,
+            remaining: Cell::new(None),
+        }
+    }
+}
+
+impl<B: BitSetLike + Clone> Iterator for CountedBitIter<B> {
+    type Item = u32;
+
+    #[inline]
+    fn next(&mut self) -> Option<u32> {
+        let next = self.iter.next();
+        if next.is_some() {
+            if let Some(remaining) = self.remaining.get_mut() {
+                *remaining -= 1;
+            }
+        }
+        next
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match self.remaining.get() {
+            Some(remaining) => remaining,
+            None => {
+                let remaining = self.iter.clone().count();
+                self.remaining.set(Some(remaining));
+                remaining
+            }
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl<B: BitSetLike + Clone> ExactSizeIterator for CountedBitIter<B> {}
+
+// `BitIter` keeps returning `None` once all its bits have been visited.
+impl<B: BitSetLike + Clone> FusedIterator for CountedBitIter<B> {}
+
 /// Trait used internally, implemented for every tuple of component.
 ///
 /// Do not implement externally.
 pub trait MultiComponent<'a, E: EntityBase> {
-    type BitSet: BitSetLike;
+    type BitSet: BitSetLike + Clone;
+
+    /// True if the query contains filters which need a tick to match, like `Changed<C>`.
+    const TICK_FILTERED: bool;
+
+    /// Fails to compile when evaluated if the query contains filters which need a tick, like
+    /// `Changed<C>`: those can only be used with `iter_since` and `iter_mut_since`.
+    const NO_TICK_FILTER: () = assert!(
+        !Self::TICK_FILTERED,
+        "`Added` and `Changed` can only be used with `iter_since` and `iter_mut_since`"
+    );
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet;
 
-    fn bitset(bitsets: &'a HashMap<TypeId, BitSet>) -> Self::BitSet;
+    /// Per-entity check for filters that cannot be expressed as bitsets, like `Changed<C>`.
+    fn matches(bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool;
 
-    fn iter(bitsets: &'a HashMap<TypeId, BitSet>, arena: &'a GenArena<E>) -> MultiComponentIter<'a, E, Self::BitSet> {
-        MultiComponentIter::new(Self::bitset(bitsets).iter(), arena)
+    /// Iterate over the enabled entities matching the query, see `EntityList::set_enabled`.
+    fn iter(bitsets: &'a ComponentBitSets, arena: &'a GenArena<E>) -> MultiComponentIter<'a, E, Enabled<'a, Self::BitSet>> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NO_TICK_FILTER;
+        MultiComponentIter::new(bitsets.enabled(Self::bitset(bitsets)).iter(), arena)
     }
 
-    fn iter_mut(bitsets: &'a HashMap<TypeId, BitSet>, arena: &'a mut GenArena<E>) -> MultiComponentIterMut<'a, E, Self::BitSet> {
-        MultiComponentIterMut::new(Self::bitset(bitsets).iter(), arena)
+    fn iter_mut(bitsets: &'a ComponentBitSets, arena: &'a mut GenArena<E>) -> MultiComponentIterMut<'a, E, Enabled<'a, Self::BitSet>> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NO_TICK_FILTER;
+        MultiComponentIterMut::new(bitsets.enabled(Self::bitset(bitsets)).iter(), arena)
     }
 }
 
 impl<'a, E: EntityBase> MultiComponent<'a, E> for () {
-    type BitSet = BitSetAll;
+    type BitSet = &'a BitSet;
+
+    const TICK_FILTERED: bool = false;
 
-    fn bitset(_bitsets: &'a HashMap<TypeId, BitSet>) -> Self::BitSet {
-        BitSetAll
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        bitsets.occupied()
+    }
+
+    fn matches(_bitsets: &'a ComponentBitSets, _ticks: &ChangeTicks, _index: usize, _since: Tick) -> bool {
+        true
     }
 }
 
-impl<'a, E: EntityBase, C: Component<E>> MultiComponent<'a, E> for (C,) {
-    type BitSet = &'a BitSet;
+impl<'a, E: EntityBase, C: QueryElement<'a, E>> MultiComponent<'a, E> for (C,) {
+    type BitSet = C::BitSet;
+
+    const TICK_FILTERED: bool = C::TICK_FILTERED;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        C::bitset(bitsets)
+    }
+
+    #[inline]
+    fn matches(bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+        C::matches(bitsets, ticks, index, since)
+    }
+}
+
+/// Trait used internally, implemented for every component by `define_entity!`, and for
+/// query filters like `Without<C>`.
+///
+/// Do not implement externally.
+pub trait QueryElement<'a, E: EntityBase> {
+    type BitSet: BitSetLike + Clone;
+
+    /// True for filters which need a tick to match, like `Changed<C>`, see `MultiComponent::NO_TICK_FILTER`.
+    const TICK_FILTERED: bool = false;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet;
+
+    #[inline]
+    /// Per-entity check for filters that cannot be expressed as bitsets, like `Changed<C>`.
+    fn matches(_bitsets: &'a ComponentBitSets, _ticks: &ChangeTicks, _index: usize, _since: Tick) -> bool {
+        true
+    }
+}
+
+#[doc(hidden)]
+/// Used by `define_entity!` to implement `QueryElement` for components.
+pub fn component_bitset<E, C: Component<E>>(bitsets: &ComponentBitSets) -> &BitSet {
+    bitsets.get_at(C::POSITION)
+}
+
+/// Query filter, matches entities which do NOT have the component `C`.
+///
+/// # Example
+///
+/// `for (id, entity) in entities.iter::<(Speed, Without<Frozen>)>() { }`
+pub struct Without<C>(PhantomData<C>);
 
-    fn bitset(bitsets: &'a HashMap<TypeId, BitSet>) -> Self::BitSet {
-        bitsets.get(&TypeId::of::<C>()).expect("FATAL: bitset is non-existant for composant")
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for Without<C> {
+    // restricted to the occupied slots, so that queries made only of filters skip free slots
+    type BitSet = BitSetAnd<&'a BitSet, BitSetNot<&'a BitSet>>;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        BitSetAnd(bitsets.occupied(), BitSetNot(component_bitset::<E, C>(bitsets)))
     }
 }
 
 macro_rules! multi_component_impl {
     // use variables to indicate the arity of the tuple
     ($($ty:ident),*) => {
-        impl<'a, E: EntityBase, $($ty: Component<E>),*> MultiComponent<'a, E> for ($($ty),*)
+        impl<'a, E: EntityBase, $($ty: QueryElement<'a, E>),*> MultiComponent<'a, E> for ($($ty),*)
         {
             type BitSet = BitSetAnd<
                 <<Self as Split>::Left as MultiComponent<'a, E>>::BitSet,
                 <<Self as Split>::Right as MultiComponent<'a, E>>::BitSet
             >;
 
-            fn bitset(bitsets: &'a HashMap<TypeId, BitSet>) -> Self::BitSet {
+            const TICK_FILTERED: bool = <<Self as Split>::Left as MultiComponent<'a, E>>::TICK_FILTERED
+                || <<Self as Split>::Right as MultiComponent<'a, E>>::TICK_FILTERED;
+
+            fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
                 let (l, r) = (
                     <<Self as Split>::Left as MultiComponent<'a, E>>::bitset(bitsets),
                     <<Self as Split>::Right as MultiComponent<'a, E>>::bitset(bitsets)
                 );
                 BitSetAnd(l, r)
             }
+
+            #[inline]
+            fn matches(bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+                <<Self as Split>::Left as MultiComponent<'a, E>>::matches(bitsets, ticks, index, since) &&
+                <<Self as Split>::Right as MultiComponent<'a, E>>::matches(bitsets, ticks, index, since)
+            }
         }
     }
 }
@@ -234,4 +1102,318 @@ multi_component_impl!(C1, C2, C3, C4);
 multi_component_impl!(C1, C2, C3, C4, C5);
 multi_component_impl!(C1, C2, C3, C4, C5, C6);
 multi_component_impl!(C1, C2, C3, C4, C5, C6, C7);
-multi_component_impl!(C1, C2, C3, C4, C5, C6, C7, C8);
\ No newline at end of file
+multi_component_impl!(C1, C2, C3, C4, C5, C6, C7, C8);
+
+/// Query filter, matches entities which have at least one of the components of the tuple `T`.
+///
+/// # Example
+///
+/// `for (id, entity) in entities.iter::<(Or<(Burning, Frozen)>,)>() { }`
+pub struct Or<T>(PhantomData<T>);
+
+impl<'a, E: EntityBase, C: QueryElement<'a, E>> QueryElement<'a, E> for Or<(C,)> {
+    type BitSet = C::BitSet;
+
+    const TICK_FILTERED: bool = C::TICK_FILTERED;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        C::bitset(bitsets)
+    }
+
+    #[inline]
+    fn matches(bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+        C::matches(bitsets, ticks, index, since)
+    }
+}
+
+macro_rules! or_component_impl {
+    ($($ty:ident),*) => {
+        impl<'a, E: EntityBase, $($ty: QueryElement<'a, E>),*> QueryElement<'a, E> for Or<($($ty),*)>
+        where
+            Or<<($($ty),*) as Split>::Left>: QueryElement<'a, E>,
+            Or<<($($ty),*) as Split>::Right>: QueryElement<'a, E>,
+        {
+            type BitSet = BitSetOr<
+                <Or<<($($ty),*) as Split>::Left> as QueryElement<'a, E>>::BitSet,
+                <Or<<($($ty),*) as Split>::Right> as QueryElement<'a, E>>::BitSet
+            >;
+
+            const TICK_FILTERED: bool = <Or<<($($ty),*) as Split>::Left> as QueryElement<'a, E>>::TICK_FILTERED
+                || <Or<<($($ty),*) as Split>::Right> as QueryElement<'a, E>>::TICK_FILTERED;
+
+            fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+                BitSetOr(
+                    <Or<<($($ty),*) as Split>::Left> as QueryElement<'a, E>>::bitset(bitsets),
+                    <Or<<($($ty),*) as Split>::Right> as QueryElement<'a, E>>::bitset(bitsets)
+                )
+            }
+
+            // one of the sides must have its components, and match the filters on them
+            fn matches(bitsets: &'a ComponentBitSets, ticks: &ChangeTicks, index: usize, since: Tick) -> bool {
+                if !Self::TICK_FILTERED {
+                    return true;
+                }
+                let l = <Or<<($($ty),*) as Split>::Left> as QueryElement<'a, E>>::bitset(bitsets).contains(index as u32)
+                    && <Or<<($($ty),*) as Split>::Left> as QueryElement<'a, E>>::matches(bitsets, ticks, index, since);
+                l || (
+                    <Or<<($($ty),*) as Split>::Right> as QueryElement<'a, E>>::bitset(bitsets).contains(index as u32)
+                    && <Or<<($($ty),*) as Split>::Right> as QueryElement<'a, E>>::matches(bitsets, ticks, index, since)
+                )
+            }
+        }
+    }
+}
+
+or_component_impl!(C1, C2);
+or_component_impl!(C1, C2, C3);
+or_component_impl!(C1, C2, C3, C4);
+or_component_impl!(C1, C2, C3, C4, C5);
+or_component_impl!(C1, C2, C3, C4, C5, C6);
+or_component_impl!(C1, C2, C3, C4, C5, C6, C7);
+or_component_impl!(C1, C2, C3, C4, C5, C6, C7, C8);
+
+/// Trait used internally, implemented for every component by `define_entity!`, and for `Option<C>`,
+/// to fetch components directly from the components storage.
+///
+/// Do not implement externally.
+pub trait ComponentFetch<'a, E: EntityRefBase>: QueryElement<'a, E> {
+    type Component: RefComponent<E>;
+    type Item;
+    type ItemMut;
+
+    /// True if `fetch_mut` never returns a mutable reference, so that the same component may be
+    /// fetched more than once in a query.
+    const READ_ONLY: bool = false;
+
+    /// # Safety
+    ///
+    /// `slab` must point to a live slab, not mutably borrowed elsewhere for 'a.
+    unsafe fn fetch(slab: *mut Slab<ComponentCell<Self::Component>>, entity: &E) -> Self::Item;
+
+    /// # Safety
+    ///
+    /// `slab` must point to a live slab, not borrowed elsewhere for 'a, and the same entity
+    /// must not be fetched twice.
+    unsafe fn fetch_mut(slab: *mut Slab<ComponentCell<Self::Component>>, entity: &E) -> Self::ItemMut;
+}
+
+#[doc(hidden)]
+/// Used by `define_entity!` to implement `ComponentFetch` for components.
+///
+/// # Safety
+///
+/// See `ComponentFetch::fetch`
+pub unsafe fn fetch_component<'a, E: EntityRefBase, C: RefComponent<E>>(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Option<&'a C> {
+    C::get_cs_id(entity).map(|cs_id| (*slab).get(cs_id).expect(FATAL_ERR_CS).get())
+}
+
+#[doc(hidden)]
+/// Used by `define_entity!` to implement `ComponentFetch` for components.
+///
+/// # Safety
+///
+/// See `ComponentFetch::fetch_mut`
+pub unsafe fn fetch_component_mut<'a, E: EntityRefBase, C: RefComponent<E>>(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Option<&'a mut C> {
+    C::get_cs_id(entity).map(|cs_id| (*slab).get(cs_id).expect(FATAL_ERR_CS).get_mut())
+}
+
+/// A component in a query tuple may be wrapped in an `Option`: it will not be used to filter the
+/// entities, but will still be fetched if it exists.
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for Option<C> {
+    // every entity matches, but free slots must not
+    type BitSet = &'a BitSet;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        bitsets.occupied()
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentFetch<'a, E> for Option<C> {
+    type Component = C;
+    type Item = Option<&'a C>;
+    type ItemMut = Option<&'a mut C>;
+
+    unsafe fn fetch(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::Item {
+        fetch_component(slab, entity)
+    }
+
+    unsafe fn fetch_mut(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::ItemMut {
+        fetch_component_mut(slab, entity)
+    }
+}
+
+/// `Without<C>` can be fetched, it returns the marker itself. This allows it to be used in `query!`.
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentFetch<'a, E> for Without<C> {
+    type Component = C;
+    type Item = Without<C>;
+    type ItemMut = Without<C>;
+
+    const READ_ONLY: bool = true;
+
+    unsafe fn fetch(_slab: *mut Slab<ComponentCell<C>>, _entity: &E) -> Self::Item {
+        Without(PhantomData)
+    }
+
+    unsafe fn fetch_mut(_slab: *mut Slab<ComponentCell<C>>, _entity: &E) -> Self::ItemMut {
+        Without(PhantomData)
+    }
+}
+
+/// Access marker: `&C` fetches the component `C` immutably, even in `iter_components_mut`.
+///
+/// # Example
+///
+/// `for (id, speed, gravity) in entities.iter_components_mut::<(&mut Speed, &Gravity)>() { }`
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for &C {
+    type BitSet = &'a BitSet;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        component_bitset::<E, C>(bitsets)
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentFetch<'a, E> for &C {
+    type Component = C;
+    type Item = &'a C;
+    type ItemMut = &'a C;
+
+    const READ_ONLY: bool = true;
+
+    unsafe fn fetch(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::Item {
+        fetch_component(slab, entity).expect(FATAL_ERR_BITSET)
+    }
+
+    unsafe fn fetch_mut(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::ItemMut {
+        fetch_component(slab, entity).expect(FATAL_ERR_BITSET)
+    }
+}
+
+/// Access marker: `&mut C` fetches the component `C` mutably. It can only be used in
+/// `iter_components_mut`, using it in `iter_components` does not compile.
+impl<'a, E: EntityBase, C: Component<E>> QueryElement<'a, E> for &mut C {
+    type BitSet = &'a BitSet;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        component_bitset::<E, C>(bitsets)
+    }
+}
+
+impl<'a, E: EntityRefBase, C: RefComponent<E>> ComponentFetch<'a, E> for &mut C {
+    type Component = C;
+    type Item = &'a C;
+    type ItemMut = &'a mut C;
+
+    unsafe fn fetch(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::Item {
+        fetch_component(slab, entity).expect(FATAL_ERR_BITSET)
+    }
+
+    unsafe fn fetch_mut(slab: *mut Slab<ComponentCell<C>>, entity: &E) -> Self::ItemMut {
+        fetch_component_mut(slab, entity).expect(FATAL_ERR_BITSET)
+    }
+}
+
+/// Marker trait for the fetch types which may be used in a read-only query such as
+/// `iter_components`: every type except `&mut C`.
+///
+/// Implemented for every component by `define_entity!`. Do not implement externally.
+pub trait ReadOnlyFetch<E: EntityRefBase> {}
+
+impl<E: EntityRefBase, C: RefComponent<E>> ReadOnlyFetch<E> for Option<C> {}
+impl<E: EntityRefBase, C: RefComponent<E>> ReadOnlyFetch<E> for Without<C> {}
+impl<E: EntityRefBase, C: RefComponent<E>> ReadOnlyFetch<E> for &C {}
+
+/// Marker trait for the tuples of `ReadOnlyFetch`, see `EntityList::iter_components`.
+pub trait ReadOnlyMultiFetch<E: EntityRefBase> {}
+
+/// Trait used internally, implemented for every tuple of component to fetch them
+/// from the components storage.
+///
+/// Do not implement externally.
+pub trait MultiComponentFetch<'a, E: EntityRefBase>: MultiComponent<'a, E> {
+    type Item;
+    type ItemMut;
+    type Slabs: Copy;
+
+    /// Fails to compile when evaluated if the same component is fetched twice in the tuple, unless
+    /// every fetch is read-only.
+    const DISJOINT: ();
+
+    /// # Safety
+    ///
+    /// `cs` must point to the components storage of a live `EntityList`.
+    unsafe fn slabs(cs: *mut E::CS) -> Self::Slabs;
+
+    /// # Safety
+    ///
+    /// `slabs` must come from `Self::slabs`, see `ComponentFetch::fetch`.
+    unsafe fn fetch(slabs: Self::Slabs, id: EntityId, entity: &E) -> Self::Item;
+
+    /// # Safety
+    ///
+    /// `slabs` must come from `Self::slabs`, see `ComponentFetch::fetch_mut`.
+    unsafe fn fetch_mut(slabs: Self::Slabs, id: EntityId, entity: &E) -> Self::ItemMut;
+
+    /// Record the components fetched mutably by `fetch_mut` as changed for the entity at `index`.
+    fn record_changes(ticks: &mut ChangeTicks, bitsets: &ComponentBitSets, index: usize);
+}
+
+macro_rules! multi_component_fetch_impl {
+    ($($ty:ident),*) => {
+        impl<'a, E: EntityRefBase, $($ty: ComponentFetch<'a, E>),*> MultiComponentFetch<'a, E> for ($($ty,)*)
+        where ($($ty,)*): MultiComponent<'a, E>
+        {
+            type Item = (EntityId, $($ty::Item),*);
+            type ItemMut = (EntityId, $($ty::ItemMut),*);
+            type Slabs = ($(*mut Slab<ComponentCell<$ty::Component>>,)*);
+
+            const DISJOINT: () = {
+                let accesses = [$((<$ty::Component as Component<E>>::POSITION, $ty::READ_ONLY)),*];
+                let mut i = 0;
+                while i < accesses.len() {
+                    let mut j = i + 1;
+                    while j < accesses.len() {
+                        assert!(
+                            accesses[i].0 != accesses[j].0 || (accesses[i].1 && accesses[j].1),
+                            "the same component cannot be fetched mutably twice"
+                        );
+                        j += 1;
+                    }
+                    i += 1;
+                }
+            };
+
+            unsafe fn slabs(cs: *mut E::CS) -> Self::Slabs {
+                ($($ty::Component::get_single_cs_ptr(cs),)*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch(slabs: Self::Slabs, id: EntityId, entity: &E) -> Self::Item {
+                let ($($ty,)*) = slabs;
+                (id, $(<$ty as ComponentFetch<'a, E>>::fetch($ty, entity)),*)
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch_mut(slabs: Self::Slabs, id: EntityId, entity: &E) -> Self::ItemMut {
+                let ($($ty,)*) = slabs;
+                (id, $(<$ty as ComponentFetch<'a, E>>::fetch_mut($ty, entity)),*)
+            }
+
+            fn record_changes(ticks: &mut ChangeTicks, bitsets: &ComponentBitSets, index: usize) {
+                $(if !$ty::READ_ONLY {
+                    ticks.set_changed_if_present(bitsets, <$ty::Component as Component<E>>::POSITION, index);
+                })*
+            }
+        }
+
+        impl<E: EntityRefBase, $($ty: ReadOnlyFetch<E>),*> ReadOnlyMultiFetch<E> for ($($ty,)*) {}
+    }
+}
+
+multi_component_fetch_impl!(C1);
+multi_component_fetch_impl!(C1, C2);
+multi_component_fetch_impl!(C1, C2, C3);
+multi_component_fetch_impl!(C1, C2, C3, C4);
+multi_component_fetch_impl!(C1, C2, C3, C4, C5);
+multi_component_fetch_impl!(C1, C2, C3, C4, C5, C6);
+multi_component_fetch_impl!(C1, C2, C3, C4, C5, C6, C7);
+multi_component_fetch_impl!(C1, C2, C3, C4, C5, C6, C7, C8);
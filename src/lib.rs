@@ -4,14 +4,79 @@ mod entity;
 pub use entity::*;
 mod entity_list;
 pub use entity_list::*;
+mod bundle;
+pub use bundle::*;
+mod entity_diff;
+pub use entity_diff::*;
+mod builder;
+pub use builder::*;
 mod component_storage;
 pub use component_storage::*;
+mod borrows;
+pub use borrows::*;
+mod type_id_map;
+pub use type_id_map::*;
+mod component_bitsets;
+pub use component_bitsets::*;
 mod macro_define;
-pub use macro_define::*;
+mod macro_query;
 mod iter;
 pub use iter::*;
+mod query;
+pub use query::*;
+mod change_ticks;
+pub use change_ticks::*;
+mod tracked;
+pub use tracked::*;
+mod spatial;
+pub use spatial::*;
+mod archetypes;
+pub use archetypes::*;
+mod cached_query;
+mod component_layout;
+mod memory;
+pub use memory::*;
+mod prop_index;
+pub use prop_index::*;
+mod uuids;
+pub use uuids::*;
+mod resources;
+mod tags;
+pub use tags::*;
+mod enabled;
+pub use enabled::*;
+mod validate;
+pub use validate::*;
+mod snapshot;
+pub use snapshot::*;
+mod entry;
+pub use entry::*;
+mod maintain;
+mod commands;
+pub use commands::*;
+mod recycle_bin;
+mod observers;
+mod events;
+pub use events::*;
+mod world;
+pub use world::*;
+mod send;
+pub use send::*;
+mod frozen;
+pub use frozen::*;
+mod view;
+pub use view::*;
+mod pinned;
+pub use pinned::*;
+mod handle;
+pub use handle::*;
+#[cfg(feature = "profiling")]
+mod profiling;
+#[cfg(feature = "profiling")]
+pub use profiling::*;
 
 pub use paste;
+pub use hibitset;
 pub use slab;
 #[cfg(feature = "use_serde")]
 pub use serde;
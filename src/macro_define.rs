@@ -5,7 +5,7 @@
 ///
 /// * Properties, which are mandatory members on all your entities. Example: a position.
 /// * Components, which are optional members taht may be added or removed at runtime. Examples:
-/// a speed, a body, ...
+///   a speed, a body, ...
 ///
 /// The code below:
 ///
@@ -47,6 +47,29 @@
 /// }
 /// ```
 ///
+/// Props are given to `Entity::new` as a tuple, in declaration order. `Entity::with_props` sets them
+/// by name instead, and props which implement `Default` can be omitted:
+///
+/// ```ignore
+/// let e = Entity::with_props().a(A::new()).build();
+/// ```
+///
+/// Components are boxed on the owned entity. Small components can be stored inline instead, as
+/// `Option<C>`, by marking them `#[inline]`: this saves an allocation every time the entity is inserted
+/// in a list or removed from it.
+///
+/// ```ignore
+/// define_entity!{
+///     pub struct Entity {
+///         props => { a: A }
+///         components => {
+///             b => B,
+///             #[inline] health => Health,
+///         }
+///     }
+/// }
+/// ```
+///
 /// You can derive just as many things as you'd like with a regular struct. Only `Copy` is forbidden
 /// if using components. Example:
 ///
@@ -58,7 +81,7 @@
 ///         components => {}
 ///     }
 /// }
-
+/// ```
 #[macro_export]
 macro_rules! define_entity {
     (
@@ -74,12 +97,23 @@ macro_rules! define_entity {
     ) => {
         $crate::paste::paste! {
 
-        impl Clone for [<$entityname ComponentsStorage>] {
+        /// Position of the bitset of every component, in declaration order, see `Component::POSITION`.
+        #[allow(non_camel_case_types, dead_code)]
+        enum [<$entityname ComponentPosition>] {
+            $(
+                $componentname,
+            )*
+        }
+
+        // the `for<'a>` keeps the bounds from being checked on the concrete types, so that entities
+        // with components which are not `Clone` still compile, without these impls
+        impl Clone for [<$entityname ComponentsStorage>] where $(for<'a> $componenttype: Clone),* {
             fn clone(&self) -> Self {
                 Self {
                     $(
                         $componentname: self.$componentname.clone(),
                     )*
+                    borrow_tracker: Default::default(),
                 }
             }
 
@@ -89,164 +123,245 @@ macro_rules! define_entity {
                 )*
             }
         }
+
+        impl $crate::CloneStorage for [<$entityname ComponentsStorage>] where $(for<'a> $componenttype: Clone),* {
+            fn clone_owned(&self, e: &[<$entityname Ref>]) -> $entityname {
+                $entityname {
+                    $(
+                        $propname : e.$propname.clone(),
+                    )*
+                    $(
+                        $componentname : $crate::ComponentSlot::from_component(
+                            e.$componentname.map(|c_id| unsafe { self.$componentname.slab()[c_id].get() }.clone())
+                        ),
+                    )*
+                }
+            }
+
+            fn merge_from_owned(e: &mut [<$entityname Ref>], other: &$entityname, mode: $crate::MergeMode) {
+                $(
+                    if let Some(c) = <$componenttype as smec::Component<$entityname>>::get(other) {
+                        if mode == $crate::MergeMode::Overwrite || <$componenttype as smec::Component<[<$entityname Ref>]>>::get(e).is_none() {
+                            <$componenttype as smec::Component<[<$entityname Ref>]>>::set(c.clone(), e);
+                        }
+                    }
+                )*
+            }
+        }
         }
 
         $(
             impl smec::Component<$entityname> for $componenttype {
+                const POSITION: usize = $crate::paste::paste!([<$entityname ComponentPosition>]::$componentname) as usize;
+                const NAME: &'static str = stringify!($componenttype);
+
                 #[inline]
                 fn set(self, entity: &mut $entityname) {
-                    entity.$componentname = Some(Box::new(self))
+                    entity.$componentname = $crate::ComponentSlot::from_component(Some(self))
                 }
 
                 #[inline]
                 fn get(entity: &$entityname) -> Option<&$componenttype> {
-                    entity.$componentname.as_ref().map(|s| &**s)
+                    $crate::ComponentSlot::<$componenttype>::component(&entity.$componentname)
                 }
 
                 #[inline]
                 fn get_mut(entity: &mut $entityname) -> Option<&mut $componenttype> {
-                    entity.$componentname.as_mut().map(|s| &mut **s)
+                    $crate::ComponentSlot::<$componenttype>::component_mut(&mut entity.$componentname)
+                }
+
+                #[inline]
+                unsafe fn get_mut_ptr(entity: *mut $entityname) -> Option<*mut $componenttype> {
+                    // only the field of this component is borrowed, not the whole entity
+                    $crate::ComponentSlot::<$componenttype>::component_mut(&mut *std::ptr::addr_of_mut!((*entity).$componentname))
+                        .map(|c| c as *mut $componenttype)
                 }
 
                 #[inline]
                 fn remove(entity: &mut $entityname) -> Option<Box<$componenttype>> {
-                    entity.$componentname.take()
+                    $crate::ComponentSlot::<$componenttype>::take_boxed(&mut entity.$componentname)
+                }
+
+                #[inline]
+                fn take(entity: &mut $entityname) -> Option<$componenttype> {
+                    $crate::ComponentSlot::<$componenttype>::take_component(&mut entity.$componentname)
                 }
 
                 #[inline]
                 fn peek<O, F: FnOnce(&Self) -> O>(entity: &$entityname, f: F) -> Option<O> {
-                    entity.$componentname.as_ref().map(|c| &**c).map(f)
+                    Self::get(entity).map(f)
                 }
 
                 #[inline]
                 fn update<O, F: FnOnce(&mut Self) -> O>(entity: &mut $entityname, f: F) -> Option<O> {
-                    entity.$componentname.as_mut().map(|c| &mut **c).map(f)
+                    Self::get_mut(entity).map(f)
+                }
+            }
+
+            impl<'a> smec::QueryElement<'a, $entityname> for $componenttype {
+                type BitSet = &'a $crate::hibitset::BitSet;
+
+                #[inline]
+                fn bitset(bitsets: &'a $crate::ComponentBitSets) -> Self::BitSet {
+                    $crate::component_bitset::<$entityname, $componenttype>(bitsets)
                 }
             }
 
             $crate::paste::paste! {
+            impl<'a> smec::QueryElement<'a, [<$entityname Ref>]> for $componenttype {
+                type BitSet = &'a $crate::hibitset::BitSet;
+
+                #[inline]
+                fn bitset(bitsets: &'a $crate::ComponentBitSets) -> Self::BitSet {
+                    $crate::component_bitset::<[<$entityname Ref>], $componenttype>(bitsets)
+                }
+            }
+
+            impl<'a> smec::ComponentFetch<'a, [<$entityname Ref>]> for $componenttype {
+                type Component = Self;
+                type Item = &'a Self;
+                type ItemMut = &'a mut Self;
+
+                #[inline]
+                unsafe fn fetch(slab: *mut $crate::slab::Slab<$crate::ComponentCell<Self>>, entity: &[<$entityname Ref>]) -> Self::Item {
+                    $crate::fetch_component(slab, entity).expect($crate::FATAL_ERR_BITSET)
+                }
+
+                #[inline]
+                unsafe fn fetch_mut(slab: *mut $crate::slab::Slab<$crate::ComponentCell<Self>>, entity: &[<$entityname Ref>]) -> Self::ItemMut {
+                    $crate::fetch_component_mut(slab, entity).expect($crate::FATAL_ERR_BITSET)
+                }
+            }
+
+            impl smec::ReadOnlyFetch<[<$entityname Ref>]> for $componenttype {}
+
             impl smec::Component<[<$entityname Ref>]> for $componenttype {
-                fn set(self, entity: &mut EntityRef) {
-                    let current = entity.$componentname;
-                    if let Some(storage) = entity.components_storage.upgrade() {
-                        unsafe {
-                            if let Some(current) = current {
-                                if let Some(old) = (*storage.get()).$componentname.get_mut(current)  {
-                                    *old = self;
-                                    return;
-                                }
-                            }
-                            entity.$componentname = Some((*storage.get()).$componentname.insert(self));
+                const POSITION: usize = [<$entityname ComponentPosition>]::$componentname as usize;
+                const NAME: &'static str = stringify!($componenttype);
+
+                fn set(self, entity: &mut [<$entityname Ref>]) {
+                    // SAFETY: see `[<$entityname Ref>]::storage_ptr`
+                    unsafe {
+                        let storage = entity.storage_ptr();
+                        let tracker = &*std::ptr::addr_of!((*storage).borrow_tracker);
+                        tracker.check_exclusive::<[<$entityname Ref>], $componenttype>();
+                        let component_slab = std::ptr::addr_of_mut!((*storage).$componentname);
+                        (*component_slab).unshare();
+                        let slab = $crate::ComponentSlab::slab_ptr(component_slab);
+                        match entity.$componentname.and_then(|current| (*slab).get(current)) {
+                            // replaced in place, the slab itself is not written
+                            Some(old) => *old.get_mut() = self,
+                            None => {
+                                (*component_slab).borrower().check_resize::<[<$entityname Ref>], $componenttype>(tracker, entity as *const _ as usize);
+                                let key = (*component_slab).slab_mut().insert($crate::ComponentCell::new(self));
+                                entity.$componentname = Some(key);
+                            },
                         }
-                    } else {
-                        unreachable!()
                     }
                 }
 
-                fn get(entity: &EntityRef) -> Option<&$componenttype> {
-                    if let Some(current) = entity.$componentname {
-                        if let Some(storage) = entity.components_storage.upgrade() {
-                            unsafe {
-                                (*storage.get()).$componentname.get(current)
-                            }
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        None
+                #[inline]
+                fn get(entity: &[<$entityname Ref>]) -> Option<&$componenttype> {
+                    let current = entity.$componentname?;
+                    // SAFETY: see `[<$entityname Ref>]::storage_ptr`
+                    unsafe {
+                        let storage = entity.storage_ptr();
+                        let tracker = &*std::ptr::addr_of!((*storage).borrow_tracker);
+                        tracker.check_shared::<[<$entityname Ref>], $componenttype>();
+                        let component_slab = std::ptr::addr_of_mut!((*storage).$componentname);
+                        (*component_slab).borrower().borrow(tracker, entity as *const _ as usize);
+                        let slab = $crate::ComponentSlab::slab_ptr(component_slab);
+                        (*slab).get(current).map(|c| c.get())
                     }
                 }
 
-                fn get_mut(entity: &mut EntityRef) -> Option<&mut $componenttype> {
-                    if let Some(current) = entity.$componentname {
-                        if let Some(storage) = entity.components_storage.upgrade() {
-                            // SAFETY: a bit more debatable, if we have 2 EntityRef mutable at the same time this is a violation
-                            // of safety !!BUT!! this is technically not possible because all EntityRef are stored in the arena,
-                            // and there is no get2(..) method in there.
-                            // we also cannot (or should not if this is not implemented yet) be able to construct EntityRef ourselves
-                            unsafe {
-                                (*storage.get()).$componentname.get_mut(current)
-                            }
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        None
+                #[inline]
+                fn get_mut(entity: &mut [<$entityname Ref>]) -> Option<&mut $componenttype> {
+                    let current = entity.$componentname?;
+                    // SAFETY: see `[<$entityname Ref>]::storage_ptr`
+                    unsafe {
+                        let storage = entity.storage_ptr();
+                        let tracker = &*std::ptr::addr_of!((*storage).borrow_tracker);
+                        tracker.check_exclusive::<[<$entityname Ref>], $componenttype>();
+                        let component_slab = std::ptr::addr_of_mut!((*storage).$componentname);
+                        (*component_slab).unshare();
+                        (*component_slab).borrower().borrow(tracker, entity as *const _ as usize);
+                        let slab = $crate::ComponentSlab::slab_ptr(component_slab);
+                        (*slab).get(current).map(|c| c.get_mut())
                     }
                 }
 
-                fn remove(entity: &mut EntityRef) -> Option<Box<$componenttype>> {
-                    if let Some(current) = entity.$componentname.take() {
-                        if let Some(storage) = entity.components_storage.upgrade() {
-                            // SAFETY: in theory we only access the component of the entity from the storage,
-                            // so this is safe?
-                            unsafe {
-                                Some(Box::new((*storage.get()).$componentname.remove(current)))
-                            }
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        None
-                    }
+                #[inline]
+                unsafe fn get_mut_ptr(entity: *mut [<$entityname Ref>]) -> Option<*mut $componenttype> {
+                    // the component lives in its own slab, not in the entity
+                    Self::get_mut(&mut *entity).map(|c| c as *mut $componenttype)
                 }
 
-                fn peek<O, F: FnOnce(&Self) -> O>(entity: &EntityRef, f: F) -> Option<O> {
-                    if let Some(current) = entity.$componentname {
-                        if let Some(storage) = entity.components_storage.upgrade() {
-                            // SAFETY: in theory we only access the component of the entity from the storage,
-                            // so this is safe?
-                            unsafe {
-                                if let Some(c) = (*storage.get()).$componentname.get(current) {
-                                    Some(f(c))
-                                } else {
-                                    None
-                                }
-                            }
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        None
-                    }
+                #[inline]
+                fn remove(entity: &mut [<$entityname Ref>]) -> Option<Box<$componenttype>> {
+                    Self::take(entity).map(Box::new)
                 }
 
-                fn update<O, F: FnOnce(&mut Self) -> O>(entity: &mut EntityRef, f: F) -> Option<O> {
-                    if let Some(current) = entity.$componentname {
-                        if let Some(storage) = entity.components_storage.upgrade() {
-                            // SAFETY: in theory we only access the component of the entity from the storage,
-                            // so this is safe?
-                            unsafe { 
-                                if let Some(c) = (*storage.get()).$componentname.get_mut(current) {
-                                    Some(f(c))
-                                } else {
-                                    None
-                                }
-                            }
-                        } else {
-                            unreachable!()
-                        }
-                    } else {
-                        None
+                fn take(entity: &mut [<$entityname Ref>]) -> Option<$componenttype> {
+                    let current = entity.$componentname?;
+                    // SAFETY: see `[<$entityname Ref>]::storage_ptr`
+                    unsafe {
+                        let storage = entity.storage_ptr();
+                        let tracker = &*std::ptr::addr_of!((*storage).borrow_tracker);
+                        tracker.check_exclusive::<[<$entityname Ref>], $componenttype>();
+                        let component_slab = std::ptr::addr_of_mut!((*storage).$componentname);
+                        (*component_slab).borrower().check_resize::<[<$entityname Ref>], $componenttype>(tracker, entity as *const _ as usize);
+                        entity.$componentname = None;
+                        Some((*component_slab).slab_mut().remove(current).into_inner())
                     }
                 }
+
+                #[inline]
+                fn peek<O, F: FnOnce(&Self) -> O>(entity: &[<$entityname Ref>], f: F) -> Option<O> {
+                    Self::get(entity).map(f)
+                }
+
+                #[inline]
+                fn update<O, F: FnOnce(&mut Self) -> O>(entity: &mut [<$entityname Ref>], f: F) -> Option<O> {
+                    Self::get_mut(entity).map(f)
+                }
             }
             
             impl smec::RefComponent<[<$entityname Ref>]> for $componenttype {
                 #[inline]
-                fn get_single_cs(cs: &[<$entityname ComponentsStorage>]) -> &$crate::slab::Slab<Self> {
-                    &cs.$componentname
+                fn get_single_cs(cs: &[<$entityname ComponentsStorage>]) -> &$crate::slab::Slab<$crate::ComponentCell<Self>> {
+                    cs.$componentname.slab()
+                }
+
+                #[inline]
+                fn get_single_cs_mut(cs: &mut [<$entityname ComponentsStorage>]) -> &mut $crate::slab::Slab<$crate::ComponentCell<Self>> {
+                    cs.$componentname.slab_mut()
+                }
+
+                #[inline]
+                unsafe fn get_borrower<'a>(cs: *const [<$entityname ComponentsStorage>]) -> &'a $crate::SlabBorrower {
+                    (*std::ptr::addr_of!((*cs).$componentname)).borrower()
+                }
+
+                #[inline]
+                unsafe fn get_single_cs_ptr(cs: *mut [<$entityname ComponentsStorage>]) -> *mut $crate::slab::Slab<$crate::ComponentCell<Self>> {
+                    $crate::ComponentSlab::slab_ptr(std::ptr::addr_of_mut!((*cs).$componentname))
                 }
 
                 #[inline]
                 fn get_cs_id(entity: &[<$entityname Ref>]) -> Option<usize> {
                     entity.$componentname
                 }
+
+                #[inline]
+                unsafe fn set_cs_id(entity: &mut [<$entityname Ref>], key: usize) {
+                    entity.$componentname = Some(key);
+                }
             }
             }
         )*
 
-        impl Clone for $entityname {
+        impl Clone for $entityname where $(for<'a> $componenttype: Clone),* {
             fn clone(&self) -> Self {
                 Self {
                     $(
@@ -268,7 +383,132 @@ macro_rules! define_entity {
             }
         }
 
+        impl $crate::HashState for $entityname where $(for<'a> $propt: std::hash::Hash,)* $(for<'a> $componenttype: std::hash::Hash,)* {
+            fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H) {
+                $(
+                    std::hash::Hash::hash(&self.$propname, hasher);
+                )*
+                $(
+                    std::hash::Hash::hash(&<$componenttype as smec::Component<Self>>::get(self), hasher);
+                )*
+            }
+        }
+
+        impl $crate::CloneComponents for $entityname where $(for<'a> $componenttype: Clone),* {
+            fn diff_components(&self, other: &Self) -> $crate::EntityDiff<Self> {
+                #[allow(unused_imports)]
+                use $crate::{ComponentEqPartialEq as _, ComponentEqFallback as _};
+                let mut diff = $crate::EntityDiff::new();
+                $(
+                    diff.compare::<$componenttype>(
+                        <$componenttype as smec::Component<Self>>::get(self),
+                        <$componenttype as smec::Component<Self>>::get(other),
+                        |a, b| (&$crate::ComponentEq(a, b)).component_eq(),
+                    );
+                )*
+                diff
+            }
+
+            fn merge_components(&mut self, other: &Self, mode: $crate::MergeMode) {
+                $(
+                    if let Some(c) = <$componenttype as smec::Component<Self>>::get(other) {
+                        if mode == $crate::MergeMode::Overwrite || <$componenttype as smec::Component<Self>>::get(self).is_none() {
+                            let c = c.clone();
+                            <$componenttype as smec::Component<Self>>::set(c, self);
+                        }
+                    }
+                )*
+            }
+        }
+
         $crate::paste::paste! {
+        impl [<$entityname Ref>] {
+            /// Returns a pointer to the storage of the list this entity belongs to.
+            ///
+            /// Components are only ever borrowed through a shared borrow of their slab, see
+            /// `ComponentCell`. A component key is used by a single entity: clones are detached, and
+            /// keys cannot be set from outside. So borrowing the components of two entities never
+            /// aliases, and a component is borrowed mutably only when its entity is. Since entities
+            /// only live in the arena of their list, the storage outlives every borrow of an entity.
+            /// A slab is only borrowed mutably to add or remove a component, which panics while
+            /// several entities are lent if another one borrowed a component of the slab, see
+            /// `SlabBorrower`.
+            /// Going through `Weak::as_ptr` leaves the reference counts untouched.
+            /// With the `cow` feature, a slab shared with a clone of the list is copied before any of its
+            /// components is borrowed mutably, and before several entities are handed out at once.
+            ///
+            /// Panics if the entity is not attached to a list, see `Clone`.
+            #[inline]
+            fn storage_ptr(&self) -> *mut [<$entityname ComponentsStorage>] {
+                assert!(self.components_storage.strong_count() > 0, "entity is not attached to an EntityList");
+                std::cell::UnsafeCell::raw_get(self.components_storage.as_ptr())
+            }
+        }
+
+        /// The clone is detached from the storage: its components cannot be accessed until it is
+        /// attached to a storage with `set_cs`, which `EntityList` does when it is cloned.
+        impl Clone for [<$entityname Ref>] {
+            fn clone(&self) -> Self {
+                Self {
+                    $(
+                        $propname: self.$propname.clone(),
+                    )*
+                    $(
+                        $componentname: self.$componentname,
+                    )*
+                    components_storage: std::rc::Weak::new(),
+                }
+            }
+        }
+
+        impl $crate::HashState for [<$entityname Ref>] where $(for<'a> $propt: std::hash::Hash,)* $(for<'a> $componenttype: std::hash::Hash,)* {
+            fn hash_state<H: std::hash::Hasher>(&self, hasher: &mut H) {
+                $(
+                    std::hash::Hash::hash(&self.$propname, hasher);
+                )*
+                $(
+                    std::hash::Hash::hash(&<$componenttype as smec::Component<Self>>::get(self), hasher);
+                )*
+            }
+        }
+
+        impl $crate::CloneComponents for [<$entityname Ref>] where $(for<'a> $componenttype: Clone),* {
+            fn diff_components(&self, other: &Self) -> $crate::EntityDiff<Self> {
+                #[allow(unused_imports)]
+                use $crate::{ComponentEqPartialEq as _, ComponentEqFallback as _};
+                let mut diff = $crate::EntityDiff::new();
+                $(
+                    diff.compare::<$componenttype>(
+                        <$componenttype as smec::Component<Self>>::get(self),
+                        <$componenttype as smec::Component<Self>>::get(other),
+                        |a, b| (&$crate::ComponentEq(a, b)).component_eq(),
+                    );
+                )*
+                diff
+            }
+
+            fn merge_components(&mut self, other: &Self, mode: $crate::MergeMode) {
+                $(
+                    if let Some(key) = other.$componentname {
+                        if mode == $crate::MergeMode::Overwrite || self.$componentname.is_none() {
+                            // SAFETY: see `[<$entityname Ref>]::storage_ptr`. The component is cloned
+                            // before `self` is written, so it is not recorded as borrowed through `other`,
+                            // which may be lent along with `self`.
+                            let c = unsafe {
+                                let storage = other.storage_ptr();
+                                (*std::ptr::addr_of!((*storage).borrow_tracker)).check_shared::<[<$entityname Ref>], $componenttype>();
+                                let slab = $crate::ComponentSlab::slab_ptr(std::ptr::addr_of_mut!((*storage).$componentname));
+                                (*slab).get(key).map(|c| c.get().clone())
+                            };
+                            if let Some(c) = c {
+                                <$componenttype as smec::Component<Self>>::set(c, self);
+                            }
+                        }
+                    }
+                )*
+            }
+        }
+
         impl smec::EntityBase for [<$entityname Ref>] {
             fn for_each_active_component(&self, mut f: impl FnMut(std::any::TypeId)) {
                 $(
@@ -289,6 +529,31 @@ macro_rules! define_entity {
                     f(std::any::TypeId::of::< $componenttype >());
                 )*
             }
+
+            #[inline]
+            fn has_type(&self, type_id: std::any::TypeId) -> bool {
+                $(
+                    if type_id == std::any::TypeId::of::< $componenttype >() {
+                        return self.$componentname.is_some();
+                    }
+                )*
+                false
+            }
+
+            fn component_name(type_id: std::any::TypeId) -> Option<&'static str> {
+                $(
+                    if type_id == std::any::TypeId::of::< $componenttype >() {
+                        return Some(<$componenttype as smec::Component<Self>>::NAME);
+                    }
+                )*
+                None
+            }
+
+            fn clear_components(&mut self) {
+                $(
+                    <$componenttype as smec::Component<Self>>::take(self);
+                )*
+            }
         }
 
         impl smec::EntityRefBase for [<$entityname Ref>] {
@@ -304,8 +569,8 @@ macro_rules! define_entity {
                         $propname : owned.$propname,
                     )*
                     $(
-                        $componentname : owned.$componentname.take().map(|c| {
-                            unsafe { (*borrowed_cell).$componentname.insert(*c) }
+                        $componentname : $crate::ComponentSlot::<$componenttype>::take_component(&mut owned.$componentname).map(|c| {
+                            unsafe { (*borrowed_cell).$componentname.slab_mut().insert($crate::ComponentCell::new(c)) }
                         }),
                     )*
                     components_storage: weak,
@@ -318,20 +583,26 @@ macro_rules! define_entity {
                         $propname : self.$propname,
                     )*
                     $(
-                        $componentname : self.$componentname.map(|c_id| {
-                            if let Some(cs) = self.components_storage.upgrade() {
-                                unsafe {
-                                    Box::new((*cs.get()).$componentname.remove(c_id))
-                                }
-                            } else {
-                                unreachable!()
-                            }
-                        }),
+                        $componentname : $crate::ComponentSlot::from_component(
+                            self.$componentname.map(|c_id| cs.$componentname.slab_mut().remove(c_id).into_inner())
+                        ),
+                    )*
+                }
+            }
+
+            fn from_params(( $( $propname ,)* ): ( $( $propt ,)* ), cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self {
+                Self {
+                    $(
+                        $propname,
                     )*
+                    $(
+                        $componentname : None,
+                    )*
+                    components_storage: std::rc::Rc::downgrade(cs)
                 }
             }
 
-            fn from_naked(naked: Self::Naked, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self {
+            unsafe fn from_naked(naked: Self::Naked, cs: &std::rc::Rc<std::cell::UnsafeCell<Self::CS>>) -> Self {
                 Self {
                     $(
                         $propname : naked.$propname,
@@ -354,9 +625,41 @@ macro_rules! define_entity {
                 }
             }
 
+            fn move_components(naked: Self::Naked, from: &mut Self::CS, to: &mut Self::CS) -> Self::Naked {
+                Self::Naked {
+                    $(
+                        $propname : naked.$propname,
+                    )*
+                    $(
+                        $componentname : naked.$componentname.map(|c_id| to.$componentname.slab_mut().insert(from.$componentname.slab_mut().remove(c_id))),
+                    )*
+                }
+            }
+
             fn set_cs(&mut self, cs: std::rc::Weak<std::cell::UnsafeCell<Self::CS>>) {
                 self.components_storage = cs;
             }
+
+            #[inline]
+            fn cs_ptr(&self) -> *const std::cell::UnsafeCell<Self::CS> {
+                self.components_storage.as_ptr()
+            }
+
+            fn for_each_component_key(&self, mut f: impl FnMut(std::any::TypeId, usize)) {
+                $(
+                    if let Some(key) = self.$componentname {
+                        f(std::any::TypeId::of::< $componenttype >(), key);
+                    }
+                )*
+            }
+
+            unsafe fn remap_component_keys(&mut self, mut f: impl FnMut(std::any::TypeId, usize) -> usize) {
+                $(
+                    if let Some(key) = &mut self.$componentname {
+                        *key = f(std::any::TypeId::of::< $componenttype >(), *key);
+                    }
+                )*
+            }
         }
         
         impl smec::ComponentsStorage for [<$entityname ComponentsStorage>] {
@@ -365,10 +668,51 @@ macro_rules! define_entity {
             fn new() -> Self {
                 Self {
                     $(
-                        $componentname: $crate::slab::Slab::new(),
+                        $componentname: $crate::ComponentSlab::new(),
                     )*
+                    borrow_tracker: Default::default(),
                 }
             }
+
+            fn clear(&mut self) {
+                $(
+                    self.$componentname.clear();
+                )*
+            }
+
+            fn for_each_slab(&self, mut f: impl FnMut(&'static str, usize, usize, usize)) {
+                $(
+                    f(<$componenttype as smec::Component<[<$entityname Ref>]>>::NAME, self.$componentname.slab().len(), self.$componentname.slab().capacity(), $crate::slab_slot_size::< $componenttype >());
+                )*
+            }
+
+            fn for_each_key(&self, mut f: impl FnMut(std::any::TypeId, usize)) {
+                $(
+                    for (key, _) in self.$componentname.slab().iter() {
+                        f(std::any::TypeId::of::< $componenttype >(), key);
+                    }
+                )*
+            }
+
+            fn unshare(&mut self) {
+                $(
+                    self.$componentname.unshare();
+                )*
+            }
+
+            fn compact(&mut self, mut f: impl FnMut(std::any::TypeId, usize, usize)) {
+                $(
+                    self.$componentname.slab_mut().compact(|_, from, to| {
+                        f(std::any::TypeId::of::< $componenttype >(), from, to);
+                        true
+                    });
+                )*
+            }
+
+            #[inline]
+            fn borrow_tracker(&self) -> &$crate::BorrowTracker {
+                &self.borrow_tracker
+            }
         }
         }
 
@@ -392,6 +736,31 @@ macro_rules! define_entity {
                     f(std::any::TypeId::of::< $componenttype >());
                 )*
             }
+
+            #[inline]
+            fn has_type(&self, type_id: std::any::TypeId) -> bool {
+                $(
+                    if type_id == std::any::TypeId::of::< $componenttype >() {
+                        return self.$componentname.is_some();
+                    }
+                )*
+                false
+            }
+
+            fn component_name(type_id: std::any::TypeId) -> Option<&'static str> {
+                $(
+                    if type_id == std::any::TypeId::of::< $componenttype >() {
+                        return Some(<$componenttype as smec::Component<Self>>::NAME);
+                    }
+                )*
+                None
+            }
+
+            fn clear_components(&mut self) {
+                $(
+                    <$componenttype as smec::Component<Self>>::take(self);
+                )*
+            }
         }
 
         impl smec::EntityOwnedBase for $entityname {
@@ -408,6 +777,64 @@ macro_rules! define_entity {
                 }
             }
         }
+
+        $crate::paste::paste! {
+        /// Builder of an entity, where props are set by name, see `with_props`.
+        #[derive(Default)]
+        $vis struct [<$entityname PropsBuilder>] {
+            $(
+                $propname: Option<$propt>,
+            )*
+        }
+
+        impl [<$entityname PropsBuilder>] {
+            $(
+                pub fn $propname(mut self, $propname: $propt) -> Self {
+                    self.$propname = Some($propname);
+                    self
+                }
+            )*
+
+            /// Creates the entity, with the default value of every prop which was not set.
+            ///
+            /// Panics if a prop which does not implement `Default` was not set.
+            pub fn build(self) -> $entityname {
+                #[allow(unused_imports)]
+                use $crate::{PropDefaultDefault as _, PropDefaultFallback as _};
+                <$entityname as smec::EntityOwnedBase>::new(( $(
+                    self.$propname.unwrap_or_else(|| {
+                        (&$crate::PropDefault::<$propt>(std::marker::PhantomData)).prop_default()
+                            .expect(concat!("prop `", stringify!($propname), "` was not set, and does not implement Default"))
+                    }),
+                )* ))
+            }
+
+            /// Same as `build`, but returns `None` if a prop which does not implement `Default` was
+            /// not set.
+            pub fn try_build(self) -> Option<$entityname> {
+                #[allow(unused_imports)]
+                use $crate::{PropDefaultDefault as _, PropDefaultFallback as _};
+                Some(<$entityname as smec::EntityOwnedBase>::new(( $(
+                    match self.$propname {
+                        Some($propname) => $propname,
+                        None => (&$crate::PropDefault::<$propt>(std::marker::PhantomData)).prop_default()?,
+                    },
+                )* )))
+            }
+        }
+
+        impl $entityname {
+            /// Returns a builder of this entity, where props are set by name, and the ones which
+            /// implement `Default` can be omitted.
+            ///
+            /// # Example
+            ///
+            /// `let e = Entity::with_props().age(AgeProp { age: 5 }).build().with(Speed(1.0));`
+            pub fn with_props() -> [<$entityname PropsBuilder>] {
+                Default::default()
+            }
+        }
+        }
     };
     (   
         serde;
@@ -417,7 +844,7 @@ macro_rules! define_entity {
                 $( $propname:ident : $propt:ty),* $(,)*
             } $(,)?
             components => {
-                $( $componentname:ident => $componenttype:ty ),* $(,)*
+                $( $(#[$slot:ident])? $componentname:ident => $componenttype:ty ),* $(,)*
             } $(,)?
         }
     ) => {
@@ -429,17 +856,16 @@ macro_rules! define_entity {
                 pub $propname : $propt,
             )*
             $(
-                pub $componentname: Option<Box<$componenttype>>,
+                pub $componentname: $crate::component_slot!($($slot)? $componenttype),
             )*
         }
 
-        #[derive(Clone)]
         $vis struct [<$entityname Ref>] {
             $(
                 pub $propname : $propt,
             )*
             $(
-                pub $componentname: Option<usize>,
+                $componentname: Option<usize>,
             )*
             components_storage: std::rc::Weak<::std::cell::UnsafeCell<[<$entityname ComponentsStorage>]>>
         }
@@ -458,8 +884,10 @@ macro_rules! define_entity {
         #[derive($crate::serde::Serialize, $crate::serde::Deserialize)]
         $vis struct [<$entityname ComponentsStorage>] {
             $(
-                $componentname: $crate::slab::Slab<$componenttype>,
+                $componentname: $crate::ComponentSlab<$componenttype>,
             )*
+            #[serde(skip)]
+            borrow_tracker: $crate::BorrowTracker,
         }
         }
 
@@ -486,7 +914,7 @@ macro_rules! define_entity {
                 $( $propname:ident : $propt:ty),* $(,)*
             } $(,)?
             components => {
-                $( $componentname:ident => $componenttype:ty ),* $(,)*
+                $( $(#[$slot:ident])? $componentname:ident => $componenttype:ty ),* $(,)*
             } $(,)?
         }
     ) => {
@@ -497,17 +925,16 @@ macro_rules! define_entity {
                 pub $propname : $propt,
             )*
             $(
-                pub $componentname: Option<Box<$componenttype>>,
+                pub $componentname: $crate::component_slot!($($slot)? $componenttype),
             )*
         }
 
-        #[derive(Clone)]
         $vis struct [<$entityname Ref>] {
             $(
                 pub $propname : $propt,
             )*
             $(
-                pub $componentname: Option<usize>,
+                $componentname: Option<usize>,
             )*
             components_storage: std::rc::Weak<::std::cell::UnsafeCell<[<$entityname ComponentsStorage>]>>
         }
@@ -524,8 +951,9 @@ macro_rules! define_entity {
 
         $vis struct [<$entityname ComponentsStorage>] {
             $(
-                $componentname: $crate::slab::Slab<$componenttype>,
+                $componentname: $crate::ComponentSlab<$componenttype>,
             )*
+            borrow_tracker: $crate::BorrowTracker,
         }
         }
 
@@ -546,3 +974,11 @@ macro_rules! define_entity {
         }
     }
 }
+
+/// Type of a component on an owned entity, see `define_entity!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! component_slot {
+    (inline $componenttype:ty) => { Option<$componenttype> };
+    ($componenttype:ty) => { Option<Box<$componenttype>> };
+}
@@ -0,0 +1,93 @@
+/// Macro to run a closure-like body on every entity matching a query, with its components.
+///
+/// The first parameter is the id of the entity, every other parameter is a component:
+///
+/// * `&C` and `&mut C` fetch the component `C`, and require the entity to have it.
+/// * `Option<&C>` and `Option<&mut C>` fetch the component `C` if the entity has it.
+/// * `Without<C>` requires the entity to NOT have the component `C`.
+///
+/// At least one parameter must be a required component (`&C` or `&mut C`), see `Without`.
+/// If no component is borrowed mutably, the list is only borrowed immutably. At most 8
+/// components can be given.
+///
+/// The body is run as a closure, so `return` skips to the next entity.
+///
+/// ```ignore
+/// query!(entities, |id, pos: &mut Position, speed: &Speed, _f: Without<Frozen>| {
+///     pos.x += speed.x;
+/// });
+/// ```
+///
+/// is the same as:
+///
+/// ```ignore
+/// for (id, pos, speed, _f) in entities.iter_components_mut::<(Position, Speed, Without<Frozen>)>() {
+///     pos.x += speed.x;
+/// }
+/// ```
+#[macro_export]
+macro_rules! query {
+    ($list:expr, |$id:tt| $body:expr) => {
+        compile_error!("query! needs at least one component")
+    };
+    ($list:expr, |$id:tt, $($params:tt)*) => {
+        $crate::query!(@parse ($list) ($id) iter_components [] [] [] [] $($params)*)
+    };
+
+    // Parameters are parsed one by one, accumulating:
+    // * the types to fetch,
+    // * the parameters of the body closure,
+    // * the variables the fetched components are bound to,
+    // * the arguments given to the body closure.
+    // Every parameter gets its own `__c` variable. They do not collide, because every step of the
+    // parsing is a different macro expansion.
+
+    // &mut C
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : &mut $ty:ty , $($rest:tt)*) => {
+        $crate::query!(@parse $list $id iter_components_mut [$($f,)* $ty,] [$($p)* $name: &mut $ty,] [$($v)* __c] [$($a,)* __c,] $($rest)*)
+    };
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : &mut $ty:ty | $($rest:tt)*) => {
+        $crate::query!(@parse $list $id iter_components_mut [$($f,)* $ty,] [$($p)* $name: &mut $ty,] [$($v)* __c] [$($a,)* __c,] | $($rest)*)
+    };
+
+    // &C
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : & $ty:ty , $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* & $ty,] [$($p)* $name: & $ty,] [$($v)* __c] [$($a,)* __c,] $($rest)*)
+    };
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : & $ty:ty | $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* & $ty,] [$($p)* $name: & $ty,] [$($v)* __c] [$($a,)* __c,] | $($rest)*)
+    };
+
+    // Option<&mut C>
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : Option<&mut $ty:ty> , $($rest:tt)*) => {
+        $crate::query!(@parse $list $id iter_components_mut [$($f,)* Option<$ty>,] [$($p)* $name: Option<&mut $ty>,] [$($v)* __c] [$($a,)* __c,] $($rest)*)
+    };
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : Option<&mut $ty:ty> | $($rest:tt)*) => {
+        $crate::query!(@parse $list $id iter_components_mut [$($f,)* Option<$ty>,] [$($p)* $name: Option<&mut $ty>,] [$($v)* __c] [$($a,)* __c,] | $($rest)*)
+    };
+
+    // Option<&C>
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : Option<& $ty:ty> , $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* Option<$ty>,] [$($p)* $name: Option<& $ty>,] [$($v)* __c] [$($a,)* __c.map(|c| &*c),] $($rest)*)
+    };
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : Option<& $ty:ty> | $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* Option<$ty>,] [$($p)* $name: Option<& $ty>,] [$($v)* __c] [$($a,)* __c.map(|c| &*c),] | $($rest)*)
+    };
+
+    // Without<C>, or any other fetchable type
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : $ty:ty , $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* $ty,] [$($p)* $name: $ty,] [$($v)* __c] [$($a,)* __c,] $($rest)*)
+    };
+    (@parse $list:tt $id:tt $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] $name:tt : $ty:ty | $($rest:tt)*) => {
+        $crate::query!(@parse $list $id $iter [$($f,)* $ty,] [$($p)* $name: $ty,] [$($v)* __c] [$($a,)* __c,] | $($rest)*)
+    };
+
+    // end of the parameters
+    (@parse ($list:expr) ($id:tt) $iter:ident [$($f:ty,)*] [$($p:tt)*] [$($v:ident)*] [$($a:expr,)*] | $body:expr) => {{
+        #[allow(unused_variables)]
+        let mut __body = |$id: $crate::EntityId, $($p)*| $body;
+        for (__id, $($v,)*) in $list.$iter::<($($f,)*)>() {
+            __body(__id, $($a),*);
+        }
+    }};
+}
@@ -0,0 +1,197 @@
+use hashbrown::HashMap;
+use hibitset::{BitSet, BitSetLike};
+
+use crate::{EntityRefBase, EntityList, EntityId, Component, Commands};
+
+/// Operations queued on a list, which are applied by `EntityList::maintain`.
+pub (crate) struct Pending<E: EntityRefBase> {
+    pub (crate) commands: Commands<E>,
+    pub (crate) removals: Vec<EntityId>,
+    /// Number of `maintain` calls left before removing an entity, see `set_lifetime`
+    pub (crate) lifetimes: HashMap<EntityId, u32>,
+    /// Indexes of the entities to refresh, if refreshes are deferred, see `set_deferred_refresh`
+    pub (crate) refreshes: Option<BitSet>,
+}
+
+impl<E: EntityRefBase> Default for Pending<E> {
+    fn default() -> Self {
+        Pending {
+            commands: Commands::new(),
+            removals: Vec::new(),
+            lifetimes: HashMap::new(),
+            refreshes: None,
+        }
+    }
+}
+
+/// Commands cannot be cloned, every other operation is.
+impl<E: EntityRefBase> Clone for Pending<E> {
+    fn clone(&self) -> Self {
+        Pending {
+            commands: Commands::new(),
+            removals: self.removals.clone(),
+            lifetimes: self.lifetimes.clone(),
+            refreshes: self.refreshes.clone(),
+        }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Queue the removal of an entity, which happens at the next `maintain`.
+    ///
+    /// Unlike `remove`, this can be called while the entity is still used, for instance from a
+    /// system which only holds ids. Queuing the same id twice, or an id which does not exist, is fine.
+    pub fn remove_later(&mut self, id: EntityId) {
+        self.pending.removals.push(id);
+    }
+
+    /// Returns true if the entity is queued for removal, see `remove_later`.
+    pub fn is_removal_pending(&self, id: EntityId) -> bool {
+        self.pending.removals.contains(&id)
+    }
+
+    /// Remove the entity automatically after `frames` calls to `maintain`, for instance for particles
+    /// and projectiles. A lifetime of 0 or 1 removes it at the next `maintain`.
+    ///
+    /// Replaces the previous lifetime of the entity. Returns false if the entity does not exist.
+    pub fn set_lifetime(&mut self, id: EntityId, frames: u32) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+        self.pending.lifetimes.insert(id, frames);
+        true
+    }
+
+    /// Returns the number of `maintain` calls left before the entity is removed, see `set_lifetime`.
+    pub fn lifetime(&self, id: EntityId) -> Option<u32> {
+        self.pending.lifetimes.get(&id).copied()
+    }
+
+    /// Cancel the automatic removal of the entity, and return the lifetime it had left.
+    pub fn clear_lifetime(&mut self, id: EntityId) -> Option<u32> {
+        self.pending.lifetimes.remove(&id)
+    }
+
+    /// Choose whether the bitsets of an entity are updated as soon as its components change, or once
+    /// for all the changes, before the next mutable query or at `maintain`. Defaults to false.
+    ///
+    /// When refreshes are deferred, `refresh`, `add_component_for_entity`, `remove_component_for_entity`
+    /// and their batch versions only queue the entity, so adding and removing several components of
+    /// the same entity updates its bitsets, change ticks, observers and indices once.
+    ///
+    /// Queued entities are refreshed by `flush_refresh`, which `maintain` and every mutable query
+    /// (`iter_mut`, `iter_single_mut`, ...) call. Until then, observers are not called, and immutable
+    /// queries (`iter`, `count`, `contains_component`, ..., as well as views and frozen lists) see the
+    /// components the entities had at the last flush: they only borrow the list immutably, so they
+    /// cannot flush it. Call `flush_refresh` first to query the latest changes.
+    ///
+    /// Going back to immediate refreshes flushes the queued entities.
+    pub fn set_deferred_refresh(&mut self, deferred: bool) {
+        if deferred {
+            self.pending.refreshes.get_or_insert_with(BitSet::new);
+        } else {
+            self.flush_refresh();
+            self.pending.refreshes = None;
+        }
+    }
+
+    /// Returns true if refreshes are deferred, see `set_deferred_refresh`.
+    pub fn is_refresh_deferred(&self) -> bool {
+        self.pending.refreshes.is_some()
+    }
+
+    /// Refresh every entity queued since the last flush, see `set_deferred_refresh`.
+    pub fn flush_refresh(&mut self) {
+        let mut queued = match &mut self.pending.refreshes {
+            Some(queued) if !queued.is_empty() => std::mem::take(queued),
+            _ => return,
+        };
+        for index in (&queued).iter() {
+            if let Some(generation) = self.entities.generation_at(index as usize) {
+                self.apply_refresh(EntityId::new(index as usize, generation));
+            }
+        }
+        // give the allocation back, so that queuing is free until the next flush
+        queued.clear();
+        self.pending.refreshes = Some(queued);
+    }
+
+    /// Refresh the entity at `index` now if it is queued, before it is removed, see `set_deferred_refresh`.
+    pub (crate) fn flush_refresh_at(&mut self, index: usize) {
+        let queued = self.pending.refreshes.as_mut().is_some_and(|queued| queued.remove(index as u32));
+        if let (true, Some(generation)) = (queued, self.entities.generation_at(index)) {
+            self.apply_refresh(EntityId::new(index, generation));
+        }
+    }
+
+    /// Queue the refresh of an entity if refreshes are deferred. Returns false if they are not.
+    pub (crate) fn queue_refresh(&mut self, id: EntityId) -> bool {
+        match &mut self.pending.refreshes {
+            Some(queued) => {
+                if self.entities.contains(id) {
+                    queued.add(id.index as u32);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Deferred version of `add_component_for_entity`: only the change tick is set immediately.
+    pub (crate) fn add_component_deferred<C: Component<E>>(&mut self, id: EntityId, component: C) -> Option<C> {
+        self.lend_entity();
+        let e = match self.entities.get_mut(id) {
+            Some(e) => e,
+            None => return Some(component),
+        };
+        if e.has::<C>() {
+            self.ticks.set_changed(C::POSITION, id.index);
+        }
+        component.set(e);
+        self.queue_refresh(id);
+        None
+    }
+
+    /// Deferred version of `take_component_for_entity`.
+    pub (crate) fn take_component_deferred<C: Component<E>>(&mut self, id: EntityId) -> Option<C> {
+        self.lend_entity();
+        let component = self.entities.get_mut(id).and_then(C::take)?;
+        self.queue_refresh(id);
+        Some(component)
+    }
+
+    /// Apply every queued operation. Call this once per frame, at a point where nothing else uses
+    /// the list.
+    ///
+    /// This first forgets the removals recorded since the previous `maintain`, see `removed_since`.
+    /// It then applies the commands queued by `queue_commands`, refreshes the entities queued while
+    /// refreshes are deferred (see `set_deferred_refresh`), then removes the entities queued by
+    /// `remove_later`, and the entities whose lifetime ran out, see `set_lifetime`. Finally, every event
+    /// is dropped, see `send`.
+    pub fn maintain(&mut self) {
+        // before removing anything, so that the removals below stay visible until the next maintain
+        self.clear_removed();
+        // commands may queue other commands, which are applied at the next maintain
+        let mut commands = std::mem::take(&mut self.pending.commands);
+        commands.apply(self);
+        self.flush_refresh();
+        let entities = &self.entities;
+        let removals = &mut self.pending.removals;
+        self.pending.lifetimes.retain(|id, left| {
+            *left = left.saturating_sub(1);
+            if *left == 0 && entities.contains(*id) {
+                removals.push(*id);
+            }
+            *left > 0 && entities.contains(*id)
+        });
+
+        let removals = std::mem::take(&mut self.pending.removals);
+        for id in &removals {
+            self.remove(*id);
+        }
+        // give the allocation back, so that queuing is free next frame
+        self.pending.removals = removals;
+        self.pending.removals.clear();
+        self.events.clear();
+    }
+}
@@ -0,0 +1,65 @@
+use hibitset::BitSet;
+
+use crate::{EntityRefBase, EntityList, ComponentsStorage};
+
+/// The bytes held by a list, see `EntityList::memory_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Bytes of the arena of entities, including its free slots
+    pub arena: usize,
+    /// Storage and bitset of every component, in the order of `EntityBase::for_all_components`
+    pub components: Vec<ComponentMemory>,
+    /// Bytes of the bitsets of the tags, see `EntityList::tag`
+    pub tags: usize,
+}
+
+/// The bytes held by a component in a list, see `MemoryReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComponentMemory {
+    /// Name of the component, as written in `define_entity!`
+    pub name: &'static str,
+    /// Number of components stored
+    pub len: usize,
+    /// Number of components which can be stored without reallocating
+    pub capacity: usize,
+    /// Bytes of the slab of the component, including its free slots
+    pub storage: usize,
+    /// Bytes of the bitset of the component
+    pub bitset: usize,
+}
+
+impl MemoryReport {
+    /// Returns the bytes held by the arena, the component storages and the bitsets.
+    pub fn total(&self) -> usize {
+        self.arena + self.tags + self.components.iter().map(|c| c.storage + c.bitset).sum::<usize>()
+    }
+}
+
+/// Returns the bytes held by the layers of a bitset, along with the bitset itself.
+fn bitset_bytes(bitset: &BitSet) -> usize {
+    let words = bitset.layer0_as_slice().len() + bitset.layer1_as_slice().len() + bitset.layer2_as_slice().len();
+    std::mem::size_of::<BitSet>() + words * std::mem::size_of::<usize>()
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Returns the bytes held by the arena of entities, the storage and the bitset of every
+    /// component, and the bitsets of the tags.
+    ///
+    /// Nothing is given back to the allocator by itself: this tells what is worth compacting, see
+    /// `shrink_to_fit`. Components are counted by their size only, what they allocate themselves is
+    /// not. Indices like the spatial index or the archetypes are not counted either.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut components = Vec::new();
+        // SAFETY: the storage is owned by the list, which we borrow immutably
+        let cs = unsafe { &*self.components_storage.get() };
+        cs.for_each_slab(|name, len, capacity, slot_size| {
+            let bitset = bitset_bytes(self.bitsets.get_at(components.len()));
+            components.push(ComponentMemory { name, len, capacity, storage: capacity * slot_size, bitset });
+        });
+        MemoryReport {
+            arena: self.entities.allocated_bytes(),
+            components,
+            tags: self.bitsets.tags.values().map(bitset_bytes).sum(),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{Component, EntityBase, EntityRefBase, EntityList, EntityId};
+
+type EntityObserver<E> = Box<dyn FnMut(EntityId, &E)>;
+type RemovedObserver = Box<dyn FnMut(EntityId)>;
+
+/// Callbacks registered on a list, see `EntityList::on_insert`.
+///
+/// Component observers are stored by `ComponentBitSets` position.
+pub (crate) struct Observers<E> {
+    inserted: Vec<EntityObserver<E>>,
+    removed: Vec<EntityObserver<E>>,
+    component_added: Vec<Vec<EntityObserver<E>>>,
+    component_removed: Vec<Vec<RemovedObserver>>,
+}
+
+impl<E> Default for Observers<E> {
+    fn default() -> Self {
+        Observers {
+            inserted: Vec::new(),
+            removed: Vec::new(),
+            component_added: Vec::new(),
+            component_removed: Vec::new(),
+        }
+    }
+}
+
+impl<E: EntityBase> Observers<E> {
+    pub (crate) fn inserted(&mut self, id: EntityId, e: &E) {
+        for f in &mut self.inserted {
+            f(id, e);
+        }
+    }
+
+    pub (crate) fn removed(&mut self, id: EntityId, e: &E) {
+        for f in &mut self.removed {
+            f(id, e);
+        }
+    }
+
+    pub (crate) fn component_added(&mut self, position: usize, id: EntityId, e: &E) {
+        if let Some(observers) = self.component_added.get_mut(position) {
+            for f in observers {
+                f(id, e);
+            }
+        }
+    }
+
+    pub (crate) fn component_removed(&mut self, position: usize, id: EntityId) {
+        if let Some(observers) = self.component_removed.get_mut(position) {
+            for f in observers {
+                f(id);
+            }
+        }
+    }
+}
+
+fn at_position<T>(observers: &mut Vec<Vec<T>>, position: usize) -> &mut Vec<T> {
+    if observers.len() <= position {
+        observers.resize_with(position + 1, Vec::new);
+    }
+    &mut observers[position]
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Call `f` every time an entity is inserted (or restored, see `restore`), after its bitsets are
+    /// updated.
+    ///
+    /// Observers cannot access the list, they are meant to keep external data in sync (render handles,
+    /// physics bodies, ...). They are not kept by `clone`.
+    ///
+    /// # Example
+    ///
+    /// `entities.on_insert(move |id, _e| spawned.borrow_mut().push(id));`
+    pub fn on_insert<F: FnMut(EntityId, &E) + 'static>(&mut self, f: F) {
+        self.observers.inserted.push(Box::new(f));
+    }
+
+    /// Call `f` every time an entity is removed (or soft removed, see `remove_soft`), before its
+    /// components are dropped.
+    pub fn on_remove<F: FnMut(EntityId, &E) + 'static>(&mut self, f: F) {
+        self.observers.removed.push(Box::new(f));
+    }
+
+    /// Call `f` every time an entity gets the component `C`, including when it is inserted with it.
+    ///
+    /// Replacing a component which was already there, or mutating it, is not an addition.
+    ///
+    /// Additions through `get_mut` are only seen at the next `refresh`, like for the bitsets.
+    pub fn on_component_added<C: Component<E>, F: FnMut(EntityId, &C) + 'static>(&mut self, mut f: F) {
+        at_position(&mut self.observers.component_added, C::POSITION).push(Box::new(move |id, e: &E| {
+            if let Some(c) = C::get(e) {
+                f(id, c);
+            }
+        }));
+    }
+
+    /// Call `f` every time an entity loses the component `C`, including when it is removed.
+    ///
+    /// Removals through `get_mut` are only seen at the next `refresh`, like for the bitsets.
+    pub fn on_component_removed<C: Component<E>, F: FnMut(EntityId) + 'static>(&mut self, f: F) {
+        at_position(&mut self.observers.component_removed, C::POSITION).push(Box::new(f));
+    }
+
+    /// Remove every observer.
+    pub fn clear_observers(&mut self) {
+        self.observers = Default::default();
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::{EntityRefBase, EntityList, RefComponent, ComponentsStorage, MultiComponent, MultiComponentIterMut, Enabled};
+
+/// The components storage of a list, resolved once, see `EntityList::pin`.
+///
+/// Getting a component through an entity (`EntityBase::get`) first finds the storage of the entity,
+/// and checks that it still exists. In a hot loop over a single list, the storage can be pinned once
+/// instead: getting a component only checks that the entity belongs to the pinned list (a pointer
+/// comparison), and reads the slab of the component directly.
+///
+/// # Example
+///
+/// ```ignore
+/// let storage = entities.pin();
+/// for (id, e) in entities.iter::<(Speed, Position)>() {
+///     let speed: &Speed = storage.get(e).unwrap();
+/// }
+/// ```
+pub struct PinnedStorage<'a, E: EntityRefBase> {
+    cs: *const UnsafeCell<E::CS>,
+    _list: PhantomData<&'a EntityList<E>>,
+}
+
+impl<'a, E: EntityRefBase> Clone for PinnedStorage<'a, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, E: EntityRefBase> Copy for PinnedStorage<'a, E> {}
+
+/// Same as `PinnedStorage`, but components can be borrowed mutably, see `EntityList::iter_mut_pinned`.
+pub struct PinnedStorageMut<'a, E: EntityRefBase> {
+    cs: *const UnsafeCell<E::CS>,
+    _list: PhantomData<&'a mut EntityList<E>>,
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Pin the components storage of this list, to get the components of its entities without
+    /// finding the storage again for every access, see `PinnedStorage`.
+    pub fn pin(&self) -> PinnedStorage<'_, E> {
+        PinnedStorage {
+            cs: Rc::as_ptr(&self.components_storage),
+            _list: PhantomData,
+        }
+    }
+
+    /// Same as `iter_mut`, along with the pinned storage of this list, to get the components of the
+    /// entities mutably, see `PinnedStorage`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (storage, iter) = entities.iter_mut_pinned::<(Speed, Position)>();
+    /// for (id, e) in iter {
+    ///     let speed = *storage.get::<Speed>(e).unwrap();
+    ///     storage.get_mut::<Position>(e).unwrap().add(speed);
+    /// }
+    /// ```
+    pub fn iter_mut_pinned<'a, C: MultiComponent<'a, E>>(&'a mut self) -> (PinnedStorageMut<'a, E>, MultiComponentIterMut<'a, E, Enabled<'a, C::BitSet>>) {
+        let storage = PinnedStorageMut {
+            cs: Rc::as_ptr(&self.components_storage),
+            _list: PhantomData,
+        };
+        // `iter_mut` unshares the storage, so components can be borrowed mutably without copying
+        // the slabs again, see `ComponentSlab`
+        (storage, self.iter_mut::<C>())
+    }
+}
+
+/// Returns a pointer to the slab of `C` in the pinned storage, along with the key of the component.
+///
+/// Panics if the entity is not attached to the pinned storage.
+#[inline]
+fn pinned_slab<E: EntityRefBase, C: RefComponent<E>>(cs: *const UnsafeCell<E::CS>, e: &E) -> Option<(*mut slab::Slab<crate::ComponentCell<C>>, usize)> {
+    let key = C::get_cs_id(e)?;
+    assert!(std::ptr::eq(e.cs_ptr(), cs), "entity does not belong to the pinned list");
+    // SAFETY: the storage belongs to the list, which is borrowed for as long as the pin exists
+    unsafe {
+        let cs = UnsafeCell::raw_get(cs);
+        Some((C::get_single_cs_ptr(cs), key))
+    }
+}
+
+impl<'a, E: EntityRefBase> PinnedStorage<'a, E> {
+    /// Returns the component `C` of the entity, see `EntityBase::get`.
+    ///
+    /// Panics if the entity is not one of the pinned list.
+    #[inline]
+    pub fn get<'e, C: RefComponent<E>>(&self, e: &'e E) -> Option<&'e C> where 'a: 'e {
+        let (slab, key) = pinned_slab::<E, C>(self.cs, e)?;
+        // SAFETY: the list is borrowed immutably, so no component is borrowed mutably
+        unsafe {
+            (*UnsafeCell::raw_get(self.cs)).borrow_tracker().check_shared::<E, C>();
+            (*slab).get(key).map(|c| c.get())
+        }
+    }
+}
+
+impl<'a, E: EntityRefBase> PinnedStorageMut<'a, E> {
+    /// Returns the component `C` of the entity, see `EntityBase::get`.
+    ///
+    /// Panics if the entity is not one of the pinned list.
+    #[inline]
+    pub fn get<'e, C: RefComponent<E>>(&self, e: &'e E) -> Option<&'e C> where 'a: 'e {
+        let (slab, key) = pinned_slab::<E, C>(self.cs, e)?;
+        // SAFETY: the component is borrowed along with its entity, see `storage_ptr` in `define_entity!`
+        unsafe {
+            let cs = UnsafeCell::raw_get(self.cs);
+            (*cs).borrow_tracker().check_shared::<E, C>();
+            C::get_borrower(cs).borrow((*cs).borrow_tracker(), e as *const E as usize);
+            (*slab).get(key).map(|c| c.get())
+        }
+    }
+
+    /// Returns the component `C` of the entity mutably, see `EntityBase::get_mut`.
+    ///
+    /// Panics if the entity is not one of the pinned list.
+    #[inline]
+    pub fn get_mut<'e, C: RefComponent<E>>(&self, e: &'e mut E) -> Option<&'e mut C> where 'a: 'e {
+        let (slab, key) = pinned_slab::<E, C>(self.cs, e)?;
+        // SAFETY: the component is borrowed along with its entity, which is borrowed mutably, and the
+        // slab is not shared with another list since `iter_mut_pinned` unshared it
+        unsafe {
+            let cs = UnsafeCell::raw_get(self.cs);
+            (*cs).borrow_tracker().check_exclusive::<E, C>();
+            C::get_borrower(cs).borrow((*cs).borrow_tracker(), e as *const E as usize);
+            (*slab).get(key).map(|c| c.get_mut())
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{EntityRefBase, EntityList};
+
+/// Statistics of a query type, see `EntityList::query_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Number of iterators created for this query
+    pub runs: u64,
+    /// Number of entities returned by the bitset
+    pub bitset_hits: u64,
+    /// Number of entities actually yielded, after filters such as `Changed<C>`
+    pub matches: u64,
+    /// Time spent inside `Iterator::next`, excluding the body of the loop
+    pub time: Duration,
+}
+
+pub (crate) type QueryStatsMap = RefCell<HashMap<&'static str, Rc<Cell<QueryStats>>>>;
+
+/// Records the stats of a single iterator into the stats of its query type.
+///
+/// This does not borrow the list, so that profiled iterators do not outlive their last use.
+#[derive(Clone)]
+pub (crate) struct QueryProfile {
+    stats: Rc<Cell<QueryStats>>,
+    /// false if the matches are counted outside of the iterator, by a filter
+    count_matches: bool,
+}
+
+impl QueryProfile {
+    pub (crate) fn new(stats: &QueryStatsMap, name: &'static str) -> Self {
+        let stats = stats.borrow_mut().entry(name).or_default().clone();
+        stats.set(QueryStats { runs: stats.get().runs + 1, ..stats.get() });
+        QueryProfile {
+            stats,
+            count_matches: true,
+        }
+    }
+
+    /// Same as `new`, but matches are not counted, see `record_match`.
+    pub (crate) fn new_filtered(stats: &QueryStatsMap, name: &'static str) -> Self {
+        QueryProfile {
+            count_matches: false,
+            ..QueryProfile::new(stats, name)
+        }
+    }
+
+    #[inline]
+    pub (crate) fn record_next(&self, start: Instant, hit: bool) {
+        let mut stats = self.stats.get();
+        stats.time += start.elapsed();
+        if hit {
+            stats.bitset_hits += 1;
+            if self.count_matches {
+                stats.matches += 1;
+            }
+        }
+        self.stats.set(stats);
+    }
+
+    #[inline]
+    pub (crate) fn record_match(&self) {
+        let mut stats = self.stats.get();
+        stats.matches += 1;
+        self.stats.set(stats);
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Returns the statistics of every query type run on this list, by query type name.
+    ///
+    /// Only `iter`, `iter_mut`, `iter_since`, `iter_mut_since` and `Query` are profiled.
+    pub fn query_stats(&self) -> HashMap<&'static str, QueryStats> {
+        self.query_stats.borrow().iter().map(|(name, stats)| (*name, stats.get())).collect()
+    }
+
+    /// Reset all the query statistics, see `query_stats`.
+    pub fn reset_query_stats(&mut self) {
+        self.query_stats.get_mut().clear();
+    }
+}
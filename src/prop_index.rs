@@ -0,0 +1,188 @@
+use std::any::Any;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use hashbrown::HashMap;
+
+use crate::{EntityRefBase, EntityList, EntityId};
+
+/// Handle to an index on a property, returned by `EntityList::index_by`.
+///
+/// A handle must only be used with the list which created it, or with a clone of this list.
+pub struct PropIndex<K> {
+    position: usize,
+    _phantom: PhantomData<fn() -> K>,
+}
+
+impl<K> Clone for PropIndex<K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K> Copy for PropIndex<K> {}
+
+impl<K> std::fmt::Debug for PropIndex<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PropIndex").field("position", &self.position).finish()
+    }
+}
+
+/// The entities of a list, grouped by the value of a key read from every entity.
+struct KeyIndex<E, K> {
+    key: fn(&E) -> K,
+    by_key: HashMap<K, Vec<EntityId>>,
+    /// Key of every entity, by entity index
+    entity_keys: Vec<Option<K>>,
+}
+
+impl<E, K: Hash + Eq + Clone> KeyIndex<E, K> {
+    fn remove_from_key(&mut self, key: &K, id: EntityId) {
+        if let Some(ids) = self.by_key.get_mut(key) {
+            if let Some(i) = ids.iter().position(|other| *other == id) {
+                ids.swap_remove(i);
+            }
+            if ids.is_empty() {
+                self.by_key.remove(key);
+            }
+        }
+    }
+}
+
+/// Type-erased `KeyIndex`, so that indices with different key types can be stored together.
+trait AnyKeyIndex<E> {
+    fn update(&mut self, id: EntityId, e: &E);
+    fn remove(&mut self, id: EntityId);
+    fn clear(&mut self);
+    fn clone_box(&self) -> Box<dyn AnyKeyIndex<E>>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<E: 'static, K: Hash + Eq + Clone + 'static> AnyKeyIndex<E> for KeyIndex<E, K> {
+    fn update(&mut self, id: EntityId, e: &E) {
+        let new_key = (self.key)(e);
+        if let Some(old_key) = self.entity_keys.get_mut(id.index).and_then(|k| k.take()) {
+            if old_key == new_key {
+                self.entity_keys[id.index] = Some(old_key);
+                return;
+            }
+            self.remove_from_key(&old_key, id);
+        }
+        if self.entity_keys.len() <= id.index {
+            self.entity_keys.resize(id.index + 1, None);
+        }
+        self.by_key.entry(new_key.clone()).or_default().push(id);
+        self.entity_keys[id.index] = Some(new_key);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        if let Some(key) = self.entity_keys.get_mut(id.index).and_then(|k| k.take()) {
+            self.remove_from_key(&key, id);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.by_key.clear();
+        self.entity_keys.clear();
+    }
+
+    fn clone_box(&self) -> Box<dyn AnyKeyIndex<E>> {
+        Box::new(KeyIndex {
+            key: self.key,
+            by_key: self.by_key.clone(),
+            entity_keys: self.entity_keys.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Every property index of a list, see `EntityList::index_by`.
+pub (crate) struct PropIndices<E> {
+    indices: Vec<Box<dyn AnyKeyIndex<E>>>,
+}
+
+impl<E> Default for PropIndices<E> {
+    fn default() -> Self {
+        PropIndices { indices: Vec::new() }
+    }
+}
+
+impl<E> Clone for PropIndices<E> {
+    fn clone(&self) -> Self {
+        PropIndices { indices: self.indices.iter().map(|index| index.clone_box()).collect() }
+    }
+}
+
+impl<E> PropIndices<E> {
+    /// Move the entity to the key of its current value, in every index.
+    pub (crate) fn update(&mut self, id: EntityId, e: &E) {
+        for index in &mut self.indices {
+            index.update(id, e);
+        }
+    }
+
+    pub (crate) fn remove(&mut self, id: EntityId) {
+        for index in &mut self.indices {
+            index.remove(id);
+        }
+    }
+
+    /// Remove every entity from every index, keeping the indices.
+    pub (crate) fn clear(&mut self) {
+        for index in &mut self.indices {
+            index.clear();
+        }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Index the entities by the value returned by `key`, usually a property, and return a handle
+    /// to query the index with `iter_where`.
+    ///
+    /// Like the spatial index, the index is kept up to date on `insert`, `remove`, `refresh`,
+    /// `add_component_for_entity` and `remove_component_for_entity`.
+    ///
+    /// **WARNING**: After modifying the indexed value via `get_mut` or `iter_mut`, you must call
+    /// `refresh`, otherwise the entity will stay under its previous key.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let by_team = entities.index_by(|e| e.team);
+    /// for (id, e) in entities.iter_where(by_team, &Team::Red) { }
+    /// ```
+    pub fn index_by<K: Hash + Eq + Clone + 'static>(&mut self, key: fn(&E) -> K) -> PropIndex<K> {
+        let mut index = KeyIndex {
+            key,
+            by_key: HashMap::new(),
+            entity_keys: Vec::new(),
+        };
+        for (id, e) in self.entities.iter() {
+            index.update(id, e);
+        }
+        self.prop_indices.indices.push(Box::new(index));
+        PropIndex {
+            position: self.prop_indices.indices.len() - 1,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Iterate over all entities whose indexed value is equal to `key`.
+    ///
+    /// The order of iteration is unspecified.
+    ///
+    /// Panics if `index` was not created by this list, see `index_by`.
+    pub fn iter_where<'a, K: Hash + Eq + Clone + 'static>(&'a self, index: PropIndex<K>, key: &K) -> impl Iterator<Item=(EntityId, &'a E)> {
+        let index = self.prop_indices.indices.get(index.position)
+            .and_then(|index| index.as_any().downcast_ref::<KeyIndex<E, K>>())
+            .expect("the index was not created by this list");
+        index.by_key.get(key)
+            .map(|ids| ids.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(move |id| self.entities.get(*id).map(|e| (*id, e)))
+    }
+}
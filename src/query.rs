@@ -0,0 +1,84 @@
+use std::marker::PhantomData;
+
+use hibitset::BitSetLike;
+
+use crate::{EntityRefBase, EntityList, MultiComponent, MultiComponentIter, MultiComponentIterMut, Enabled};
+use crate::change_ticks::ChangeRecorder;
+#[cfg(feature = "profiling")]
+use crate::profiling::QueryProfile;
+
+/// A query over the components (C1, C2, C3, ...), as a value.
+///
+/// A `Query` is not faster than `EntityList::iter::<C>()`: every component has a fixed bitset
+/// position (`Component::POSITION`), so both resolve the bitsets at compile time. It is a zero-sized
+/// handle, which names the query type once, so that it can be stored, for instance in a system,
+/// and run on any list of the same entity type.
+///
+/// Like `iter`, a `Query` cannot use `Added` or `Changed`.
+///
+/// # Example
+///
+/// ```ignore
+/// let query = Query::<(Speed, Gravity)>::new(&entities);
+/// loop {
+///     for (id, entity) in query.iter_mut(&mut entities) { }
+/// }
+/// ```
+pub struct Query<C> {
+    _phantom: PhantomData<C>,
+}
+
+impl<C> Query<C> {
+    /// Create the query. The list is only used to infer the entity type.
+    pub fn new<'a, E: EntityRefBase>(_list: &'a EntityList<E>) -> Self where C: MultiComponent<'a, E> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        Query {
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Iterate over all entities matching this query, see `EntityList::iter`
+    ///
+    /// Like `EntityList::iter`, this does not flush deferred refreshes.
+    pub fn iter<'a, E: EntityRefBase>(&self, list: &'a EntityList<E>) -> MultiComponentIter<'a, E, Enabled<'a, C::BitSet>>
+    where C: MultiComponent<'a, E> {
+        let bitset = list.bitsets.enabled(C::bitset(&list.bitsets));
+        #[allow(unused_mut)]
+        let mut iter = MultiComponentIter::new(bitset.iter(), &list.entities);
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(QueryProfile::new(&list.query_stats, std::any::type_name::<C>()));
+        }
+        iter
+    }
+
+    /// Iterate over all entities matching this query mutably, see `EntityList::iter_mut`
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    pub fn iter_mut<'a, E: EntityRefBase>(&self, list: &'a mut EntityList<E>) -> MultiComponentIterMut<'a, E, Enabled<'a, C::BitSet>>
+    where C: MultiComponent<'a, E> {
+        list.flush_refresh();
+        list.lend_entities();
+        let bitset = list.bitsets.enabled(C::bitset(&list.bitsets));
+        let mut iter = MultiComponentIterMut::new(bitset.iter(), &mut list.entities);
+        iter.changes = Some(ChangeRecorder { ticks: &mut list.ticks, bitsets: &list.bitsets });
+        #[cfg(feature = "profiling")] {
+            iter.profile = Some(QueryProfile::new(&list.query_stats, std::any::type_name::<C>()));
+        }
+        iter
+    }
+}
+
+impl<C> Clone for Query<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C> Copy for Query<C> {}
+
+impl<C> std::fmt::Debug for Query<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Query").field("type", &std::any::type_name::<C>()).finish()
+    }
+}
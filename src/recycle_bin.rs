@@ -0,0 +1,119 @@
+use std::any::TypeId;
+
+use hashbrown::HashMap;
+
+use crate::{EntityRefBase, EntityList, EntityId};
+use crate::entity_list::refresh_bitsets;
+
+/// Entities removed by `EntityList::remove_soft`, by id.
+#[derive(Clone)]
+pub (crate) struct RecycleBin<E> {
+    pub (crate) entities: HashMap<EntityId, SoftRemoved<E>>,
+}
+
+#[derive(Clone)]
+pub (crate) struct SoftRemoved<E> {
+    pub (crate) entity: E,
+    pub (crate) tags: Vec<TypeId>,
+}
+
+impl<E> Default for RecycleBin<E> {
+    fn default() -> Self {
+        RecycleBin { entities: HashMap::new() }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Hide an entity from every query and lookup, but keep it along with its components, tags and
+    /// uuid, so that `restore` can bring it back with the same id.
+    ///
+    /// Until then, the id is not contained in the list, and is not given to other entities. See `purge`
+    /// to remove soft removed entities for good. Soft removed entities are not serialized.
+    ///
+    /// Returns false if the entity does not exist.
+    pub fn remove_soft(&mut self, id: EntityId) -> bool {
+        if self.contains(id) {
+            self.flush_refresh_at(id.index);
+        }
+        let e = match self.entities.remove_reserved(id) {
+            Some(e) => e,
+            None => return false,
+        };
+        self.sync_liveness(id.index);
+        self.observers.removed(id, &e);
+        e.for_each_component_position(|p: usize, is_active: bool| {
+            if is_active {
+                self.bitsets.get_at_mut(p).remove(id.index as u32);
+                self.ticks.set_removed(p, id);
+                self.observers.component_removed(p, id);
+            }
+        });
+        let tags = self.bitsets.tags.iter()
+            .filter(|(_, bitset)| bitset.contains(id.index as u32))
+            .map(|(type_id, _)| *type_id)
+            .collect();
+        self.bitsets.remove_tags(id.index as u32);
+        self.bitsets.occupied.remove(id.index as u32);
+        self.bitsets.cached.remove(id.index as u32);
+        if let Some(spatial) = &mut self.spatial {
+            spatial.remove(id);
+        }
+        if let Some(archetypes) = &mut self.archetypes {
+            archetypes.remove(id);
+        }
+        self.prop_indices.remove(id);
+        self.recycle_bin.entities.insert(id, SoftRemoved { entity: e, tags });
+        true
+    }
+
+    /// Returns true if the entity was removed by `remove_soft`, and can be restored.
+    pub fn is_soft_removed(&self, id: EntityId) -> bool {
+        self.recycle_bin.entities.contains_key(&id)
+    }
+
+    /// Bring back an entity removed by `remove_soft`, with the same id.
+    ///
+    /// Returns false if the entity is not soft removed.
+    pub fn restore(&mut self, id: EntityId) -> bool {
+        let SoftRemoved { entity, tags } = match self.recycle_bin.entities.remove(&id) {
+            Some(soft_removed) => soft_removed,
+            None => return false,
+        };
+        if self.entities.insert_reserved(id, entity).is_err() {
+            panic!("FATAL: slot of soft removed entity {id} is not reserved");
+        }
+        self.sync_liveness(id.index);
+        self.bitsets.occupied.add(id.index as u32);
+        let e = &self.entities[id];
+        refresh_bitsets(&mut self.bitsets, &mut self.ticks, &mut self.observers, id, e);
+        for type_id in tags {
+            self.bitsets.tag_mut(type_id).add(id.index as u32);
+        }
+        self.bitsets.update_cached(id.index as u32);
+        if let Some(spatial) = &mut self.spatial {
+            spatial.update(id, e);
+        }
+        if let Some(archetypes) = &mut self.archetypes {
+            archetypes.update(&self.bitsets, id, e);
+        }
+        self.prop_indices.update(id, e);
+        self.observers.inserted(id, e);
+        true
+    }
+
+    /// Remove for good every entity removed by `remove_soft`, and return how many there were.
+    pub fn purge(&mut self) -> usize {
+        let count = self.recycle_bin.entities.len();
+        for (id, soft_removed) in self.recycle_bin.entities.drain() {
+            // SAFETY: the storage is owned by the list, which we borrow mutably
+            unsafe {
+                drop(soft_removed.entity.to_owned(&mut *self.components_storage.get()));
+            }
+            if let Some(uuids) = &mut self.uuids {
+                uuids.remove(id);
+            }
+            self.entities.release_reserved(id);
+        }
+        count
+    }
+}
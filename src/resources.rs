@@ -0,0 +1,69 @@
+use std::any::{Any, TypeId};
+
+use crate::{EntityRefBase, EntityList, TypeIdMap};
+
+struct Resource {
+    value: Box<dyn Any>,
+    clone: fn(&dyn Any) -> Box<dyn Any>,
+}
+
+fn clone_resource<R: Clone + 'static>(value: &dyn Any) -> Box<dyn Any> {
+    Box::new(value.downcast_ref::<R>().expect("FATAL: resource has the wrong type").clone())
+}
+
+/// Singletons attached to a list, at most one by type.
+#[derive(Default)]
+pub (crate) struct Resources {
+    resources: TypeIdMap<Resource>,
+}
+
+impl Clone for Resources {
+    fn clone(&self) -> Self {
+        let resources = self.resources.iter()
+            .map(|(type_id, r)| (*type_id, Resource { value: (r.clone)(&*r.value), clone: r.clone }))
+            .collect();
+        Resources { resources }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Attach a resource to this list, such as the current time or the settings of the game.
+    ///
+    /// There is at most one resource by type: the previous resource `R` is returned, if any.
+    /// Resources are kept by `clear`, and are cloned with the list. They are neither serialized
+    /// nor moved by `merge`.
+    ///
+    /// # Example
+    ///
+    /// `entities.insert_resource(DeltaTime(0.016));`
+    pub fn insert_resource<R: Clone + 'static>(&mut self, resource: R) -> Option<R> {
+        let resource = Resource { value: Box::new(resource), clone: clone_resource::<R> };
+        self.resources.resources.insert(TypeId::of::<R>(), resource)
+            .map(|r| *r.value.downcast::<R>().expect("FATAL: resource has the wrong type"))
+    }
+
+    /// Detach the resource `R` from this list, and return it.
+    ///
+    /// Removing a resource and inserting it back allows to use it while iterating mutably.
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources.resources.remove(&TypeId::of::<R>())
+            .map(|r| *r.value.downcast::<R>().expect("FATAL: resource has the wrong type"))
+    }
+
+    /// Retrieves the resource `R` immutably.
+    pub fn resource<R: 'static>(&self) -> Option<&R> {
+        self.resources.resources.get(&TypeId::of::<R>())
+            .and_then(|r| r.value.downcast_ref::<R>())
+    }
+
+    /// Retrieves the resource `R` mutably.
+    pub fn resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.resources.resources.get_mut(&TypeId::of::<R>())
+            .and_then(|r| r.value.downcast_mut::<R>())
+    }
+
+    /// Returns true if a resource `R` is attached to this list.
+    pub fn has_resource<R: 'static>(&self) -> bool {
+        self.resources.resources.contains_key(&TypeId::of::<R>())
+    }
+}
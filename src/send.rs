@@ -0,0 +1,141 @@
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{EntityRefBase, EntityList, EntityId, ComponentBitSets, ChangeTicks};
+use crate::genarena::{GenArena, Entry};
+use crate::spatial::SpatialIndex;
+use crate::archetypes::Archetypes;
+use crate::uuids::Uuids;
+
+/// An `EntityList` which can be sent to another thread, see `EntityList::into_send`.
+///
+/// `EntityList` is neither `Send` nor `Sync`, since every entity shares the component storage through
+/// an `Rc`. This holds the entities without their handle to the storage instead, like serialization
+/// does, so it is `Send` and `Sync` as long as the properties and the components are.
+///
+/// With the `cow` feature, the components may still be shared with a clone of the list left on this
+/// thread, so they must be `Sync` as well for the list to be `Send`.
+///
+/// # Example
+///
+/// ```ignore
+/// let level = entities.into_send();
+/// let simulation = std::thread::spawn(move || {
+///     let mut entities = level.into_list();
+///     simulate(&mut entities);
+///     entities.into_send()
+/// });
+/// let entities = simulation.join().unwrap().into_list();
+/// ```
+pub struct SendEntityList<E: EntityRefBase> {
+    entities: GenArena<E::Naked>,
+    components_storage: E::CS,
+    bitsets: ComponentBitSets,
+    ticks: ChangeTicks,
+    spatial: Option<SpatialIndex<E>>,
+    archetypes: Option<Archetypes>,
+    uuids: Option<Uuids>,
+}
+
+impl<E: EntityRefBase> SendEntityList<E> {
+    /// Rebuild the list, on the current thread.
+    pub fn into_list(self) -> EntityList<E> {
+        let components_storage = Rc::new(UnsafeCell::new(self.components_storage));
+        let mut list = EntityList::with_bitset_capacity(0);
+        // SAFETY: the keys come from `into_send`, which took them from a list along with its storage
+        list.entities = self.entities.map(|naked| unsafe { E::from_naked(naked, &components_storage) });
+        list.components_storage = components_storage;
+        list.bitsets = self.bitsets;
+        list.ticks = self.ticks;
+        list.spatial = self.spatial;
+        list.archetypes = self.archetypes;
+        list.uuids = self.uuids;
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Convert this list to a list which can be sent to another thread, see `SendEntityList`.
+    ///
+    /// Entities keep their ids, along with their tags, change ticks and uuids, as well as the spatial
+    /// index and the archetypes. Soft removed entities are purged. Property indices, resources, observers, events and
+    /// queued operations are not kept.
+    pub fn into_send(mut self) -> SendEntityList<E> {
+        self.purge();
+        let entities = std::mem::take(&mut self.entities).map(|e| e.as_naked());
+        // the entities only held weak references, the list holds the only `Rc` to its storage
+        let components_storage = match Rc::try_unwrap(self.components_storage) {
+            Ok(cs) => cs.into_inner(),
+            Err(_) => unreachable!("the storage of a list is only held by the list"),
+        };
+        SendEntityList {
+            entities,
+            components_storage,
+            bitsets: self.bitsets,
+            ticks: self.ticks,
+            spatial: self.spatial,
+            archetypes: self.archetypes,
+            uuids: self.uuids,
+        }
+    }
+
+    /// Move the entities of lists filled on other threads (the shards) into this list, and return
+    /// the new id of every moved entity by its id in its shard, for every shard in order.
+    ///
+    /// Components are moved from the storage of the shards to the storage of this list, without
+    /// being boxed as `merge` does. Tags are kept, and so are uuids, unless they are already used in
+    /// this list. Change ticks are not: every moved entity is recorded as added.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let workers: Vec<_> = regions.into_iter().map(|region| std::thread::spawn(move || {
+    ///     let mut shard = EntityList::new();
+    ///     generate(region, &mut shard);
+    ///     shard.into_send()
+    /// })).collect();
+    /// let mut entities = EntityList::new();
+    /// let remaps = entities.merge_shards(workers.into_iter().map(|w| w.join().unwrap()));
+    /// ```
+    pub fn merge_shards<I>(&mut self, shards: I) -> Vec<HashMap<EntityId, EntityId>>
+    where I: IntoIterator<Item=SendEntityList<E>> {
+        shards.into_iter().map(|shard| self.merge_shard(shard)).collect()
+    }
+
+    fn merge_shard(&mut self, mut shard: SendEntityList<E>) -> HashMap<EntityId, EntityId> {
+        let mut remap = HashMap::with_capacity(shard.len());
+        self.reserve(shard.len());
+        let tags: Vec<_> = shard.bitsets.tags.drain().collect();
+        for (index, entry) in shard.entities.take_entries().into_iter().enumerate() {
+            let (old_id, naked) = match entry {
+                Entry::Occupied { generation, value } => (EntityId::new(index, generation), value),
+                Entry::Free { .. } => continue,
+            };
+            // SAFETY: the storage is owned by the list, which we borrow mutably
+            let naked = E::move_components(naked, &mut shard.components_storage, unsafe { &mut *self.components_storage.get() });
+            // SAFETY: the keys were just given by the storage of this list
+            let id = self.entities.push(unsafe { E::from_naked(naked, &self.components_storage) });
+            self.register_inserted(id);
+            if let (Some(uuids), Some(uuid)) = (&mut self.uuids, shard.uuids.as_ref().and_then(|u| u.get(old_id))) {
+                uuids.assign(id, Some(uuid));
+            }
+            for (type_id, bitset) in &tags {
+                if bitset.contains(index as u32) {
+                    self.bitsets.tag_mut(*type_id).add(id.index as u32);
+                }
+            }
+            self.bitsets.update_cached(id.index as u32);
+            remap.insert(old_id, id);
+        }
+        remap
+    }
+}
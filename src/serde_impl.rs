@@ -1,9 +1,11 @@
-use crate::{EntityList, EntityRefBase};
+use crate::{EntityList, EntityRefBase, EntityId};
 
 use serde::de::{self, Deserialize, Deserializer, Visitor, SeqAccess, MapAccess};
 use serde::ser::{Serialize, Serializer, SerializeStruct};
+use hibitset::BitSetLike;
 
 use crate::genarena::{GenArena, Entry};
+use crate::uuids::{Uuid, Uuids};
 
 impl<E> Serialize for EntityList<E>
 where E: EntityRefBase, E::CS: Serialize, E::Naked: Serialize
@@ -12,7 +14,7 @@ where E: EntityRefBase, E::CS: Serialize, E::Naked: Serialize
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("EntityList", 4)?;
+        let mut state = serializer.serialize_struct("EntityList", 6)?;
         let entries = self.entities.entries.iter().map(|e| {
             e.as_ref().map(|v| v.as_naked())
         }).collect::<Vec<_>>();
@@ -20,6 +22,10 @@ where E: EntityRefBase, E::CS: Serialize, E::Naked: Serialize
         state.serialize_field("length", &self.entities.length)?;
         state.serialize_field("next_free", &self.entities.next_free)?;
         state.serialize_field("components_storage", unsafe { &*self.components_storage.get() })?;
+        state.serialize_field("uuids", &self.uuids.as_ref().map(Uuids::as_raw))?;
+        // unlike other tags, disabled entities are kept, see `EntityList::set_enabled`. The field is
+        // read as an `Option`, which is `None` for lists saved before it was added
+        state.serialize_field("disabled", &Some(self.bitsets.disabled().iter().collect::<Vec<u32>>()))?;
         state.end()
     }
 }
@@ -34,7 +40,7 @@ impl<'de, E> Deserialize<'de> for EntityList<E> where E: EntityRefBase, E::CS: D
             type Value = EntityList<E>;
             
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("EntityList struct with 4 fields: entries, length, next_free, components_storage")
+                formatter.write_str("EntityList struct with 6 fields: entries, length, next_free, components_storage, uuids, disabled")
             }
 
             fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error> where V: SeqAccess<'de>,
@@ -47,14 +53,32 @@ impl<'de, E> Deserialize<'de> for EntityList<E> where E: EntityRefBase, E::CS: D
                     .ok_or_else(|| de::Error::invalid_length(2, &self))?;
                 let components_storage: E::CS  = seq.next_element()?
                     .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                // lists saved before uuids were added do not have this field
+                let uuids: Option<(u64, u64, Vec<Option<Uuid>>)> = seq.next_element()?.flatten();
+                // lists saved before entities could be disabled do not have this field
+                let disabled: Vec<u32> = seq.next_element::<Option<Vec<u32>>>()?.flatten().unwrap_or_default();
                 let components_storage = std::rc::Rc::new(std::cell::UnsafeCell::new(components_storage));
+                // SAFETY: no component is accessed before the keys are checked below
                 let entries = entries.into_iter().map(|e| {
-                    e.map(|v| E::from_naked(v, &components_storage))
+                    e.map(|v| unsafe { E::from_naked(v, &components_storage) })
                 }).collect();
-                Ok(EntityList::from_raw(
+                let mut list = EntityList::from_raw(
                     GenArena::from_raw(entries, length, next_free),
                     components_storage
-                ))
+                );
+                let report = list.validate();
+                if !report.dangling_components.is_empty() || !report.shared_components.is_empty() {
+                    return Err(de::Error::custom("entities reference missing or shared components"));
+                }
+                list.uuids = uuids.map(|(seed, counter, by_index)| {
+                    Uuids::from_raw(seed, counter, by_index, &list.entities)
+                });
+                for index in disabled {
+                    if let Some(generation) = list.entities.generation_at(index as usize) {
+                        list.set_enabled(EntityId::new(index as usize, generation), false);
+                    }
+                }
+                Ok(list)
             }
 
             fn visit_map<V>(self, _map: V) -> Result<Self::Value, V::Error> where V: MapAccess<'de>,
@@ -67,7 +91,7 @@ impl<'de, E> Deserialize<'de> for EntityList<E> where E: EntityRefBase, E::CS: D
 
         deserializer.deserialize_struct(
             "EntityList",
-            &["entries", "length", "next_free", "components_storage"],
+            &["entries", "length", "next_free", "components_storage", "uuids", "disabled"],
             EntityListVisitor { _phantom: std::marker::PhantomData }
         )
         // let arena: GenArena<E> = Deserialize::deserialize(deserializer)?;
@@ -0,0 +1,161 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use crate::{EntityRefBase, CloneStorage, EntityList, EntityId, Tick};
+use crate::prop_index::PropIndices;
+use crate::resources::Resources;
+
+/// A copy of the entities of a list at a given tick, see `EntityList::snapshot`.
+pub struct Snapshot<E: EntityRefBase> {
+    list: EntityList<E>,
+    tick: Tick,
+}
+
+impl<E: EntityRefBase> Snapshot<E> {
+    /// Returns the tick at which the snapshot was taken.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    /// Returns the entities of the snapshot.
+    pub fn list(&self) -> &EntityList<E> {
+        &self.list
+    }
+
+    /// Returns the entities of the snapshot, for instance to restore them.
+    pub fn into_list(self) -> EntityList<E> {
+        self.list
+    }
+}
+
+impl<E: EntityRefBase> Clone for Snapshot<E> where E::CS: CloneStorage {
+    fn clone(&self) -> Self {
+        Snapshot {
+            list: self.list.clone(),
+            tick: self.tick,
+        }
+    }
+}
+
+/// An entity which had components added, changed or removed between two snapshots.
+pub struct ChangedEntity<E: EntityRefBase> {
+    pub id: EntityId,
+    /// The components which were added, changed or removed
+    pub components: Vec<TypeId>,
+    /// The entity in the newest snapshot
+    pub entity: E::Owned,
+}
+
+/// The differences between two snapshots, see `EntityList::diff`.
+pub struct Diff<E: EntityRefBase> {
+    /// Entities which only exist in the newest snapshot
+    pub spawned: Vec<(EntityId, E::Owned)>,
+    /// Entities which only exist in the oldest snapshot
+    pub despawned: Vec<EntityId>,
+    pub changed: Vec<ChangedEntity<E>>,
+}
+
+impl<E: EntityRefBase> Diff<E> {
+    /// Returns true if the two snapshots had the same entities and components.
+    pub fn is_empty(&self) -> bool {
+        self.spawned.is_empty() && self.despawned.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Copy the entities and their components, along with the change ticks, and advance the tick.
+    ///
+    /// Indices, uuids and resources are not part of the snapshot.
+    ///
+    /// Since the tick is advanced, every later change can be told apart from the snapshot, see `diff`.
+    pub fn snapshot(&mut self) -> Snapshot<E> where E::CS: CloneStorage {
+        let mut list = self.clone();
+        list.spatial = None;
+        list.prop_indices = PropIndices::default();
+        list.uuids = None;
+        list.resources = Resources::default();
+        let tick = self.current_tick();
+        self.advance_tick();
+        Snapshot { list, tick }
+    }
+
+    /// Returns the entities spawned, despawned and changed between two snapshots of the same list.
+    ///
+    /// Changes are found with the change ticks, not by comparing the components. Only changes recorded
+    /// by the list are found, see `Changed`. Changes to properties are not found.
+    pub fn diff(old: &Snapshot<E>, new: &Snapshot<E>) -> Diff<E> where E::CS: CloneStorage {
+        let mut diff = Diff {
+            spawned: Vec::new(),
+            despawned: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (id, _) in old.list.entities.iter() {
+            if !new.list.contains(id) {
+                diff.despawned.push(id);
+            }
+        }
+        // SAFETY: the storage is owned by the snapshot, which we borrow immutably
+        let new_cs = unsafe { &*new.list.components_storage.get() };
+        for (id, e) in new.list.entities.iter() {
+            if !old.list.contains(id) {
+                diff.spawned.push((id, e.clone_owned(new_cs)));
+                continue;
+            }
+            let index = id.index as u32;
+            let mut components = Vec::new();
+            E::for_all_components(|type_id: TypeId| {
+                let p = match new.list.bitsets.position(&type_id) {
+                    Some(p) => p,
+                    None => return,
+                };
+                let in_old = old.list.bitsets.get_at(p).contains(index);
+                let in_new = new.list.bitsets.get_at(p).contains(index);
+                if in_old != in_new || (in_new && new.list.ticks.changed(p, id.index) > old.tick) {
+                    components.push(type_id);
+                }
+            });
+            if !components.is_empty() {
+                diff.changed.push(ChangedEntity { id, components, entity: e.clone_owned(new_cs) });
+            }
+        }
+        diff
+    }
+
+    /// Apply a diff to this list, which must have the same entities as the oldest snapshot of the diff.
+    ///
+    /// Changed entities get all of their components replaced, and are recorded as changed. Spawned
+    /// entities get new ids: returns the new id of every spawned entity, by its id in the diff.
+    pub fn apply_diff(&mut self, diff: Diff<E>) -> HashMap<EntityId, EntityId> {
+        for id in diff.despawned {
+            self.remove(id);
+        }
+        for changed in diff.changed {
+            self.overwrite(changed.id, changed.entity);
+        }
+        let mut remap = HashMap::with_capacity(diff.spawned.len());
+        for (old_id, e) in diff.spawned {
+            remap.insert(old_id, self.insert(e));
+        }
+        remap
+    }
+
+    /// Replace the components of an entity by the ones of `owned`, keeping its id.
+    fn overwrite(&mut self, id: EntityId, owned: E::Owned) {
+        if !self.entities.contains(id) {
+            return;
+        }
+        let new = E::from_owned(owned, &self.components_storage);
+        let old = std::mem::replace(&mut self.entities[id], new);
+        // SAFETY: the storage is owned by the list, which we borrow mutably
+        unsafe {
+            drop(old.to_owned(&mut *self.components_storage.get()));
+        }
+        self.refresh(id);
+        let e = &self.entities[id];
+        e.for_each_component_position(|p: usize, is_active: bool| {
+            if is_active {
+                self.ticks.set_changed(p, id.index);
+            }
+        });
+    }
+}
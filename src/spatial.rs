@@ -0,0 +1,181 @@
+use hashbrown::HashMap;
+
+use crate::{EntityRefBase, EntityList, EntityId};
+use crate::genarena::GenArena;
+
+/// An axis-aligned rectangle, with inclusive bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl Aabb {
+    pub fn new(min: (f32, f32), max: (f32, f32)) -> Aabb {
+        Aabb { min, max }
+    }
+
+    #[inline]
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.min.0 && x <= self.max.0 && y >= self.min.1 && y <= self.max.1
+    }
+}
+
+type Cell = (i32, i32);
+
+/// A uniform grid, storing which entities are in which cell.
+///
+/// Positions are read from the entities with the function given to `EntityList::enable_spatial_index`.
+pub (crate) struct SpatialIndex<E> {
+    cell_size: f32,
+    position: fn(&E) -> Option<(f32, f32)>,
+    cells: HashMap<Cell, Vec<EntityId>>,
+    /// Cell of every entity, by entity index
+    entity_cells: Vec<Option<Cell>>,
+}
+
+impl<E> Clone for SpatialIndex<E> {
+    fn clone(&self) -> Self {
+        SpatialIndex {
+            cell_size: self.cell_size,
+            position: self.position,
+            cells: self.cells.clone(),
+            entity_cells: self.entity_cells.clone(),
+        }
+    }
+}
+
+impl<E> SpatialIndex<E> {
+    fn new(cell_size: f32, position: fn(&E) -> Option<(f32, f32)>) -> Self {
+        assert!(cell_size > 0.0, "cell_size must be positive");
+        SpatialIndex {
+            cell_size,
+            position,
+            cells: HashMap::new(),
+            entity_cells: Vec::new(),
+        }
+    }
+
+    /// Returns an empty index with the same cell size and position function.
+    pub (crate) fn empty_like(&self) -> Self {
+        SpatialIndex::new(self.cell_size, self.position)
+    }
+
+    /// Cell of the given position. The casts saturate, so infinite coordinates give the cells at the
+    /// edge of the grid.
+    #[inline]
+    fn cell_of(&self, (x, y): (f32, f32)) -> Cell {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+
+    /// Iterate over the entities of `entities` whose position is inside `rect`, see `EntityList::iter_in_aabb`.
+    pub (crate) fn iter_in_aabb<'a>(&'a self, entities: &'a GenArena<E>, rect: Aabb) -> impl Iterator<Item=(EntityId, &'a E)> {
+        let (min_x, min_y) = self.cell_of(rect.min);
+        let (max_x, max_y) = self.cell_of(rect.max);
+        // a rect with a NaN bound contains nothing
+        let is_nan = [rect.min.0, rect.min.1, rect.max.0, rect.max.1].iter().any(|v| v.is_nan());
+        let width = (i64::from(max_x) - i64::from(min_x) + 1).max(0) as u64;
+        let height = (i64::from(max_y) - i64::from(min_y) + 1).max(0) as u64;
+        let cell_count = if is_nan { 0 } else { width.saturating_mul(height) };
+        // large rects, or infinite ones, cover more cells than are occupied: scan the occupied
+        // cells instead of looking up every cell of the rect
+        let scanned = (cell_count > self.cells.len() as u64).then(|| {
+            self.cells.iter()
+                .filter(move |((x, y), _)| (min_x..=max_x).contains(x) && (min_y..=max_y).contains(y))
+                .map(|(_, ids)| ids)
+        });
+        let looked_up = (cell_count > 0 && cell_count <= self.cells.len() as u64).then(|| {
+            (min_x..=max_x)
+                .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+                .filter_map(move |cell| self.cells.get(&cell))
+        });
+        scanned.into_iter().flatten()
+            .chain(looked_up.into_iter().flatten())
+            .flatten()
+            .filter_map(move |id| {
+                let e = entities.get(*id)?;
+                let p = (self.position)(e)?;
+                if rect.contains(p) { Some((*id, e)) } else { None }
+            })
+    }
+
+    /// Move the entity to the cell of its current position, or out of the grid if it has none.
+    pub (crate) fn update(&mut self, id: EntityId, e: &E) {
+        let new_cell = (self.position)(e).map(|p| self.cell_of(p));
+        let old_cell = self.entity_cells.get(id.index).copied().flatten();
+        if old_cell == new_cell {
+            return;
+        }
+        if let Some(cell) = old_cell {
+            self.remove_from_cell(cell, id);
+        }
+        if self.entity_cells.len() <= id.index {
+            self.entity_cells.resize(id.index + 1, None);
+        }
+        self.entity_cells[id.index] = new_cell;
+        if let Some(cell) = new_cell {
+            self.cells.entry(cell).or_default().push(id);
+        }
+    }
+
+    /// Remove every entity from the grid, keeping the settings.
+    pub (crate) fn clear(&mut self) {
+        self.cells.clear();
+        self.entity_cells.clear();
+    }
+
+    pub (crate) fn remove(&mut self, id: EntityId) {
+        if let Some(cell) = self.entity_cells.get_mut(id.index).and_then(|c| c.take()) {
+            self.remove_from_cell(cell, id);
+        }
+    }
+
+    fn remove_from_cell(&mut self, cell: Cell, id: EntityId) {
+        if let Some(ids) = self.cells.get_mut(&cell) {
+            if let Some(i) = ids.iter().position(|other| *other == id) {
+                ids.swap_remove(i);
+            }
+            if ids.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Enable the spatial index, using `position` to read the position of every entity.
+    ///
+    /// Entities are indexed in a grid of `cell_size` wide cells, which is kept up to date on
+    /// `insert`, `remove`, `refresh`, `add_component_for_entity` and `remove_component_for_entity`.
+    /// Entities for which `position` returns `None` are not indexed.
+    ///
+    /// **WARNING**: After moving an entity via `get_mut` or `iter_mut`, you must call `refresh`,
+    /// otherwise it will stay in its previous cell.
+    ///
+    /// Calling this again replaces the previous index.
+    ///
+    /// # Example
+    ///
+    /// `entities.enable_spatial_index(32.0, |e| Some((e.position.x, e.position.y)));`
+    pub fn enable_spatial_index(&mut self, cell_size: f32, position: fn(&E) -> Option<(f32, f32)>) {
+        let mut index = SpatialIndex::new(cell_size, position);
+        for (id, e) in self.entities.iter() {
+            index.update(id, e);
+        }
+        self.spatial = Some(index);
+    }
+
+    /// Disable the spatial index, see `enable_spatial_index`.
+    pub fn disable_spatial_index(&mut self) {
+        self.spatial = None;
+    }
+
+    /// Iterate over all entities whose position is inside `rect`.
+    ///
+    /// The order of iteration is unspecified.
+    ///
+    /// Panics if the spatial index is not enabled, see `enable_spatial_index`.
+    pub fn iter_in_aabb(&self, rect: Aabb) -> impl Iterator<Item=(EntityId, &E)> {
+        self.spatial.as_ref().expect("spatial index is not enabled").iter_in_aabb(&self.entities, rect)
+    }
+}
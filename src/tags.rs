@@ -0,0 +1,74 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use hibitset::{BitSet, BitSetAnd, BitSetNot};
+
+use crate::{EntityBase, EntityRefBase, EntityList, EntityId, ComponentBitSets, QueryElement};
+
+/// Query filter, matches entities which have the tag `T`, see `EntityList::tag`.
+///
+/// Unlike components, tags are not known by `Query`, so their bitset is looked up on every run.
+///
+/// # Example
+///
+/// `for (id, entity) in entities.iter::<(Speed, Tagged<Hostile>)>() { }`
+pub struct Tagged<T>(PhantomData<T>);
+
+impl<'a, E: EntityBase, T: 'static> QueryElement<'a, E> for Tagged<T> {
+    type BitSet = &'a BitSet;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        bitsets.tag(&TypeId::of::<T>())
+    }
+}
+
+/// Query filter, matches entities which do NOT have the tag `T`.
+pub struct Untagged<T>(PhantomData<T>);
+
+impl<'a, E: EntityBase, T: 'static> QueryElement<'a, E> for Untagged<T> {
+    type BitSet = BitSetAnd<&'a BitSet, BitSetNot<&'a BitSet>>;
+
+    fn bitset(bitsets: &'a ComponentBitSets) -> Self::BitSet {
+        BitSetAnd(bitsets.occupied(), BitSetNot(bitsets.tag(&TypeId::of::<T>())))
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Tag an entity with `T`, usually a zero-sized type.
+    ///
+    /// Tags are only stored as bitsets: they take no room in the entity nor in the component
+    /// storage, and carry no data. They can be queried with `Tagged<T>` and `Untagged<T>`.
+    /// Tags are not serialized.
+    ///
+    /// Returns false if the entity does not exist.
+    ///
+    /// # Example
+    ///
+    /// `entities.tag::<Hostile>(id);`
+    pub fn tag<T: 'static>(&mut self, id: EntityId) -> bool {
+        if !self.entities.contains(id) {
+            return false;
+        }
+        self.bitsets.tag_mut(TypeId::of::<T>()).add(id.index as u32);
+        self.bitsets.update_cached(id.index as u32);
+        true
+    }
+
+    /// Remove the tag `T` from an entity.
+    ///
+    /// Returns true if the entity had the tag.
+    pub fn untag<T: 'static>(&mut self, id: EntityId) -> bool {
+        if !self.entities.contains(id) {
+            return false;
+        }
+        let had_tag = self.bitsets.tags.get_mut(&TypeId::of::<T>())
+            .is_some_and(|bitset| bitset.remove(id.index as u32));
+        self.bitsets.update_cached(id.index as u32);
+        had_tag
+    }
+
+    /// Returns true if the entity has the tag `T`.
+    pub fn has_tag<T: 'static>(&self, id: EntityId) -> bool {
+        self.entities.contains(id) && self.bitsets.tag(&TypeId::of::<T>()).contains(id.index as u32)
+    }
+}
@@ -0,0 +1,179 @@
+use hibitset::BitSetLike;
+
+use crate::{EntityRefBase, EntityList, EntityId, MultiComponent, ComponentBitSets, ChangeTicks};
+use crate::genarena::EntriesMut;
+use crate::entity_list::refresh_bitsets;
+use crate::spatial::SpatialIndex;
+use crate::archetypes::Archetypes;
+use crate::prop_index::PropIndices;
+use crate::observers::Observers;
+
+/// A mutable reference to an entity, which refreshes the bitsets of this entity when dropped.
+///
+/// Components may freely be added or removed via `add` and `remove` through this reference,
+/// there is no need to call `EntityList::refresh` afterwards. If the entity was never accessed
+/// mutably, nothing is refreshed, and its components are not recorded as changed.
+pub struct TrackedMut<'a, E: EntityRefBase> {
+    id: EntityId,
+    entity: &'a mut E,
+    dirty: bool,
+    bitsets: *mut ComponentBitSets,
+    ticks: *mut ChangeTicks,
+    spatial: *mut Option<SpatialIndex<E>>,
+    archetypes: *mut Option<Archetypes>,
+    prop_indices: *mut PropIndices<E>,
+    observers: *mut Observers<E>,
+}
+
+impl<'a, E: EntityRefBase> TrackedMut<'a, E> {
+    #[inline]
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+}
+
+impl<'a, E: EntityRefBase> std::ops::Deref for TrackedMut<'a, E> {
+    type Target = E;
+
+    fn deref(&self) -> &E {
+        self.entity
+    }
+}
+
+impl<'a, E: EntityRefBase> std::ops::DerefMut for TrackedMut<'a, E> {
+    fn deref_mut(&mut self) -> &mut E {
+        self.dirty = true;
+        self.entity
+    }
+}
+
+impl<'a, E: EntityRefBase> Drop for TrackedMut<'a, E> {
+    fn drop(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        // SAFETY: the list is mutably borrowed for 'a by whoever created this, and bitsets, ticks, spatial,
+        // archetypes, property indices & observers are never borrowed by anything else while TrackedMut exist; guards
+        // are dropped one at a time.
+        unsafe {
+            refresh_bitsets(&mut *self.bitsets, &mut *self.ticks, &mut *self.observers, self.id, &*self.entity);
+            (*self.ticks).set_entity_changed(&*self.bitsets, self.id.index);
+            if let Some(spatial) = &mut *self.spatial {
+                spatial.update(self.id, &*self.entity);
+            }
+            if let Some(archetypes) = &mut *self.archetypes {
+                archetypes.update(&*self.bitsets, self.id, &*self.entity);
+            }
+            (*self.prop_indices).update(self.id, &*self.entity);
+        }
+    }
+}
+
+/// Iterator returned by `EntityList::iter_mut_tracked`.
+///
+/// The matching entities are gathered when the iterator is created, so that the bitsets can be
+/// refreshed while iterating.
+pub struct TrackedIterMut<'a, E: EntityRefBase> {
+    entities: EntriesMut<'a, E>,
+    bitsets: *mut ComponentBitSets,
+    ticks: *mut ChangeTicks,
+    spatial: *mut Option<SpatialIndex<E>>,
+    archetypes: *mut Option<Archetypes>,
+    prop_indices: *mut PropIndices<E>,
+    observers: *mut Observers<E>,
+    indexes: std::vec::IntoIter<u32>,
+}
+
+impl<'a, E: EntityRefBase> Iterator for TrackedIterMut<'a, E> {
+    type Item = TrackedMut<'a, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.indexes.next().map(|index| {
+            // SAFETY: indexes are all distinct, so each entity is only handed out once
+            let (entity, generation) = unsafe { self.entities.get_raw_mut(index as usize) }
+                .expect(crate::FATAL_ERR_BITSET);
+            TrackedMut {
+                id: EntityId::new(index as usize, generation),
+                entity,
+                dirty: false,
+                bitsets: self.bitsets,
+                ticks: self.ticks,
+                spatial: self.spatial,
+                archetypes: self.archetypes,
+                prop_indices: self.prop_indices,
+                observers: self.observers,
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indexes.size_hint()
+    }
+}
+
+impl<'a, E: EntityRefBase> ExactSizeIterator for TrackedIterMut<'a, E> {}
+
+impl<'a, E: EntityRefBase> std::iter::FusedIterator for TrackedIterMut<'a, E> {}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), mutably.
+    ///
+    /// Unlike `iter_mut`, components can be added or removed during the iteration: the bitsets of
+    /// every yielded entity are refreshed when its `TrackedMut` is dropped.
+    ///
+    /// Deferred refreshes are flushed first, see `set_deferred_refresh`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for mut e in entities.iter_mut_tracked::<(Health,)>() {
+    ///     if e.get::<Health>().unwrap().hp <= 0 {
+    ///         e.add(Dead);
+    ///     }
+    /// }
+    /// ```
+    pub fn iter_mut_tracked<'a, C: for<'b> MultiComponent<'b, E>>(&'a mut self) -> TrackedIterMut<'a, E> {
+        #[allow(clippy::let_unit_value)]
+        let () = C::NO_TICK_FILTER;
+        self.flush_refresh();
+        self.lend_entities();
+        let indexes: Vec<u32> = self.bitsets.enabled(C::bitset(&self.bitsets)).iter().collect();
+        TrackedIterMut {
+            entities: self.entities.entries_mut(),
+            bitsets: &mut self.bitsets,
+            ticks: &mut self.ticks,
+            spatial: &mut self.spatial,
+            archetypes: &mut self.archetypes,
+            prop_indices: &mut self.prop_indices,
+            observers: &mut self.observers,
+            indexes: indexes.into_iter(),
+        }
+    }
+
+    /// Retrieves an entity mutably, and refreshes its bitsets when the returned guard is dropped.
+    ///
+    /// Unlike `get_mut`, components can be added or removed via the guard, without calling `refresh`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// if let Some(mut e) = entities.get_mut_tracked(id) {
+    ///     e.add(Dead);
+    /// }
+    /// ```
+    pub fn get_mut_tracked(&mut self, id: EntityId) -> Option<TrackedMut<'_, E>> {
+        self.lend_entity();
+        let entity = self.entities.get_mut(id)?;
+        Some(TrackedMut {
+            id,
+            entity,
+            dirty: false,
+            bitsets: &mut self.bitsets,
+            ticks: &mut self.ticks,
+            spatial: &mut self.spatial,
+            archetypes: &mut self.archetypes,
+            prop_indices: &mut self.prop_indices,
+            observers: &mut self.observers,
+        })
+    }
+}
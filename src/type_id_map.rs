@@ -0,0 +1,38 @@
+use std::any::TypeId;
+use std::hash::{BuildHasherDefault, Hasher};
+
+use hashbrown::HashMap;
+
+/// A map keyed by `TypeId`, used for the tables of the lists kept by type: bitsets, tags, resources,
+/// events...
+pub type TypeIdMap<V> = HashMap<TypeId, V, BuildHasherDefault<TypeIdHasher>>;
+
+/// Hasher for `TypeId` keys, see `TypeIdMap`.
+///
+/// A `TypeId` already is a hash of its type, so hashing it again with the default hasher only costs
+/// time, which shows in frames adding and removing a lot of components. Its bits are mixed with a
+/// single multiplication instead, like FxHash does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TypeIdHasher(u64);
+
+impl Hasher for TypeIdHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        // `TypeId` only writes integers, but its layout is not guaranteed
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_ne_bytes(word));
+        }
+    }
+
+    #[inline]
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,136 @@
+use std::hash::{BuildHasher, Hasher};
+
+use hashbrown::HashMap;
+
+use crate::{EntityRefBase, EntityList, EntityId};
+#[cfg(feature = "use_serde")]
+use crate::genarena::GenArena;
+
+/// A persistent id of an entity, see `EntityList::enable_uuids`.
+pub type Uuid = u128;
+
+/// The uuids of every entity of a list.
+///
+/// A uuid is made of a random seed, picked when the uuids are enabled, and of a counter. Two lists
+/// created separately will never have the same uuids, unless one is a clone of the other.
+#[derive(Debug, Clone)]
+pub (crate) struct Uuids {
+    seed: u64,
+    counter: u64,
+    by_uuid: HashMap<Uuid, EntityId>,
+    /// Uuid of every entity, by entity index
+    by_index: Vec<Option<Uuid>>,
+}
+
+impl Uuids {
+    fn new() -> Self {
+        let seed = std::collections::hash_map::RandomState::new().build_hasher().finish();
+        Uuids {
+            seed,
+            counter: 0,
+            by_uuid: HashMap::new(),
+            by_index: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "use_serde")]
+    /// Rebuild the uuids from their raw parts, see `as_raw`.
+    pub (crate) fn from_raw<T>(seed: u64, counter: u64, by_index: Vec<Option<Uuid>>, entities: &GenArena<T>) -> Self {
+        let by_uuid = by_index.iter().enumerate()
+            .filter_map(|(index, uuid)| {
+                let generation = entities.generation_at(index)?;
+                Some(((*uuid)?, EntityId::new(index, generation)))
+            })
+            .collect();
+        Uuids {
+            seed,
+            counter,
+            by_uuid,
+            by_index,
+        }
+    }
+
+    #[cfg(feature = "use_serde")]
+    pub (crate) fn as_raw(&self) -> (u64, u64, &Vec<Option<Uuid>>) {
+        (self.seed, self.counter, &self.by_index)
+    }
+
+    /// Give the entity `uuid` if it is given and not used already, or a new uuid otherwise.
+    pub (crate) fn assign(&mut self, id: EntityId, uuid: Option<Uuid>) -> Uuid {
+        self.remove(id);
+        let uuid = match uuid {
+            Some(uuid) if !self.by_uuid.contains_key(&uuid) => uuid,
+            _ => {
+                self.counter += 1;
+                ((self.seed as u128) << 64) | self.counter as u128
+            }
+        };
+        if self.by_index.len() <= id.index {
+            self.by_index.resize(id.index + 1, None);
+        }
+        self.by_index[id.index] = Some(uuid);
+        self.by_uuid.insert(uuid, id);
+        uuid
+    }
+
+    pub (crate) fn remove(&mut self, id: EntityId) -> Option<Uuid> {
+        let uuid = self.by_index.get_mut(id.index).and_then(|uuid| uuid.take())?;
+        self.by_uuid.remove(&uuid);
+        Some(uuid)
+    }
+
+    pub (crate) fn clear(&mut self) {
+        self.by_uuid.clear();
+        self.by_index.clear();
+    }
+
+    #[inline]
+    pub (crate) fn get(&self, id: EntityId) -> Option<Uuid> {
+        self.by_index.get(id.index).copied().flatten()
+    }
+
+    #[inline]
+    pub (crate) fn id(&self, uuid: Uuid) -> Option<EntityId> {
+        self.by_uuid.get(&uuid).copied()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Give a persistent uuid to every entity of this list, and to every entity inserted afterwards.
+    ///
+    /// Unlike `EntityId`s, uuids are kept by `merge`, and are serialized with the list. Does nothing
+    /// if uuids are already enabled.
+    pub fn enable_uuids(&mut self) {
+        if self.uuids.is_some() {
+            return;
+        }
+        let mut uuids = Uuids::new();
+        for (id, _) in self.entities.iter() {
+            uuids.assign(id, None);
+        }
+        self.uuids = Some(uuids);
+    }
+
+    /// Returns the uuid of the given entity, if uuids are enabled.
+    #[inline]
+    pub fn uuid(&self, id: EntityId) -> Option<Uuid> {
+        self.uuids.as_ref().and_then(|uuids| uuids.get(id))
+            .filter(|_| self.entities.contains(id))
+    }
+
+    /// Returns the id of the entity with the given uuid.
+    pub fn id_by_uuid(&self, uuid: Uuid) -> Option<EntityId> {
+        self.uuids.as_ref().and_then(|uuids| uuids.id(uuid))
+    }
+
+    /// Retrieves an entity by its uuid, immutably.
+    pub fn get_by_uuid(&self, uuid: Uuid) -> Option<&E> {
+        self.id_by_uuid(uuid).and_then(|id| self.entities.get(id))
+    }
+
+    /// Retrieves an entity by its uuid, mutably. See `get_mut`.
+    pub fn get_by_uuid_mut(&mut self, uuid: Uuid) -> Option<&mut E> {
+        let id = self.id_by_uuid(uuid)?;
+        self.get_mut(id)
+    }
+}
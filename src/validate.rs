@@ -0,0 +1,107 @@
+use std::any::TypeId;
+
+use hashbrown::HashMap;
+use hibitset::BitSetLike;
+
+use crate::{EntityRefBase, EntityList, EntityId, ComponentsStorage};
+
+/// Every inconsistency found by `EntityList::validate`.
+///
+/// Components and tags are identified by their `TypeId`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Entities having a component which is not in its bitset
+    pub missing_bits: Vec<(EntityId, TypeId)>,
+    /// Entities in the bitset of a component they do not have
+    pub extra_bits: Vec<(EntityId, TypeId)>,
+    /// Bits set for free slots of the arena, by index
+    pub stale_bits: Vec<(u32, TypeId)>,
+    /// Entities referencing a component which is not in the component storage
+    pub dangling_components: Vec<(EntityId, TypeId)>,
+    /// Entities referencing a component which is also referenced by another entity
+    pub shared_components: Vec<(EntityId, TypeId)>,
+    /// Keys of the components stored but not referenced by any entity
+    pub orphan_components: Vec<(usize, TypeId)>,
+    /// The length recorded by the arena, and the actual number of entities, if they differ
+    pub length_mismatch: Option<(usize, usize)>,
+}
+
+impl ValidationReport {
+    /// Returns true if no inconsistency was found.
+    pub fn is_valid(&self) -> bool {
+        *self == ValidationReport::default()
+    }
+}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Cross-check the bitsets, the entities, the component storage and the arena, and report every
+    /// inconsistency found.
+    ///
+    /// This goes through every entity and every component, it is meant for debugging: when a bitset
+    /// gets out of date, this helps finding which entity and which component.
+    ///
+    /// # Example
+    ///
+    /// `debug_assert!(entities.validate().is_valid(), "{:?}", entities.validate());`
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        let actual_length = self.entities.iter().count();
+        if actual_length != self.entities.len() {
+            report.length_mismatch = Some((self.entities.len(), actual_length));
+        }
+
+        for (id, e) in self.entities.iter() {
+            e.for_each_component(|type_id: TypeId, is_active: bool| {
+                if let Some(bitset) = self.bitsets.get(&type_id) {
+                    match (is_active, bitset.contains(id.index as u32)) {
+                        (true, false) => report.missing_bits.push((id, type_id)),
+                        (false, true) => report.extra_bits.push((id, type_id)),
+                        _ => {},
+                    }
+                }
+            });
+        }
+
+        let component_bitsets = self.bitsets.positions.iter()
+            .map(|(type_id, p)| (type_id, &self.bitsets.bitsets[*p]));
+        for (type_id, bitset) in component_bitsets.chain(self.bitsets.tags.iter()) {
+            for index in bitset.iter() {
+                if self.entities.generation_at(index as usize).is_none() {
+                    report.stale_bits.push((index, *type_id));
+                }
+            }
+        }
+
+        // number of entities referencing every stored component
+        let mut references: HashMap<(TypeId, usize), usize> = HashMap::new();
+        // SAFETY: the storage is owned by the list, which we borrow immutably
+        let cs = unsafe { &*self.components_storage.get() };
+        cs.for_each_key(|type_id, key| {
+            references.insert((type_id, key), 0);
+        });
+        let soft_removed = self.recycle_bin.entities.values().map(|soft_removed| &soft_removed.entity);
+        for e in self.entities.values().chain(soft_removed) {
+            e.for_each_component_key(|type_id, key| {
+                if let Some(count) = references.get_mut(&(type_id, key)) {
+                    *count += 1;
+                }
+            });
+        }
+        for (id, e) in self.entities.iter() {
+            e.for_each_component_key(|type_id, key| {
+                match references.get(&(type_id, key)) {
+                    None => report.dangling_components.push((id, type_id)),
+                    Some(count) if *count > 1 => report.shared_components.push((id, type_id)),
+                    Some(_) => {},
+                }
+            });
+        }
+        report.orphan_components = references.iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|((type_id, key), _)| (*key, *type_id))
+            .collect();
+
+        report
+    }
+}
@@ -0,0 +1,146 @@
+use std::hash::Hash;
+
+use crate::{
+    EntityRefBase, EntityList, EntityId, Component, RefComponent, Aabb, Tick, Uuid, PropIndex,
+    MultiComponent, MultiComponentIter, MultiComponentFetch, ReadOnlyMultiFetch,
+    SingleComponentIter, ComponentIter, ComponentsIter, IdsIter, Enabled,
+};
+
+/// A read-only view of an `EntityList`, returned by `EntityList::view`.
+///
+/// A `&EntityList` is only read-only by convention: its component storage is reachable, and could be
+/// written through. A view only exposes the queries which read the list, so it can be handed to any
+/// number of systems at once without them being able to change anything. It is `Copy`.
+///
+/// # Example
+///
+/// ```ignore
+/// fn draw(entities: EntityListView<EntityRef>) {
+///     for (id, sprite) in entities.iter_component::<Sprite>() { }
+/// }
+/// draw(entities.view());
+/// ```
+pub struct EntityListView<'a, E: EntityRefBase> {
+    list: &'a EntityList<E>,
+}
+
+impl<'a, E: EntityRefBase> Clone for EntityListView<'a, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, E: EntityRefBase> Copy for EntityListView<'a, E> {}
+
+impl<E: EntityRefBase> EntityList<E> {
+    /// Returns a read-only view of this list, see `EntityListView`.
+    ///
+    /// Like the list, the view does not flush deferred refreshes, see `set_deferred_refresh`.
+    pub fn view(&self) -> EntityListView<'_, E> {
+        EntityListView { list: self }
+    }
+}
+
+impl<'a, E: EntityRefBase> EntityListView<'a, E> {
+    /// Retrieves an entity, see `EntityList::get`.
+    pub fn get(&self, id: EntityId) -> Option<&'a E> {
+        self.list.get(id)
+    }
+
+    /// Returns true if the id exists.
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.list.contains(id)
+    }
+
+    /// Returns true if the entity exists and has the component `C`, see `EntityList::contains_component`.
+    pub fn contains_component<C: Component<E>>(&self, id: EntityId) -> bool {
+        self.list.contains_component::<C>(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns true if the entity has the tag `T`.
+    pub fn has_tag<T: 'static>(&self, id: EntityId) -> bool {
+        self.list.has_tag::<T>(id)
+    }
+
+    /// Returns the uuid of the given entity, if uuids are enabled.
+    pub fn uuid(&self, id: EntityId) -> Option<Uuid> {
+        self.list.uuid(id)
+    }
+
+    /// Retrieves an entity by its uuid.
+    pub fn get_by_uuid(&self, uuid: Uuid) -> Option<&'a E> {
+        self.list.get_by_uuid(uuid)
+    }
+
+    /// Retrieves the resource `R`.
+    pub fn resource<R: 'static>(&self) -> Option<&'a R> {
+        self.list.resource::<R>()
+    }
+
+    /// Returns the current tick of the list, see `EntityList::iter_since`.
+    pub fn current_tick(&self) -> Tick {
+        self.list.current_tick()
+    }
+
+    /// Iterate over all entities
+    pub fn iter_all(&self) -> impl Iterator<Item=(EntityId, &'a E)> + Clone {
+        self.list.iter_all()
+    }
+
+    /// Iterate over all entities which have the components (C1, C2, C3, ...), see `EntityList::iter`.
+    pub fn iter<C: MultiComponent<'a, E>>(&self) -> MultiComponentIter<'a, E, Enabled<'a, C::BitSet>> {
+        self.list.iter::<C>()
+    }
+
+    /// Same as `iter`, with a predicate, see `EntityList::iter_filtered`.
+    pub fn iter_filtered<C, F>(&self, predicate: F) -> impl Iterator<Item=(EntityId, &'a E)>
+    where
+        C: MultiComponent<'a, E>,
+        F: FnMut(&E) -> bool,
+    {
+        self.list.iter_filtered::<C, F>(predicate)
+    }
+
+    /// Iterate over the ids of all entities which have the components (C1, C2, C3, ...).
+    pub fn iter_ids<C: MultiComponent<'a, E>>(&self) -> IdsIter<'a, E, Enabled<'a, C::BitSet>> {
+        self.list.iter_ids::<C>()
+    }
+
+    /// Iterate over all entities which have the component `C`, see `EntityList::iter_single`.
+    pub fn iter_single<C: RefComponent<E>>(&self) -> SingleComponentIter<'a, E, C> {
+        self.list.iter_single::<C>()
+    }
+
+    /// Iterate over the component `C` of every entity which has it, see `EntityList::iter_component`.
+    pub fn iter_component<C: RefComponent<E>>(&self) -> ComponentIter<'a, E, C> {
+        self.list.iter_component::<C>()
+    }
+
+    /// Iterate over the components of every entity which has them, see `EntityList::iter_components`.
+    pub fn iter_components<C: MultiComponentFetch<'a, E> + ReadOnlyMultiFetch<E>>(&self) -> ComponentsIter<'a, E, C> {
+        self.list.iter_components::<C>()
+    }
+
+    /// Iterate over the entities changed since `since`, see `EntityList::iter_since`.
+    pub fn iter_since<C: MultiComponent<'a, E>>(&self, since: Tick) -> impl Iterator<Item=(EntityId, &'a E)> {
+        self.list.iter_since::<C>(since)
+    }
+
+    /// Iterate over all entities whose position is inside `rect`, see `EntityList::iter_in_aabb`.
+    pub fn iter_in_aabb(&self, rect: Aabb) -> impl Iterator<Item=(EntityId, &'a E)> {
+        self.list.iter_in_aabb(rect)
+    }
+
+    /// Iterate over all entities whose indexed value is equal to `key`, see `EntityList::iter_where`.
+    pub fn iter_where<K: Hash + Eq + Clone + 'static>(&self, index: PropIndex<K>, key: &K) -> impl Iterator<Item=(EntityId, &'a E)> {
+        self.list.iter_where(index, key)
+    }
+}
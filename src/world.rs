@@ -0,0 +1,171 @@
+use std::any::{Any, TypeId};
+
+use crate::{TypeIdMap, EntityRefBase, EntityList, EntityId, MultiComponent};
+
+/// The id of an entity in a `World`: the id of the entity in its list, along with the type of the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldId {
+    /// `TypeId` of the entity type `E` of the list
+    pub kind: TypeId,
+    pub id: EntityId,
+}
+
+impl WorldId {
+    /// Returns true if the entity is in the list of `E`.
+    pub fn is<E: EntityRefBase>(&self) -> bool {
+        self.kind == TypeId::of::<E>()
+    }
+}
+
+struct AnyList {
+    list: Box<dyn Any>,
+    len: fn(&dyn Any) -> usize,
+    contains: fn(&dyn Any, EntityId) -> bool,
+    remove: fn(&mut dyn Any, EntityId) -> bool,
+    maintain: fn(&mut dyn Any),
+    clear: fn(&mut dyn Any),
+}
+
+fn downcast<E: EntityRefBase>(list: &dyn Any) -> &EntityList<E> {
+    list.downcast_ref::<EntityList<E>>().expect("FATAL: world list has the wrong type")
+}
+
+fn downcast_mut<E: EntityRefBase>(list: &mut dyn Any) -> &mut EntityList<E> {
+    list.downcast_mut::<EntityList<E>>().expect("FATAL: world list has the wrong type")
+}
+
+impl AnyList {
+    fn new<E: EntityRefBase>(list: EntityList<E>) -> Self {
+        AnyList {
+            list: Box::new(list),
+            len: |l| downcast::<E>(l).len(),
+            contains: |l, id| downcast::<E>(l).contains(id),
+            remove: |l, id| downcast_mut::<E>(l).remove(id).is_some(),
+            maintain: |l| downcast_mut::<E>(l).maintain(),
+            clear: |l| downcast_mut::<E>(l).clear(),
+        }
+    }
+}
+
+/// Several lists of structurally different entities (actors, projectiles, ...), at most one list by
+/// entity type.
+///
+/// Entities are identified by a `WorldId`, which knows the list of the entity.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut world = World::new();
+/// let actor = world.insert::<ActorRef>(Actor::new((Position::default(),)));
+/// let projectile = world.insert::<ProjectileRef>(Projectile::new((Position::default(),)));
+/// for (id, p) in world.iter::<ProjectileRef, (Speed,)>() { }
+/// world.remove(actor);
+/// ```
+#[derive(Default)]
+pub struct World {
+    lists: TypeIdMap<AnyList>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World { lists: TypeIdMap::default() }
+    }
+
+    /// Add a list to the world, and return the previous list of the same entity type, if any.
+    pub fn insert_list<E: EntityRefBase>(&mut self, list: EntityList<E>) -> Option<EntityList<E>> {
+        self.lists.insert(TypeId::of::<E>(), AnyList::new(list))
+            .map(|l| *l.list.downcast::<EntityList<E>>().expect("FATAL: world list has the wrong type"))
+    }
+
+    /// Remove the list of `E` from the world, and return it.
+    pub fn remove_list<E: EntityRefBase>(&mut self) -> Option<EntityList<E>> {
+        self.lists.remove(&TypeId::of::<E>())
+            .map(|l| *l.list.downcast::<EntityList<E>>().expect("FATAL: world list has the wrong type"))
+    }
+
+    /// Retrieves the list of `E`, if there is one.
+    pub fn list<E: EntityRefBase>(&self) -> Option<&EntityList<E>> {
+        self.lists.get(&TypeId::of::<E>()).map(|l| downcast::<E>(&*l.list))
+    }
+
+    /// Same as `list`, but mutably. Unlike `list_mut`, no list is created.
+    fn existing_list_mut<E: EntityRefBase>(&mut self) -> Option<&mut EntityList<E>> {
+        self.lists.get_mut(&TypeId::of::<E>()).map(|l| downcast_mut::<E>(&mut *l.list))
+    }
+
+    /// Retrieves the list of `E` mutably, creating an empty one if needed.
+    pub fn list_mut<E: EntityRefBase>(&mut self) -> &mut EntityList<E> {
+        let l = self.lists.entry(TypeId::of::<E>()).or_insert_with(|| AnyList::new(EntityList::<E>::new()));
+        downcast_mut::<E>(&mut *l.list)
+    }
+
+    /// Insert an entity in the list of `E`, creating the list if needed.
+    pub fn insert<E: EntityRefBase>(&mut self, entity: E::Owned) -> WorldId {
+        WorldId { kind: TypeId::of::<E>(), id: self.list_mut::<E>().insert(entity) }
+    }
+
+    /// Remove an entity, whatever its list. Returns false if the entity does not exist.
+    ///
+    /// See `list_mut` to get the removed entity back.
+    pub fn remove(&mut self, id: WorldId) -> bool {
+        self.lists.get_mut(&id.kind).is_some_and(|l| (l.remove)(&mut *l.list, id.id))
+    }
+
+    pub fn contains(&self, id: WorldId) -> bool {
+        self.lists.get(&id.kind).is_some_and(|l| (l.contains)(&*l.list, id.id))
+    }
+
+    /// Retrieves an entity of the list of `E`. Returns `None` if the entity is in another list.
+    pub fn get<E: EntityRefBase>(&self, id: WorldId) -> Option<&E> {
+        if !id.is::<E>() {
+            return None;
+        }
+        self.list::<E>()?.get(id.id)
+    }
+
+    /// Retrieves an entity of the list of `E` mutably. Returns `None` if the entity is in another list.
+    pub fn get_mut<E: EntityRefBase>(&mut self, id: WorldId) -> Option<&mut E> {
+        if !id.is::<E>() {
+            return None;
+        }
+        self.existing_list_mut::<E>()?.get_mut(id.id)
+    }
+
+    /// Iterate over the entities of `E` which have the components (C1, C2, C3, ...), see `EntityList::iter`.
+    ///
+    /// Yields nothing if there is no list of `E`.
+    pub fn iter<'a, E: EntityRefBase, C: MultiComponent<'a, E>>(&'a self) -> impl Iterator<Item=(EntityId, &'a E)> {
+        self.list::<E>().into_iter().flat_map(|list| list.iter::<C>())
+    }
+
+    /// Iterate mutably over the entities of `E` which have the components (C1, C2, C3, ...), see
+    /// `EntityList::iter_mut`.
+    ///
+    /// Yields nothing if there is no list of `E`, without creating one.
+    pub fn iter_mut<'a, E: EntityRefBase, C: MultiComponent<'a, E>>(&'a mut self) -> impl Iterator<Item=(EntityId, &'a mut E)> {
+        self.existing_list_mut::<E>().into_iter().flat_map(|list| list.iter_mut::<C>())
+    }
+
+    /// Total number of entities, in every list.
+    pub fn len(&self) -> usize {
+        self.lists.values().map(|l| (l.len)(&*l.list)).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Call `EntityList::maintain` on every list.
+    pub fn maintain(&mut self) {
+        for l in self.lists.values_mut() {
+            (l.maintain)(&mut *l.list);
+        }
+    }
+
+    /// Remove every entity of every list, keeping the lists.
+    pub fn clear(&mut self) {
+        for l in self.lists.values_mut() {
+            (l.clear)(&mut *l.list);
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use std::any::TypeId;
+
 use smec::{
     define_entity,
     EntityList,
     EntityBase,
-    EntityRefBase,
     EntityOwnedBase,
+    EntityListView,
+    Component,
+    ComponentBitSets,
 };
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -104,7 +108,7 @@ fn entity_with_component_change() {
             .with(ComponentA { alpha: 5.0 })
             .with(ComponentB { beta: 5 });
     let e = e.with_component_change(|e: &mut Entity| -> ChangeComponent<ComponentA> {
-        if let Some(_) = e.get::<ComponentB>() {
+        if e.get::<ComponentB>().is_some() {
             ChangeComponent::Remove
         } else {
             ChangeComponent::NoChange
@@ -135,12 +139,19 @@ fn entity_with_component_change() {
     });
 
     debug_assert_eq!(e.get::<ComponentA>(), Some(&ComponentA { alpha: 15.0 }));
+
+    let e = e.with_component_change(|e: &mut Entity| -> ChangeComponent<ComponentA, _> {
+        let beta = e.get::<ComponentB>().map_or(0, |b| b.beta);
+        ChangeComponent::Mutate(move |a: &mut ComponentA| a.alpha -= beta as f32)
+    });
+
+    debug_assert_eq!(e.get::<ComponentA>(), Some(&ComponentA { alpha: 10.0 }));
     
     let e = e.with_component_change(|_: &mut Entity| -> ChangeComponent<ComponentA> {
         ChangeComponent::NoChange
     });
 
-    debug_assert_eq!(e.get::<ComponentA>(), Some(&ComponentA { alpha: 15.0 }));
+    debug_assert_eq!(e.get::<ComponentA>(), Some(&ComponentA { alpha: 10.0 }));
 }
 
 #[test]
@@ -347,4 +358,2534 @@ fn iter_refresh() {
     debug_assert_eq!(only_comp_a, &[id_1, id_2, id_3, id_6]);
     debug_assert_eq!(only_comp_b, &[id_2, id_3, id_5]);
     debug_assert_eq!(only_comp_c, &[id_4, id_5, id_6]);
-}
\ No newline at end of file
+}
+#[test]
+fn iter_components_mut() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+
+    for (_id, a, b) in entity_list.iter_components_mut::<(ComponentA, ComponentB)>() {
+        a.alpha += b.beta as f32;
+        b.beta = 0;
+    }
+    let ids: Vec<_> = entity_list.iter_components_mut::<(ComponentA,)>().map(|(id, _a)| id).collect();
+
+    debug_assert_eq!(ids, &[id_1, id_2]);
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 5.0 }));
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 12.0 }));
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 0 }));
+}
+
+#[test]
+fn iter_without() {
+    use smec::Without;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let _id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+    let id_3 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentC { ceta: 6 })
+    );
+
+    let a_without_b: Vec<_> = entity_list.iter::<(ComponentA, Without<ComponentB>)>().map(|(i, _e)| i).collect();
+    let a_without_b_c: Vec<_> = entity_list.iter_mut::<(ComponentA, Without<ComponentB>, Without<ComponentC>)>().map(|(i, _e)| i).collect();
+
+    debug_assert_eq!(a_without_b, &[id_1, id_3]);
+    debug_assert_eq!(a_without_b_c, &[id_1]);
+}
+
+#[test]
+fn iter_only_without() {
+    use smec::{Untagged, Without};
+
+    struct Hostile;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentB { beta: 2 }));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    entity_list.tag::<Hostile>(id_3);
+    entity_list.remove(id_2);
+
+    debug_assert_eq!(entity_list.count::<(Without<ComponentB>,)>(), 2);
+    debug_assert_eq!(entity_list.iter::<(Without<ComponentB>,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1, id_3]);
+    debug_assert_eq!(entity_list.iter_mut::<(Without<ComponentA>, Without<ComponentB>)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_3]);
+    debug_assert_eq!(entity_list.iter::<(Untagged<Hostile>,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1]);
+
+    entity_list.clear();
+    debug_assert_eq!(entity_list.count::<(Without<ComponentB>,)>(), 0);
+}
+
+#[test]
+fn iter_components_optional() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+    let _id_3 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentB { beta: 7 })
+    );
+
+    let v: Vec<_> = entity_list.iter_components::<(ComponentA, Option<ComponentB>)>()
+        .map(|(id, a, b)| (id, a.alpha, b.map(|b| b.beta)))
+        .collect();
+    debug_assert_eq!(v, &[(id_1, 5.0, None), (id_2, 6.0, Some(6))]);
+
+    for (_id, a, b) in entity_list.iter_components_mut::<(ComponentA, Option<ComponentB>)>() {
+        if let Some(b) = b {
+            a.alpha -= b.beta as f32;
+        }
+    }
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 5.0 }));
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 0.0 }));
+}
+
+#[test]
+fn iter_only_optional() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentB { beta: 1 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentB { beta: 3 }));
+    entity_list.remove(id_1);
+
+    let v: Vec<_> = entity_list.iter_components::<(Option<ComponentB>,)>()
+        .map(|(id, b)| (id, b.map(|b| b.beta)))
+        .collect();
+    debug_assert_eq!(v, &[(id_2, None), (id_3, Some(3))]);
+    debug_assert_eq!(entity_list.count::<(Option<ComponentA>,)>(), 2);
+    debug_assert_eq!(entity_list.count::<()>(), 2);
+    debug_assert_eq!(entity_list.iter_mut::<()>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_2, id_3]);
+}
+
+#[test]
+fn iter_or() {
+    use smec::{Or, Without};
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentB { beta: 6 })
+            .with(ComponentC { ceta: 6 })
+    );
+    let id_3 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentC { ceta: 6 })
+    );
+
+    let a_or_b: Vec<_> = entity_list.iter::<(Or<(ComponentA, ComponentB)>,)>().map(|(i, _e)| i).collect();
+    let any: Vec<_> = entity_list.iter::<(Or<(ComponentA, ComponentB, ComponentC)>,)>().map(|(i, _e)| i).collect();
+    let c_and_not_b: Vec<_> = entity_list.iter::<(Or<(ComponentA, ComponentC)>, Without<ComponentB>)>().map(|(i, _e)| i).collect();
+
+    debug_assert_eq!(a_or_b, &[id_1, id_2]);
+    debug_assert_eq!(any, &[id_1, id_2, id_3]);
+    debug_assert_eq!(c_and_not_b, &[id_1, id_3]);
+}
+
+#[test]
+fn query() {
+    use smec::{Query, Without};
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let query = Query::<(ComponentA, Without<ComponentB>)>::new(&entity_list);
+    debug_assert_eq!(std::mem::size_of_val(&query), 0);
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let _id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+
+    let ids: Vec<_> = query.iter(&entity_list).map(|(i, _e)| i).collect();
+    debug_assert_eq!(ids, &[id_1]);
+
+    let id_3 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+    );
+    let ids: Vec<_> = query.iter_mut(&mut entity_list).map(|(i, _e)| i).collect();
+    debug_assert_eq!(ids, &[id_1, id_3]);
+}
+
+#[test]
+fn change_ticks() {
+    use smec::{Added, Changed};
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+    let last_run = entity_list.advance_tick();
+
+    let changed: Vec<_> = entity_list.iter_since::<(Changed<ComponentA>,)>(last_run).map(|(i, _e)| i).collect();
+    debug_assert!(changed.is_empty());
+    let changed: Vec<_> = entity_list.iter_since::<(Changed<ComponentA>,)>(0).map(|(i, _e)| i).collect();
+    debug_assert_eq!(changed, &[id_1, id_2]);
+
+    entity_list.get_component_mut::<ComponentA>(id_2).unwrap().alpha = 1.0;
+    entity_list.add_component_for_entity(id_1, ComponentB { beta: 1 });
+    entity_list.remove_component_for_entity::<ComponentA>(id_1);
+
+    let changed: Vec<_> = entity_list.iter_since::<(Changed<ComponentA>,)>(last_run).map(|(i, _e)| i).collect();
+    let added: Vec<_> = entity_list.iter_mut_since::<(Added<ComponentB>,)>(last_run).map(|(i, _e)| i).collect();
+    let removed: Vec<_> = entity_list.removed_since::<ComponentA>(last_run).collect();
+    debug_assert_eq!(changed, &[id_2]);
+    debug_assert_eq!(added, &[id_1]);
+    debug_assert_eq!(removed, &[id_1]);
+
+    let last_run = entity_list.advance_tick();
+    let added: Vec<_> = entity_list.iter_since::<(Added<ComponentB>,)>(last_run).map(|(i, _e)| i).collect();
+    debug_assert!(added.is_empty());
+}
+
+#[test]
+fn change_ticks_record_mutable_access() {
+    use smec::{Changed, Or};
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentB { beta: 3 }));
+    let changed_since = |entity_list: &EntityList<EntityRef>, since| {
+        entity_list.iter_since::<(Changed<ComponentA>,)>(since).map(|(id, _)| id).collect::<Vec<_>>()
+    };
+
+    let since = entity_list.advance_tick();
+    entity_list.get_mut(id_1).unwrap().mutate(|a: &mut ComponentA| a.alpha = 10.0);
+    debug_assert_eq!(changed_since(&entity_list, since), vec![id_1]);
+
+    let since = entity_list.advance_tick();
+    for (_id, _e) in entity_list.iter_mut::<(ComponentB,)>() {}
+    debug_assert_eq!(changed_since(&entity_list, since), vec![id_2]);
+
+    // only the components fetched mutably are recorded
+    let since = entity_list.advance_tick();
+    for (_id, _a, _b) in entity_list.iter_components_mut::<(&ComponentA, &mut ComponentB)>() {}
+    debug_assert!(changed_since(&entity_list, since).is_empty());
+    for (_id, _a) in entity_list.iter_components_mut::<(Option<ComponentA>,)>() {}
+    debug_assert_eq!(changed_since(&entity_list, since), vec![id_1, id_2]);
+
+    // an entity matches an `Or` if it matches the filter of one of its sides
+    let since = entity_list.advance_tick();
+    entity_list.get_component_mut::<ComponentB>(id_3).unwrap().beta = 30;
+    let changed: Vec<_> = entity_list.iter_since::<(Or<(Changed<ComponentA>, Changed<ComponentB>)>,)>(since).map(|(id, _)| id).collect();
+    debug_assert_eq!(changed, vec![id_3]);
+    let changed: Vec<_> = entity_list.iter_since::<(Or<(Changed<ComponentA>, ComponentB)>,)>(since).map(|(id, _)| id).collect();
+    debug_assert_eq!(changed, vec![id_2, id_3]);
+}
+
+#[test]
+fn iter_single_mut() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let _id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentB { beta: 6 })
+    );
+
+    for (_id, e, a) in entity_list.iter_single_mut::<ComponentA>() {
+        e.age.age += 1;
+        a.alpha += 1.0;
+    }
+
+    let e1 = entity_list.get(id_1).unwrap();
+    debug_assert_eq!(e1.age, AgeProp { age: 6 });
+    debug_assert_eq!(e1.get::<ComponentA>(), Some(&ComponentA { alpha: 6.0 }));
+}
+
+#[test]
+fn iter_exact_size() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    for i in 0..10 {
+        let e = Entity::new((CommonProp, AgeProp { age: i }))
+            .with(ComponentA { alpha: i as f32 });
+        let e = if i % 2 == 0 { e.with(ComponentB { beta: i as i32 }) } else { e };
+        entity_list.insert(e);
+    }
+
+    let mut iter = entity_list.iter::<(ComponentA, ComponentB)>();
+    debug_assert_eq!(iter.len(), 5);
+    iter.next();
+    debug_assert_eq!(iter.size_hint(), (4, Some(4)));
+    debug_assert_eq!(entity_list.iter_single::<ComponentA>().len(), 10);
+    debug_assert_eq!(entity_list.iter_components_mut::<(ComponentB,)>().len(), 5);
+}
+
+#[test]
+fn iter_chunks() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    for i in 0..10 {
+        entity_list.insert(
+            Entity::new((CommonProp, AgeProp { age: i }))
+                .with(ComponentA { alpha: i as f32 })
+        );
+    }
+
+    let chunk_lengths: Vec<_> = entity_list.iter_chunks::<(ComponentA,)>(4).map(|c| c.len()).collect();
+    debug_assert_eq!(chunk_lengths, &[4, 4, 2]);
+
+    for chunk in entity_list.iter_chunks_mut::<(ComponentA,)>(3) {
+        let chunk_len = chunk.len() as u32;
+        for (_id, e) in chunk {
+            e.age.age = chunk_len;
+        }
+    }
+    let ages: Vec<_> = entity_list.iter_all().map(|(_id, e)| e.age.age).collect();
+    debug_assert_eq!(ages, &[3, 3, 3, 3, 3, 3, 3, 3, 3, 1]);
+}
+
+#[test]
+fn collect_ids_into() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let mut ids = Vec::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+
+    entity_list.collect_ids_into::<(ComponentA,)>(&mut ids);
+    debug_assert_eq!(ids, &[id_1, id_2]);
+    entity_list.collect_ids_into::<(ComponentB,)>(&mut ids);
+    debug_assert_eq!(ids, &[id_2]);
+}
+
+#[test]
+fn iter_mut_tracked() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 5 }))
+            .with(ComponentA { alpha: 5.0 })
+    );
+    let id_2 = entity_list.insert(
+        Entity::new((CommonProp, AgeProp { age: 6 }))
+            .with(ComponentA { alpha: 6.0 })
+            .with(ComponentB { beta: 6 })
+    );
+
+    for mut e in entity_list.iter_mut_tracked::<(ComponentA,)>() {
+        if e.has::<ComponentB>() {
+            e.remove::<ComponentB>();
+        } else {
+            e.add(ComponentC { ceta: 1 });
+        }
+    }
+
+    let only_comp_b: Vec<_> = entity_list.iter::<(ComponentB,)>().map(|(i, _e)| i).collect();
+    let only_comp_c: Vec<_> = entity_list.iter::<(ComponentC,)>().map(|(i, _e)| i).collect();
+    debug_assert!(only_comp_b.is_empty());
+    debug_assert_eq!(only_comp_c, &[id_1]);
+    debug_assert!(entity_list.get(id_2).is_some());
+}
+
+#[test]
+fn iter_grouped_by() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids: Vec<_> = [3, 1, 3, 2, 1].iter().map(|age| {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age: *age })))
+    }).collect();
+
+    let groups: Vec<_> = entity_list.iter_grouped_by(|e| e.age.age)
+        .map(|(k, group)| (k, group.into_iter().map(|(id, _e)| id).collect::<Vec<_>>()))
+        .collect();
+    debug_assert_eq!(groups, vec![
+        (1, vec![ids[1], ids[4]]),
+        (2, vec![ids[3]]),
+        (3, vec![ids[0], ids[2]]),
+    ]);
+}
+
+#[test]
+fn join_by() {
+    let mut actors: EntityList<EntityRef> = EntityList::new();
+    let mut tiles: EntityList<EntityRef> = EntityList::new();
+
+    let actor_1 = actors.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let actor_2 = actors.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let _actor_3 = actors.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+
+    let tile_1 = tiles.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let tile_2a = tiles.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let tile_2b = tiles.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+
+    let pairs: Vec<_> = actors.join_by(&tiles, |e| e.age.age, |e| e.age.age)
+        .map(|((a, _), (t, _))| (a, t))
+        .collect();
+    debug_assert_eq!(pairs, vec![(actor_1, tile_1), (actor_2, tile_2a), (actor_2, tile_2b)]);
+}
+
+#[test]
+fn spatial_index() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    // use the age as a position on the x axis
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 5 })));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 50 })));
+    entity_list.enable_spatial_index(16.0, |e| Some((e.age.age as f32, 0.0)));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 12 })));
+
+    let mut found: Vec<_> = entity_list.iter_in_aabb(smec::Aabb::new((0.0, -1.0), (20.0, 1.0))).map(|(id, _)| id).collect();
+    found.sort();
+    debug_assert_eq!(found, vec![id_1, id_3]);
+
+    entity_list.get_mut(id_2).unwrap().age.age = 15;
+    entity_list.refresh(id_2);
+    entity_list.remove(id_1);
+
+    let mut found: Vec<_> = entity_list.iter_in_aabb(smec::Aabb::new((0.0, -1.0), (20.0, 1.0))).map(|(id, _)| id).collect();
+    found.sort();
+    debug_assert_eq!(found, vec![id_2, id_3]);
+
+    // rects covering more cells than are occupied scan the occupied cells instead
+    let mut found: Vec<_> = entity_list.iter_in_aabb(smec::Aabb::new((f32::NEG_INFINITY, -1.0), (f32::INFINITY, 1.0))).map(|(id, _)| id).collect();
+    found.sort();
+    debug_assert_eq!(found, vec![id_2, id_3]);
+    debug_assert_eq!(entity_list.iter_in_aabb(smec::Aabb::new((14.0, -1e30), (1e30, 1e30))).map(|(id, _)| id).collect::<Vec<_>>(), vec![id_2]);
+    debug_assert_eq!(entity_list.iter_in_aabb(smec::Aabb::new((f32::NAN, -1.0), (20.0, 1.0))).count(), 0);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn query_stats() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    for i in 0..10 {
+        let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: i })));
+        if i % 2 == 0 {
+            entity_list.add_component_for_entity(id, ComponentA { alpha: 0.0 });
+        }
+    }
+    let since = entity_list.advance_tick();
+    let id = entity_list.iter::<(ComponentA,)>().next().unwrap().0;
+    entity_list.mark_changed::<ComponentA>(id);
+
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 5);
+    debug_assert_eq!(entity_list.iter_since::<(smec::Changed<ComponentA>,)>(since).count(), 1);
+
+    let stats = entity_list.query_stats();
+    let a = stats[std::any::type_name::<(ComponentA,)>()];
+    debug_assert_eq!((a.runs, a.bitset_hits, a.matches), (2, 6, 6));
+    let changed = stats[std::any::type_name::<(smec::Changed<ComponentA>,)>()];
+    debug_assert_eq!((changed.runs, changed.bitset_hits, changed.matches), (1, 5, 1));
+
+    entity_list.reset_query_stats();
+    debug_assert!(entity_list.query_stats().is_empty());
+}
+
+#[test]
+fn iter_sorted_by() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 3.0 }));
+    let _id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 1.0 }));
+    let id_4 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 4 })).with(ComponentA { alpha: 2.0 }));
+
+    let sorted: Vec<_> = entity_list.iter_sorted_by::<(ComponentA,), _>(|(_, a1), (_, a2)| a1.alpha.total_cmp(&a2.alpha))
+        .map(|(id, _)| id)
+        .collect();
+    debug_assert_eq!(sorted, vec![id_3, id_4, id_1]);
+}
+
+#[test]
+fn iter_filtered() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let _id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 5 })).with(ComponentA { alpha: 0.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 15 })).with(ComponentA { alpha: 0.0 }));
+    let _id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 25 })));
+
+    let ids: Vec<_> = entity_list.iter_filtered::<(ComponentA,), _>(|e| e.age.age > 10).map(|(id, _)| id).collect();
+    debug_assert_eq!(ids, vec![id_2]);
+
+    for (_id, e) in entity_list.iter_filtered_mut::<(ComponentA,), _>(|e| e.age.age > 10) {
+        e.age.age = 0;
+    }
+    debug_assert_eq!(entity_list.get(id_2).unwrap().age.age, 0);
+}
+
+#[test]
+fn iter_clone_fused() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    for i in 0..4 {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age: i })).with(ComponentA { alpha: 0.0 }));
+    }
+
+    let mut iter = entity_list.iter::<(ComponentA,)>();
+    iter.next();
+    let saved = iter.clone();
+    debug_assert_eq!(iter.by_ref().count(), 3);
+    debug_assert_eq!(iter.next().map(|(id, _)| id), None);
+    debug_assert_eq!(saved.count(), 3);
+}
+
+#[test]
+fn iter_ids() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 0.0 }).with(ComponentB { beta: 0 }));
+    let _id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 0.0 }));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 0.0 }).with(ComponentB { beta: 0 }));
+
+    let ids = entity_list.iter_ids::<(ComponentA, ComponentB)>();
+    debug_assert_eq!(ids.len(), 2);
+    debug_assert_eq!(ids.collect::<Vec<_>>(), vec![id_1, id_3]);
+}
+
+#[test]
+fn query_macro() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 2 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 3 }).with(ComponentC { ceta: 0 }));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 1.0 }));
+
+    smec::query!(entity_list, |_id, a: &mut ComponentA, b: &ComponentB, _c: smec::Without<ComponentC>| {
+        a.alpha += b.beta as f32;
+    });
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 3.0);
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+
+    let mut seen = Vec::new();
+    smec::query!(entity_list, |id, _a: &ComponentA, b: Option<&ComponentB>,| {
+        if b.is_none() {
+            return;
+        }
+        seen.push(id);
+    });
+    debug_assert_eq!(seen, vec![id_1, id_2]);
+
+    smec::query!(entity_list, |_, _a: &ComponentA, b: Option<&mut ComponentB>| {
+        if let Some(b) = b {
+            b.beta = 0;
+        }
+    });
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentB>().unwrap().beta, 0);
+    debug_assert!(entity_list.get(id_3).unwrap().get::<ComponentB>().is_none());
+}
+
+#[test]
+fn iter_components_access_markers() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 2 }));
+
+    for (_id, a, b) in entity_list.iter_components_mut::<(&mut ComponentA, &ComponentB)>() {
+        a.alpha += b.beta as f32;
+    }
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentA>().unwrap().alpha, 3.0);
+
+    // the same component may be fetched twice immutably
+    for (_id, b1, b2) in entity_list.iter_components_mut::<(&ComponentB, &ComponentB)>() {
+        debug_assert_eq!(b1, b2);
+    }
+    debug_assert_eq!(entity_list.iter_components::<(&ComponentA,)>().count(), 1);
+}
+
+#[cfg(feature = "debug-borrows")]
+#[test]
+#[should_panic(expected = "borrowed by an iterator")]
+fn debug_borrows_iter_single_mut() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    for (_id, e, _a) in entity_list.iter_single_mut::<ComponentA>() {
+        e.mutate(|b: &mut ComponentB| b.beta += 1);
+        let _ = e.get_mut::<ComponentA>();
+    }
+}
+
+#[cfg(feature = "debug-borrows")]
+#[test]
+fn debug_borrows_released() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    for (_id, _e, a) in entity_list.iter_single_mut::<ComponentA>() {
+        a.alpha += 1.0;
+    }
+    entity_list.get_mut(id).unwrap().get_mut::<ComponentA>().unwrap().alpha += 1.0;
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentA>().unwrap().alpha, 3.0);
+}
+
+#[test]
+fn count() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    debug_assert_eq!(entity_list.count::<(ComponentA,)>(), 0);
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 1 }));
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentB { beta: 3 }));
+
+    debug_assert_eq!(entity_list.count::<(ComponentA,)>(), 2);
+    debug_assert_eq!(entity_list.count::<(ComponentA, ComponentB)>(), 1);
+    debug_assert_eq!(entity_list.count::<(ComponentB, smec::Without<ComponentA>)>(), 1);
+    debug_assert_eq!(entity_list.count::<(ComponentC,)>(), 0);
+
+    entity_list.remove(id_1);
+    debug_assert_eq!(entity_list.count::<(ComponentA, ComponentB)>(), 0);
+}
+
+#[test]
+fn first_and_single() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    debug_assert!(entity_list.first::<(ComponentA,)>().is_none());
+    debug_assert_eq!(entity_list.single::<(ComponentA,)>().err(), Some(smec::SingleError::NoMatch));
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    debug_assert_eq!(entity_list.first::<(ComponentA,)>().unwrap().0, id_1);
+    debug_assert_eq!(entity_list.single::<(ComponentA,)>().unwrap().0, id_1);
+    entity_list.single_mut::<(ComponentA,)>().unwrap().1.age.age = 5;
+    debug_assert_eq!(entity_list.get(id_1).unwrap().age.age, 5);
+
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+    debug_assert_eq!(entity_list.first::<(ComponentA,)>().unwrap().0, id_1);
+    debug_assert_eq!(entity_list.single::<(ComponentA,)>().err(), Some(smec::SingleError::MultipleMatches));
+    debug_assert!(entity_list.single_mut::<(ComponentA,)>().is_err());
+    debug_assert_eq!(entity_list.single::<(ComponentA, ComponentB)>().unwrap().0, id_2);
+}
+
+#[test]
+#[should_panic]
+fn cloned_entity_is_detached() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let e = entity_list.get(id).unwrap().clone();
+    debug_assert_eq!(e.age.age, 1);
+    let _ = e.get::<ComponentA>();
+}
+
+#[test]
+fn retain() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids: Vec<_> = (0..6).map(|age| {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age })).with(ComponentA { alpha: 0.0 }))
+    }).collect();
+
+    entity_list.retain(|_id, e| e.age.age % 2 == 0);
+    debug_assert_eq!(entity_list.len(), 3);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![ids[0], ids[2], ids[4]]);
+    debug_assert!(!entity_list.contains(ids[1]));
+
+    let mut removed = Vec::new();
+    entity_list.retain_with(|_id, e| e.age.age < 4, |id, e| removed.push((id, e.age.age)));
+    debug_assert_eq!(removed, vec![(ids[4], 4)]);
+}
+
+#[test]
+fn drain() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let mut other_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids: Vec<_> = (0..4).map(|age| {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age })).with(ComponentA { alpha: age as f32 }))
+    }).collect();
+
+    let mut drain = entity_list.drain();
+    debug_assert_eq!(drain.len(), 4);
+    let (id, e) = drain.next().unwrap();
+    debug_assert_eq!(id, ids[0]);
+    other_list.insert(e);
+    drop(drain);
+
+    debug_assert_eq!(entity_list.len(), 0);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert_eq!(other_list.iter_components::<(ComponentA,)>().map(|(_, a)| a.alpha).collect::<Vec<_>>(), vec![0.0]);
+}
+
+#[test]
+fn clear() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids: Vec<_> = (0..4).map(|age| {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age })).with(ComponentA { alpha: 0.0 }))
+    }).collect();
+
+    entity_list.clear();
+    debug_assert_eq!(entity_list.len(), 0);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert!(ids.iter().all(|id| !entity_list.contains(*id)));
+    debug_assert_eq!(entity_list.removed_since::<ComponentA>(0).count(), 4);
+
+    let new_id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 10 })).with(ComponentA { alpha: 1.0 }));
+    debug_assert!(!ids.contains(&new_id));
+    debug_assert_eq!(entity_list.iter_components::<(ComponentA,)>().map(|(_, a)| a.alpha).collect::<Vec<_>>(), vec![1.0]);
+}
+
+#[test]
+fn with_capacity() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::with_capacity(10_000);
+    entity_list.reserve_components::<ComponentA>(10_000);
+
+    for age in 0..10_000 {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age })).with(ComponentA { alpha: 0.0 }));
+    }
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 10_000);
+}
+
+#[test]
+fn get_many_mut() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+
+    let [e1, e2] = entity_list.get_many_mut([id_1, id_2]).unwrap();
+    let a1 = e1.get_mut::<ComponentA>().unwrap();
+    let a2 = e2.get_mut::<ComponentA>().unwrap();
+    std::mem::swap(a1, a2);
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 2.0);
+    debug_assert!(entity_list.get_many_mut([id_1, id_1]).is_none());
+}
+
+#[test]
+#[should_panic(expected = "borrowed through another entity")]
+fn get_many_mut_add_while_borrowed() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+
+    let [e1, e2] = entity_list.get_many_mut([id_1, id_2]).unwrap();
+    let a1 = e1.get::<ComponentA>().unwrap();
+    // inserting in the slab could move the component borrowed by `a1`
+    e2.add(ComponentA { alpha: 2.0 });
+    debug_assert_eq!(a1.alpha, 1.0);
+}
+
+#[test]
+fn get_many_mut_add_and_remove() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+
+    let [e1, e2] = entity_list.get_many_mut([id_1, id_2]).unwrap();
+    debug_assert_eq!(e1.get::<ComponentA>().unwrap().alpha, 1.0);
+    // the component is only borrowed through `e1`, which can remove it
+    debug_assert!(e1.remove::<ComponentA>().is_some());
+    e2.add(ComponentC { ceta: 2 });
+    entity_list.refresh(id_1);
+    entity_list.refresh(id_2);
+
+    // the list is borrowed again, the entities lent before cannot be used anymore
+    entity_list.get_mut(id_2).unwrap().add(ComponentA { alpha: 2.0 });
+    entity_list.refresh(id_2);
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentC)>().count(), 1);
+}
+
+#[test]
+fn get_mut_tracked() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    debug_assert!(entity_list.get_mut_tracked(smec::EntityId::new(5, 0)).is_none());
+
+    {
+        let mut e = entity_list.get_mut_tracked(id).unwrap();
+        debug_assert_eq!(e.id(), id);
+        e.add(ComponentA { alpha: 1.0 });
+    }
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 1);
+
+    entity_list.get_mut_tracked(id).unwrap().remove::<ComponentA>();
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+}
+
+#[test]
+fn extend() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids = entity_list.extend((0..100).map(|age| {
+        Entity::new((CommonProp, AgeProp { age })).with(ComponentA { alpha: age as f32 })
+    }));
+    debug_assert_eq!(ids.len(), 100);
+    debug_assert_eq!(entity_list.len(), 100);
+    let mut sorted_ids = ids.clone();
+    sorted_ids.sort();
+    debug_assert_eq!(entity_list.iter_ids::<(ComponentA,)>().collect::<Vec<_>>(), sorted_ids);
+
+    let mut buffer = Vec::new();
+    entity_list.extend_into(std::iter::once(Entity::new((CommonProp, AgeProp { age: 100 }))), &mut buffer);
+    debug_assert_eq!(buffer.len(), 1);
+    debug_assert_eq!(entity_list.get(buffer[0]).unwrap().age.age, 100);
+}
+
+#[test]
+fn spawn_batch() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let ids = entity_list.spawn_batch((0..10).map(|age| (CommonProp, AgeProp { age })), |e| {
+        if e.age.age % 2 == 0 {
+            e.add(ComponentA { alpha: e.age.age as f32 });
+        }
+    });
+    debug_assert_eq!(ids.len(), 10);
+    debug_assert_eq!(entity_list.get(ids[3]).unwrap().age.age, 3);
+    debug_assert_eq!(entity_list.iter_components::<(ComponentA,)>().map(|(_, a)| a.alpha).sum::<f32>(), 20.0);
+
+    let removed = entity_list.remove(ids[2]).unwrap();
+    debug_assert_eq!(removed.get::<ComponentA>().unwrap().alpha, 2.0);
+}
+
+#[test]
+fn duplicate() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 7 })).with(ComponentA { alpha: 1.0 }));
+    let copies: Vec<_> = (0..3).map(|_| entity_list.duplicate(id).unwrap()).collect();
+    debug_assert_eq!(entity_list.len(), 4);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 4);
+
+    entity_list.get_mut(copies[0]).unwrap().get_mut::<ComponentA>().unwrap().alpha = 5.0;
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+    debug_assert_eq!(entity_list.get(copies[1]).unwrap().age.age, 7);
+
+    entity_list.remove(id);
+    debug_assert!(entity_list.duplicate(id).is_none());
+}
+
+#[test]
+fn merge() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let mut chunk: EntityList<EntityRef> = EntityList::new();
+
+    let _id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let chunk_id_1 = chunk.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    let chunk_id_2 = chunk.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+
+    let remap = entity_list.merge(chunk);
+    debug_assert_eq!(remap.len(), 2);
+    debug_assert_eq!(entity_list.len(), 3);
+    debug_assert_eq!(entity_list.get(remap[&chunk_id_1]).unwrap().get::<ComponentA>().unwrap().alpha, 2.0);
+    debug_assert_eq!(entity_list.get(remap[&chunk_id_2]).unwrap().age.age, 3);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 1);
+}
+
+#[test]
+fn split_off_matching() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+
+    for age in 0..6 {
+        let mut e = Entity::new((CommonProp, AgeProp { age }));
+        if age % 2 == 0 {
+            e.add(ComponentA { alpha: age as f32 });
+        }
+        entity_list.insert(e);
+    }
+
+    let frozen = entity_list.split_off_matching::<(ComponentA,)>();
+    debug_assert_eq!(entity_list.len(), 3);
+    debug_assert_eq!(frozen.len(), 3);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert_eq!(frozen.iter_components::<(ComponentA,)>().map(|(_, a)| a.alpha).collect::<Vec<_>>(), vec![0.0, 2.0, 4.0]);
+
+    let old = entity_list.extract_if(|_id, e| e.age.age > 2);
+    debug_assert_eq!(entity_list.len(), 1);
+    debug_assert_eq!(old.iter_all().map(|(_, e)| e.age.age).collect::<Vec<_>>(), vec![3, 5]);
+}
+
+#[test]
+fn split_off_keeps_configuration() {
+    use smec::Aabb;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::builder().uuids(true).build();
+    entity_list.enable_spatial_index(1.0, |e| Some((e.age.age as f32, 0.0)));
+    entity_list.set_deferred_refresh(true);
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let uuid_1 = entity_list.uuid(id_1).unwrap();
+    let uuid_2 = entity_list.uuid(id_2).unwrap();
+
+    // queued refreshes are flushed before matching
+    entity_list.add_component_for_entity(id_1, ComponentA { alpha: 1.0 });
+    let split = entity_list.split_off_matching::<(ComponentA,)>();
+    debug_assert_eq!(split.len(), 1);
+    debug_assert!(split.is_refresh_deferred());
+    let new_id_1 = split.id_by_uuid(uuid_1).unwrap();
+    debug_assert_eq!(split.iter_in_aabb(Aabb::new((0.0, -1.0), (1.5, 1.0))).map(|(id, _)| id).collect::<Vec<_>>(), vec![new_id_1]);
+
+    let extracted = entity_list.extract_if(|_id, e| e.age.age == 2);
+    debug_assert_eq!(extracted.get_by_uuid(uuid_2).unwrap().age.age, 2);
+    debug_assert!(extracted.is_refresh_deferred());
+}
+
+#[test]
+fn uuids() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    debug_assert_eq!(entity_list.uuid(id_1), None);
+
+    entity_list.enable_uuids();
+    let uuid_1 = entity_list.uuid(id_1).unwrap();
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let uuid_2 = entity_list.uuid(id_2).unwrap();
+    assert_ne!(uuid_1, uuid_2);
+    debug_assert_eq!(entity_list.id_by_uuid(uuid_2), Some(id_2));
+    debug_assert_eq!(entity_list.get_by_uuid(uuid_1).unwrap().age.age, 1);
+
+    let mut chunk: EntityList<EntityRef> = EntityList::new();
+    chunk.enable_uuids();
+    let chunk_id = chunk.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    let chunk_uuid = chunk.uuid(chunk_id).unwrap();
+    let remap = entity_list.merge(chunk);
+    debug_assert_eq!(entity_list.id_by_uuid(chunk_uuid), Some(remap[&chunk_id]));
+
+    entity_list.remove(id_1);
+    debug_assert_eq!(entity_list.uuid(id_1), None);
+    debug_assert!(entity_list.get_by_uuid(uuid_1).is_none());
+}
+
+#[test]
+fn resources() {
+    #[derive(Debug, Clone, PartialEq)]
+    struct DeltaTime(f32);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    debug_assert!(entity_list.resource::<DeltaTime>().is_none());
+    debug_assert_eq!(entity_list.insert_resource(DeltaTime(0.5)), None);
+    debug_assert_eq!(entity_list.insert_resource(DeltaTime(1.0)), Some(DeltaTime(0.5)));
+    entity_list.resource_mut::<DeltaTime>().unwrap().0 += 1.0;
+
+    let cloned = entity_list.clone();
+    debug_assert_eq!(cloned.resource::<DeltaTime>(), Some(&DeltaTime(2.0)));
+    entity_list.clear();
+    debug_assert!(entity_list.has_resource::<DeltaTime>());
+    debug_assert_eq!(entity_list.remove_resource::<DeltaTime>(), Some(DeltaTime(2.0)));
+    debug_assert!(!entity_list.has_resource::<DeltaTime>());
+}
+
+#[test]
+fn tags() {
+    struct Hostile;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+
+    debug_assert_eq!(entity_list.iter::<(ComponentA, smec::Tagged<Hostile>)>().count(), 0);
+    debug_assert!(entity_list.tag::<Hostile>(id_2));
+    debug_assert!(entity_list.tag::<Hostile>(id_3));
+    debug_assert!(entity_list.has_tag::<Hostile>(id_2));
+    debug_assert!(!entity_list.has_tag::<Hostile>(id_1));
+    debug_assert_eq!(entity_list.iter::<(ComponentA, smec::Tagged<Hostile>)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_2]);
+    debug_assert_eq!(entity_list.iter::<(ComponentA, smec::Untagged<Hostile>)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1]);
+
+    debug_assert!(entity_list.untag::<Hostile>(id_2));
+    debug_assert!(!entity_list.untag::<Hostile>(id_2));
+    entity_list.remove(id_3);
+    let id_4 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 4 })));
+    debug_assert!(!entity_list.has_tag::<Hostile>(id_4));
+}
+
+#[test]
+fn iter_where() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+
+    let by_age = entity_list.index_by(|e| e.age.age);
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let mut ones: Vec<_> = entity_list.iter_where(by_age, &1).map(|(id, _)| id).collect();
+    ones.sort();
+    debug_assert_eq!(ones, vec![id_1, id_3]);
+
+    entity_list.get_mut(id_1).unwrap().age.age = 2;
+    entity_list.refresh(id_1);
+    entity_list.remove(id_3);
+    debug_assert_eq!(entity_list.iter_where(by_age, &1).count(), 0);
+    let mut twos: Vec<_> = entity_list.iter_where(by_age, &2).map(|(id, _)| id).collect();
+    twos.sort();
+    debug_assert_eq!(twos, vec![id_1, id_2]);
+
+    if let Some(mut e) = entity_list.get_mut_tracked(id_2) {
+        e.age.age = 3;
+    }
+    debug_assert_eq!(entity_list.iter_where(by_age, &3).map(|(id, _)| id).collect::<Vec<_>>(), vec![id_2]);
+}
+
+#[test]
+fn capacities() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::with_capacity(16);
+    debug_assert!(entity_list.is_empty());
+    debug_assert!(entity_list.capacity() >= 16);
+    entity_list.reserve_components::<ComponentA>(8);
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    debug_assert!(!entity_list.is_empty());
+
+    let capacities = entity_list.component_capacities();
+    debug_assert_eq!(capacities.len(), 3);
+    let a = capacities.iter().find(|c| c.name.ends_with("ComponentA")).unwrap();
+    debug_assert_eq!(a.len, 1);
+    debug_assert!(a.capacity >= 8);
+}
+
+#[test]
+fn builder() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::builder()
+        .capacity(32)
+        .bitset_capacity(64)
+        .change_tracking(false)
+        .uuids(true)
+        .build();
+    debug_assert!(entity_list.capacity() >= 32);
+
+    entity_list.advance_tick();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    debug_assert!(entity_list.uuid(id).is_some());
+    debug_assert_eq!(entity_list.iter_since::<(smec::Added<ComponentA>,)>(1).count(), 0);
+    entity_list.remove(id);
+    debug_assert_eq!(entity_list.removed_since::<ComponentA>(0).count(), 0);
+}
+
+#[test]
+fn contains_component() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    debug_assert!(entity_list.contains_component::<ComponentA>(id_1));
+    debug_assert!(!entity_list.contains_component::<ComponentB>(id_1));
+
+    entity_list.remove(id_1);
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    debug_assert_eq!(id_1.index, id_2.index);
+    debug_assert!(!entity_list.contains_component::<ComponentA>(id_1));
+    debug_assert!(entity_list.contains_component::<ComponentA>(id_2));
+}
+
+#[test]
+fn validate() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    entity_list.remove(id_2);
+    debug_assert!(entity_list.validate().is_valid());
+
+    // adding a component without refreshing leaves the bitsets out of date
+    entity_list.get_mut(id_1).unwrap().add(ComponentB { beta: 1 });
+    let report = entity_list.validate();
+    debug_assert!(!report.is_valid());
+    debug_assert_eq!(report.missing_bits, vec![(id_1, std::any::TypeId::of::<ComponentB>())]);
+
+    entity_list.refresh(id_1);
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn rebuild_bitsets() {
+    struct Hostile;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    entity_list.tag::<Hostile>(id_1);
+    entity_list.enable_archetypes();
+    entity_list.cache_query::<(ComponentA,)>();
+
+    entity_list.get_mut(id_1).unwrap().add(ComponentB { beta: 1 });
+    entity_list.get_mut(id_2).unwrap().add(ComponentA { alpha: 2.0 });
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 1);
+    entity_list.rebuild_bitset_for::<ComponentA>();
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentA,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter_archetypes::<(ComponentA,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 0);
+
+    entity_list.rebuild_bitsets();
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1]);
+    debug_assert!(entity_list.has_tag::<Hostile>(id_1));
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn take_dirty() {
+    use smec::hibitset::BitSetLike;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 3.0 }));
+
+    debug_assert_eq!(entity_list.take_dirty::<ComponentA>().iter().count(), 0);
+    entity_list.enable_dirty_tracking::<ComponentA>();
+    entity_list.get_component_mut::<ComponentA>(id_1).unwrap().alpha = 2.0;
+    entity_list.add_component_for_entity(id_2, ComponentA { alpha: 2.0 });
+    entity_list.add_component_for_entity(id_3, ComponentB { beta: 3 });
+    debug_assert_eq!(entity_list.take_dirty::<ComponentA>().iter().collect::<Vec<_>>(), vec![id_1.index as u32, id_2.index as u32]);
+    debug_assert_eq!(entity_list.take_dirty::<ComponentA>().iter().count(), 0);
+
+    entity_list.mark_changed::<ComponentA>(id_3);
+    entity_list.remove(id_3);
+    debug_assert_eq!(entity_list.take_dirty::<ComponentA>().iter().count(), 0);
+}
+
+#[test]
+fn snapshot_diff() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentB { beta: 3 }));
+
+    let old = entity_list.snapshot();
+    let mut replica = old.list().clone();
+    debug_assert!(EntityList::diff(&old, &entity_list.snapshot()).is_empty());
+
+    entity_list.get_component_mut::<ComponentA>(id_1).unwrap().alpha = 10.0;
+    entity_list.remove(id_2);
+    entity_list.remove_component_for_entity::<ComponentB>(id_3);
+    let id_4 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 4 })).with(ComponentC { ceta: 4 }));
+    let new = entity_list.snapshot();
+
+    let diff = EntityList::diff(&old, &new);
+    debug_assert_eq!(diff.despawned, vec![id_2]);
+    debug_assert_eq!(diff.spawned.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![id_4]);
+    debug_assert_eq!(diff.changed.iter().map(|c| c.id).collect::<Vec<_>>(), vec![id_1, id_3]);
+    debug_assert_eq!(diff.changed[0].components, vec![std::any::TypeId::of::<ComponentA>()]);
+
+    let remap = replica.apply_diff(diff);
+    debug_assert_eq!(replica.len(), 3);
+    debug_assert_eq!(replica.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 10.0);
+    debug_assert!(!replica.contains(id_2));
+    debug_assert_eq!(replica.iter::<(ComponentB,)>().count(), 0);
+    debug_assert_eq!(replica.get(remap[&id_4]).unwrap().get::<ComponentC>().unwrap().ceta, 4);
+    debug_assert!(replica.validate().is_valid());
+}
+
+#[test]
+fn add_component_for_entities() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..4).map(|age| entity_list.insert(Entity::new((CommonProp, AgeProp { age })))).collect();
+    entity_list.remove(ids[3]);
+
+    let not_added = entity_list.add_component_for_entities(ids.iter().map(|id| (*id, ComponentB { beta: id.index as i32 })));
+    debug_assert_eq!(not_added.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![ids[3]]);
+    debug_assert_eq!(entity_list.iter_components::<(ComponentB,)>().map(|(_, b)| b.beta).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+    let not_added = entity_list.add_component_for_entities_cloned(ids[1..].iter().copied(), ComponentC { ceta: 7 });
+    debug_assert_eq!(not_added, vec![ids[3]]);
+    debug_assert_eq!(entity_list.iter::<(ComponentB, ComponentC)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![ids[1], ids[2]]);
+}
+
+#[test]
+fn remove_component_for_entities() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..4).map(|age| {
+        entity_list.insert(Entity::new((CommonProp, AgeProp { age })).with(ComponentB { beta: age as i32 }))
+    }).collect();
+    entity_list.remove_component_for_entity::<ComponentB>(ids[0]);
+    entity_list.remove(ids[3]);
+
+    let removed = entity_list.remove_component_for_entities::<ComponentB, _>(ids.iter().copied());
+    debug_assert_eq!(removed.iter().map(|(id, b)| (*id, b.beta)).collect::<Vec<_>>(), vec![(ids[1], 1), (ids[2], 2)]);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 0);
+    debug_assert_eq!(entity_list.len(), 3);
+}
+
+#[test]
+fn remove_all() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..4).map(|age| {
+        let mut e = Entity::new((CommonProp, AgeProp { age }));
+        if age % 2 == 0 {
+            e.add(ComponentA { alpha: age as f32 });
+        }
+        e.add(ComponentB { beta: age as i32 });
+        entity_list.insert(e)
+    }).collect();
+
+    let drained = entity_list.drain_component::<ComponentA>();
+    debug_assert_eq!(drained.iter().map(|(id, a)| (*id, a.alpha)).collect::<Vec<_>>(), vec![(ids[0], 0.0), (ids[2], 2.0)]);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert_eq!(entity_list.removed_since::<ComponentA>(0).count(), 2);
+
+    entity_list.remove_all::<ComponentB>();
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 0);
+    debug_assert_eq!(entity_list.len(), 4);
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn iter_component() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..4).map(|age| {
+        let mut e = Entity::new((CommonProp, AgeProp { age }));
+        if age != 1 {
+            e.add(ComponentB { beta: age as i32 });
+        }
+        entity_list.insert(e)
+    }).collect();
+
+    for (_id, b) in entity_list.iter_component_mut::<ComponentB>() {
+        b.beta *= 10;
+    }
+    let iter = entity_list.iter_component::<ComponentB>();
+    debug_assert_eq!(iter.len(), 3);
+    debug_assert_eq!(iter.map(|(id, b)| (id, b.beta)).collect::<Vec<_>>(), vec![(ids[0], 0), (ids[2], 20), (ids[3], 30)]);
+}
+
+#[test]
+fn reserve_id() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let reserved = entity_list.reserve_id();
+    debug_assert!(!entity_list.contains(reserved));
+    let other = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    debug_assert_ne!(reserved, other);
+
+    let e = Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 });
+    debug_assert!(entity_list.insert_reserved(reserved, e).is_ok());
+    debug_assert_eq!(entity_list.get(reserved).unwrap().age.age, 2);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 1);
+
+    let e = Entity::new((CommonProp, AgeProp { age: 3 }));
+    debug_assert!(entity_list.insert_reserved(reserved, e).is_err());
+
+    let cancelled = entity_list.reserve_id();
+    debug_assert!(entity_list.cancel_reserved_id(cancelled));
+    debug_assert!(!entity_list.cancel_reserved_id(cancelled));
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn insert_at() {
+    let mut server: EntityList<EntityRef> = EntityList::new();
+    let mut client: EntityList<EntityRef> = EntityList::new();
+    client.insert(Entity::new((CommonProp, AgeProp { age: 0 })));
+
+    let a = server.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let b = server.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentB { beta: 2 }));
+    server.remove(a);
+
+    debug_assert!(client.insert_at(b, server.remove(b).unwrap()).is_ok());
+    debug_assert_eq!(client.get(b).unwrap().age.age, 2);
+    debug_assert_eq!(client.iter::<(ComponentB,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+
+    let e = Entity::new((CommonProp, AgeProp { age: 3 }));
+    debug_assert!(client.insert_at(b, e).is_err());
+    debug_assert!(client.validate().is_valid());
+}
+
+#[test]
+fn never_reuse_indices() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::builder().reuse_indices(false).build();
+    let first = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    entity_list.remove(first);
+    let second = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    debug_assert_ne!(first.index, second.index);
+    debug_assert!(entity_list.validate().is_valid());
+
+    debug_assert_eq!(entity_list.vacuum(), 1);
+    entity_list.set_reuse_indices(true);
+    let third = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    debug_assert_eq!(third.index, first.index);
+    debug_assert_ne!(third, first);
+}
+
+#[test]
+fn entry() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+
+    let mut e = entity_list.entry(id)
+        .and_modify(|e| e.age.age += 1)
+        .or_insert_with(|| Entity::new((CommonProp, AgeProp { age: 10 })));
+    debug_assert_eq!(e.id(), id);
+    debug_assert_eq!(e.get().age.age, 2);
+    e.add(ComponentA { alpha: 1.0 });
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 1);
+
+    entity_list.remove(id);
+    let e = entity_list.entry(id)
+        .and_modify(|e| e.age.age += 1)
+        .or_insert_with(|| Entity::new((CommonProp, AgeProp { age: 10 })));
+    let new_id = e.id();
+    debug_assert_ne!(new_id, id);
+    debug_assert_eq!(entity_list.get(new_id).unwrap().age.age, 10);
+
+    match entity_list.entry(new_id) {
+        smec::Entry::Occupied(o) => { o.remove_entity(); },
+        smec::Entry::Vacant(_) => panic!("entity should exist"),
+    }
+    debug_assert!(entity_list.is_empty());
+}
+
+#[test]
+fn maintain() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    entity_list.remove_later(a);
+    entity_list.remove_later(a);
+    debug_assert!(entity_list.is_removal_pending(a));
+    debug_assert!(entity_list.contains(a));
+
+    entity_list.maintain();
+    debug_assert!(!entity_list.contains(a));
+    debug_assert!(!entity_list.is_removal_pending(a));
+    debug_assert!(entity_list.contains(b));
+}
+
+#[test]
+fn maintain_forgets_removals() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    entity_list.remove(a);
+    entity_list.remove_later(b);
+
+    // removals made by maintain itself are kept until the next one
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.removed_since::<ComponentA>(0).collect::<Vec<_>>(), vec![b]);
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.removed_since::<ComponentA>(0).count(), 0);
+}
+
+#[test]
+fn lifetime() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    debug_assert!(entity_list.set_lifetime(a, 2));
+    debug_assert!(entity_list.set_lifetime(b, 2));
+    debug_assert_eq!(entity_list.clear_lifetime(b), Some(2));
+
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.lifetime(a), Some(1));
+    debug_assert!(entity_list.contains(a));
+    entity_list.maintain();
+    debug_assert!(!entity_list.contains(a));
+    debug_assert_eq!(entity_list.lifetime(a), None);
+    debug_assert!(!entity_list.set_lifetime(a, 1));
+    debug_assert!(entity_list.contains(b));
+}
+
+#[test]
+fn deferred_refresh() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let added = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&added);
+    entity_list.on_component_added(move |_id, _b: &ComponentB| counter.set(counter.get() + 1));
+    entity_list.set_deferred_refresh(true);
+    debug_assert!(entity_list.is_refresh_deferred());
+
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    entity_list.add_component_for_entity(a, ComponentB { beta: 1 });
+    entity_list.add_component_for_entity(a, ComponentB { beta: 2 });
+    entity_list.add_component_for_entity(b, ComponentA { alpha: 1.0 });
+    entity_list.remove_component_for_entity::<ComponentA>(b);
+    entity_list.get_mut(b).unwrap().add(ComponentB { beta: 3 });
+    entity_list.refresh(b);
+    // immutable queries see the bitsets of the last flush
+    debug_assert!(!entity_list.contains_component::<ComponentB>(a));
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 0);
+    debug_assert_eq!(added.get(), 0);
+
+    // mutable queries flush first
+    debug_assert_eq!(entity_list.iter_mut::<(ComponentB,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert_eq!(added.get(), 2);
+
+    entity_list.remove_component_for_entity::<ComponentB>(a);
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+
+    // a removed entity is refreshed first, and its index can be reused
+    entity_list.add_component_for_entity(a, ComponentA { alpha: 2.0 });
+    entity_list.remove(a);
+    let c = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    debug_assert_eq!(c.index, a.index);
+    entity_list.set_deferred_refresh(false);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 1);
+}
+
+#[test]
+fn deferred_refresh_queries() {
+    use smec::Query;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    entity_list.enable_archetypes();
+    entity_list.cache_query::<(ComponentC,)>();
+    entity_list.set_deferred_refresh(true);
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+
+    // immutable queries do not flush
+    entity_list.add_component_for_entity(id, ComponentC { ceta: 1 });
+    debug_assert!(!entity_list.contains_component::<ComponentC>(id));
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.iter_single::<ComponentC>().count(), 0);
+    debug_assert_eq!(entity_list.iter_component::<ComponentC>().count(), 0);
+    debug_assert_eq!(entity_list.iter_components::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.iter_ids::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.count::<(ComponentC,)>(), 0);
+    debug_assert!(entity_list.first::<(ComponentC,)>().is_none());
+    debug_assert_eq!(entity_list.iter_since::<(ComponentC,)>(0).count(), 0);
+    debug_assert_eq!(entity_list.iter_archetypes::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.iter_including_disabled::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(Query::<(ComponentC,)>::new(&entity_list).iter(&entity_list).count(), 0);
+    debug_assert_eq!(entity_list.view().iter::<(ComponentC,)>().count(), 0);
+    debug_assert_eq!(entity_list.freeze().iter::<(ComponentC,)>().count(), 0);
+    entity_list.flush_refresh();
+    debug_assert_eq!(entity_list.count::<(ComponentC,)>(), 1);
+
+    // mutable queries flush first
+    let queries: Vec<fn(&mut EntityList<EntityRef>) -> usize> = vec![
+        |list| list.iter_mut::<(ComponentC,)>().count(),
+        |list| list.iter_single_mut::<ComponentC>().count(),
+        |list| list.iter_component_mut::<ComponentC>().count(),
+        |list| list.iter_components_mut::<(ComponentC,)>().count(),
+        |list| list.iter_mut_since::<(ComponentC,)>(0).count(),
+        |list| list.iter_archetypes_mut::<(ComponentC,)>().count(),
+        |list| list.iter_cached_mut::<(ComponentC,)>().count(),
+        |list| list.iter_including_disabled_mut::<(ComponentC,)>().count(),
+        |list| list.iter_mut_tracked::<(ComponentC,)>().count(),
+        |list| Query::<(ComponentC,)>::new(list).iter_mut(list).count(),
+    ];
+    for query in queries {
+        entity_list.remove_component_for_entity::<ComponentC>(id);
+        debug_assert_eq!(query(&mut entity_list), 0);
+        entity_list.add_component_for_entity(id, ComponentC { ceta: 1 });
+        debug_assert_eq!(query(&mut entity_list), 1);
+    }
+}
+
+#[test]
+fn soft_remove() {
+    struct Selected;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    entity_list.tag::<Selected>(a);
+
+    debug_assert!(entity_list.remove_soft(a));
+    debug_assert!(!entity_list.remove_soft(a));
+    debug_assert!(!entity_list.contains(a));
+    debug_assert!(entity_list.is_soft_removed(a));
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+    debug_assert!(entity_list.validate().is_valid());
+
+    debug_assert!(entity_list.restore(a));
+    debug_assert_eq!(entity_list.get(a).unwrap().age.age, 1);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 2);
+    debug_assert!(entity_list.has_tag::<Selected>(a));
+
+    entity_list.remove_soft(a);
+    entity_list.remove_soft(b);
+    debug_assert_eq!(entity_list.purge(), 2);
+    debug_assert!(!entity_list.restore(a));
+    let c = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    debug_assert!(c != a && c != b);
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn commands() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+
+    let mut commands = smec::Commands::new();
+    for (id, _e) in entity_list.iter::<(ComponentA,)>() {
+        commands.despawn(id);
+        commands.spawn(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentB { beta: 3 }));
+    }
+    commands.add_component(b, ComponentC { ceta: 2 });
+    debug_assert_eq!(commands.len(), 3);
+    commands.apply(&mut entity_list);
+    debug_assert!(commands.is_empty());
+    debug_assert!(!entity_list.contains(a));
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 1);
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+
+    let mut commands = smec::Commands::new();
+    commands.remove_component::<ComponentC>(b);
+    entity_list.queue_commands(commands);
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().count(), 1);
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().count(), 0);
+}
+
+#[test]
+fn observers() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let e = events.clone();
+    entity_list.on_insert(move |_id, entity: &EntityRef| e.borrow_mut().push(format!("insert {}", entity.age.age)));
+    let e = events.clone();
+    entity_list.on_remove(move |_id, entity: &EntityRef| e.borrow_mut().push(format!("remove {}", entity.age.age)));
+    let e = events.clone();
+    entity_list.on_component_added::<ComponentA, _>(move |_id, a| e.borrow_mut().push(format!("add a {}", a.alpha)));
+    let e = events.clone();
+    entity_list.on_component_removed::<ComponentA, _>(move |_id| e.borrow_mut().push("remove a".to_string()));
+
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    entity_list.remove_component_for_entity::<ComponentA>(id);
+    entity_list.add_component_for_entity(id, ComponentA { alpha: 2.0 });
+    entity_list.add_component_for_entity(id, ComponentA { alpha: 3.0 });
+    if let Some(mut e) = entity_list.get_mut_tracked(id) {
+        e.remove::<ComponentA>();
+    }
+    entity_list.add_component_for_entity(id, ComponentA { alpha: 4.0 });
+    entity_list.remove(id);
+
+    debug_assert_eq!(*events.borrow(), vec![
+        "add a 1", "insert 1", "remove a", "add a 2", "remove a", "add a 4", "remove 1", "remove a",
+    ]);
+}
+
+#[test]
+fn events() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Damage(u32);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let mut early = entity_list.event_reader::<Damage>();
+    entity_list.send(Damage(1));
+    let mut late = entity_list.event_reader::<Damage>();
+    entity_list.send(Damage(2));
+
+    debug_assert_eq!(entity_list.events::<Damage>().cloned().collect::<Vec<_>>(), vec![Damage(1), Damage(2)]);
+    debug_assert_eq!(entity_list.read_events(&mut early).cloned().collect::<Vec<_>>(), vec![Damage(1), Damage(2)]);
+    debug_assert_eq!(entity_list.read_events(&mut late).cloned().collect::<Vec<_>>(), vec![Damage(2)]);
+    debug_assert_eq!(entity_list.read_events(&mut early).count(), 0);
+
+    entity_list.send(Damage(3));
+    entity_list.maintain();
+    debug_assert_eq!(entity_list.events::<Damage>().count(), 0);
+    entity_list.send(Damage(4));
+    debug_assert_eq!(entity_list.read_events(&mut early).cloned().collect::<Vec<_>>(), vec![Damage(4)]);
+    debug_assert_eq!(entity_list.events::<u32>().count(), 0);
+}
+
+mod projectile {
+    use smec::define_entity;
+    use super::{AgeProp, ComponentA};
+
+    define_entity! {
+        pub struct Projectile {
+            props => {
+                age: AgeProp,
+            },
+            components => {
+                a => ComponentA,
+            }
+        }
+    }
+}
+
+#[test]
+fn world() {
+    use projectile::{Projectile, ProjectileRef};
+
+    let mut world = smec::World::new();
+    let actor = world.insert::<EntityRef>(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let shot = world.insert::<ProjectileRef>(Projectile::new((AgeProp { age: 2 },)).with(ComponentA { alpha: 2.0 }));
+    debug_assert_eq!(world.len(), 2);
+    debug_assert!(actor.is::<EntityRef>() && shot.is::<ProjectileRef>());
+    debug_assert_eq!(world.get::<ProjectileRef>(shot).unwrap().age.age, 2);
+    debug_assert!(world.get::<EntityRef>(shot).is_none());
+
+    for (_id, p) in world.iter_mut::<ProjectileRef, (ComponentA,)>() {
+        p.get_mut::<ComponentA>().unwrap().alpha *= 10.0;
+    }
+    let alphas: Vec<_> = world.iter::<ProjectileRef, (ComponentA,)>().map(|(_, p)| p.get::<ComponentA>().unwrap().alpha).collect();
+    debug_assert_eq!(alphas, vec![20.0]);
+    debug_assert_eq!(world.iter::<EntityRef, (ComponentA,)>().count(), 1);
+
+    debug_assert!(world.remove(shot));
+    debug_assert!(!world.contains(shot));
+    debug_assert!(world.contains(actor));
+    debug_assert_eq!(world.list::<ProjectileRef>().unwrap().len(), 0);
+
+    let mut empty = smec::World::new();
+    debug_assert_eq!(empty.iter::<EntityRef, (ComponentA,)>().count(), 0);
+    debug_assert_eq!(empty.iter_mut::<EntityRef, (ComponentA,)>().count(), 0);
+    debug_assert!(empty.get_mut::<EntityRef>(actor).is_none());
+    debug_assert!(empty.list::<EntityRef>().is_none());
+}
+
+#[test]
+fn send_to_thread() {
+    struct Selected;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentB { beta: 2 }));
+    entity_list.tag::<Selected>(b);
+    entity_list.remove(a);
+
+    let sent = entity_list.into_send();
+    let sent = std::thread::spawn(move || {
+        let mut entity_list = sent.into_list();
+        entity_list.add_component_for_entity(b, ComponentA { alpha: 3.0 });
+        entity_list.into_send()
+    }).join().unwrap();
+    let entity_list = sent.into_list();
+
+    debug_assert!(!entity_list.contains(a));
+    debug_assert_eq!(entity_list.get(b).unwrap().age.age, 2);
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+    debug_assert!(entity_list.has_tag::<Selected>(b));
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn merge_shards() {
+    struct Selected;
+
+    let workers: Vec<_> = (0..3).map(|shard_index| std::thread::spawn(move || {
+        let mut shard: EntityList<EntityRef> = EntityList::new();
+        let mut ids = Vec::new();
+        for age in 0..4 {
+            ids.push(shard.insert(Entity::new((CommonProp, AgeProp { age: shard_index * 10 + age })).with(ComponentA { alpha: age as f32 })));
+        }
+        shard.remove(ids[1]);
+        shard.add_component_for_entity(ids[2], ComponentB { beta: shard_index as i32 });
+        shard.tag::<Selected>(ids[3]);
+        (ids, shard.into_send())
+    })).collect();
+    let (shard_ids, shards): (Vec<_>, Vec<_>) = workers.into_iter().map(|w| w.join().unwrap()).unzip();
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 100 })).with(ComponentA { alpha: 100.0 }));
+    let remaps = entity_list.merge_shards(shards);
+
+    debug_assert_eq!(remaps.len(), 3);
+    debug_assert_eq!(entity_list.len(), 1 + 3 * 3);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 10);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 3);
+    for (shard_index, (ids, remap)) in shard_ids.iter().zip(&remaps).enumerate() {
+        debug_assert!(!remap.contains_key(&ids[1]));
+        let e = entity_list.get(remap[&ids[2]]).unwrap();
+        debug_assert_eq!(e.age.age, shard_index as u32 * 10 + 2);
+        debug_assert_eq!(e.get::<ComponentB>().unwrap().beta, shard_index as i32);
+        debug_assert!(entity_list.has_tag::<Selected>(remap[&ids[3]]));
+        debug_assert!(!entity_list.has_tag::<Selected>(remap[&ids[2]]));
+    }
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn clone_is_independent() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+
+    let mut speculative = entity_list.clone();
+    speculative.get_mut(id_1).unwrap().get_mut::<ComponentA>().unwrap().alpha += 10.0;
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+    debug_assert_eq!(speculative.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 11.0);
+
+    let mut speculative_2 = entity_list.clone();
+    {
+        let [e_1, e_2] = speculative_2.get_many_mut([id_1, id_2]).unwrap();
+        let b = e_2.get_mut::<ComponentB>().unwrap();
+        e_1.get_mut::<ComponentA>().unwrap().alpha += b.beta as f32;
+        b.beta += 1;
+    }
+    for (_id, e) in speculative_2.iter_mut::<(ComponentA,)>() {
+        e.get_mut::<ComponentA>().unwrap().alpha *= 2.0;
+    }
+    speculative_2.add_component_for_entity(id_1, ComponentB { beta: 1 });
+    drop(speculative);
+
+    debug_assert_eq!(speculative_2.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 6.0);
+    debug_assert_eq!(speculative_2.get(id_2).unwrap().get::<ComponentB>().unwrap().beta, 3);
+    debug_assert_eq!(entity_list.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentA>().unwrap().alpha, 2.0);
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentB>().unwrap().beta, 2);
+    debug_assert!(!entity_list.get(id_1).unwrap().has::<ComponentB>());
+    debug_assert!(entity_list.validate().is_valid());
+    debug_assert!(speculative_2.validate().is_valid());
+}
+
+#[test]
+fn freeze() {
+    struct Selected;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+    entity_list.tag::<Selected>(id_2);
+
+    let frozen = std::sync::Arc::new(entity_list.freeze());
+    let readers: Vec<_> = (0..2).map(|_| {
+        let frozen = std::sync::Arc::clone(&frozen);
+        std::thread::spawn(move || {
+            let alphas: f32 = frozen.iter_component::<ComponentA>().map(|(_id, a)| a.alpha).sum();
+            let selected: Vec<_> = frozen.iter::<(ComponentB,)>().map(|(id, _)| id).filter(|id| frozen.has_tag::<Selected>(*id)).collect();
+            (alphas, selected)
+        })
+    }).collect();
+
+    entity_list.get_mut(id_1).unwrap().get_mut::<ComponentA>().unwrap().alpha = 10.0;
+    entity_list.remove(id_2);
+
+    for reader in readers {
+        debug_assert_eq!(reader.join().unwrap(), (3.0, vec![id_2]));
+    }
+    debug_assert_eq!(frozen.len(), 2);
+    debug_assert_eq!(frozen.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+    debug_assert!(frozen.contains_component::<ComponentB>(id_2));
+    debug_assert_eq!(frozen.iter_single::<ComponentA>().count(), 2);
+}
+
+#[test]
+fn view() {
+    fn sum_alpha(entities: EntityListView<EntityRef>) -> f32 {
+        entities.iter_component::<ComponentA>().map(|(_id, a)| a.alpha).sum()
+    }
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+
+    let view = entity_list.view();
+    let copy = view;
+    debug_assert_eq!(sum_alpha(view), 3.0);
+    debug_assert_eq!(copy.len(), 2);
+    debug_assert_eq!(copy.get(id_1).unwrap().get::<ComponentA>().unwrap().alpha, 1.0);
+    debug_assert_eq!(view.iter::<(ComponentA, ComponentB)>().count(), 1);
+    debug_assert_eq!(view.iter_filtered::<(ComponentA,), _>(|e| e.age.age > 1).count(), 1);
+}
+
+#[test]
+fn archetypes() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..12).map(|age| {
+        let e = Entity::new((CommonProp, AgeProp { age }));
+        let e = if age % 2 == 0 { e.with(ComponentA { alpha: age as f32 }) } else { e };
+        let e = if age % 3 == 0 { e.with(ComponentB { beta: age as i32 }) } else { e };
+        entity_list.insert(e)
+    }).collect();
+    entity_list.enable_archetypes();
+
+    fn sorted(mut ids: Vec<smec::EntityId>) -> Vec<smec::EntityId> {
+        ids.sort_by_key(|id| id.index);
+        ids
+    }
+    let matching = |entity_list: &EntityList<EntityRef>| {
+        debug_assert_eq!(
+            sorted(entity_list.iter_archetypes::<(ComponentA, smec::Without<ComponentB>)>().map(|(id, _)| id).collect()),
+            entity_list.iter::<(ComponentA, smec::Without<ComponentB>)>().map(|(id, _)| id).collect::<Vec<_>>(),
+        );
+        sorted(entity_list.iter_archetypes::<(ComponentA, ComponentB)>().map(|(id, _)| id).collect())
+    };
+    debug_assert_eq!(matching(&entity_list), vec![ids[0], ids[6]]);
+
+    entity_list.add_component_for_entity(ids[2], ComponentB { beta: 2 });
+    entity_list.remove_component_for_entity::<ComponentA>(ids[6]);
+    entity_list.remove(ids[0]);
+    entity_list.get_mut(ids[3]).unwrap().add(ComponentA { alpha: 3.0 });
+    entity_list.refresh(ids[3]);
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 12 })).with(ComponentA { alpha: 0.0 }).with(ComponentB { beta: 0 }));
+    debug_assert_eq!(matching(&entity_list), sorted(vec![ids[2], ids[3], id]));
+
+    for (_id, e) in entity_list.iter_archetypes_mut::<(ComponentA, ComponentB)>() {
+        e.get_mut::<ComponentB>().unwrap().beta += 100;
+    }
+    debug_assert_eq!(entity_list.get(ids[2]).unwrap().get::<ComponentB>().unwrap().beta, 102);
+    debug_assert_eq!(entity_list.get(ids[9]).unwrap().get::<ComponentB>().unwrap().beta, 9);
+}
+
+mod particle {
+    use smec::define_entity;
+    use super::{AgeProp, ComponentA, ComponentB};
+
+    define_entity! {
+        #[derive(Debug)]
+        pub struct Particle {
+            props => {
+                age: AgeProp,
+            },
+            components => {
+                a => ComponentA,
+                #[inline] b => ComponentB,
+            }
+        }
+    }
+}
+
+#[test]
+fn inline_component() {
+    use particle::{Particle, ParticleRef};
+
+    let mut e = Particle::new((AgeProp { age: 1 },)).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 1 });
+    debug_assert_eq!(e.b, Some(ComponentB { beta: 1 }));
+    e.mutate(|b: &mut ComponentB| b.beta += 1);
+    debug_assert_eq!(e.remove::<ComponentB>(), Some(Box::new(ComponentB { beta: 2 })));
+    debug_assert_eq!(e.b, None);
+
+    let mut entity_list: EntityList<ParticleRef> = EntityList::new();
+    let id = entity_list.insert(e.with(ComponentB { beta: 3 }));
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 3 }));
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB)>().count(), 1);
+    entity_list.get_mut(id).unwrap().get_mut::<ComponentB>().unwrap().beta += 1;
+    let e = entity_list.remove(id).unwrap();
+    debug_assert_eq!(e.b, Some(ComponentB { beta: 4 }));
+    debug_assert_eq!(*e.a.unwrap(), ComponentA { alpha: 1.0 });
+}
+
+#[test]
+fn component_positions() {
+    debug_assert_eq!(<ComponentA as Component<EntityRef>>::POSITION, 0);
+    debug_assert_eq!(<ComponentB as Component<EntityRef>>::POSITION, 1);
+    debug_assert_eq!(<ComponentC as Component<Entity>>::POSITION, 2);
+
+    let bitsets = ComponentBitSets::new::<EntityRef>(0);
+    debug_assert_eq!(bitsets.position(&TypeId::of::<ComponentA>()), Some(<ComponentA as Component<EntityRef>>::POSITION));
+    debug_assert_eq!(bitsets.position(&TypeId::of::<ComponentC>()), Some(<ComponentC as Component<EntityRef>>::POSITION));
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentC { ceta: 1 }));
+    debug_assert!(entity_list.contains_component::<ComponentC>(id));
+    debug_assert!(!entity_list.contains_component::<ComponentA>(id));
+    entity_list.remove_component_for_entity::<ComponentC>(id);
+    debug_assert!(!entity_list.contains_component::<ComponentC>(id));
+}
+
+#[test]
+fn pinned_storage() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 2 }));
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+
+    let (storage, iter) = entity_list.iter_mut_pinned::<(ComponentA,)>();
+    for (_id, e) in iter {
+        let beta = storage.get::<ComponentB>(e).map_or(0, |b| b.beta);
+        storage.get_mut::<ComponentA>(e).unwrap().alpha += beta as f32;
+    }
+
+    let storage = entity_list.pin();
+    let alphas: Vec<f32> = entity_list.iter::<(ComponentA,)>()
+        .map(|(_id, e)| storage.get::<ComponentA>(e).unwrap().alpha)
+        .collect();
+    debug_assert_eq!(alphas, vec![3.0, 2.0]);
+    debug_assert_eq!(storage.get::<ComponentC>(entity_list.get(id).unwrap()), None);
+}
+
+#[test]
+#[should_panic(expected = "does not belong to the pinned list")]
+fn pinned_storage_other_list() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let mut other: EntityList<EntityRef> = EntityList::new();
+    entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id = other.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 2.0 }));
+    let storage = entity_list.pin();
+    let _ = storage.get::<ComponentA>(other.get(id).unwrap());
+}
+
+#[test]
+fn cached_query() {
+    use smec::{EntityId, Untagged, Without};
+
+    struct Frozen;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+    entity_list.cache_query::<(ComponentA, Without<ComponentB>)>();
+    entity_list.cache_query::<(ComponentB,)>();
+    debug_assert!(entity_list.is_query_cached::<(ComponentB,)>());
+    debug_assert!(!entity_list.is_query_cached::<(ComponentC,)>());
+
+    let ids = |list: &EntityList<EntityRef>| list.iter_cached::<(ComponentA, Without<ComponentB>)>().map(|(id, _)| id).collect::<Vec<_>>();
+    debug_assert_eq!(ids(&entity_list), vec![a]);
+
+    let c = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 3.0 }));
+    entity_list.remove_component_for_entity::<ComponentB>(b);
+    entity_list.add_component_for_entity(a, ComponentB { beta: 1 });
+    debug_assert_eq!(ids(&entity_list), vec![b, c]);
+
+    entity_list.get_mut(c).unwrap().add(ComponentB { beta: 3 });
+    entity_list.refresh(c);
+    entity_list.remove(b);
+    debug_assert_eq!(ids(&entity_list), Vec::<EntityId>::new());
+    for (_id, e) in entity_list.iter_cached_mut::<(ComponentB,)>() {
+        e.get_mut::<ComponentB>().unwrap().beta += 10;
+    }
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentB>().unwrap().beta, 11);
+
+    entity_list.cache_query::<(ComponentA, Untagged<Frozen>)>();
+    entity_list.tag::<Frozen>(a);
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentA, Untagged<Frozen>)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![c]);
+
+    debug_assert!(entity_list.uncache_query::<(ComponentA, Without<ComponentB>)>());
+    debug_assert!(!entity_list.uncache_query::<(ComponentA, Without<ComponentB>)>());
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentB,)>().count(), 2);
+    entity_list.clear();
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentB,)>().count(), 0);
+}
+
+#[test]
+fn sort_component_storage() {
+    use smec::RefComponent;
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..6).map(|i| entity_list.insert(Entity::new((CommonProp, AgeProp { age: i }))))
+        .collect();
+    // add the components backwards, and replace some of them, so their keys do not follow the ids
+    for (i, id) in ids.iter().enumerate().rev() {
+        entity_list.add_component_for_entity(*id, ComponentA { alpha: i as f32 });
+    }
+    entity_list.remove_component_for_entity::<ComponentA>(ids[4]);
+    entity_list.remove_component_for_entity::<ComponentA>(ids[1]);
+    entity_list.add_component_for_entity(ids[1], ComponentA { alpha: 1.0 });
+    entity_list.remove_soft(ids[2]);
+    let keys = |list: &EntityList<EntityRef>| list.iter::<(ComponentA,)>()
+        .map(|(_id, e)| <ComponentA as RefComponent<EntityRef>>::get_cs_id(e).unwrap())
+        .collect::<Vec<_>>();
+    debug_assert_ne!(keys(&entity_list), vec![0, 1, 2, 3]);
+
+    entity_list.sort_component_storage::<ComponentA>();
+    debug_assert_eq!(keys(&entity_list), vec![0, 1, 2, 3]);
+    let alphas: Vec<_> = entity_list.iter::<(ComponentA,)>().map(|(_id, e)| e.get::<ComponentA>().unwrap().alpha).collect();
+    debug_assert_eq!(alphas, vec![0.0, 1.0, 3.0, 5.0]);
+    debug_assert!(entity_list.restore(ids[2]));
+    debug_assert_eq!(entity_list.get(ids[2]).unwrap().get::<ComponentA>().unwrap().alpha, 2.0);
+    debug_assert_eq!(keys(&entity_list), vec![0, 1, 4, 2, 3]);
+}
+
+#[test]
+fn shrink_component_storage() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..64).map(|i| entity_list.insert(Entity::new((CommonProp, AgeProp { age: i }))
+        .with(ComponentA { alpha: i as f32 })
+        .with(ComponentB { beta: i as i32 })))
+        .collect();
+    for id in &ids[..48] {
+        entity_list.remove_component_for_entity::<ComponentA>(*id);
+    }
+    for id in &ids[..60] {
+        entity_list.remove_component_for_entity::<ComponentB>(*id);
+    }
+    entity_list.remove_soft(ids[62]);
+    let capacity = |list: &EntityList<EntityRef>, name: &str| list.component_capacities().into_iter()
+        .find(|c| c.name.ends_with(name))
+        .unwrap()
+        .capacity;
+    debug_assert!(capacity(&entity_list, "ComponentA") >= 64);
+
+    entity_list.shrink_component_storage::<ComponentA>();
+    debug_assert_eq!(capacity(&entity_list, "ComponentA"), 16);
+    debug_assert!(capacity(&entity_list, "ComponentB") >= 64);
+    entity_list.shrink_to_fit();
+    debug_assert_eq!(capacity(&entity_list, "ComponentB"), 4);
+
+    for (i, id) in ids.iter().enumerate().skip(48).filter(|(i, _)| *i != 62) {
+        let e = entity_list.get(*id).unwrap();
+        debug_assert_eq!(e.get::<ComponentA>().unwrap().alpha, i as f32);
+        debug_assert_eq!(e.get::<ComponentB>().map(|b| b.beta), (i >= 60).then_some(i as i32));
+    }
+    debug_assert!(entity_list.restore(ids[62]));
+    debug_assert_eq!(entity_list.get(ids[62]).unwrap().get::<ComponentB>().unwrap().beta, 62);
+    debug_assert!(entity_list.validate().is_valid());
+}
+
+#[test]
+fn get_unchecked() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    // SAFETY: both entities exist
+    unsafe {
+        debug_assert_eq!(entity_list.get_unchecked(a).get::<ComponentA>().unwrap().alpha, 1.0);
+        entity_list.get_unchecked_mut(b).age.age = 3;
+        let (id, e) = entity_list.get_raw_unchecked(b.index);
+        debug_assert_eq!(id, b);
+        debug_assert_eq!(e.age.age, 3);
+    }
+}
+
+#[test]
+fn memory_report() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let ids: Vec<_> = (0..100).map(|i| entity_list.insert(Entity::new((CommonProp, AgeProp { age: i }))
+        .with(ComponentA { alpha: i as f32 })))
+        .collect();
+    entity_list.tag::<()>(ids[99]);
+    let report = entity_list.memory_report();
+    debug_assert!(report.arena >= 100 * std::mem::size_of::<EntityRef>());
+    let names: Vec<_> = entity_list.component_capacities().iter().map(|c| c.name).collect();
+    debug_assert_eq!(report.components.iter().map(|c| c.name).collect::<Vec<_>>(), names);
+    let a = report.components.iter().find(|c| c.name.ends_with("ComponentA")).unwrap();
+    debug_assert_eq!(a.len, 100);
+    debug_assert!(a.storage >= 100 * std::mem::size_of::<ComponentA>());
+    debug_assert!(a.bitset > 0);
+    debug_assert!(report.tags > 0);
+    debug_assert_eq!(report.total(), report.arena + report.tags + report.components.iter().map(|c| c.storage + c.bitset).sum::<usize>());
+
+    for id in &ids[10..] {
+        entity_list.remove_component_for_entity::<ComponentA>(*id);
+    }
+    entity_list.shrink_to_fit();
+    let shrunk = entity_list.memory_report();
+    let shrunk_a = shrunk.components.iter().find(|c| c.name.ends_with("ComponentA")).unwrap();
+    debug_assert!(shrunk_a.storage < a.storage);
+    debug_assert!(shrunk.total() < report.total());
+}
+
+#[test]
+fn take_component() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentB { beta: 2 });
+    debug_assert_eq!(owned.take::<ComponentB>(), Some(ComponentB { beta: 2 }));
+    debug_assert_eq!(owned.take::<ComponentB>(), None);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    debug_assert_eq!(entity_list.get_mut(id).unwrap().take::<ComponentA>(), Some(ComponentA { alpha: 1.0 }));
+    entity_list.refresh(id);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 0);
+
+    entity_list.add_component_for_entity(id, ComponentC { ceta: 3 });
+    debug_assert_eq!(entity_list.take_component_for_entity::<ComponentC>(id), Some(ComponentC { ceta: 3 }));
+    debug_assert_eq!(entity_list.take_component_for_entity::<ComponentC>(id), None);
+    debug_assert!(!entity_list.contains_component::<ComponentC>(id));
+}
+
+#[test]
+fn replace_component() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 }));
+    debug_assert_eq!(owned.replace(ComponentB { beta: 1 }), None);
+    debug_assert_eq!(owned.replace(ComponentB { beta: 2 }), Some(ComponentB { beta: 1 }));
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    let e = entity_list.get_mut(id).unwrap();
+    debug_assert_eq!(e.replace(ComponentB { beta: 3 }), Some(ComponentB { beta: 2 }));
+    debug_assert_eq!(e.replace(ComponentA { alpha: 1.0 }), None);
+    entity_list.refresh(id);
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB)>().count(), 1);
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 3 }));
+}
+
+#[test]
+fn get_or_insert_with() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 }));
+    owned.get_or_insert_with(|| ComponentC { ceta: 5 }).ceta += 1;
+    owned.get_or_insert_with(|| -> ComponentC { unreachable!() }).ceta += 1;
+    debug_assert_eq!(owned.get::<ComponentC>(), Some(&ComponentC { ceta: 7 }));
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    entity_list.get_mut(id).unwrap().get_or_insert_with(|| ComponentA { alpha: 2.0 }).alpha *= 2.0;
+    entity_list.refresh(id);
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentC)>().count(), 1);
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 4.0 }));
+}
+
+#[test]
+fn bundles() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with_bundle((ComponentA { alpha: 1.0 }, ComponentB { beta: 1 }));
+    debug_assert!(owned.has::<ComponentA>() && owned.has::<ComponentB>() && !owned.has::<ComponentC>());
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let added = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&added);
+    entity_list.on_component_added(move |_id, _c: &ComponentC| counter.set(counter.get() + 1));
+    let a = entity_list.insert(owned);
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    debug_assert!(entity_list.add_bundle_for_entity(a, (ComponentB { beta: 2 }, ComponentC { ceta: 2 })).is_none());
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB, ComponentC)>().count(), 1);
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 2 }));
+    debug_assert_eq!(added.get(), 1);
+
+    entity_list.get_mut(b).unwrap().add_bundle((ComponentC { ceta: 3 },));
+    entity_list.refresh(b);
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().count(), 2);
+
+    entity_list.set_deferred_refresh(true);
+    entity_list.add_bundle_for_entity(b, (ComponentA { alpha: 3.0 }, ComponentB { beta: 3 }));
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB, ComponentC)>().count(), 1);
+    entity_list.flush_refresh();
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentB, ComponentC)>().count(), 2);
+
+    entity_list.remove(b);
+    debug_assert!(entity_list.add_bundle_for_entity(b, (ComponentA { alpha: 4.0 },)).is_some());
+}
+
+#[test]
+fn clear_components() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentC { ceta: 1 });
+    owned.clear_components();
+    debug_assert!(!owned.has::<ComponentA>() && !owned.has::<ComponentC>());
+    debug_assert_eq!(owned.age.age, 1);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }).with(ComponentB { beta: 2 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })).with(ComponentA { alpha: 3.0 }));
+    debug_assert!(entity_list.clear_components_for_entity(a));
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![b]);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 0);
+    debug_assert_eq!(entity_list.removed_since::<ComponentB>(0).count(), 1);
+    debug_assert_eq!(entity_list.get(a).unwrap().age.age, 2);
+    debug_assert!(entity_list.component_capacities().iter().all(|c| c.len == usize::from(c.name.ends_with("ComponentA"))));
+
+    entity_list.remove(b);
+    debug_assert!(!entity_list.clear_components_for_entity(b));
+}
+
+mod loadout {
+    use smec::define_entity;
+    use super::{AgeProp, ComponentA};
+
+    /// A component without `PartialEq`
+    #[derive(Debug, Clone)]
+    pub struct Weapon(pub &'static str);
+
+    define_entity! {
+        pub struct Loadout {
+            props => {
+                age: AgeProp,
+            },
+            components => {
+                a => ComponentA,
+                weapon => Weapon,
+            }
+        }
+    }
+}
+
+#[test]
+fn entity_diff() {
+    use loadout::{Loadout, LoadoutRef, Weapon};
+    use smec::ComponentChange;
+
+    let before = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentB { beta: 1 });
+    let after = Entity::new((CommonProp, AgeProp { age: 2 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentB { beta: 2 })
+        .with(ComponentC { ceta: 2 });
+    debug_assert!(before.diff(&before).is_empty());
+    let diff = before.diff(&after);
+    debug_assert_eq!(diff.components().len(), 2);
+    debug_assert_eq!(diff.change::<ComponentA>(), None);
+    debug_assert_eq!(diff.change::<ComponentB>(), Some(ComponentChange::Changed));
+    debug_assert_eq!(diff.change::<ComponentC>(), Some(ComponentChange::Added));
+    debug_assert_eq!(diff.components()[0].value::<ComponentB>(), Some(&ComponentB { beta: 2 }));
+
+    let mut owned = before.clone();
+    owned.apply_diff(&diff);
+    debug_assert!(owned.diff(&after).is_empty());
+    let undo = after.diff(&before);
+    debug_assert_eq!(undo.change::<ComponentC>(), Some(ComponentChange::Removed));
+    owned.apply_diff(&undo);
+    debug_assert!(owned.diff(&before).is_empty());
+
+    // in a list, the components are set in the storage of the list
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(before);
+    let b = entity_list.insert(after);
+    let diff = entity_list.get(a).unwrap().diff(entity_list.get(b).unwrap());
+    let undo = entity_list.get(b).unwrap().diff(entity_list.get(a).unwrap());
+    entity_list.get_mut(a).unwrap().apply_diff(&diff);
+    entity_list.refresh(a);
+    debug_assert_eq!(entity_list.iter::<(ComponentB, ComponentC)>().count(), 2);
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 2 }));
+    entity_list.get_mut(a).unwrap().apply_diff(&undo);
+    entity_list.refresh(a);
+    debug_assert_eq!(entity_list.iter::<(ComponentC,)>().count(), 1);
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 1 }));
+    debug_assert_eq!(entity_list.component_capacities().iter().map(|c| c.len).sum::<usize>(), 5);
+
+    // components without `PartialEq` are always considered changed
+    let mut loadouts: EntityList<LoadoutRef> = EntityList::new();
+    let a = loadouts.insert(Loadout::new((AgeProp { age: 1 },)).with(Weapon("sword")).with(ComponentA { alpha: 1.0 }));
+    let b = loadouts.insert(Loadout::new((AgeProp { age: 1 },)).with(Weapon("sword")).with(ComponentA { alpha: 1.0 }));
+    let diff = loadouts.get(a).unwrap().diff(loadouts.get(b).unwrap());
+    debug_assert_eq!(diff.change::<Weapon>(), Some(ComponentChange::Changed));
+    debug_assert_eq!(diff.change::<ComponentA>(), None);
+}
+
+#[test]
+fn merge_from() {
+    use smec::{EntityId, MergeMode};
+
+    let template = Entity::new((CommonProp, AgeProp { age: 0 }))
+        .with(ComponentA { alpha: 10.0 })
+        .with(ComponentC { ceta: 10 });
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 });
+    owned.merge_from(&template, MergeMode::KeepExisting);
+    debug_assert_eq!(owned.get::<ComponentA>(), Some(&ComponentA { alpha: 1.0 }));
+    debug_assert_eq!(owned.get::<ComponentC>(), Some(&ComponentC { ceta: 10 }));
+    owned.merge_from(&template, MergeMode::Overwrite);
+    debug_assert_eq!(owned.get::<ComponentA>(), Some(&ComponentA { alpha: 10.0 }));
+    debug_assert_eq!(owned.age.age, 1);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 1 }));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    entity_list.advance_tick();
+    debug_assert!(entity_list.merge_into_entity(a, &template, MergeMode::Overwrite));
+    debug_assert!(entity_list.merge_into_entity(b, &template, MergeMode::KeepExisting));
+    debug_assert_eq!(entity_list.iter::<(ComponentA, ComponentC)>().count(), 2);
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 10.0 }));
+    debug_assert_eq!(entity_list.get(a).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 1 }));
+    debug_assert_eq!(entity_list.iter_since::<(smec::Changed<ComponentA>,)>(1).count(), 2);
+
+    // between entities of the same list
+    let [a_ref, b_ref] = entity_list.get_many_mut([a, b]).unwrap();
+    b_ref.merge_from(a_ref, MergeMode::KeepExisting);
+    entity_list.refresh(b);
+    debug_assert_eq!(entity_list.iter::<(ComponentB,)>().count(), 2);
+    debug_assert!(!entity_list.merge_into_entity(EntityId::new(9, 0), &template, MergeMode::Overwrite));
+}
+
+#[test]
+fn has_all_any() {
+    let a = TypeId::of::<ComponentA>();
+    let b = TypeId::of::<ComponentB>();
+    let c = TypeId::of::<ComponentC>();
+    let owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentB { beta: 1 });
+    debug_assert!(owned.has_type(a) && !owned.has_type(c) && !owned.has_type(TypeId::of::<u32>()));
+    debug_assert!(owned.has_all(&[a, b]) && !owned.has_all(&[a, c]) && owned.has_all(&[]));
+    debug_assert!(owned.has_any(&[c, b]) && !owned.has_any(&[c]) && !owned.has_any(&[]));
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    let e = entity_list.get(id).unwrap();
+    debug_assert!(e.has_all(&[b, a]) && !e.has_any(&[c, TypeId::of::<u32>()]));
+}
+
+#[test]
+fn update2() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentA { alpha: 1.0 })
+        .with(ComponentB { beta: 2 });
+    // ComponentB is stored inline, next to ComponentA in the owned entity
+    owned.update2::<ComponentA, ComponentB, _>(|a, b| {
+        a.alpha += b.beta as f32;
+        b.beta += 1;
+    });
+    debug_assert_eq!(owned.peek2::<ComponentA, ComponentB, _>(|a, b| (a.alpha, b.beta)), Some((3.0, 3)));
+    debug_assert_eq!(owned.update2::<ComponentA, ComponentC, _>(|_, _| ()), None);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    let e = entity_list.get_mut(id).unwrap();
+    debug_assert_eq!(e.update2::<ComponentB, ComponentA, _>(|b, a| {
+        b.beta *= 2;
+        a.alpha *= 2.0;
+        (b.beta, a.alpha)
+    }), Some((6, 6.0)));
+    debug_assert_eq!(e.peek2::<ComponentC, ComponentA, _>(|_, _| ()), None);
+}
+
+#[test]
+#[should_panic]
+fn update2_same_component() {
+    let mut owned = Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 });
+    owned.update2::<ComponentA, ComponentA, _>(|_, _| ());
+}
+
+#[cfg(not(feature = "cow"))]
+mod devices {
+    use smec::define_entity;
+    use super::{AgeProp, ComponentA};
+
+    /// A component without `Clone`, like a file or a channel
+    #[derive(Debug, PartialEq)]
+    pub struct Handle(pub u32);
+
+    define_entity! {
+        pub struct Device {
+            props => {
+                age: AgeProp,
+            },
+            components => {
+                a => ComponentA,
+                handle => Handle,
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(not(feature = "cow"))]
+fn component_without_clone() {
+    use devices::{Device, DeviceRef, Handle};
+
+    let mut owned = Device::new((AgeProp { age: 1 },)).with(Handle(1));
+    owned.get_mut::<Handle>().unwrap().0 += 1;
+
+    let mut entity_list: EntityList<DeviceRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    entity_list.add_component_for_entity(id, ComponentA { alpha: 1.0 });
+    for (_, e) in entity_list.iter_mut::<(Handle, ComponentA)>() {
+        e.update2::<Handle, ComponentA, _>(|h, a| a.alpha += h.0 as f32);
+    }
+    debug_assert_eq!(entity_list.get(id).unwrap().get::<ComponentA>(), Some(&ComponentA { alpha: 3.0 }));
+
+    let owned = entity_list.remove(id).unwrap();
+    debug_assert_eq!(owned.get::<Handle>(), Some(&Handle(2)));
+}
+
+#[test]
+fn component_introspection() {
+    let owned = Entity::new((CommonProp, AgeProp { age: 1 }))
+        .with(ComponentC { ceta: 1 })
+        .with(ComponentA { alpha: 1.0 });
+    debug_assert_eq!(owned.component_count(), 2);
+    debug_assert_eq!(owned.active_components().collect::<Vec<_>>(), vec![TypeId::of::<ComponentA>(), TypeId::of::<ComponentC>()]);
+    debug_assert_eq!(owned.active_component_names().collect::<Vec<_>>(), vec!["ComponentA", "ComponentC"]);
+    debug_assert_eq!(Entity::component_name(TypeId::of::<ComponentB>()), Some("ComponentB"));
+    debug_assert_eq!(Entity::component_name(TypeId::of::<u32>()), None);
+
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id = entity_list.insert(owned);
+    entity_list.remove_component_for_entity::<ComponentA>(id);
+    let e = entity_list.get(id).unwrap();
+    debug_assert_eq!(e.component_count(), 1);
+    debug_assert_eq!(e.active_component_names().collect::<Vec<_>>(), vec!["ComponentC"]);
+
+    // every name reported for a component is the one of `component_name`
+    let names: Vec<_> = entity_list.component_capacities().iter().map(|c| c.name).collect();
+    debug_assert_eq!(names, vec!["ComponentA", "ComponentB", "ComponentC"]);
+    debug_assert_eq!(entity_list.memory_report().components.iter().map(|c| c.name).collect::<Vec<_>>(), names);
+    debug_assert_eq!(<ComponentC as Component<EntityRef>>::NAME, "ComponentC");
+}
+
+#[test]
+fn entity_handle() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let a = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })));
+    let b = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })));
+    let handle_a = entity_list.handle(a).unwrap();
+    let handle_b = entity_list.handle(b).unwrap();
+    debug_assert!(handle_a.is_alive() && handle_b.is_alive());
+    debug_assert_eq!(handle_a.get(&entity_list).unwrap().age.age, 1);
+    handle_b.get_mut(&mut entity_list).unwrap().age.age = 3;
+    debug_assert_eq!(entity_list.get(b).unwrap().age.age, 3);
+
+    // the slot of `a` is reused by `c`, with another generation
+    entity_list.remove(a);
+    let c = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 4 })));
+    debug_assert_eq!(c.index, a.index);
+    debug_assert!(!handle_a.is_alive());
+    debug_assert!(handle_a.get(&entity_list).is_none());
+    debug_assert!(entity_list.handle(c).unwrap().is_alive());
+    debug_assert!(entity_list.handle(a).is_none());
+
+    entity_list.remove_soft(b);
+    debug_assert!(!handle_b.is_alive());
+    entity_list.restore(b);
+    debug_assert!(handle_b.is_alive());
+
+    // handles only refer to the list which created them
+    let clone = entity_list.clone();
+    debug_assert!(clone.contains(b) && handle_b.get(&clone).is_none());
+    debug_assert!(!handle_b.belongs_to(&clone));
+
+    entity_list.clear();
+    debug_assert!(!handle_b.is_alive());
+    let d = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 5 })));
+    let handle_d = entity_list.handle(d).unwrap();
+    drop(entity_list);
+    debug_assert!(!handle_d.is_alive());
+}
+
+#[test]
+fn enabled_entities() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 1 })).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 2 })).with(ComponentA { alpha: 2.0 }));
+    entity_list.cache_query::<(ComponentA,)>();
+
+    debug_assert!(entity_list.set_enabled(id_2, false));
+    debug_assert!(!entity_list.is_enabled(id_2) && entity_list.is_enabled(id_1));
+    // disabled entities keep their components, and can still be accessed by id
+    debug_assert_eq!(entity_list.get(id_2).unwrap().get::<ComponentA>().unwrap().alpha, 2.0);
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1]);
+    debug_assert_eq!(entity_list.iter_mut::<(ComponentA,)>().count(), 1);
+    debug_assert_eq!(entity_list.count::<(ComponentA,)>(), 1);
+    debug_assert_eq!(entity_list.iter_ids::<(ComponentA,)>().collect::<Vec<_>>(), vec![id_1]);
+    debug_assert_eq!(entity_list.iter_single::<ComponentA>().count(), 1);
+    debug_assert_eq!(entity_list.iter_components::<(ComponentA,)>().count(), 1);
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentA,)>().count(), 1);
+    debug_assert_eq!(entity_list.iter_including_disabled::<(ComponentA,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter_including_disabled::<(smec::Tagged<smec::Disabled>,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_2]);
+
+    debug_assert!(entity_list.set_enabled(id_2, true));
+    debug_assert_eq!(entity_list.iter::<(ComponentA,)>().count(), 2);
+    debug_assert_eq!(entity_list.iter_cached::<(ComponentA,)>().count(), 2);
+
+    // removed entities are enabled again once their slot is reused
+    entity_list.set_enabled(id_1, false);
+    entity_list.remove(id_1);
+    debug_assert!(!entity_list.set_enabled(id_1, true));
+    let id_3 = entity_list.insert(Entity::new((CommonProp, AgeProp { age: 3 })));
+    debug_assert!(entity_list.is_enabled(id_3));
+}
+
+mod spawner {
+    use smec::define_entity;
+    use super::{AgeProp, ComponentB};
+
+    #[derive(Debug, PartialEq, Clone, Copy, Default)]
+    pub struct Layer(pub u8);
+
+    define_entity! {
+        pub struct Spawner {
+            props => {
+                layer: Layer,
+                age: AgeProp,
+            },
+            components => {
+                b => ComponentB,
+            }
+        }
+    }
+}
+
+#[test]
+fn entity_props_builder() {
+    use spawner::{Spawner, Layer};
+
+    // `Layer` implements `Default`, `AgeProp` does not
+    let e = Spawner::with_props().age(AgeProp { age: 5 }).build().with(ComponentB { beta: 1 });
+    debug_assert_eq!(e.age.age, 5);
+    debug_assert_eq!(e.layer, Layer(0));
+    debug_assert_eq!(e.get::<ComponentB>().unwrap().beta, 1);
+
+    let e = Spawner::with_props().age(AgeProp { age: 1 }).layer(Layer(2)).try_build().unwrap();
+    debug_assert_eq!(e.age.age, 1);
+    debug_assert_eq!(e.layer, Layer(2));
+    debug_assert!(Spawner::with_props().layer(Layer(2)).try_build().is_none());
+}
+
+#[test]
+#[should_panic(expected = "prop `age` was not set")]
+fn entity_props_builder_missing_prop() {
+    spawner::Spawner::with_props().build();
+}
+
+mod lockstep {
+    use smec::define_entity;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Hash)]
+    pub struct Turn(pub u32);
+
+    #[derive(Debug, Clone, PartialEq, Hash)]
+    pub struct Health(pub u32);
+
+    #[derive(Debug, Clone, PartialEq, Hash)]
+    pub struct Team(pub u8);
+
+    define_entity! {
+        pub struct Unit {
+            props => {
+                turn: Turn,
+            },
+            components => {
+                health => Health,
+                #[inline] team => Team,
+            }
+        }
+    }
+}
+
+#[test]
+fn entity_state_hash() {
+    use lockstep::{Unit, UnitRef, Turn, Health, Team};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    fn hash<E: EntityBase + smec::HashState>(e: &E) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        e.state_hash(&mut hasher);
+        hasher.finish()
+    }
+
+    let a = Unit::new((Turn(1),)).with(Health(10)).with(Team(1));
+    let b = Unit::new((Turn(1),)).with(Team(1)).with(Health(10));
+    debug_assert_eq!(hash(&a), hash(&b));
+    debug_assert_ne!(hash(&a), hash(&Unit::new((Turn(2),)).with(Health(10)).with(Team(1))));
+    debug_assert_ne!(hash(&a), hash(&Unit::new((Turn(1),)).with(Health(9)).with(Team(1))));
+    // a missing component does not hash like another one
+    debug_assert_ne!(hash(&Unit::new((Turn(1),)).with(Health(1))), hash(&Unit::new((Turn(1),)).with(Team(1))));
+
+    // entities with the same state hash the same, whatever their list and their component keys
+    let mut list_1: EntityList<UnitRef> = EntityList::new();
+    let mut list_2: EntityList<UnitRef> = EntityList::new();
+    list_2.insert(Unit::new((Turn(3),)).with(Health(3)));
+    let id_1 = list_1.insert(a);
+    let id_2 = list_2.insert(b);
+    debug_assert_eq!(hash(list_1.get(id_1).unwrap()), hash(list_2.get(id_2).unwrap()));
+    list_2.get_mut(id_2).unwrap().get_mut::<Health>().unwrap().0 = 11;
+    debug_assert_ne!(hash(list_1.get(id_1).unwrap()), hash(list_2.get(id_2).unwrap()));
+}
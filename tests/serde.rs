@@ -7,7 +7,7 @@ use serde::{
 use smec::{
     define_entity,
     EntityList,
-    EntityBase, EntityRefBase, EntityOwnedBase
+    EntityBase, EntityOwnedBase
 };
 
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
@@ -112,7 +112,7 @@ fn post_deserialize_same_behavior() {
             .with(ComponentB { beta: 6 })
             .with(ComponentA { alpha: 6.0 })
     );
-    let id_4 = entity_list.insert(
+    let _id_4 = entity_list.insert(
         Entity::new((CommonProp,))
             .with(ComponentB { beta: 6 })
             .with(ComponentA { alpha: 6.0 })
@@ -148,4 +148,103 @@ fn post_deserialize_same_behavior() {
     let only_comp_b2: Vec<_> = deserialized_entity_list.iter::<(ComponentB,)>().map(|(i, _e)| i).collect();
     assert_eq!(only_comp_a1, only_comp_a2);
     assert_eq!(only_comp_b1, only_comp_b2);
-}
\ No newline at end of file
+}
+#[test]
+fn uuids_are_serialized() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    entity_list.enable_uuids();
+    let id_1 = entity_list.insert(Entity::new((CommonProp,)).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp,)));
+    entity_list.remove(id_1);
+
+    let bytes = bincode::serialize(&entity_list).expect("EntityList should be serializable");
+    let mut deserialized_entity_list: EntityList<EntityRef> = bincode::deserialize(&bytes).expect("EntityList should be deserializable");
+
+    let uuid_2 = entity_list.uuid(id_2).unwrap();
+    assert_eq!(deserialized_entity_list.uuid(id_2), Some(uuid_2));
+    assert_eq!(deserialized_entity_list.id_by_uuid(uuid_2), Some(id_2));
+    let id_3 = deserialized_entity_list.insert(Entity::new((CommonProp,)));
+    assert_ne!(deserialized_entity_list.uuid(id_3), Some(uuid_2));
+    assert!(deserialized_entity_list.uuid(id_3).is_some());
+}
+
+#[test]
+fn retired_indices_are_freed_on_deserialize() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::builder().reuse_indices(false).build();
+    let id_1 = entity_list.insert(Entity::new((CommonProp,)));
+    entity_list.remove(id_1);
+
+    let bytes = bincode::serialize(&entity_list).expect("EntityList should be serializable");
+    let mut deserialized_entity_list: EntityList<EntityRef> = bincode::deserialize(&bytes).expect("EntityList should be deserializable");
+
+    let id_2 = deserialized_entity_list.insert(Entity::new((CommonProp,)));
+    assert_eq!(id_2.index, id_1.index);
+    assert_ne!(id_2, id_1);
+}
+
+#[test]
+fn lists_without_uuids_are_deserialized() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp,)).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp,)).with(ComponentB { beta: 2 }));
+
+    // lists were saved with only these 4 fields before uuids were added
+    let value = serde_json::to_value(&entity_list).expect("EntityList should be serializable");
+    let old_fields = ["entries", "length", "next_free", "components_storage"];
+    let payload = serde_json::Value::Array(old_fields.iter().map(|field| value[*field].clone()).collect());
+    let deserialized_entity_list: EntityList<EntityRef> = serde_json::from_value(payload).expect("EntityList should be deserializable");
+    assert!(are_equal(entity_list.get(id_1), deserialized_entity_list.get(id_1)));
+    assert!(are_equal(entity_list.get(id_2), deserialized_entity_list.get(id_2)));
+    assert_eq!(deserialized_entity_list.uuid(id_1), None);
+}
+
+#[test]
+fn disabled_entities_are_serialized() {
+    let mut entity_list: EntityList<EntityRef> = EntityList::new();
+    let id_1 = entity_list.insert(Entity::new((CommonProp,)).with(ComponentA { alpha: 1.0 }));
+    let id_2 = entity_list.insert(Entity::new((CommonProp,)).with(ComponentA { alpha: 2.0 }));
+    entity_list.set_enabled(id_2, false);
+
+    let bytes = bincode::serialize(&entity_list).expect("EntityList should be serializable");
+    let deserialized_entity_list: EntityList<EntityRef> = bincode::deserialize(&bytes).expect("EntityList should be deserializable");
+    assert!(deserialized_entity_list.is_enabled(id_1));
+    assert!(!deserialized_entity_list.is_enabled(id_2));
+    assert_eq!(deserialized_entity_list.iter::<(ComponentA,)>().map(|(id, _)| id).collect::<Vec<_>>(), vec![id_1]);
+}
+
+mod particle {
+    use smec::define_entity;
+    use super::{CommonProp, ComponentA, ComponentB};
+
+    define_entity! {
+        serde;
+        #[derive(Debug)]
+        pub struct Particle {
+            props => {
+                common: CommonProp,
+            },
+            components => {
+                a => ComponentA,
+                #[inline] b => ComponentB,
+            }
+        }
+    }
+}
+
+#[test]
+fn inline_components_are_serialized() {
+    use particle::{Particle, ParticleRef};
+
+    let owned = Particle::new((CommonProp,)).with(ComponentA { alpha: 1.0 }).with(ComponentB { beta: 1 });
+    let bytes = bincode::serialize(&owned).expect("Particle should be serializable");
+    let deserialized: Particle = bincode::deserialize(&bytes).expect("Particle should be deserializable");
+    assert_eq!(deserialized.b, Some(ComponentB { beta: 1 }));
+    assert_eq!(deserialized.a.as_deref(), Some(&ComponentA { alpha: 1.0 }));
+
+    let mut entity_list: EntityList<ParticleRef> = EntityList::new();
+    let id = entity_list.insert(deserialized);
+    let bytes = bincode::serialize(&entity_list).expect("EntityList should be serializable");
+    let mut deserialized_entity_list: EntityList<ParticleRef> = bincode::deserialize(&bytes).expect("EntityList should be deserializable");
+    assert_eq!(deserialized_entity_list.get(id).unwrap().get::<ComponentB>(), Some(&ComponentB { beta: 1 }));
+    assert_eq!(deserialized_entity_list.remove(id).unwrap().b, Some(ComponentB { beta: 1 }));
+}